@@ -1,7 +1,19 @@
 const COMMANDS: &[&str] = &[
     "parse_epub",
     "index_epub",
+    "pause_index",
+    "resume_index",
+    "cancel_index",
+    "list_index_jobs",
+    "enqueue_index",
+    "remove_from_index_queue",
+    "set_index_priority",
+    "clear_index_queue",
+    "start_index_queue",
+    "stop_index_queue",
+    "get_index_queue",
     "search_db",
+    "search_library",
     "convert_to_mdbook",
     "parse_toc",
     "get_chunk_with_context",
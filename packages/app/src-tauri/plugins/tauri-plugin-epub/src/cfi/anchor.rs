@@ -0,0 +1,383 @@
+// 标注重定位：书籍文件换成新版本后，依据引文与上下文在新 EPUB 中重新寻找标注位置
+
+use anyhow::Result;
+
+use super::{Cfi, EpubLocator, TextPosition};
+
+/// 低于此分数的候选视为找不到
+pub const MIN_ANCHOR_SCORE: f64 = 0.6;
+/// 达到此分数且没有同分候选时视为可信
+pub const CONFIDENT_ANCHOR_SCORE: f64 = 0.9;
+
+/// 参与比较的上下文长度（字符）
+const CONTEXT_CHARS: usize = 32;
+/// 模糊匹配时最多比较的候选位置数
+const MAX_CANDIDATES: usize = 64;
+/// 两个候选分数差在此范围内视为无法区分
+const AMBIGUITY_MARGIN: f64 = 0.02;
+
+/// 待重定位的标注：引文、前后文，以及原 CFI（用于优先搜索原章节）
+#[derive(Debug, Clone, Default)]
+pub struct AnchorQuery<'a> {
+    pub exact: &'a str,
+    pub prefix: Option<&'a str>,
+    pub suffix: Option<&'a str>,
+    pub hint: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorMatch {
+    pub cfi: String,
+    pub spine_index: usize,
+    pub score: f64,
+    pub ambiguous: bool, // 存在分数几乎相同的其他位置
+}
+
+impl AnchorMatch {
+    pub fn is_confident(&self) -> bool {
+        self.score >= CONFIDENT_ANCHOR_SCORE && !self.ambiguous
+    }
+}
+
+/// 折叠空白后的章节正文，以及每个字符对应的 DOM 位置
+#[derive(Debug, Clone, Default)]
+pub struct NormalizedText {
+    pub chars: Vec<char>,
+    pub positions: Vec<TextPosition>,
+}
+
+impl NormalizedText {
+    /// 覆盖 `[start, end)` 字符的起止 DOM 位置
+    pub fn range(&self, start: usize, end: usize) -> (TextPosition, TextPosition) {
+        let last = self.positions[end - 1];
+        (
+            self.positions[start],
+            TextPosition {
+                node: last.node,
+                offset: last.offset + 1,
+            },
+        )
+    }
+
+    /// DOM 位置对应的字符下标（第一个不早于该位置的字符）
+    fn index_of(&self, position: TextPosition) -> usize {
+        let key = |p: &TextPosition| (p.node.get_usize(), p.offset);
+        let target = key(&position);
+        self.positions.partition_point(|p| key(p) < target)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    spine_index: usize,
+    start: usize,
+    end: usize,
+    score: f64,
+}
+
+impl EpubLocator {
+    /// CFI 范围当前覆盖的文本（空白已折叠）；无法解析时返回 None
+    pub fn text_at(&mut self, cfi: &str) -> Result<Option<String>> {
+        let Ok(parsed) = cfi.parse::<Cfi>() else {
+            return Ok(None);
+        };
+        let Some(spine_index) = parsed.spine_index().filter(|i| *i < self.spine_len()) else {
+            return Ok(None);
+        };
+        let document = self.content(spine_index)?;
+        let (Ok(start), Ok(end)) = (
+            document.resolve_steps(&parsed.start()),
+            document.resolve_steps(&parsed.end()),
+        ) else {
+            return Ok(None);
+        };
+
+        let text = self.normalized_text(spine_index)?;
+        let from = text.index_of(start);
+        let to = text.index_of(end).max(from);
+        let slice: String = text.chars[from..to].iter().collect();
+        Ok(Some(slice.trim().to_string()))
+    }
+
+    /// 在全书中重新定位一段引文。先找完全一致的文本，再用引文首尾与上下文作为种子做模糊比对；
+    /// 同一段文字出现多次时以上下文区分，仍无法区分则偏向原章节并标记为有歧义。
+    pub fn anchor(&mut self, query: &AnchorQuery) -> Result<Option<AnchorMatch>> {
+        let exact = collapse_whitespace(query.exact);
+        if exact.is_empty() {
+            return Ok(None);
+        }
+        let prefix = query.prefix.map(collapse_whitespace).unwrap_or_default();
+        let prefix = prefix[prefix.len().saturating_sub(CONTEXT_CHARS)..].to_vec();
+        let suffix = query.suffix.map(collapse_whitespace).unwrap_or_default();
+        let suffix = suffix[..suffix.len().min(CONTEXT_CHARS)].to_vec();
+
+        let hint = query
+            .hint
+            .and_then(|cfi| cfi.parse::<Cfi>().ok())
+            .and_then(|cfi| cfi.spine_index())
+            .unwrap_or(0);
+        let mut spines: Vec<usize> = (0..self.spine_len()).collect();
+        spines.sort_by_key(|i| i.abs_diff(hint));
+
+        // 1. 完全一致
+        let mut candidates = Vec::new();
+        for &spine_index in &spines {
+            let text = self.normalized_text(spine_index)?;
+            for start in find_all(&text.chars, &exact) {
+                let end = start + exact.len();
+                let context = context_score(&text.chars, start, end, &prefix, &suffix);
+                candidates.push(Candidate {
+                    spine_index,
+                    start,
+                    end,
+                    score: combine(1.0, context),
+                });
+                if candidates.len() >= MAX_CANDIDATES {
+                    break;
+                }
+            }
+            if candidates.len() >= MAX_CANDIDATES {
+                break;
+            }
+        }
+
+        // 2. 模糊匹配：以引文首尾片段和上下文为种子，在附近窗口内做近似比对
+        if candidates.is_empty() {
+            let seed_len = (exact.len() / 4).clamp(4, 16).min(exact.len());
+            let slack = exact.len() / 4 + 8;
+            let mut seeds = Vec::new();
+            for &spine_index in &spines {
+                let text = self.normalized_text(spine_index)?;
+                let chars = &text.chars;
+                let mut starts = Vec::new();
+                starts.extend(find_all(chars, &exact[..seed_len]).map(|p| p as isize));
+                starts.extend(
+                    find_all(chars, &exact[exact.len() - seed_len..])
+                        .map(|p| (p + seed_len) as isize - exact.len() as isize),
+                );
+                if prefix.len() >= seed_len {
+                    let tail = &prefix[prefix.len() - seed_len..];
+                    starts.extend(find_all(chars, tail).map(|p| (p + seed_len) as isize));
+                }
+                if suffix.len() >= seed_len {
+                    let head = &suffix[..seed_len];
+                    starts.extend(find_all(chars, head).map(|p| p as isize - exact.len() as isize));
+                }
+                starts.sort_unstable();
+                starts.dedup_by(|a, b| a.abs_diff(*b) <= slack);
+                seeds.extend(starts.into_iter().map(|s| (spine_index, s)));
+                if seeds.len() >= MAX_CANDIDATES {
+                    seeds.truncate(MAX_CANDIDATES);
+                    break;
+                }
+            }
+
+            for (spine_index, seed) in seeds {
+                let text = self.normalized_text(spine_index)?;
+                let chars = &text.chars;
+                let from = (seed - slack as isize).max(0) as usize;
+                let to = (seed.max(0) as usize + exact.len() + slack).min(chars.len());
+                if from >= to {
+                    continue;
+                }
+                let (start, end, distance) = align(&exact, &chars[from..to]);
+                let (start, end) = trim_range(chars, from + start, from + end);
+                if start >= end {
+                    continue;
+                }
+                let quote = 1.0 - distance as f64 / exact.len() as f64;
+                let context = context_score(chars, start, end, &prefix, &suffix);
+                candidates.push(Candidate {
+                    spine_index,
+                    start,
+                    end,
+                    score: combine(quote, context),
+                });
+            }
+        }
+
+        // 分数优先；同分时偏向离原章节更近的位置（spines 已按距离排序，稳定排序即可保留）
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        let Some(best) = candidates.first().copied() else {
+            return Ok(None);
+        };
+        if best.score < MIN_ANCHOR_SCORE {
+            return Ok(None);
+        }
+        let ambiguous = candidates.iter().skip(1).any(|c| {
+            best.score - c.score <= AMBIGUITY_MARGIN
+                && (c.spine_index != best.spine_index || c.start.abs_diff(best.start) >= exact.len() / 2)
+        });
+
+        let (start, end) = self.normalized_text(best.spine_index)?.range(best.start, best.end);
+        let cfi = self.range_cfi(best.spine_index, start, end)?;
+        Ok(Some(AnchorMatch {
+            cfi,
+            spine_index: best.spine_index,
+            score: best.score,
+            ambiguous,
+        }))
+    }
+
+    /// 获取（并缓存）折叠空白后的章节正文
+    pub fn normalized_text(&mut self, spine_index: usize) -> Result<&NormalizedText> {
+        if !self.texts.contains_key(&spine_index) {
+            let mut text = NormalizedText::default();
+            let mut last_space = true;
+            for (node, content) in self.content(spine_index)?.text_nodes()? {
+                for (offset, c) in content.chars().enumerate() {
+                    let is_space = c.is_whitespace();
+                    if is_space && last_space {
+                        continue;
+                    }
+                    text.chars.push(if is_space { ' ' } else { c });
+                    text.positions.push(TextPosition { node, offset });
+                    last_space = is_space;
+                }
+            }
+            self.texts.insert(spine_index, text);
+        }
+        Ok(&self.texts[&spine_index])
+    }
+}
+
+/// 折叠连续空白并去掉首尾空白
+pub fn collapse_whitespace(text: &str) -> Vec<char> {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect()
+}
+
+fn find_all<'a>(haystack: &'a [char], needle: &'a [char]) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(needle.len().max(1))
+        .enumerate()
+        .filter(move |(_, w)| !needle.is_empty() && *w == needle)
+        .map(|(i, _)| i)
+}
+
+/// 引文相似度与上下文相似度加权；没有上下文时只看引文
+fn combine(quote: f64, context: Option<f64>) -> f64 {
+    match context {
+        Some(context) => quote * 0.75 + context * 0.25,
+        None => quote,
+    }
+}
+
+/// 匹配位置前后的文本与保存的上下文的相似度（取平均）
+fn context_score(chars: &[char], start: usize, end: usize, prefix: &[char], suffix: &[char]) -> Option<f64> {
+    let mut scores = Vec::new();
+    if !prefix.is_empty() {
+        let before = &chars[start.saturating_sub(prefix.len() + 1)..start];
+        scores.push(similarity(prefix, trim_chars(before)));
+    }
+    if !suffix.is_empty() {
+        let after = &chars[end..(end + suffix.len() + 1).min(chars.len())];
+        scores.push(similarity(suffix, trim_chars(after)));
+    }
+    (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+fn trim_chars(chars: &[char]) -> &[char] {
+    let start = chars.iter().position(|c| *c != ' ').unwrap_or(chars.len());
+    let end = chars.iter().rposition(|c| *c != ' ').map_or(start, |i| i + 1);
+    &chars[start..end]
+}
+
+fn trim_range(chars: &[char], mut start: usize, mut end: usize) -> (usize, usize) {
+    while start < end && chars[start] == ' ' {
+        start += 1;
+    }
+    while end > start && chars[end - 1] == ' ' {
+        end -= 1;
+    }
+    (start, end)
+}
+
+/// 1 - 编辑距离 / 较长者长度
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// 半全局比对：引文必须完整比对，文本窗口两端可自由跳过。
+/// 返回窗口内最佳匹配的 (起点, 终点, 编辑距离)。
+fn align(pattern: &[char], window: &[char]) -> (usize, usize, usize) {
+    let n = window.len();
+    let mut prev = vec![0usize; n + 1];
+    let mut prev_start: Vec<usize> = (0..=n).collect();
+    let mut cur = vec![0usize; n + 1];
+    let mut cur_start = vec![0usize; n + 1];
+
+    for (i, pc) in pattern.iter().enumerate() {
+        cur[0] = i + 1;
+        cur_start[0] = 0;
+        for (j, wc) in window.iter().enumerate() {
+            let mut best = (prev[j] + usize::from(pc != wc), prev_start[j]);
+            if prev[j + 1] + 1 < best.0 {
+                best = (prev[j + 1] + 1, prev_start[j + 1]);
+            }
+            if cur[j] + 1 < best.0 {
+                best = (cur[j] + 1, cur_start[j]);
+            }
+            cur[j + 1] = best.0;
+            cur_start[j + 1] = best.1;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+        std::mem::swap(&mut prev_start, &mut cur_start);
+    }
+
+    let end = (0..=n).min_by_key(|&j| prev[j]).unwrap_or(0);
+    (prev_start[end], end, prev[end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn test_align_finds_edited_quote() {
+        let window = chars("It was the best of tymes, it was the worst of times.");
+        let pattern = chars("the best of times");
+        let (start, end, distance) = align(&pattern, &window);
+        assert_eq!(window[start..end].iter().collect::<String>(), "the best of tymes");
+        assert_eq!(distance, 1);
+    }
+
+    #[test]
+    fn test_context_score_prefers_matching_neighbourhood() {
+        let text = chars("foo quote bar. baz quote qux.");
+        let first = context_score(&text, 4, 9, &chars("foo"), &chars("bar."));
+        let second = context_score(&text, 19, 24, &chars("foo"), &chars("bar."));
+        assert!(first.unwrap() > second.unwrap());
+        assert_eq!(context_score(&text, 4, 9, &[], &[]), None);
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        assert_eq!(collapse_whitespace("  a \n\t b "), chars("a b"));
+    }
+}
@@ -0,0 +1,452 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use roxmltree::{Document, Node, NodeId};
+
+use super::parser::CfiStep;
+use super::xpointer::XPointer;
+
+/// 一个 spine 内容文档（XHTML），负责 CFI / XPointer 与 DOM 文本位置之间的换算
+pub struct ContentDocument {
+    source: String,
+}
+
+/// DOM 中的文本位置：文本节点 + 字符偏移（Unicode 标量）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPosition {
+    pub node: NodeId,
+    pub offset: usize,
+}
+
+/// 按 CFI 规则划分的子节点：元素占偶数位，相邻文本节点合并占奇数位
+struct CfiChildren<'a, 'input> {
+    elements: Vec<Node<'a, 'input>>,
+    texts: Vec<Vec<Node<'a, 'input>>>,
+}
+
+impl ContentDocument {
+    /// 从原始 XHTML 构建（会清洗 DOCTYPE 与 HTML 命名实体）
+    pub fn new(raw: &str) -> Result<Self> {
+        let source = sanitize_xhtml(raw);
+        // 提前校验一次，避免后续每次调用才发现无法解析
+        Document::parse(&source).context("Failed to parse XHTML content document")?;
+        Ok(Self { source })
+    }
+
+    /// 在解析后的 DOM 上执行操作
+    pub fn with_dom<T>(&self, f: impl FnOnce(&Document) -> Result<T>) -> Result<T> {
+        let doc = Document::parse(&self.source).context("Failed to parse XHTML content document")?;
+        f(&doc)
+    }
+
+    /// 将内容文档内的 CFI 步骤解析为文本位置
+    pub fn resolve_steps(&self, steps: &[CfiStep]) -> Result<TextPosition> {
+        self.with_dom(|doc| resolve_steps(doc, steps))
+    }
+
+    /// 将文本位置转换为 CFI 步骤
+    pub fn steps_for(&self, position: TextPosition) -> Result<Vec<CfiStep>> {
+        self.with_dom(|doc| {
+            let node = doc
+                .get_node(position.node)
+                .ok_or_else(|| anyhow!("Text node not found"))?;
+            Ok(steps_for_text(node, position.offset))
+        })
+    }
+
+    /// 将 XPointer 解析为文本位置
+    pub fn resolve_xpointer(&self, xpointer: &XPointer) -> Result<TextPosition> {
+        self.with_dom(|doc| resolve_xpointer(doc, xpointer))
+    }
+
+    /// 将文本位置转换为 XPointer
+    pub fn xpointer_for(&self, fragment: usize, position: TextPosition) -> Result<XPointer> {
+        self.with_dom(|doc| {
+            let node = doc
+                .get_node(position.node)
+                .ok_or_else(|| anyhow!("Text node not found"))?;
+            Ok(xpointer_for_text(node, fragment, position.offset))
+        })
+    }
+
+    /// 通过元素 id 定位（如 Kobo kepub 的 `kobo.12.3` span）
+    pub fn resolve_element_id(&self, id: &str, offset: usize) -> Result<TextPosition> {
+        self.with_dom(|doc| {
+            let element = doc
+                .descendants()
+                .find(|n| n.is_element() && n.attribute("id") == Some(id))
+                .ok_or_else(|| anyhow!("Element #{} not found", id))?;
+            position_in_element(element, offset)
+                .ok_or_else(|| anyhow!("Element #{} has no text", id))
+        })
+    }
+
+    /// 按文档顺序列出 <body> 中的所有文本节点及其内容
+    pub fn text_nodes(&self) -> Result<Vec<(NodeId, String)>> {
+        self.with_dom(|doc| {
+            let body = body_element(doc)?;
+            Ok(body
+                .descendants()
+                .filter(|n| n.is_text())
+                .map(|n| (n.id(), n.text().unwrap_or_default().to_string()))
+                .collect())
+        })
+    }
+}
+
+fn body_element<'a, 'input>(doc: &'a Document<'input>) -> Result<Node<'a, 'input>> {
+    doc.root_element()
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == "body")
+        .ok_or_else(|| anyhow!("<body> not found in content document"))
+}
+
+fn cfi_children<'a, 'input>(node: Node<'a, 'input>) -> CfiChildren<'a, 'input> {
+    let mut elements = Vec::new();
+    let mut texts = vec![Vec::new()];
+    for child in node.children() {
+        if child.is_element() {
+            elements.push(child);
+            texts.push(Vec::new());
+        } else if child.is_text() {
+            if let Some(group) = texts.last_mut() {
+                group.push(child);
+            }
+        }
+    }
+    CfiChildren { elements, texts }
+}
+
+fn resolve_steps(doc: &Document, steps: &[CfiStep]) -> Result<TextPosition> {
+    if steps.is_empty() {
+        bail!("Empty CFI path");
+    }
+
+    let mut node = doc.root_element();
+    for (i, step) in steps.iter().enumerate() {
+        let is_last = i + 1 == steps.len();
+        let children = cfi_children(node);
+
+        if step.index % 2 == 0 {
+            // 优先使用 id 断言，容忍轻微的结构偏差
+            let by_assertion = step.assertion.as_deref().and_then(|id| {
+                node.descendants()
+                    .find(|n| n.is_element() && n.attribute("id") == Some(id))
+            });
+            let element = match by_assertion {
+                Some(el) => el,
+                None => *children
+                    .elements
+                    .get(step.index / 2 - 1)
+                    .ok_or_else(|| anyhow!("CFI step /{} out of range", step.index))?,
+            };
+            if is_last {
+                return position_in_element(element, step.offset.unwrap_or(0))
+                    .ok_or_else(|| anyhow!("CFI target element has no text"));
+            }
+            node = element;
+        } else {
+            let group = children
+                .texts
+                .get((step.index - 1) / 2)
+                .ok_or_else(|| anyhow!("CFI text step /{} out of range", step.index))?;
+            return position_in_group(group, step.offset.unwrap_or(0)).or_else(|| {
+                // 空文本组时退回到后一个元素的首个文本
+                children
+                    .elements
+                    .get((step.index - 1) / 2)
+                    .and_then(|el| position_in_element(*el, 0))
+            })
+            .ok_or_else(|| anyhow!("CFI text step /{} has no text", step.index));
+        }
+    }
+
+    unreachable!("loop always returns on the last step")
+}
+
+/// 在合并文本组中按 UTF-16 偏移定位
+fn position_in_group(group: &[Node], utf16_offset: usize) -> Option<TextPosition> {
+    let mut remaining = utf16_offset;
+    for (i, node) in group.iter().enumerate() {
+        let text = node.text().unwrap_or_default();
+        let len = utf16_len(text);
+        if remaining <= len || i + 1 == group.len() {
+            let offset = utf16_to_char_offset(text, remaining.min(len));
+            return Some(TextPosition {
+                node: node.id(),
+                offset,
+            });
+        }
+        remaining -= len;
+    }
+    None
+}
+
+/// 元素内的位置：从首个非空白文本节点开始按字符偏移前进
+fn position_in_element(element: Node, char_offset: usize) -> Option<TextPosition> {
+    let texts: Vec<Node> = element
+        .descendants()
+        .filter(|n| n.is_text())
+        .collect();
+    let first_meaningful = texts
+        .iter()
+        .position(|n| !n.text().unwrap_or_default().trim().is_empty())
+        .unwrap_or(0);
+
+    let mut remaining = char_offset;
+    for (i, node) in texts.iter().enumerate().skip(first_meaningful) {
+        let len = node.text().unwrap_or_default().chars().count();
+        if remaining <= len || i + 1 == texts.len() {
+            return Some(TextPosition {
+                node: node.id(),
+                offset: remaining.min(len),
+            });
+        }
+        remaining -= len;
+    }
+    None
+}
+
+fn element_steps(element: Node) -> Vec<CfiStep> {
+    let mut steps = Vec::new();
+    let mut current = element;
+    while let Some(parent) = current.parent() {
+        if !parent.is_element() {
+            // current 是根元素 <html>，CFI 路径从它的子节点开始
+            break;
+        }
+        let position = parent
+            .children()
+            .filter(|n| n.is_element())
+            .position(|n| n == current)
+            .unwrap_or(0);
+        steps.push(CfiStep {
+            index: (position + 1) * 2,
+            assertion: current.attribute("id").map(|s| s.to_string()),
+            offset: None,
+        });
+        current = parent;
+    }
+    steps.reverse();
+    steps
+}
+
+fn steps_for_text(node: Node, char_offset: usize) -> Vec<CfiStep> {
+    let parent = match node.parent() {
+        Some(p) => p,
+        None => return Vec::new(),
+    };
+
+    // 文本组序号 = 该节点之前的兄弟元素个数；组内偏移需要累加组内前面的文本
+    let mut group_index = 0;
+    let mut utf16_offset = 0;
+    for sibling in parent.children() {
+        if sibling == node {
+            break;
+        }
+        if sibling.is_element() {
+            group_index += 1;
+            utf16_offset = 0;
+        } else if sibling.is_text() {
+            utf16_offset += utf16_len(sibling.text().unwrap_or_default());
+        }
+    }
+    let text = node.text().unwrap_or_default();
+    let prefix: String = text.chars().take(char_offset).collect();
+    utf16_offset += utf16_len(&prefix);
+
+    let mut steps = element_steps(parent);
+    steps.push(CfiStep {
+        index: group_index * 2 + 1,
+        assertion: None,
+        offset: Some(utf16_offset),
+    });
+    steps
+}
+
+fn resolve_xpointer(doc: &Document, xpointer: &XPointer) -> Result<TextPosition> {
+    let mut node = body_element(doc)?;
+    for (name, index) in &xpointer.steps {
+        node = node
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() == name)
+            .nth(index.saturating_sub(1))
+            .ok_or_else(|| anyhow!("XPointer step {}[{}] not found", name, index))?;
+    }
+
+    match xpointer.text_index {
+        Some(k) => {
+            // crengine 会丢弃纯空白文本节点，计数时需要同样跳过
+            let text = node
+                .children()
+                .filter(|n| n.is_text() && !n.text().unwrap_or_default().trim().is_empty())
+                .nth(k.saturating_sub(1))
+                .ok_or_else(|| anyhow!("XPointer text()[{}] not found", k))?;
+            let len = text.text().unwrap_or_default().chars().count();
+            Ok(TextPosition {
+                node: text.id(),
+                offset: xpointer.offset.min(len),
+            })
+        }
+        None => position_in_element(node, 0).ok_or_else(|| anyhow!("XPointer target has no text")),
+    }
+}
+
+fn xpointer_for_text(node: Node, fragment: usize, char_offset: usize) -> XPointer {
+    let mut steps = Vec::new();
+    let parent = node.parent();
+    let mut current = parent;
+    while let Some(element) = current {
+        if !element.is_element() || element.tag_name().name() == "body" {
+            break;
+        }
+        let name = element.tag_name().name();
+        // roxmltree 的 prev_siblings 包含自身，需要跳过
+        let index = element
+            .prev_siblings()
+            .skip(1)
+            .filter(|n| n.is_element() && n.tag_name().name() == name)
+            .count();
+        steps.push((name.to_string(), index + 1));
+        current = element.parent();
+    }
+    steps.reverse();
+
+    let text_index = parent.map(|p| {
+        p.children()
+            .filter(|n| n.is_text() && !n.text().unwrap_or_default().trim().is_empty())
+            .position(|n| n == node)
+            .unwrap_or(0)
+            + 1
+    });
+
+    XPointer {
+        fragment,
+        steps,
+        text_index,
+        offset: char_offset,
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn utf16_to_char_offset(text: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units >= utf16_offset {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+/// 移除 DOCTYPE，并把 XML 未定义的 HTML 命名实体替换为数字实体，以便 roxmltree 解析
+fn sanitize_xhtml(raw: &str) -> String {
+    let doctype = Regex::new(r"(?is)<!DOCTYPE[^\[>]*(\[.*?\])?\s*>").unwrap();
+    let without_doctype = doctype.replace(raw, "");
+
+    let entity = Regex::new(r"&([A-Za-z][A-Za-z0-9]*);").unwrap();
+    entity
+        .replace_all(&without_doctype, |caps: &regex::Captures| {
+            let name = &caps[1];
+            match name {
+                "amp" | "lt" | "gt" | "quot" | "apos" => caps[0].to_string(),
+                _ => format!("&#{};", html_entity_codepoint(name).unwrap_or(0xFFFD)),
+            }
+        })
+        .to_string()
+}
+
+fn html_entity_codepoint(name: &str) -> Option<u32> {
+    let code = match name {
+        "nbsp" => 0xA0,
+        "ensp" => 0x2002,
+        "emsp" => 0x2003,
+        "thinsp" => 0x2009,
+        "zwnj" => 0x200C,
+        "zwj" => 0x200D,
+        "shy" => 0xAD,
+        "ndash" => 0x2013,
+        "mdash" => 0x2014,
+        "lsquo" => 0x2018,
+        "rsquo" => 0x2019,
+        "sbquo" => 0x201A,
+        "ldquo" => 0x201C,
+        "rdquo" => 0x201D,
+        "bdquo" => 0x201E,
+        "hellip" => 0x2026,
+        "middot" => 0xB7,
+        "bull" => 0x2022,
+        "laquo" => 0xAB,
+        "raquo" => 0xBB,
+        "copy" => 0xA9,
+        "reg" => 0xAE,
+        "trade" => 0x2122,
+        "deg" => 0xB0,
+        "times" => 0xD7,
+        "divide" => 0xF7,
+        "sect" => 0xA7,
+        "para" => 0xB6,
+        "dagger" => 0x2020,
+        "Dagger" => 0x2021,
+        "prime" => 0x2032,
+        "Prime" => 0x2033,
+        "eacute" => 0xE9,
+        "egrave" => 0xE8,
+        "agrave" => 0xE0,
+        "aacute" => 0xE1,
+        "ouml" => 0xF6,
+        "uuml" => 0xFC,
+        "auml" => 0xE4,
+        "szlig" => 0xDF,
+        "ccedil" => 0xE7,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfi::parser::parse_steps;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.1//EN" "http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Test</title></head>
+<body>
+<h1 id="c1">第一章&nbsp;开端</h1>
+<p>First paragraph.</p>
+<p>Second <em>emphasised</em> paragraph text.</p>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_cfi_roundtrip_through_dom() {
+        let doc = ContentDocument::new(SAMPLE).unwrap();
+        // body=/4，第三个元素 <p>=/6，<em> 之后的文本组=/3
+        let steps = parse_steps("/4/6/3:5").unwrap();
+        let position = doc.resolve_steps(&steps).unwrap();
+        assert_eq!(position.offset, 5);
+        assert_eq!(doc.steps_for(position).unwrap(), steps);
+    }
+
+    #[test]
+    fn test_xpointer_to_steps() {
+        let doc = ContentDocument::new(SAMPLE).unwrap();
+        let xpointer: XPointer = "/body/DocFragment[1]/body/p[2]/text()[2].1".parse().unwrap();
+        let position = doc.resolve_xpointer(&xpointer).unwrap();
+        let steps = doc.steps_for(position).unwrap();
+        assert_eq!(crate::cfi::parser::format_steps(&steps), "/4/6/3:1");
+        assert_eq!(doc.xpointer_for(1, position).unwrap(), xpointer);
+    }
+
+    #[test]
+    fn test_named_entities_are_decoded() {
+        let doc = ContentDocument::new(SAMPLE).unwrap();
+        let texts = doc.text_nodes().unwrap();
+        assert!(texts.iter().any(|(_, t)| t == "第一章\u{a0}开端"));
+    }
+}
@@ -0,0 +1,168 @@
+pub mod anchor;
+pub mod content;
+pub mod parser;
+pub mod xpointer;
+
+// Re-export public types for convenience
+pub use anchor::*;
+pub use content::*;
+pub use parser::*;
+pub use xpointer::*;
+
+use anyhow::{anyhow, Context, Result};
+use epub::doc::EpubDoc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// EPUB 定位器：在 EPUB CFI、KOReader XPointer 与 DOM 文本位置之间换算。
+/// 供主应用的标注导入/导出复用。
+pub struct EpubLocator {
+    doc: EpubDoc<BufReader<File>>,
+    documents: HashMap<usize, ContentDocument>,
+    texts: HashMap<usize, NormalizedText>,
+}
+
+impl EpubLocator {
+    /// 打开 EPUB 文件
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let doc = EpubDoc::new(path.as_ref())
+            .with_context(|| format!("Failed to open EPUB: {:?}", path.as_ref()))?;
+        Ok(Self {
+            doc,
+            documents: HashMap::new(),
+            texts: HashMap::new(),
+        })
+    }
+
+    /// spine 项数
+    pub fn spine_len(&self) -> usize {
+        self.doc.spine.len()
+    }
+
+    /// 根据资源路径（可带目录前缀或锚点）查找 spine 序号
+    pub fn spine_index_for_href(&self, href: &str) -> Option<usize> {
+        let href = href.split('#').next().unwrap_or(href).replace('\\', "/");
+        let href = percent_encoding::percent_decode_str(&href)
+            .decode_utf8_lossy()
+            .to_string();
+        self.doc.spine.iter().position(|item| {
+            self.doc
+                .resources
+                .get(&item.idref)
+                .map(|(path, _)| {
+                    let path = path.to_string_lossy().replace('\\', "/");
+                    path == href || path.ends_with(&format!("/{}", href)) || href.ends_with(&format!("/{}", path))
+                })
+                .unwrap_or(false)
+        })
+    }
+
+    /// 获取（并缓存）spine 中的内容文档
+    pub fn content(&mut self, spine_index: usize) -> Result<&ContentDocument> {
+        if !self.documents.contains_key(&spine_index) {
+            let idref = self
+                .doc
+                .spine
+                .get(spine_index)
+                .map(|item| item.idref.clone())
+                .ok_or_else(|| anyhow!("Spine index {} out of range", spine_index))?;
+            let (raw, _mime) = self
+                .doc
+                .get_resource_str(&idref)
+                .ok_or_else(|| anyhow!("Spine resource '{}' not found", idref))?;
+            let document = ContentDocument::new(&raw)
+                .with_context(|| format!("Failed to parse spine item '{}'", idref))?;
+            self.documents.insert(spine_index, document);
+        }
+        Ok(&self.documents[&spine_index])
+    }
+
+    /// spine 项对应的包文档路径
+    pub fn spine_steps(&self, spine_index: usize) -> Vec<CfiStep> {
+        let idref = self.doc.spine.get(spine_index).map(|item| item.idref.as_str());
+        spine_steps(spine_index, idref)
+    }
+
+    /// 仅定位到章节（<body>）级别的 CFI，用于无法精确定位时兜底
+    pub fn chapter_cfi(&self, spine_index: usize) -> String {
+        Cfi::point(self.spine_steps(spine_index), vec![CfiStep::new(4)]).to_string()
+    }
+
+    /// 由同一章节内的起止文本位置生成范围 CFI
+    pub fn range_cfi(
+        &mut self,
+        spine_index: usize,
+        start: TextPosition,
+        end: TextPosition,
+    ) -> Result<String> {
+        let package = self.spine_steps(spine_index);
+        let document = self.content(spine_index)?;
+        let start_steps = document.steps_for(start)?;
+        let end_steps = document.steps_for(end)?;
+        Ok(Cfi::range(package, start_steps, end_steps).to_string())
+    }
+
+    /// 在章节正文中查找一段文本，返回覆盖它的范围 CFI（忽略空白差异）
+    pub fn find_text_cfi(&mut self, spine_index: usize, needle: &str) -> Result<Option<String>> {
+        let needle = collapse_whitespace(needle);
+        if needle.is_empty() {
+            return Ok(None);
+        }
+
+        let text = self.normalized_text(spine_index)?;
+        let Some(start) = text.chars.windows(needle.len()).position(|w| w == needle.as_slice()) else {
+            return Ok(None);
+        };
+        let (first, end) = text.range(start, start + needle.len());
+        self.range_cfi(spine_index, first, end).map(Some)
+    }
+
+    /// KOReader XPointer（pos0/pos1）转 CFI
+    pub fn xpointer_to_cfi(&mut self, pos0: &str, pos1: Option<&str>) -> Result<String> {
+        let start: XPointer = pos0.parse()?;
+        let spine_index = start.spine_index();
+        let start_position = self.content(spine_index)?.resolve_xpointer(&start)?;
+
+        let end_position = match pos1 {
+            Some(raw) => {
+                let end: XPointer = raw.parse()?;
+                if end.spine_index() != spine_index {
+                    // 跨章节的高亮只保留起点所在章节
+                    start_position
+                } else {
+                    self.content(spine_index)?.resolve_xpointer(&end)?
+                }
+            }
+            None => start_position,
+        };
+
+        self.range_cfi(spine_index, start_position, end_position)
+    }
+
+    /// XPointer 在书中的排序键（spine 序号、节点文档序、偏移）
+    pub fn xpointer_order(&mut self, xpointer: &str) -> Result<(usize, usize, usize)> {
+        let parsed: XPointer = xpointer.parse()?;
+        let spine_index = parsed.spine_index();
+        let position = self.content(spine_index)?.resolve_xpointer(&parsed)?;
+        Ok((spine_index, position.node.get_usize(), position.offset))
+    }
+
+    /// CFI 转 KOReader XPointer（返回 pos0, pos1）
+    pub fn cfi_to_xpointers(&mut self, cfi: &str) -> Result<(String, String)> {
+        let parsed: Cfi = cfi.parse()?;
+        let spine_index = parsed
+            .spine_index()
+            .ok_or_else(|| anyhow!("CFI has no spine step: {}", cfi))?;
+        let fragment = spine_index + 1;
+        let document = self.content(spine_index)?;
+
+        let start = document.resolve_steps(&parsed.start())?;
+        let end = document.resolve_steps(&parsed.end())?;
+        Ok((
+            document.xpointer_for(fragment, start)?.to_string(),
+            document.xpointer_for(fragment, end)?.to_string(),
+        ))
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// CFI 路径中的单个步骤，如 `/4[chap01]:12`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfiStep {
+    pub index: usize,
+    pub assertion: Option<String>, // id 断言 [xxx]
+    pub offset: Option<usize>,     // 字符偏移 :n（UTF-16 单位，与前端 DOM 保持一致）
+}
+
+impl CfiStep {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            assertion: None,
+            offset: None,
+        }
+    }
+}
+
+/// 解析后的 EPUB CFI，支持单点和范围两种形式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfi {
+    pub package: Vec<CfiStep>, // `!` 之前：包文档中的 spine 路径
+    pub parent: Vec<CfiStep>,  // `!` 之后：内容文档中的（公共父）路径
+    pub range: Option<(Vec<CfiStep>, Vec<CfiStep>)>,
+}
+
+impl Cfi {
+    /// 单点 CFI
+    pub fn point(package: Vec<CfiStep>, steps: Vec<CfiStep>) -> Self {
+        Self {
+            package,
+            parent: steps,
+            range: None,
+        }
+    }
+
+    /// 范围 CFI，自动提取起止路径的公共父路径
+    pub fn range(package: Vec<CfiStep>, start: Vec<CfiStep>, end: Vec<CfiStep>) -> Self {
+        if start == end {
+            return Self::point(package, start);
+        }
+
+        let max_common = start.len().min(end.len()).saturating_sub(1);
+        let mut common = 0;
+        while common < max_common
+            && start[common].index == end[common].index
+            && start[common].offset.is_none()
+            && end[common].offset.is_none()
+        {
+            common += 1;
+        }
+
+        Self {
+            package,
+            parent: start[..common].to_vec(),
+            range: Some((start[common..].to_vec(), end[common..].to_vec())),
+        }
+    }
+
+    /// spine 中的序号（0 起）
+    pub fn spine_index(&self) -> Option<usize> {
+        let last = self.package.last()?;
+        if last.index < 2 || last.index % 2 != 0 {
+            return None;
+        }
+        Some(last.index / 2 - 1)
+    }
+
+    /// 起点在内容文档中的完整路径
+    pub fn start(&self) -> Vec<CfiStep> {
+        let mut steps = self.parent.clone();
+        if let Some((start, _)) = &self.range {
+            steps.extend(start.iter().cloned());
+        }
+        steps
+    }
+
+    /// 终点在内容文档中的完整路径
+    pub fn end(&self) -> Vec<CfiStep> {
+        let mut steps = self.parent.clone();
+        if let Some((_, end)) = &self.range {
+            steps.extend(end.iter().cloned());
+        }
+        steps
+    }
+
+    /// 按书中先后排序用的键：spine 序号 + 起点各步骤的 (序号, 偏移)
+    pub fn sort_key(&self) -> Vec<usize> {
+        let mut key = vec![self.spine_index().unwrap_or(usize::MAX)];
+        for step in self.start() {
+            key.push(step.index);
+            key.push(step.offset.unwrap_or(0));
+        }
+        key
+    }
+}
+
+impl fmt::Display for Cfi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "epubcfi({}!{}",
+            format_steps(&self.package),
+            format_steps(&self.parent)
+        )?;
+        if let Some((start, end)) = &self.range {
+            write!(f, ",{},{}", format_steps(start), format_steps(end))?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::str::FromStr for Cfi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_cfi(s)
+    }
+}
+
+/// 生成 spine 中第 `spine_index` 项的包文档路径（`/6/{n}[idref]`）
+pub fn spine_steps(spine_index: usize, idref: Option<&str>) -> Vec<CfiStep> {
+    vec![
+        CfiStep::new(6),
+        CfiStep {
+            index: (spine_index + 1) * 2,
+            assertion: idref.map(|s| s.to_string()),
+            offset: None,
+        },
+    ]
+}
+
+/// 解析 `epubcfi(...)` 字符串
+pub fn parse_cfi(input: &str) -> Result<Cfi> {
+    let trimmed = input.trim();
+    let inner = trimmed
+        .strip_prefix("epubcfi(")
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(trimmed);
+
+    let parts = split_top_level(inner, ',');
+    if parts.len() != 1 && parts.len() != 3 {
+        bail!("Invalid CFI (unexpected range parts): {}", input);
+    }
+
+    let path_parts = split_top_level(&parts[0], '!');
+    if path_parts.len() != 2 {
+        bail!("Invalid CFI (missing indirection): {}", input);
+    }
+
+    let package = parse_steps(&path_parts[0])?;
+    let parent = parse_steps(&path_parts[1])?;
+    if package.is_empty() {
+        bail!("Invalid CFI (empty package path): {}", input);
+    }
+
+    let range = if parts.len() == 3 {
+        let start = parse_steps(&parts[1])?;
+        let end = parse_steps(&parts[2])?;
+        if start.is_empty() || end.is_empty() {
+            bail!("Invalid CFI (empty range part): {}", input);
+        }
+        Some((start, end))
+    } else {
+        None
+    };
+
+    Ok(Cfi {
+        package,
+        parent,
+        range,
+    })
+}
+
+/// 解析形如 `/4/2[id]/1:10` 的步骤序列
+pub fn parse_steps(input: &str) -> Result<Vec<CfiStep>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut steps: Vec<CfiStep> = Vec::new();
+    let mut i = 0;
+    // 偏移之后的断言是文本位置断言，不属于元素 id
+    let mut after_offset = false;
+
+    while i < chars.len() {
+        match chars[i] {
+            '/' => {
+                i += 1;
+                let (value, next) = read_number(&chars, i)?;
+                steps.push(CfiStep::new(value));
+                after_offset = false;
+                i = next;
+            }
+            ':' => {
+                i += 1;
+                let (value, next) = read_number(&chars, i)?;
+                match steps.last_mut() {
+                    Some(step) => step.offset = Some(value),
+                    None => bail!("Offset without step in CFI: {}", input),
+                }
+                after_offset = true;
+                i = next;
+            }
+            '[' => {
+                let (assertion, next) = read_assertion(&chars, i + 1)?;
+                if !after_offset {
+                    if let Some(step) = steps.last_mut() {
+                        if !assertion.is_empty() {
+                            step.assertion = Some(assertion);
+                        }
+                    }
+                }
+                i = next;
+            }
+            '~' | '@' => {
+                // 时间/空间偏移对文本定位无意义，直接跳过
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == ':') {
+                    i += 1;
+                }
+            }
+            c if c.is_whitespace() => i += 1,
+            c => bail!("Unexpected character '{}' in CFI: {}", c, input),
+        }
+    }
+
+    Ok(steps)
+}
+
+/// 将步骤序列格式化为字符串
+pub fn format_steps(steps: &[CfiStep]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        out.push('/');
+        out.push_str(&step.index.to_string());
+        if let Some(assertion) = &step.assertion {
+            out.push('[');
+            out.push_str(&escape_assertion(assertion));
+            out.push(']');
+        }
+        if let Some(offset) = step.offset {
+            out.push(':');
+            out.push_str(&offset.to_string());
+        }
+    }
+    out
+}
+
+fn read_number(chars: &[char], start: usize) -> Result<(usize, usize)> {
+    let mut end = start;
+    while end < chars.len() && chars[end].is_ascii_digit() {
+        end += 1;
+    }
+    if end == start {
+        bail!("Expected number in CFI at position {}", start);
+    }
+    let value: String = chars[start..end].iter().collect();
+    Ok((value.parse()?, end))
+}
+
+fn read_assertion(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let mut value = String::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '^' if i + 1 < chars.len() => {
+                value.push(chars[i + 1]);
+                i += 2;
+            }
+            ']' => {
+                // 只保留 id 部分，忽略 `;s=b` 之类的参数
+                let id = value.split(';').next().unwrap_or_default().to_string();
+                return Ok((id, i + 1));
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    bail!("Unterminated assertion in CFI")
+}
+
+fn escape_assertion(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '[' | ']' | '(' | ')' | ',' | ';' | '=' | '^') {
+            out.push('^');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 按分隔符切分，但忽略断言（方括号）内部以及转义后的分隔符
+fn split_top_level(input: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut escaped = false;
+
+    for c in input.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '^' => {
+                escaped = true;
+                current.push(c);
+            }
+            '[' => {
+                depth += 1;
+                current.push(c);
+            }
+            ']' => {
+                depth = depth.saturating_sub(1);
+                current.push(c);
+            }
+            c if c == separator && depth == 0 => {
+                parts.push(std::mem::take(&mut current));
+            }
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range_cfi() {
+        let cfi = parse_cfi("epubcfi(/6/14[chap05]!/4/2/10,/1:0,/1:25)").unwrap();
+        assert_eq!(cfi.spine_index(), Some(6));
+        assert_eq!(cfi.package[1].assertion.as_deref(), Some("chap05"));
+        assert_eq!(cfi.start().last().unwrap().offset, Some(0));
+        assert_eq!(cfi.end().last().unwrap().offset, Some(25));
+        assert_eq!(cfi.to_string(), "epubcfi(/6/14[chap05]!/4/2/10,/1:0,/1:25)");
+    }
+
+    #[test]
+    fn test_parse_ignores_text_assertion_and_side_bias() {
+        let cfi = parse_cfi("epubcfi(/6/4!/4/2[p1]/3:10[yyy,zzz;s=b])").unwrap();
+        let start = cfi.start();
+        assert_eq!(start[1].assertion.as_deref(), Some("p1"));
+        assert_eq!(start[2].assertion, None);
+        assert_eq!(start[2].offset, Some(10));
+    }
+
+    #[test]
+    fn test_range_extracts_common_parent() {
+        let start = vec![CfiStep::new(4), CfiStep::new(2), CfiStep { index: 1, assertion: None, offset: Some(3) }];
+        let end = vec![CfiStep::new(4), CfiStep::new(4), CfiStep { index: 1, assertion: None, offset: Some(7) }];
+        let cfi = Cfi::range(spine_steps(0, None), start, end);
+        assert_eq!(cfi.to_string(), "epubcfi(/6/2!/4,/2/1:3,/4/1:7)");
+    }
+
+    #[test]
+    fn test_escape_assertion_roundtrip() {
+        let steps = vec![CfiStep { index: 2, assertion: Some("a[1]".to_string()), offset: None }];
+        let formatted = format_steps(&steps);
+        assert_eq!(formatted, "/2[a^[1^]]");
+        assert_eq!(parse_steps(&formatted).unwrap(), steps);
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Context, Result};
+use std::fmt;
+
+/// KOReader（crengine）使用的 XPointer，如
+/// `/body/DocFragment[12]/body/div/p[3]/text().15`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPointer {
+    pub fragment: usize,                // DocFragment 序号（1 起，对应 spine）
+    pub steps: Vec<(String, usize)>,    // body 之后的元素路径：(标签名, 同名兄弟中的序号，1 起)
+    pub text_index: Option<usize>,      // text()[k] 中的 k（1 起）；None 表示定位在元素上
+    pub offset: usize,                  // 字符偏移（Unicode 标量）
+}
+
+impl XPointer {
+    /// spine 中的序号（0 起）
+    pub fn spine_index(&self) -> usize {
+        self.fragment.saturating_sub(1)
+    }
+}
+
+impl fmt::Display for XPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/body/DocFragment[{}]/body", self.fragment)?;
+        for (name, index) in &self.steps {
+            if *index > 1 {
+                write!(f, "/{}[{}]", name, index)?;
+            } else {
+                write!(f, "/{}", name)?;
+            }
+        }
+        match self.text_index {
+            Some(k) if k > 1 => write!(f, "/text()[{}].{}", k, self.offset),
+            Some(_) => write!(f, "/text().{}", self.offset),
+            None => write!(f, ".{}", self.offset),
+        }
+    }
+}
+
+impl std::str::FromStr for XPointer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_xpointer(s)
+    }
+}
+
+/// 解析 crengine XPointer
+pub fn parse_xpointer(input: &str) -> Result<XPointer> {
+    let trimmed = input.trim();
+    let rest = trimmed
+        .strip_prefix("/body/DocFragment[")
+        .with_context(|| format!("Unsupported xpointer (no DocFragment): {}", input))?;
+    let close = rest
+        .find(']')
+        .with_context(|| format!("Invalid xpointer fragment: {}", input))?;
+    let fragment: usize = rest[..close]
+        .parse()
+        .with_context(|| format!("Invalid DocFragment index: {}", input))?;
+    let mut path = &rest[close + 1..];
+
+    // DocFragment 之后的 body 对应内容文档中的 <body>
+    path = path.strip_prefix("/body").unwrap_or(path);
+
+    // 拆出末尾的 `.offset`
+    let (path, offset) = match path.rfind('.') {
+        Some(dot) if path[dot + 1..].chars().all(|c| c.is_ascii_digit()) && dot + 1 < path.len() => {
+            (&path[..dot], path[dot + 1..].parse::<usize>()?)
+        }
+        _ => (path, 0),
+    };
+
+    let mut steps = Vec::new();
+    let mut text_index = None;
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let (name, index) = match segment.find('[') {
+            Some(open) => {
+                let close = segment
+                    .find(']')
+                    .with_context(|| format!("Invalid xpointer step '{}'", segment))?;
+                let index: usize = segment[open + 1..close]
+                    .parse()
+                    .with_context(|| format!("Invalid xpointer index '{}'", segment))?;
+                (&segment[..open], index)
+            }
+            None => (segment, 1),
+        };
+
+        if name == "text()" {
+            text_index = Some(index);
+        } else if text_index.is_some() {
+            bail!("Element step after text() in xpointer: {}", input);
+        } else {
+            steps.push((name.to_string(), index));
+        }
+    }
+
+    Ok(XPointer {
+        fragment,
+        steps,
+        text_index,
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_text_xpointer() {
+        let xp = parse_xpointer("/body/DocFragment[12]/body/div/p[3]/text().15").unwrap();
+        assert_eq!(xp.fragment, 12);
+        assert_eq!(xp.spine_index(), 11);
+        assert_eq!(xp.steps, vec![("div".to_string(), 1), ("p".to_string(), 3)]);
+        assert_eq!(xp.text_index, Some(1));
+        assert_eq!(xp.offset, 15);
+    }
+
+    #[test]
+    fn test_parse_element_xpointer() {
+        let xp = parse_xpointer("/body/DocFragment[3]/body/h2.0").unwrap();
+        assert_eq!(xp.steps, vec![("h2".to_string(), 1)]);
+        assert_eq!(xp.text_index, None);
+        assert_eq!(xp.offset, 0);
+    }
+
+    #[test]
+    fn test_xpointer_roundtrip() {
+        let raw = "/body/DocFragment[7]/body/section[2]/p[10]/text()[2].4";
+        assert_eq!(parse_xpointer(raw).unwrap().to_string(), raw);
+    }
+}
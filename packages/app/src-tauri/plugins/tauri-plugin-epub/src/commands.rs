@@ -1,15 +1,20 @@
 use serde::Serialize;
 use tauri::{AppHandle, Emitter, Manager, Runtime, State};
 
-use crate::database::VectorDatabase;
+use crate::database::{InterruptedIndex, VectorDatabase};
+use crate::library_search::{books_with_tag, resolve_books, BookSearchNotice};
+use crate::jobs::{CancelAction, IndexCancelled, IndexJobInfo, IndexJobStatus};
+use crate::queue::{emit_queue, spawn_dispatcher, QueueRunConfig, QueueSnapshot};
 use crate::epub::EpubReader;
-use crate::pipeline::process_epub_to_db;
+use crate::pipeline::{process_epub_to_db, TMP_DB_FILE};
 use crate::models::ProgressUpdate;
 use crate::state::EpubState;
+use crate::text::SegmenterConfig;
 use crate::epub::{parse_toc_file, find_toc_ncx_in_mdbook, flatten_toc};
 use crate::models::{
-    DocumentChunk, ProcessOptions, VectorizerConfig, FlatTocNode,
-    ParsedBook, IndexResult, MdbookResult
+    DocumentChunk, EmbeddingBatchConfig, ProcessOptions, VectorizerConfig, FlatTocNode,
+    ParsedBook, IndexResult, MdbookResult, SearchMode, SearchResult, FusionStrategy, RerankerConfig,
+    DiversityConfig, DEFAULT_MMR_LAMBDA
 };
 use epub2mdbook::convert_epub_to_mdbook;
 
@@ -37,6 +42,12 @@ pub async fn parse_epub<R: Runtime>(
 
 /// Index an EPUB: resolve book_dir from $AppData/books/{book_id},
 /// parse, write chapters txt, vectorize and persist locally.
+/// Each run is a job that can be paused, resumed or cancelled; an interrupted
+/// run of the same book continues from its last committed batch.
+/// `embedding_*` tune batched embedding requests (chunks and tokens per request,
+/// requests in flight); defaults suit local embedding servers.
+/// `keyword_stopwords` lists languages ("en", "zh") whose stop words are left
+/// out of the keyword index; the same setting is applied to queries.
 #[tauri::command]
 pub async fn index_epub<R: Runtime>(
     app: AppHandle<R>,
@@ -46,15 +57,43 @@ pub async fn index_epub<R: Runtime>(
     embeddings_url: String,
     model: String,
     api_key: Option<String>,
+    embedding_batch_size: Option<usize>,
+    embedding_batch_tokens: Option<usize>,
+    embedding_concurrency: Option<usize>,
+    keyword_stopwords: Option<Vec<String>>,
+) -> Result<IndexResult, String> {
+    run_index(
+        &app,
+        &book_id,
+        VectorizerConfig {
+            embeddings_url,
+            model_name: model,
+            api_key,
+        },
+        embedding_batch_config(embedding_batch_size, embedding_batch_tokens, embedding_concurrency),
+        keyword_config(keyword_stopwords)?,
+    )
+    .await
+}
+
+/// Shared by `index_epub` and the index queue: run one book as an index job.
+pub(crate) async fn run_index<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: &str,
+    vectorizer: VectorizerConfig,
+    embedding: EmbeddingBatchConfig,
+    keyword: SegmenterConfig,
 ) -> Result<IndexResult, String> {
     if book_id.trim().is_empty() {
         return Err("book_id is empty".into());
     }
     let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let book_dir = app_data_dir.join("books").join(&book_id);
+    let book_dir = app_data_dir.join("books").join(book_id);
+    let state = app.state::<EpubState>();
 
     #[derive(Serialize, Clone)]
     struct IndexProgressEvent {
+        job_id: String,
         book_id: String,
         current: usize,
         total: usize,
@@ -64,21 +103,34 @@ pub async fn index_epub<R: Runtime>(
         related_chapter_titles: String,
     }
 
+    let (job, control) = state.jobs.start(book_id)?;
+    let job_id = job.job_id.clone();
+    let _ = app.emit("epub://index-job", job);
+    if let Some(item) = state.queue.attach_job(book_id, &job_id) {
+        emit_queue(app, Some(item));
+    }
+
     let app_for_emit = app.clone();
-    let book_id_for_emit = book_id.clone();
+    let book_id_for_emit = book_id.to_string();
+    let job_id_for_emit = job_id.clone();
 
-    let report = process_epub_to_db(
+    let result = process_epub_to_db(
         &book_dir,
         ProcessOptions {
             batch_size: None,
-            vectorizer: VectorizerConfig {
-                embeddings_url,
-                model_name: model,
-                api_key,
-            },
+            vectorizer,
+            embedding,
+            keyword,
+            control: Some(control),
         },
         Some(move |u: ProgressUpdate| {
+            let state = app_for_emit.state::<EpubState>();
+            state.jobs.update_progress(&job_id_for_emit, u.current, u.total, u.percent);
+            if let Some(item) = state.queue.update_progress(&book_id_for_emit, u.percent) {
+                emit_queue(&app_for_emit, Some(item));
+            }
             let payload = IndexProgressEvent {
+                job_id: job_id_for_emit.clone(),
                 book_id: book_id_for_emit.clone(),
                 current: u.current,
                 total: u.total,
@@ -90,14 +142,235 @@ pub async fn index_epub<R: Runtime>(
             let _ = app_for_emit.emit("epub://index-progress", payload);
         }),
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await;
 
-    Ok(IndexResult {
-        success: true,
-        message: "indexed".into(),
-        report: Some(report.into()),
-    })
+    let (status, outcome) = match result {
+        Ok(report) => (
+            IndexJobStatus::Completed,
+            Ok(IndexResult {
+                success: true,
+                message: "indexed".into(),
+                job_id: Some(job_id.clone()),
+                report: Some(report.into()),
+            }),
+        ),
+        Err(e) if e.is::<IndexCancelled>() => (
+            IndexJobStatus::Cancelled,
+            Ok(IndexResult {
+                success: false,
+                message: "cancelled".into(),
+                job_id: Some(job_id.clone()),
+                report: None,
+            }),
+        ),
+        Err(e) => (IndexJobStatus::Failed, Err(e.to_string())),
+    };
+    let error = outcome.as_ref().err().cloned();
+    if let Some(job) = state.jobs.finish(&job_id, status, error) {
+        let _ = app.emit("epub://index-job", job);
+    }
+    outcome
+}
+
+fn embedding_batch_config(
+    batch_size: Option<usize>,
+    batch_tokens: Option<usize>,
+    concurrency: Option<usize>,
+) -> EmbeddingBatchConfig {
+    let defaults = EmbeddingBatchConfig::default();
+    EmbeddingBatchConfig {
+        batch_size: batch_size.unwrap_or(defaults.batch_size),
+        max_batch_tokens: batch_tokens.unwrap_or(defaults.max_batch_tokens),
+        concurrency: concurrency.unwrap_or(defaults.concurrency),
+    }
+}
+
+fn keyword_config(stopwords: Option<Vec<String>>) -> Result<SegmenterConfig, String> {
+    SegmenterConfig::with_stopwords(&stopwords.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+/// Pause a running index job after the chunk in progress; the batch collected
+/// so far is committed so that the pause survives an app restart.
+#[tauri::command]
+pub async fn pause_index<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    job_id: String,
+) -> Result<IndexJobInfo, String> {
+    let job = state.jobs.pause(&job_id)?;
+    let _ = app.emit("epub://index-job", job.clone());
+    Ok(job)
+}
+
+/// Resume a paused index job.
+#[tauri::command]
+pub async fn resume_index<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    job_id: String,
+) -> Result<IndexJobInfo, String> {
+    let job = state.jobs.resume(&job_id)?;
+    let _ = app.emit("epub://index-job", job.clone());
+    Ok(job)
+}
+
+/// Cancel an index job. A running job stops at the next chunk and drops its
+/// temporary database; an interrupted or failed job has its checkpoint discarded.
+/// The previous index, if any, is left untouched.
+#[tauri::command]
+pub async fn cancel_index<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    job_id: String,
+) -> Result<IndexJobInfo, String> {
+    let (job, action) = state.jobs.cancel(&job_id)?;
+    if let CancelAction::DiscardCheckpoint = action {
+        let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        let tmp_db_path = app_data_dir.join("books").join(&job.book_id).join(TMP_DB_FILE);
+        if tmp_db_path.exists() {
+            std::fs::remove_file(&tmp_db_path).map_err(|e| e.to_string())?;
+        }
+    }
+    let _ = app.emit("epub://index-job", job.clone());
+    Ok(job)
+}
+
+/// List index jobs of this session, plus interrupted runs left on disk
+/// (books with a checkpointed temporary database and no active job).
+#[tauri::command]
+pub async fn list_index_jobs<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+) -> Result<Vec<IndexJobInfo>, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let books_dir = app_data_dir.join("books");
+
+    if let Ok(entries) = std::fs::read_dir(&books_dir) {
+        for entry in entries.flatten() {
+            let tmp_db_path = entry.path().join(TMP_DB_FILE);
+            let interrupted = match InterruptedIndex::read(&tmp_db_path) {
+                Ok(Some(interrupted)) => interrupted,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("list_index_jobs: failed to read checkpoint {:?}: {}", tmp_db_path, e);
+                    continue;
+                }
+            };
+            let updated_at = std::fs::metadata(&tmp_db_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Utc>::from(t).timestamp_millis())
+                .unwrap_or_default();
+            state.jobs.register_interrupted(
+                &entry.file_name().to_string_lossy(),
+                interrupted.checkpoint.processed_chunks,
+                interrupted.checkpoint.percent,
+                updated_at,
+            );
+        }
+    }
+
+    Ok(state.jobs.list())
+}
+
+/// Add books to the background index queue. Books already queued get the new
+/// priority; failed or finished books are queued again.
+#[tauri::command]
+pub async fn enqueue_index<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    book_ids: Vec<String>,
+    priority: Option<i32>,
+) -> Result<QueueSnapshot, String> {
+    for item in state.queue.enqueue(&book_ids, priority.unwrap_or(0)) {
+        emit_queue(&app, Some(item));
+    }
+    Ok(state.queue.snapshot())
+}
+
+/// Remove a book that is not currently being indexed from the queue.
+#[tauri::command]
+pub async fn remove_from_index_queue<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    book_id: String,
+) -> Result<QueueSnapshot, String> {
+    state.queue.remove(&book_id)?;
+    emit_queue(&app, None);
+    Ok(state.queue.snapshot())
+}
+
+/// Change the priority of a queued book; higher runs first.
+#[tauri::command]
+pub async fn set_index_priority<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    book_id: String,
+    priority: i32,
+) -> Result<QueueSnapshot, String> {
+    let item = state.queue.set_priority(&book_id, priority)?;
+    emit_queue(&app, Some(item));
+    Ok(state.queue.snapshot())
+}
+
+/// Drop finished books from the queue; failed books stay for retry.
+#[tauri::command]
+pub async fn clear_index_queue<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+) -> Result<QueueSnapshot, String> {
+    state.queue.clear_done();
+    emit_queue(&app, None);
+    Ok(state.queue.snapshot())
+}
+
+/// Start working through the queue, `concurrency` books at a time.
+/// The embedding settings are kept in memory only, so the queue has to be
+/// started again after a restart; books that were running resume from their checkpoint.
+#[tauri::command]
+pub async fn start_index_queue<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+    embeddings_url: String,
+    model: String,
+    api_key: Option<String>,
+    concurrency: Option<usize>,
+    embedding_batch_size: Option<usize>,
+    embedding_batch_tokens: Option<usize>,
+    embedding_concurrency: Option<usize>,
+    keyword_stopwords: Option<Vec<String>>,
+) -> Result<QueueSnapshot, String> {
+    let config = QueueRunConfig {
+        vectorizer: VectorizerConfig {
+            embeddings_url,
+            model_name: model,
+            api_key,
+        },
+        embedding: embedding_batch_config(embedding_batch_size, embedding_batch_tokens, embedding_concurrency),
+        keyword: keyword_config(keyword_stopwords)?,
+    };
+    if state.queue.start(config, concurrency) {
+        spawn_dispatcher(app.clone());
+    }
+    emit_queue(&app, None);
+    Ok(state.queue.snapshot())
+}
+
+/// Stop starting new books; books already being indexed run to completion.
+#[tauri::command]
+pub async fn stop_index_queue<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, EpubState>,
+) -> Result<QueueSnapshot, String> {
+    state.queue.stop();
+    emit_queue(&app, None);
+    Ok(state.queue.snapshot())
+}
+
+/// Current queue with overall progress and per-book status.
+#[tauri::command]
+pub async fn get_index_queue(state: State<'_, EpubState>) -> Result<QueueSnapshot, String> {
+    Ok(state.queue.snapshot())
 }
 
 /// Convert an EPUB under $AppData/books/{book_id} to mdBook structure at {book_dir}/mdbook
@@ -191,9 +464,49 @@ pub struct SearchItemDto {
     pub chunk_order_in_file: usize,
     pub total_chunks_in_file: usize,
     pub global_chunk_index: usize,
+
+    // 各路检索的名次（从 1 开始）与原始分数，未命中的一路为 null
+    pub vector_rank: Option<usize>,
+    pub vector_score: Option<f32>,
+    pub bm25_rank: Option<usize>,
+    pub bm25_score: Option<f32>,
+    pub rerank_score: Option<f32>,
+
+    // 合并相邻分片后，段落包含的分片 id；未合并时为空
+    pub merged_chunk_ids: Vec<i64>,
+}
+
+impl From<SearchResult> for SearchItemDto {
+    fn from(r: SearchResult) -> Self {
+        Self {
+            book_title: r.book_title,
+            book_author: r.book_author,
+            content: r.chunk_text,
+            similarity: r.similarity_score,
+            md_file_path: r.md_file_path,
+            file_order_in_book: r.file_order_in_book,
+            related_chapter_titles: r.related_chapter_titles,
+            chunk_id: r.chunk_id,
+            chunk_order_in_file: r.chunk_order_in_file,
+            total_chunks_in_file: r.total_chunks_in_file,
+            global_chunk_index: r.global_chunk_index,
+            vector_rank: r.components.vector_rank,
+            vector_score: r.components.vector_score,
+            bm25_rank: r.components.bm25_rank,
+            bm25_score: r.components.bm25_score,
+            rerank_score: r.components.rerank_score,
+            merged_chunk_ids: r.merged_chunk_ids,
+        }
+    }
 }
 
 /// Search the vector database for similar chunks with hybrid search support.
+/// `fusion` selects how hybrid mode combines the two result lists: `"weighted"` (default) or
+/// `"rrf"` (reciprocal rank fusion with constant `rrf_k`, default 60).
+/// When `rerank_url` is set, the top `rerank_top_n` candidates are reordered by a cross-encoder
+/// `/rerank` endpoint; on error or after `rerank_timeout_ms` the original order is kept.
+/// With `diversify`, adjacent hits are merged into passages (see `merged_chunk_ids`) and
+/// near-duplicate results are dropped by MMR, weighted by `mmr_lambda` (default 0.7).
 #[tauri::command]
 pub async fn search_db<R: Runtime>(
     app: AppHandle<R>,
@@ -209,12 +522,38 @@ pub async fn search_db<R: Runtime>(
     search_mode: Option<String>,      // "vector", "bm25", "hybrid"
     vector_weight: Option<f32>,       // 向量权重 (0.0-1.0)
     bm25_weight: Option<f32>,         // BM25权重 (0.0-1.0)
+    fusion: Option<String>,           // "weighted", "rrf"
+    rrf_k: Option<f32>,               // RRF 常数 k
+    // 重排参数，rerank_url 为空时不重排
+    rerank_url: Option<String>,
+    rerank_model: Option<String>,
+    rerank_api_key: Option<String>,
+    rerank_top_n: Option<usize>,
+    rerank_timeout_ms: Option<u64>,
+    // 去冗余参数
+    diversify: Option<bool>,
+    mmr_lambda: Option<f32>,
 ) -> Result<Vec<SearchItemDto>, String> {
     let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let book_dir = app_data_dir.join("books").join(book_id);
 
     // 解析搜索模式
     let mode = search_mode.as_deref().unwrap_or("hybrid");
+    let fusion = fusion_strategy(fusion.as_deref(), rrf_k)?;
+    let reranker = rerank_url.filter(|url| !url.trim().is_empty()).map(|url| {
+        let mut config = RerankerConfig::new(url, rerank_model.unwrap_or_default(), rerank_api_key);
+        config.top_n = rerank_top_n.unwrap_or(config.top_n);
+        config.timeout_ms = rerank_timeout_ms.unwrap_or(config.timeout_ms);
+        config
+    });
+    if let Some(lambda) = mmr_lambda {
+        if !(0.0..=1.0).contains(&lambda) {
+            return Err(format!("mmr_lambda must be between 0 and 1, got {}", lambda));
+        }
+    }
+    let diversity = diversify.unwrap_or(false).then(|| DiversityConfig {
+        lambda: mmr_lambda.unwrap_or(DEFAULT_MMR_LAMBDA),
+    });
 
     let results = crate::pipeline::search_db_with_mode(
         &book_dir,
@@ -229,26 +568,126 @@ pub async fn search_db<R: Runtime>(
         mode,
         vector_weight,
         bm25_weight,
+        fusion,
+        rrf_k,
+        reranker,
+        diversity,
     )
     .await
     .map_err(|e| e.to_string())?;
 
-    Ok(results
-        .into_iter()
-        .map(|r| SearchItemDto {
-            book_title: r.book_title,
-            book_author: r.book_author,
-            content: r.chunk_text,
-            similarity: r.similarity_score,
-            md_file_path: r.md_file_path,
-            file_order_in_book: r.file_order_in_book,
-            related_chapter_titles: r.related_chapter_titles,
-            chunk_id: r.chunk_id,
-            chunk_order_in_file: r.chunk_order_in_file,
-            total_chunks_in_file: r.total_chunks_in_file,
-            global_chunk_index: r.global_chunk_index,
-        })
-        .collect())
+    Ok(results.into_iter().map(SearchItemDto::from).collect())
+}
+
+/// 解析融合方式；rrf_k 必须为正数
+fn fusion_strategy(fusion: Option<&str>, rrf_k: Option<f32>) -> Result<FusionStrategy, String> {
+    if let Some(k) = rrf_k {
+        if !k.is_finite() || k <= 0.0 {
+            return Err(format!("rrf_k must be positive, got {}", k));
+        }
+    }
+    fusion.map_or(Ok(FusionStrategy::default()), str::parse)
+}
+
+#[derive(Serialize)]
+pub struct LibrarySearchHitDto {
+    pub book_id: String,
+    #[serde(flatten)]
+    pub item: SearchItemDto,
+}
+
+#[derive(Serialize)]
+pub struct LibrarySearchResponse {
+    pub hits: Vec<LibrarySearchHitDto>,
+    pub searched_books: usize,
+    pub notices: Vec<BookSearchNotice>,
+}
+
+/// Search all indexed books, or the given `book_ids` subset, with one shared query embedding.
+/// `tag` (a shelf, by tag name or id) limits the search to books carrying that tag, intersected
+/// with `book_ids` when both are given. Books indexed with a different
+/// embedding model or dimension fall back to keyword search and are reported in `notices`.
+/// Ranks in each hit are library-wide; `fusion` and `rrf_k` behave as in `search_db`.
+#[tauri::command]
+pub async fn search_library<R: Runtime>(
+    app: AppHandle<R>,
+    _state: State<'_, EpubState>,
+    query: String,
+    book_ids: Option<Vec<String>>,
+    tag: Option<String>,
+    limit: Option<usize>,
+    embeddings_url: String,
+    model: String,
+    api_key: Option<String>,
+    search_mode: Option<String>,
+    vector_weight: Option<f32>,
+    bm25_weight: Option<f32>,
+    fusion: Option<String>,
+    rrf_k: Option<f32>,
+) -> Result<LibrarySearchResponse, String> {
+    if query.trim().is_empty() {
+        return Err("query is empty".into());
+    }
+    let fusion = fusion_strategy(fusion.as_deref(), rrf_k)?;
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let books_dir = app_data_dir.join("books");
+
+    let mode = search_mode
+        .as_deref()
+        .unwrap_or("hybrid")
+        .parse::<SearchMode>()
+        .unwrap_or(SearchMode::Hybrid);
+    let mut config = if vector_weight.is_some() || bm25_weight.is_some() {
+        crate::config::create_custom_hybrid_config(Some(mode.clone()), vector_weight, bm25_weight)
+    } else {
+        crate::config::get_smart_hybrid_config(&query)
+    };
+    config.mode = mode;
+    config.fusion = fusion;
+    if let Some(k) = rrf_k {
+        config.rrf_k = k;
+    }
+
+    let book_ids = match tag.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(tag) => {
+            let app_db_path = app_data_dir.join("database").join("app.db");
+            let tagged = books_with_tag(&app_db_path, tag).map_err(|e| e.to_string())?;
+            Some(match book_ids {
+                Some(ids) => ids.into_iter().filter(|id| tagged.contains(id)).collect(),
+                None => tagged,
+            })
+        }
+        None => book_ids,
+    };
+
+    let (books, mut notices) = resolve_books(&books_dir, book_ids.as_deref()).map_err(|e| e.to_string())?;
+    let outcome = crate::library_search::search_library(
+        books,
+        &query,
+        limit.unwrap_or(10),
+        VectorizerConfig {
+            embeddings_url,
+            model_name: model,
+            api_key,
+        },
+        config,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    notices.extend(outcome.notices);
+
+    Ok(LibrarySearchResponse {
+        hits: outcome
+            .hits
+            .into_iter()
+            .map(|hit| LibrarySearchHitDto {
+                book_id: hit.book_id,
+                item: SearchItemDto::from(hit.result),
+            })
+            .collect(),
+        searched_books: outcome.searched_books,
+        notices,
+    })
 }
 
 /// Get chunk with context by chunk ID
@@ -1,4 +1,5 @@
-use crate::models::{SearchMode, HybridSearchConfig};
+use crate::models::{SearchMode, HybridSearchConfig, FusionStrategy, DEFAULT_RRF_K};
+use crate::text::{is_cjk, Segmenter, SegmenterConfig, CjkMode};
 
 /// 简化的搜索配置
 #[derive(Debug, Clone)]
@@ -36,6 +37,8 @@ impl SimpleSearchConfig {
             bm25_weight: self.default_bm25_weight,
             k1: self.bm25_k1,
             b: self.bm25_b,
+            fusion: FusionStrategy::default(),
+            rrf_k: DEFAULT_RRF_K,
         }
     }
 
@@ -45,10 +48,10 @@ impl SimpleSearchConfig {
             return self.to_hybrid_config(None);
         }
 
-        let word_count = query.split_whitespace().count();
-        let query_len = query.len();
+        let word_count = query_word_count(query);
+        let query_len = query.chars().count();
 
-        let (vector_weight, bm25_weight) = if word_count <= 2 || query_len < 10 {
+        let (vector_weight, bm25_weight) = if word_count <= 2 {
             // 短查询：偏重关键词匹配
             (0.4, 0.6)
         } else if word_count > 10 || query_len > 100 {
@@ -65,10 +68,31 @@ impl SimpleSearchConfig {
             bm25_weight,
             k1: self.bm25_k1,
             b: self.bm25_b,
+            fusion: FusionStrategy::default(),
+            rrf_k: DEFAULT_RRF_K,
         }
     }
 }
 
+/// 估算查询的词数：其他文字按词边界计数；中日韩文字没有空格，按平均两字一词估算
+fn query_word_count(query: &str) -> usize {
+    let segmenter = Segmenter::new(&SegmenterConfig {
+        cjk: CjkMode::Unigram,
+        stopwords: Vec::new(),
+    });
+    let (cjk_chars, words) = segmenter
+        .tokenize(query)
+        .iter()
+        .fold((0usize, 0usize), |(cjk, words), token| {
+            if token.text.chars().all(is_cjk) {
+                (cjk + 1, words)
+            } else {
+                (cjk, words + 1)
+            }
+        });
+    words + cjk_chars.div_ceil(2)
+}
+
 /// 全局配置实例
 static SEARCH_CONFIG: std::sync::OnceLock<SimpleSearchConfig> = std::sync::OnceLock::new();
 
@@ -1,9 +1,13 @@
 use anyhow::{Context, Result};
 use rusqlite::params;
-use std::collections::HashMap;
 
-use crate::database::DatabaseConnection;
-use crate::models::{SearchResult, BM25Stats, BM25SearchResult};
+use crate::database::{fts_index_ready, fts_match_query, fts_segmenter_config, DatabaseConnection, BM25_FUNCTION, FTS_TABLE};
+use crate::models::{SearchResult, BM25SearchResult, ScoreComponents};
+use crate::text::Segmenter;
+
+/// 正文与章节标题两列的 BM25 权重
+const CHUNK_TEXT_WEIGHT: f64 = 1.0;
+const CHAPTER_TITLE_WEIGHT: f64 = 0.5;
 
 /// BM25搜索实现
 pub struct BM25Search<'a> {
@@ -18,195 +22,91 @@ impl<'a> BM25Search<'a> {
         Self { db, k1, b }
     }
 
-    /// 执行BM25搜索
+    /// 执行BM25搜索；分数为 FTS5 全文索引上计算的原始 BM25 分数，越高越相关
     pub fn search(&self, query: &str, limit: usize) -> Result<Vec<BM25SearchResult>> {
-        // 1. 预处理查询文本
-        let query_terms = self.preprocess_text(query);
-        if query_terms.is_empty() {
+        if !fts_index_ready(self.db.connection())? {
+            log::warn!("全文索引缺失或为旧版本，重新索引后才能进行关键词搜索");
             return Ok(Vec::new());
         }
+        let segmenter = Segmenter::new(&fts_segmenter_config(self.db.connection())?);
+        let Some(match_query) = fts_match_query(query, &segmenter) else {
+            return Ok(Vec::new());
+        };
 
-        // 2. 获取BM25统计信息
-        let stats = self.get_bm25_stats()?;
-
-        // 3. 计算每个文档的BM25分数
-        let mut scores: HashMap<i64, f32> = HashMap::new();
-        
-        for term in &query_terms {
-            let term_scores = self.calculate_term_scores(term, &stats)?;
-            for (chunk_id, score) in term_scores {
-                *scores.entry(chunk_id).or_insert(0.0) += score;
-            }
-        }
-
-        // 4. 排序并限制结果
-        let mut scored_chunks: Vec<(i64, f32)> = scores.into_iter().collect();
-        scored_chunks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        scored_chunks.truncate(limit);
-
-        // 5. 归一化分数到[0,1]区间
-        let normalized_scores = self.normalize_bm25_scores(&scored_chunks);
-
-        // 6. 获取完整的搜索结果
-        let mut results = Vec::new();
-        for ((chunk_id, _), normalized_score) in scored_chunks.iter().zip(normalized_scores.iter()) {
-            if let Ok(search_result) = self.get_search_result_by_id(*chunk_id) {
-                results.push(BM25SearchResult {
-                    score: *normalized_score,
-                    search_result,
-                });
-            }
-        }
-
-        Ok(results)
-    }
-
-    /// 文本预处理和分词
-    fn preprocess_text(&self, text: &str) -> Vec<String> {
-        text.to_lowercase()
-            .split_whitespace()
-            .filter(|word| word.len() > 1) // 过滤单字符
-            .map(|word| {
-                // 移除标点符号
-                word.chars()
-                    .filter(|c| c.is_alphanumeric() || c.is_ascii_alphabetic())
-                    .collect::<String>()
-            })
-            .filter(|word| !word.is_empty())
-            .collect()
-    }
-
-    /// 获取BM25统计信息
-    fn get_bm25_stats(&self) -> Result<BM25Stats> {
-        // 尝试从缓存表获取
-        if let Ok(stats) = self.get_cached_stats() {
-            return Ok(stats);
-        }
-
-        // 重新计算统计信息
-        let mut stmt = self.db.connection().prepare(
-            "SELECT COUNT(*) as total_docs, AVG(LENGTH(chunk_text)) as avg_length FROM document_chunks"
-        )?;
-
-        let stats = stmt.query_row([], |row| {
-            Ok(BM25Stats {
-                total_docs: row.get::<_, i64>(0)? as usize,
-                avg_doc_length: row.get::<_, f64>(1)? as f32,
-            })
-        })?;
-
-        // 缓存统计信息
-        self.cache_stats(&stats)?;
-
-        Ok(stats)
-    }
-
-    /// 从缓存获取统计信息
-    fn get_cached_stats(&self) -> Result<BM25Stats> {
-        let mut stmt = self.db.connection().prepare(
-            "SELECT total_docs, avg_doc_length FROM bm25_stats ORDER BY updated_at DESC LIMIT 1"
-        )?;
-
-        stmt.query_row([], |row| {
-            Ok(BM25Stats {
-                total_docs: row.get::<_, i64>(0)? as usize,
-                avg_doc_length: row.get::<_, f64>(1)? as f32,
-            })
-        }).context("No cached BM25 stats found")
-    }
-
-    /// 缓存统计信息
-    fn cache_stats(&self, stats: &BM25Stats) -> Result<()> {
-        // 清除旧的统计信息
-        self.db.connection().execute("DELETE FROM bm25_stats", [])?;
-
-        // 插入新的统计信息
-        self.db.connection().execute(
-            "INSERT INTO bm25_stats (total_docs, avg_doc_length, updated_at) VALUES (?1, ?2, datetime('now'))",
-            params![stats.total_docs as i64, stats.avg_doc_length],
-        )?;
-
-        Ok(())
-    }
-
-    /// 计算单个词项的BM25分数
-    fn calculate_term_scores(&self, term: &str, stats: &BM25Stats) -> Result<HashMap<i64, f32>> {
-        let mut scores = HashMap::new();
-
-        // 获取包含该词项的所有文档
-        let mut stmt = self.db.connection().prepare(
+        let sql = format!(
             r#"
-            SELECT id, chunk_text, LENGTH(chunk_text) as doc_length
-            FROM document_chunks 
-            WHERE LOWER(chunk_text) LIKE ?1
-               OR LOWER(related_chapter_titles) LIKE ?1
-               OR LOWER(book_title) LIKE ?1
-            "#
+            SELECT
+                dc.id, dc.book_title, dc.book_author, dc.md_file_path, dc.file_order_in_book,
+                dc.related_chapter_titles, dc.chunk_text, dc.chunk_order_in_file,
+                dc.total_chunks_in_file, dc.global_chunk_index,
+                {func}({fts}, ?2, ?3, ?4, ?5) AS score
+            FROM {fts}
+            JOIN document_chunks dc ON dc.id = {fts}.rowid
+            WHERE {fts} MATCH ?1
+            ORDER BY score DESC
+            LIMIT ?6
+            "#,
+            func = BM25_FUNCTION,
+            fts = FTS_TABLE,
+        );
+        let mut stmt = self.db.connection().prepare(&sql)?;
+
+        let rows = stmt.query_map(
+            params![
+                match_query,
+                self.k1 as f64,
+                self.b as f64,
+                CHUNK_TEXT_WEIGHT,
+                CHAPTER_TITLE_WEIGHT,
+                limit as i64
+            ],
+            |row| {
+                Ok(BM25SearchResult {
+                    score: row.get::<_, f64>(10)? as f32,
+                    search_result: SearchResult {
+                        chunk_id: row.get(0)?,
+                        book_title: row.get(1)?,
+                        book_author: row.get(2)?,
+                        md_file_path: row.get(3)?,
+                        file_order_in_book: row.get(4)?,
+                        related_chapter_titles: row.get(5)?,
+                        chunk_text: row.get(6)?,
+                        chunk_order_in_file: row.get(7)?,
+                        total_chunks_in_file: row.get(8)?,
+                        global_chunk_index: row.get(9)?,
+                        similarity_score: 1.0, // BM25分数将在外层设置
+                        components: ScoreComponents::default(),
+                        merged_chunk_ids: Vec::new(),
+                    },
+                })
+            },
         )?;
 
-        let search_pattern = format!("%{}%", term);
-        let rows = stmt.query_map(params![search_pattern], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,      // chunk_id
-                row.get::<_, String>(1)?,   // chunk_text
-                row.get::<_, i64>(2)? as usize, // doc_length
-            ))
-        })?;
-
-        let mut matching_docs = Vec::new();
-        for row in rows {
-            let (chunk_id, text, doc_length) = row?;
-            let tf = self.calculate_term_frequency(term, &text);
-            if tf > 0 {
-                matching_docs.push((chunk_id, tf, doc_length));
-            }
-        }
-
-        // 计算文档频率 (DF)
-        let df = matching_docs.len();
-        if df == 0 {
-            return Ok(scores);
+        let mut results = rows.collect::<Result<Vec<_>, _>>().context("Failed to collect BM25 search results")?;
+        for (i, result) in results.iter_mut().enumerate() {
+            result.search_result.components.bm25_rank = Some(i + 1);
+            result.search_result.components.bm25_score = Some(result.score);
         }
-
-        // 计算逆文档频率 (IDF)
-        let idf = ((stats.total_docs as f32 - df as f32 + 0.5) / (df as f32 + 0.5)).ln();
-
-        // 为每个匹配的文档计算BM25分数
-        for (chunk_id, tf, doc_length) in matching_docs {
-            let score = self.calculate_bm25_score(tf, doc_length, stats.avg_doc_length, idf);
-            scores.insert(chunk_id, score);
-        }
-
-        Ok(scores)
+        Ok(results)
     }
 
-    /// 计算词频
-    fn calculate_term_frequency(&self, term: &str, text: &str) -> usize {
-        let text_lower = text.to_lowercase();
-        let term_lower = term.to_lowercase();
-        
-        // 简单的词频计算（可以优化为更精确的分词）
-        text_lower.matches(&term_lower).count()
+    /// 执行BM25搜索，并把本次结果的分数归一化到[0,1]区间
+    pub fn search_normalized(&self, query: &str, limit: usize) -> Result<Vec<BM25SearchResult>> {
+        let mut results = self.search(query, limit)?;
+        let normalized = Self::normalize_bm25_scores(&results);
+        for (result, score) in results.iter_mut().zip(normalized) {
+            result.score = score;
+        }
+        Ok(results)
     }
 
-    /// 计算BM25分数
-    fn calculate_bm25_score(&self, tf: usize, doc_length: usize, avg_doc_length: f32, idf: f32) -> f32 {
-        let tf_f32 = tf as f32;
-        let doc_length_f32 = doc_length as f32;
-        
-        let numerator = tf_f32 * (self.k1 + 1.0);
-        let denominator = tf_f32 + self.k1 * (1.0 - self.b + self.b * (doc_length_f32 / avg_doc_length));
-        
-        idf * (numerator / denominator)
-    }
-    
     /// 将BM25分数归一化到[0,1]区间
-    fn normalize_bm25_scores(&self, scored_chunks: &[(i64, f32)]) -> Vec<f32> {
-        if scored_chunks.is_empty() {
+    fn normalize_bm25_scores(results: &[BM25SearchResult]) -> Vec<f32> {
+        if results.is_empty() {
             return Vec::new();
         }
         
-        let scores: Vec<f32> = scored_chunks.iter().map(|(_, score)| *score).collect();
+        let scores: Vec<f32> = results.iter().map(|r| r.score).collect();
         let min_score = scores.iter().fold(f32::INFINITY, |a, &b| a.min(b));
         let max_score = scores.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
         
@@ -221,34 +121,88 @@ impl<'a> BM25Search<'a> {
             .map(|score| (score - min_score) / (max_score - min_score))
             .collect()
     }
+}
 
-    /// 根据chunk_id获取完整的搜索结果
-    fn get_search_result_by_id(&self, chunk_id: i64) -> Result<SearchResult> {
-        let mut stmt = self.db.connection().prepare(
-            r#"
-            SELECT 
-                id, book_title, book_author, md_file_path, file_order_in_book,
-                related_chapter_titles, chunk_text, chunk_order_in_file,
-                total_chunks_in_file, global_chunk_index, created_at
-            FROM document_chunks 
-            WHERE id = ?1
-            "#
-        )?;
-
-        stmt.query_row(params![chunk_id], |row| {
-            Ok(SearchResult {
-                chunk_id: row.get(0)?,
-                book_title: row.get(1)?,
-                book_author: row.get(2)?,
-                md_file_path: row.get(3)?,
-                file_order_in_book: row.get(4)?,
-                related_chapter_titles: row.get(5)?,
-                chunk_text: row.get(6)?,
-                chunk_order_in_file: row.get(7)?,
-                total_chunks_in_file: row.get(8)?,
-                global_chunk_index: row.get(9)?,
-                similarity_score: 1.0, // BM25分数将在外层设置
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DatabaseOperations;
+    use crate::models::DocumentChunk;
+    use tempfile::TempDir;
+
+    fn create_test_db(texts: &[&str]) -> (TempDir, DatabaseConnection) {
+        let dir = TempDir::new().unwrap();
+        let mut db = DatabaseConnection::new(dir.path().join("vectors.sqlite"), 3).unwrap();
+        let mut ops = DatabaseOperations::new(&mut db);
+        for (index, text) in texts.iter().enumerate() {
+            ops.insert_chunk(&DocumentChunk {
+                id: Some(index as i64 + 1),
+                book_title: "Test Book".to_string(),
+                book_author: "Test Author".to_string(),
+                md_file_path: "test.md".to_string(),
+                file_order_in_book: 1,
+                related_chapter_titles: String::new(),
+                chunk_text: text.to_string(),
+                chunk_order_in_file: index,
+                total_chunks_in_file: texts.len(),
+                embedding: vec![1.0, 0.0, 0.0],
+                global_chunk_index: index,
             })
-        }).context("Failed to get search result by chunk_id")
+            .unwrap();
+        }
+        (dir, db)
+    }
+
+    /// 按分数排列的分片 id
+    fn ranking(db: &DatabaseConnection, query: &str, k1: f32, b: f32) -> Vec<i64> {
+        BM25Search::new(db, k1, b)
+            .search(query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.search_result.chunk_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_document_frequency_counts_chunks() {
+        let (_dir, db) = create_test_db(&["apple apple apple apple apple", "banana", "cherry", "date"]);
+        let (k1, b) = (1.2f64, 0.75f64);
+        let results = BM25Search::new(&db, k1 as f32, b as f32).search("apple", 10).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // 5 次出现都在同一个分片中，文档频率为 1 而不是 5；平均长度 (5 + 1 + 1 + 1) / 4 = 2
+        let (n, df, tf, length, avg) = (4.0f64, 1.0f64, 5.0f64, 5.0f64, 2.0f64);
+        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+        let expected = idf * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * length / avg));
+        assert!((results[0].score as f64 - expected).abs() < 1e-4, "{} vs {}", results[0].score, expected);
+        assert_eq!(results[0].search_result.components.bm25_rank, Some(1));
+    }
+
+    #[test]
+    fn test_k1_changes_ranking() {
+        // 2 号分片两个词都命中；1 号只命中一个词但出现多次
+        let (_dir, db) = create_test_db(&["apple apple apple apple", "apple banana", "cherry", "cherry"]);
+
+        // k1 小时词频很快饱和，命中更多词的分片靠前；k1 大时词频的作用接近线性
+        assert_eq!(ranking(&db, "apple banana", 0.1, 0.0), vec![2, 1]);
+        assert_eq!(ranking(&db, "apple banana", 10.0, 0.0), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_b_changes_ranking() {
+        let (_dir, db) = create_test_db(&["apple", "apple apple apple x x x x x x x x x", "pear"]);
+
+        // b = 0 不做长度归一化，词频高的长分片靠前；b = 1 时短分片靠前
+        assert_eq!(ranking(&db, "apple", 1.2, 0.0), vec![2, 1]);
+        assert_eq!(ranking(&db, "apple", 1.2, 1.0), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_normalized_scores() {
+        let (_dir, db) = create_test_db(&["apple", "apple apple apple x x x x x x x x x", "pear"]);
+        let results = BM25Search::new(&db, 1.2, 1.0).search_normalized("apple", 10).unwrap();
+        let scores: Vec<f32> = results.iter().map(|r| r.score).collect();
+        assert_eq!(scores, vec![1.0, 0.0]);
+        assert!(BM25Search::new(&db, 1.2, 0.75).search("missing", 10).unwrap().is_empty());
     }
 }
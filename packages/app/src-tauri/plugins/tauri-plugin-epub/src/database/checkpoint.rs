@@ -0,0 +1,61 @@
+use anyhow::Result;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::database::{META_EMBEDDING_DIMENSION, META_EMBEDDING_MODEL, META_EPUB_HASH};
+
+/// index_meta 中保存检查点的键，索引完成后删除
+pub const META_CHECKPOINT: &str = "checkpoint";
+
+/// 最后一次提交批次后的索引进度，写在临时向量库中；重启后从这里继续
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexCheckpoint {
+    pub position: usize, // 按遍历顺序已经处理过的分片数（含向量化失败跳过的）
+    pub global_chunk_index: usize,
+    pub processed_chunks: usize,
+    pub reused_chunks: usize,
+    pub percent: f32,
+}
+
+/// 中断的索引：临时向量库中的检查点及其对应的 EPUB 与向量模型
+#[derive(Debug, Clone)]
+pub struct InterruptedIndex {
+    pub checkpoint: IndexCheckpoint,
+    pub epub_hash: Option<String>,
+    pub model_name: Option<String>,
+    pub dimension: Option<usize>,
+}
+
+impl InterruptedIndex {
+    /// 只读打开临时向量库读取检查点；文件不存在或没有检查点时返回 None
+    pub fn read<P: AsRef<Path>>(tmp_db_path: P) -> Result<Option<Self>> {
+        let tmp_db_path = tmp_db_path.as_ref();
+        if !tmp_db_path.exists() {
+            return Ok(None);
+        }
+
+        let conn = Connection::open_with_flags(tmp_db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let get = |key: &str| -> Option<String> {
+            conn.query_row("SELECT value FROM index_meta WHERE key = ?1", [key], |row| row.get(0))
+                .ok()
+        };
+
+        let Some(checkpoint) = get(META_CHECKPOINT) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            checkpoint: serde_json::from_str(&checkpoint)?,
+            epub_hash: get(META_EPUB_HASH),
+            model_name: get(META_EMBEDDING_MODEL),
+            dimension: get(META_EMBEDDING_DIMENSION).and_then(|d| d.parse().ok()),
+        }))
+    }
+
+    /// 是否是同一本 EPUB、同一向量模型与维度的中断索引
+    pub fn matches(&self, epub_hash: &str, model_name: &str, dimension: usize) -> bool {
+        self.epub_hash.as_deref() == Some(epub_hash)
+            && self.model_name.as_deref() == Some(model_name)
+            && self.dimension == Some(dimension)
+    }
+}
@@ -4,6 +4,8 @@ use rusqlite::Connection;
 use sqlite_vec::sqlite3_vec_init;
 use std::path::Path;
 
+use crate::database::{initialize_fts_table, register_fts_extensions};
+
 /// 数据库连接管理器
 pub struct DatabaseConnection {
     conn: Connection,
@@ -87,9 +89,8 @@ impl DatabaseConnection {
             embedding_dimension,
         };
 
-        // 确保BM25表存在（混合搜索需要）
-        db.initialize_bm25_tables()
-            .with_context(|| "Failed to initialize BM25 tables for search")?;
+        // 只注册分词器与排序函数；缺失或过旧的全文索引不在搜索时重建，由重新索引升级
+        register_fts_extensions(&db.conn)?;
 
         Ok(db)
     }
@@ -115,6 +116,7 @@ impl DatabaseConnection {
                 file_order_in_book INTEGER NOT NULL,
                 related_chapter_titles TEXT NOT NULL,
                 chunk_text TEXT NOT NULL,
+                content_hash TEXT,                 -- chunk_text 的 SHA-1，重新索引时据此复用向量
                 chunk_order_in_file INTEGER NOT NULL,
                 total_chunks_in_file INTEGER NOT NULL,
                 global_chunk_index INTEGER NOT NULL,
@@ -143,6 +145,17 @@ impl DatabaseConnection {
             [],
         ).with_context(|| "Failed to create idx_global_chunk index")?;
 
+        // 索引元信息：向量模型、维度与 EPUB 哈希，决定重新索引时能否复用已有向量
+        self.conn.execute(
+            r#"
+            CREATE TABLE IF NOT EXISTS index_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )
+            "#,
+            [],
+        ).with_context(|| "Failed to create index_meta table")?;
+
         // 创建向量表（如果不存在的话）
         let table_exists = self.conn.query_row(
             "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='chunk_embeddings'",
@@ -163,9 +176,10 @@ impl DatabaseConnection {
             log::warn!("向量表已存在，跳过创建");
         }
 
-        // 初始化BM25相关表
-        self.initialize_bm25_tables()
-            .with_context(|| "Failed to initialize BM25 tables")?;
+        // 初始化全文索引（BM25 检索）
+        register_fts_extensions(&self.conn)?;
+        initialize_fts_table(&self.conn)
+            .with_context(|| "Failed to initialize FTS5 index")?;
 
         log::info!("Database schema initialized successfully");
 
@@ -234,6 +248,44 @@ impl DatabaseConnection {
 
 
 
+    /// 读取索引元信息；旧数据库没有 index_meta 表时返回 None
+    pub fn get_meta(&self, key: &str) -> Option<String> {
+        self.conn
+            .query_row("SELECT value FROM index_meta WHERE key = ?1", [key], |row| row.get(0))
+            .ok()
+    }
+
+    /// 读取一个分片已保存的向量
+    pub fn chunk_embedding(&self, chunk_id: i64) -> Result<Vec<f32>> {
+        let table = if self.supports_vector_search() {
+            "chunk_embeddings"
+        } else {
+            "chunk_embeddings_fallback"
+        };
+        let bytes: Vec<u8> = self.conn.query_row(
+            &format!("SELECT embedding FROM {} WHERE chunk_id = ?1", table),
+            [chunk_id],
+            |row| row.get(0),
+        )?;
+        if bytes.len() % 4 != 0 {
+            anyhow::bail!("Invalid embedding byte length");
+        }
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+
+    /// 写入索引元信息
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO index_meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [key, value],
+        )?;
+        Ok(())
+    }
+
     /// 开始事务
     pub fn begin_transaction(&mut self) -> Result<()> {
         self.conn.execute("BEGIN TRANSACTION", [])?;
@@ -251,23 +303,4 @@ impl DatabaseConnection {
         self.conn.execute("ROLLBACK", [])?;
         Ok(())
     }
-
-    /// 初始化BM25相关表
-    fn initialize_bm25_tables(&self) -> Result<()> {
-        // 创建BM25统计信息表
-        self.conn.execute(
-            r#"
-            CREATE TABLE IF NOT EXISTS bm25_stats (
-                total_docs INTEGER NOT NULL,
-                avg_doc_length REAL NOT NULL,
-                updated_at TEXT NOT NULL
-            )
-            "#,
-            [],
-        ).with_context(|| "Failed to create bm25_stats table")?;
-
-        log::info!("BM25 tables initialized successfully");
-        Ok(())
-    }
-
 }
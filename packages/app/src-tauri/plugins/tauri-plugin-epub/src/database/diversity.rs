@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::database::DatabaseConnection;
+use crate::models::{DiversityConfig, SearchResult};
+
+/// 一个段落最多合并的分片数，避免整章的命中连成一个过长的段落
+const MAX_PASSAGE_CHUNKS: usize = 3;
+
+/// 行内重叠至少要有的字符数，避免把偶然相同的词当成分片重叠
+const MIN_CHAR_OVERLAP: usize = 8;
+
+/// 候选段落：合并后的结果及其向量（各分片向量归一化后的平均，读取失败时为 None）
+struct Passage {
+    result: SearchResult,
+    embedding: Option<Vec<f32>>,
+}
+
+/// 去冗余：先把同一文件中 global_chunk_index 相邻的命中合并成段落，再用 MMR 选出 limit 个段落。
+/// 输入需已按最终相关度排序（加权、RRF 或重排后的顺序）
+pub fn diversify(
+    db: &DatabaseConnection,
+    results: Vec<SearchResult>,
+    limit: usize,
+    config: &DiversityConfig,
+) -> Vec<SearchResult> {
+    let passages: Vec<Passage> = merge_adjacent(results)
+        .into_iter()
+        .map(|result| {
+            let embedding = passage_embedding(db, &result);
+            Passage { result, embedding }
+        })
+        .collect();
+
+    mmr_select(passages, limit, config.lambda)
+}
+
+/// 合并相邻分片。段落的位置信息取第一个分片，分数与各路名次取段落中排名最靠前的命中，
+/// 段落排在该命中原来的位置上
+fn merge_adjacent(results: Vec<SearchResult>) -> Vec<SearchResult> {
+    // (文件, global_chunk_index) -> 在 results 中的名次
+    let positions: HashMap<(&str, usize), usize> = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| ((r.md_file_path.as_str(), r.global_chunk_index), i))
+        .collect();
+
+    // 每个段落的成员名次，按阅读顺序。从排名最靠前的未合并命中出发，
+    // 每次向左或向右并入名次更靠前的相邻命中，最多 MAX_PASSAGE_CHUNKS 个
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut grouped: HashSet<usize> = HashSet::new();
+    for (i, r) in results.iter().enumerate() {
+        if !grouped.insert(i) {
+            continue;
+        }
+        let file = r.md_file_path.as_str();
+        let (mut first, mut last) = (r.global_chunk_index, r.global_chunk_index);
+        let mut members = vec![i];
+        while members.len() < MAX_PASSAGE_CHUNKS {
+            let neighbor = |index: Option<usize>| {
+                index
+                    .and_then(|index| positions.get(&(file, index)).copied())
+                    .filter(|j| !grouped.contains(j))
+            };
+            let left = neighbor(first.checked_sub(1));
+            let right = neighbor(Some(last + 1));
+            let next = match (left, right) {
+                (Some(l), Some(r)) => l.min(r),
+                (Some(l), None) => l,
+                (None, Some(r)) => r,
+                (None, None) => break,
+            };
+            if Some(next) == left {
+                first -= 1;
+            } else {
+                last += 1;
+            }
+            grouped.insert(next);
+            members.push(next);
+        }
+        members.sort_by_key(|&j| results[j].global_chunk_index);
+        groups.push(members);
+    }
+
+    let mut slots: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    groups
+        .into_iter()
+        .map(|members| {
+            let chunks: Vec<SearchResult> = members.iter().map(|&i| slots[i].take().expect("chunk merged once")).collect();
+            let best = members.iter().enumerate().min_by_key(|(_, &rank)| rank).map_or(0, |(pos, _)| pos);
+            let (score, components) = (chunks[best].similarity_score, chunks[best].components.clone());
+            let merged_chunk_ids: Vec<i64> = chunks.iter().map(|c| c.chunk_id).collect();
+
+            let mut chunks = chunks.into_iter();
+            let mut passage = chunks.next().expect("passage has at least one chunk");
+            if merged_chunk_ids.len() > 1 {
+                for chunk in chunks {
+                    passage.chunk_text = join_overlapping(&passage.chunk_text, &chunk.chunk_text);
+                }
+                passage.similarity_score = score;
+                passage.components = components;
+                passage.merged_chunk_ids = merged_chunk_ids;
+            }
+            passage
+        })
+        .collect()
+}
+
+/// 拼接相邻分片，分片重叠的部分只保留一次。多行分片按整行重叠；长行被分块器按句子或字符切开时，
+/// 重叠只是行内的一段文字，此时找前一个分片结尾与后一个分片开头最长的相同部分
+fn join_overlapping(first: &str, second: &str) -> String {
+    let a: Vec<&str> = first.lines().collect();
+    let b: Vec<&str> = second.lines().collect();
+    let overlap = (1..=a.len().min(b.len()))
+        .rev()
+        .find(|&k| {
+            a[a.len() - k..]
+                .iter()
+                .zip(&b[..k])
+                .all(|(x, y)| x.trim_end() == y.trim_end())
+        })
+        .unwrap_or(0);
+
+    if overlap == 0 {
+        let head = first.trim_end();
+        if let Some(rest) = char_overlap_rest(head, second) {
+            return format!("{}{}", head, rest);
+        }
+    }
+
+    let mut text = first.to_string();
+    let remaining = b[overlap..].join("\n");
+    if !remaining.is_empty() {
+        text.push('\n');
+        text.push_str(&remaining);
+    }
+    text
+}
+
+/// head 的后缀与 second 的前缀相同的最长部分至少有 MIN_CHAR_OVERLAP 个字符时，返回 second 去掉该前缀后的剩余部分
+fn char_overlap_rest<'a>(head: &str, second: &'a str) -> Option<&'a str> {
+    // second 前 k 个字符（k ≥ MIN_CHAR_OVERLAP）结束处的字节位置，不超过 head 的长度
+    let ends: Vec<usize> = second
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(second.len()))
+        .skip(MIN_CHAR_OVERLAP)
+        .take_while(|&end| end <= head.len())
+        .collect();
+    ends.into_iter()
+        .rev()
+        .find(|&end| head.ends_with(&second[..end]))
+        .map(|end| &second[end..])
+}
+
+fn passage_embedding(db: &DatabaseConnection, result: &SearchResult) -> Option<Vec<f32>> {
+    let ids = if result.merged_chunk_ids.is_empty() {
+        vec![result.chunk_id]
+    } else {
+        result.merged_chunk_ids.clone()
+    };
+
+    let mut sum: Vec<f32> = Vec::new();
+    for id in ids {
+        let embedding = match db.chunk_embedding(id) {
+            Ok(embedding) => normalized(embedding),
+            Err(e) => {
+                log::warn!("读取分片向量失败 (chunk {}): {}，该结果不参与冗余惩罚", id, e);
+                return None;
+            }
+        };
+        if sum.is_empty() {
+            sum = embedding;
+        } else if sum.len() == embedding.len() {
+            sum.iter_mut().zip(embedding).for_each(|(s, e)| *s += e);
+        }
+    }
+    Some(normalized(sum))
+}
+
+fn normalized(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// 已归一化向量的余弦相似度；缺少向量时视为不相似
+fn similarity(a: &Option<Vec<f32>>, b: &Option<Vec<f32>>) -> f32 {
+    match (a, b) {
+        (Some(a), Some(b)) if a.len() == b.len() => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+        _ => 0.0,
+    }
+}
+
+/// 最大边际相关：每次选 lambda * 相关度 - (1 - lambda) * 与已选段落的最大相似度 最高的段落。
+/// 结果可能来自加权、RRF 或重排，分数尺度不一，相关度按名次换算到 (0, 1]
+fn mmr_select(passages: Vec<Passage>, limit: usize, lambda: f32) -> Vec<SearchResult> {
+    let n = passages.len();
+    let relevance: Vec<f32> = (0..n).map(|i| 1.0 - i as f32 / n as f32).collect();
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut selected: Vec<usize> = Vec::with_capacity(limit.min(n));
+
+    while selected.len() < limit && !remaining.is_empty() {
+        let (pos, _) = remaining
+            .iter()
+            .enumerate()
+            .map(|(pos, &i)| {
+                let redundancy = selected
+                    .iter()
+                    .map(|&j| similarity(&passages[i].embedding, &passages[j].embedding))
+                    .fold(0.0, f32::max);
+                (pos, lambda * relevance[i] - (1.0 - lambda) * redundancy)
+            })
+            // 分数相同时取名次靠前的
+            .fold((0, f32::NEG_INFINITY), |best, cur| if cur.1 > best.1 { cur } else { best });
+        selected.push(remaining.remove(pos));
+    }
+
+    let mut slots: Vec<Option<Passage>> = passages.into_iter().map(Some).collect();
+    selected
+        .into_iter()
+        .filter_map(|i| slots[i].take().map(|p| p.result))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ScoreComponents;
+
+    fn result(chunk_id: i64, global_chunk_index: usize, text: &str, score: f32) -> SearchResult {
+        SearchResult {
+            chunk_id,
+            book_title: "Book".to_string(),
+            book_author: "Author".to_string(),
+            md_file_path: "chapter.md".to_string(),
+            file_order_in_book: 1,
+            related_chapter_titles: "Chapter".to_string(),
+            chunk_text: text.to_string(),
+            chunk_order_in_file: global_chunk_index,
+            total_chunks_in_file: 10,
+            global_chunk_index,
+            similarity_score: score,
+            components: ScoreComponents::default(),
+            merged_chunk_ids: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_merge_adjacent_chunks() {
+        let results = vec![
+            result(13, 3, "line c\nline d", 0.9),
+            result(20, 7, "other", 0.8),
+            result(12, 2, "line a\nline b\nline c", 0.7),
+        ];
+        let merged = merge_adjacent(results);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].chunk_id, 12);
+        assert_eq!(merged[0].merged_chunk_ids, [12, 13]);
+        assert_eq!(merged[0].chunk_text, "line a\nline b\nline c\nline d");
+        assert_eq!(merged[0].similarity_score, 0.9);
+        assert!(merged[1].merged_chunk_ids.is_empty());
+    }
+
+    #[test]
+    fn test_merge_limits_passage_length() {
+        let results: Vec<SearchResult> = [2, 3, 1, 4, 0].iter().map(|&i| result(i as i64, i, "text", 1.0)).collect();
+        let merged = merge_adjacent(results);
+        assert_eq!(merged[0].merged_chunk_ids, [1, 2, 3]);
+        assert_eq!(merged.iter().map(|r| r.chunk_id).collect::<Vec<_>>(), [1, 4, 0]);
+        assert!(merged[1].merged_chunk_ids.is_empty());
+    }
+
+    #[test]
+    fn test_mmr_skips_redundant_passage() {
+        let passage = |id, embedding: Vec<f32>| Passage {
+            result: result(id, id as usize * 10, "text", 1.0),
+            embedding: Some(normalized(embedding)),
+        };
+        let passages = vec![
+            passage(1, vec![1.0, 0.0]),
+            passage(2, vec![0.99, 0.05]),
+            passage(3, vec![0.0, 1.0]),
+        ];
+        let ids: Vec<i64> = mmr_select(passages, 2, 0.5).iter().map(|r| r.chunk_id).collect();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    /// 按分块器切分长行的方式切成带重叠的字符窗口
+    fn char_windows(text: &str, size: usize, overlap: usize) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut windows = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + size).min(chars.len());
+            windows.push(chars[start..end].iter().collect());
+            if end == chars.len() {
+                return windows;
+            }
+            start = end - overlap;
+        }
+    }
+
+    #[test]
+    fn test_merge_long_line_split_by_characters() {
+        let line = "春江潮水连海平，海上明月共潮生。滟滟随波千万里，何处春江无月明！江流宛转绕芳甸，月照花林皆似霰。";
+        let windows = char_windows(line, 20, 9);
+        assert_eq!(windows.len(), 4);
+        let results: Vec<SearchResult> = windows
+            .iter()
+            .enumerate()
+            .map(|(i, text)| result(i as i64, i, text, 1.0 - i as f32 * 0.1))
+            .collect();
+        let merged = merge_adjacent(results);
+        assert_eq!(merged[0].merged_chunk_ids, [0, 1, 2]);
+        assert_eq!(merged[0].chunk_text, line.chars().take(20 + 11 * 2).collect::<String>());
+
+        let english = "The quick brown fox jumps over the lazy dog while the cat watches from the fence.";
+        let windows = char_windows(english, 30, 12);
+        let joined = windows[1..].iter().fold(windows[0].clone(), |text, w| join_overlapping(&text, w));
+        assert_eq!(joined, english);
+    }
+
+    #[test]
+    fn test_join_sentence_split_chunks() {
+        // 按句子切分的分片以 "" 拼接，重叠的是完整的句子
+        let first = "First sentence here.Second sentence here.Third one.";
+        let second = "Second sentence here.Third one.Fourth sentence.";
+        assert_eq!(
+            join_overlapping(first, second),
+            "First sentence here.Second sentence here.Third one.Fourth sentence."
+        );
+        // 后一个分片完全包含在前一个分片结尾
+        assert_eq!(join_overlapping(first, "Third one."), first);
+        // 行内重叠与多行分片混合
+        assert_eq!(
+            join_overlapping("intro\nalpha beta gamma delta", "gamma delta epsilon\nnext line"),
+            "intro\nalpha beta gamma delta epsilon\nnext line"
+        );
+    }
+
+    #[test]
+    fn test_join_without_overlap() {
+        // 过短的相同部分不视为重叠
+        assert_eq!(join_overlapping("ends with the", "the beginning"), "ends with the\nthe beginning");
+        assert_eq!(join_overlapping("line a", "line b"), "line a\nline b");
+        assert_eq!(join_overlapping("only", ""), "only");
+    }
+}
@@ -0,0 +1,459 @@
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{ffi, Connection};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use crate::text::{is_cjk, Segmenter, SegmenterConfig, SEGMENTER_NAME};
+
+/// 分片全文索引（FTS5 外部内容表，内容来自 document_chunks）
+pub const FTS_TABLE: &str = "chunk_fts";
+
+/// 可配置 k1、b 的 BM25 排序函数：epub_bm25(chunk_fts, k1, b, 列权重...)，分数越高越相关
+pub const BM25_FUNCTION: &str = "epub_bm25";
+
+/// 创建全文索引；旧数据库首次打开时根据 document_chunks 重建，并删除不再使用的 bm25_stats 缓存表。
+/// 使用其他分词器建立的旧索引会按默认分词配置重建
+pub fn initialize_fts_table(conn: &Connection) -> Result<()> {
+    conn.execute("DROP TABLE IF EXISTS bm25_stats", [])
+        .context("Failed to drop bm25_stats table")?;
+
+    match fts_tokenizer_args(conn)? {
+        Some(args) if args.split_whitespace().next() == Some(SEGMENTER_NAME) => Ok(()),
+        Some(args) => {
+            log::info!("全文索引使用旧分词器（{}），重建", args);
+            create_fts_table(conn, &SegmenterConfig::default())
+        }
+        None => create_fts_table(conn, &SegmenterConfig::default()),
+    }
+}
+
+/// 按指定分词配置建立全文索引；配置与现有索引相同时不做任何事
+pub fn configure_fts_table(conn: &Connection, config: &SegmenterConfig) -> Result<()> {
+    if fts_tokenizer_args(conn)?.as_deref() == Some(config.to_args().as_str()) {
+        return Ok(());
+    }
+    create_fts_table(conn, config)
+}
+
+/// 现有全文索引的分词配置，索引与查询共用
+pub fn fts_segmenter_config(conn: &Connection) -> Result<SegmenterConfig> {
+    match fts_tokenizer_args(conn)? {
+        Some(args) => {
+            let args: Vec<&str> = args.split_whitespace().skip(1).collect();
+            SegmenterConfig::from_args(&args)
+        }
+        None => Ok(SegmenterConfig::default()),
+    }
+}
+
+/// 全文索引存在且使用当前分词器；否则需要重新索引后才能做关键词搜索
+pub fn fts_index_ready(conn: &Connection) -> Result<bool> {
+    Ok(fts_tokenizer_args(conn)?.is_some_and(|args| args.split_whitespace().next() == Some(SEGMENTER_NAME)))
+}
+
+/// 从建表语句中读出 tokenize 参数；表不存在时返回 None
+fn fts_tokenizer_args(conn: &Connection) -> Result<Option<String>> {
+    let sql: Option<String> = match conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type='table' AND name=?1",
+        [FTS_TABLE],
+        |row| row.get(0),
+    ) {
+        Ok(sql) => Some(sql),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(sql.map(|sql| {
+        sql.split_once("tokenize='")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map(|(args, _)| args.to_string())
+            .unwrap_or_default()
+    }))
+}
+
+fn create_fts_table(conn: &Connection, config: &SegmenterConfig) -> Result<()> {
+    conn.execute(&format!("DROP TABLE IF EXISTS {}", FTS_TABLE), [])
+        .context("Failed to drop FTS5 table")?;
+    conn.execute(
+        &format!(
+            "CREATE VIRTUAL TABLE {} USING fts5(
+                chunk_text,
+                related_chapter_titles,
+                content='document_chunks',
+                content_rowid='id',
+                tokenize='{}'
+            )",
+            FTS_TABLE,
+            config.to_args()
+        ),
+        [],
+    )
+    .context("Failed to create FTS5 table")?;
+    conn.execute(&format!("INSERT INTO {0}({0}) VALUES('rebuild')", FTS_TABLE), [])
+        .context("Failed to build FTS5 index from existing chunks")?;
+
+    log::info!("FTS5 全文索引已创建，分词配置: {}", config.to_args());
+    Ok(())
+}
+
+/// 把用户查询转换为 FTS5 MATCH 表达式：用与索引相同的分词器切词，每个词作为带引号的短语，
+/// 任一词命中即为候选。单个中日韩字符用前缀查询，以匹配以它开头的二元组
+pub fn fts_match_query(query: &str, segmenter: &Segmenter) -> Option<String> {
+    let mut seen = std::collections::HashSet::new();
+    let terms: Vec<String> = segmenter
+        .tokenize(query)
+        .into_iter()
+        .filter(|token| seen.insert(token.text.clone()))
+        .map(|token| {
+            let quoted = format!("\"{}\"", token.text.replace('"', "\"\""));
+            let mut chars = token.text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if is_cjk(c) => format!("{} *", quoted),
+                _ => quoted,
+            }
+        })
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" OR "))
+    }
+}
+
+/// 在连接上注册关键词分词器与 epub_bm25 排序函数；必须在访问全文索引之前调用
+pub fn register_fts_extensions(conn: &Connection) -> Result<()> {
+    unsafe {
+        let api = fts5_api(conn)?;
+        register_tokenizer(api)?;
+        register_bm25_function(api)?;
+    }
+    Ok(())
+}
+
+unsafe fn register_tokenizer(api: *mut ffi::fts5_api) -> Result<()> {
+    let create = (*api).xCreateTokenizer.ok_or_else(|| anyhow!("FTS5 API has no xCreateTokenizer"))?;
+    let name = CString::new(SEGMENTER_NAME)?;
+    let mut tokenizer = ffi::fts5_tokenizer {
+        xCreate: Some(tokenizer_create),
+        xDelete: Some(tokenizer_delete),
+        xTokenize: Some(tokenizer_tokenize),
+    };
+    let rc = create(api, name.as_ptr(), ptr::null_mut(), &mut tokenizer, None);
+    if rc != ffi::SQLITE_OK {
+        return Err(anyhow!("Failed to register tokenizer {} (code {})", SEGMENTER_NAME, rc));
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn tokenizer_create(
+    _user: *mut c_void,
+    az_arg: *mut *const c_char,
+    n_arg: c_int,
+    pp_out: *mut *mut ffi::Fts5Tokenizer,
+) -> c_int {
+    let args: Vec<String> = (0..n_arg.max(0) as usize)
+        .map(|i| CStr::from_ptr(*az_arg.add(i)).to_string_lossy().into_owned())
+        .collect();
+    match SegmenterConfig::from_args(&args) {
+        Ok(config) => {
+            *pp_out = Box::into_raw(Box::new(Segmenter::new(&config))) as *mut ffi::Fts5Tokenizer;
+            ffi::SQLITE_OK
+        }
+        Err(e) => {
+            log::warn!("Invalid tokenizer arguments {:?}: {}", args, e);
+            ffi::SQLITE_ERROR
+        }
+    }
+}
+
+unsafe extern "C" fn tokenizer_delete(p: *mut ffi::Fts5Tokenizer) {
+    drop(Box::from_raw(p as *mut Segmenter));
+}
+
+type TokenCallback = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, c_int, c_int) -> c_int;
+
+unsafe extern "C" fn tokenizer_tokenize(
+    p: *mut ffi::Fts5Tokenizer,
+    ctx: *mut c_void,
+    _flags: c_int,
+    text: *const c_char,
+    n_text: c_int,
+    x_token: Option<TokenCallback>,
+) -> c_int {
+    let Some(x_token) = x_token else {
+        return ffi::SQLITE_ERROR;
+    };
+    let segmenter = &*(p as *const Segmenter);
+    let bytes = if n_text > 0 && !text.is_null() {
+        std::slice::from_raw_parts(text as *const u8, n_text as usize)
+    } else {
+        &[]
+    };
+    // SQLite 中的文本应是 UTF-8；万一不是，只处理有效的前缀
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    };
+
+    for token in segmenter.tokenize(text) {
+        let rc = x_token(
+            ctx,
+            0,
+            token.text.as_ptr() as *const c_char,
+            token.text.len() as c_int,
+            token.start as c_int,
+            token.end as c_int,
+        );
+        if rc != ffi::SQLITE_OK {
+            return rc;
+        }
+    }
+    ffi::SQLITE_OK
+}
+
+/// 注册 epub_bm25 排序函数；FTS5 内置的 bm25() 固定使用 k1=1.2、b=0.75
+unsafe fn register_bm25_function(api: *mut ffi::fts5_api) -> Result<()> {
+    let create = (*api).xCreateFunction.ok_or_else(|| anyhow!("FTS5 API has no xCreateFunction"))?;
+    let name = CString::new(BM25_FUNCTION)?;
+    let rc = create(api, name.as_ptr(), ptr::null_mut(), Some(bm25_function), None);
+    if rc != ffi::SQLITE_OK {
+        return Err(anyhow!("Failed to register {} (code {})", BM25_FUNCTION, rc));
+    }
+    Ok(())
+}
+
+/// 通过 `SELECT fts5(?1)` 取得连接上的 fts5_api 指针
+unsafe fn fts5_api(conn: &Connection) -> Result<*mut ffi::fts5_api> {
+    let db = conn.handle();
+    let mut stmt: *mut ffi::sqlite3_stmt = ptr::null_mut();
+    let rc = ffi::sqlite3_prepare_v2(db, c"SELECT fts5(?1)".as_ptr(), -1, &mut stmt, ptr::null_mut());
+    if rc != ffi::SQLITE_OK {
+        return Err(anyhow!("FTS5 is not available (code {})", rc));
+    }
+
+    let mut api: *mut ffi::fts5_api = ptr::null_mut();
+    ffi::sqlite3_bind_pointer(
+        stmt,
+        1,
+        &mut api as *mut *mut ffi::fts5_api as *mut c_void,
+        c"fts5_api_ptr".as_ptr(),
+        None,
+    );
+    ffi::sqlite3_step(stmt);
+    ffi::sqlite3_finalize(stmt);
+
+    if api.is_null() || (*api).iVersion < 2 {
+        return Err(anyhow!("FTS5 API is not available"));
+    }
+    Ok(api)
+}
+
+/// 同一次查询内共享的统计量：每个短语的 IDF 与平均文档长度（token 数）
+struct QueryStats {
+    idf: Vec<f64>,
+    avg_doc_length: f64,
+}
+
+unsafe extern "C" fn drop_query_stats(p: *mut c_void) {
+    drop(Box::from_raw(p as *mut QueryStats));
+}
+
+unsafe extern "C" fn count_hit(_api: *const ffi::Fts5ExtensionApi, _fts: *mut ffi::Fts5Context, user: *mut c_void) -> c_int {
+    *(user as *mut i64) += 1;
+    ffi::SQLITE_OK
+}
+
+unsafe fn query_stats(api: &ffi::Fts5ExtensionApi, fts: *mut ffi::Fts5Context) -> Result<*const QueryStats, c_int> {
+    let get_aux = api.xGetAuxdata.ok_or(ffi::SQLITE_ERROR)?;
+    let cached = get_aux(fts, 0) as *const QueryStats;
+    if !cached.is_null() {
+        return Ok(cached);
+    }
+
+    let mut total_docs: i64 = 0;
+    check(api.xRowCount.ok_or(ffi::SQLITE_ERROR)?(fts, &mut total_docs))?;
+    let mut total_tokens: i64 = 0;
+    check(api.xColumnTotalSize.ok_or(ffi::SQLITE_ERROR)?(fts, -1, &mut total_tokens))?;
+
+    let phrase_count = api.xPhraseCount.ok_or(ffi::SQLITE_ERROR)?(fts);
+    let query_phrase = api.xQueryPhrase.ok_or(ffi::SQLITE_ERROR)?;
+    let mut idf = Vec::with_capacity(phrase_count.max(0) as usize);
+    for phrase in 0..phrase_count {
+        // 文档频率：包含该短语的分片数
+        let mut hits: i64 = 0;
+        check(query_phrase(fts, phrase, &mut hits as *mut i64 as *mut c_void, Some(count_hit)))?;
+        let n = total_docs as f64;
+        let df = hits as f64;
+        // 使用 Lucene 的 IDF 形式，常见词也保持正值，不会像经典公式那样在小书里归零
+        idf.push((1.0 + (n - df + 0.5) / (df + 0.5)).ln());
+    }
+
+    let stats = Box::new(QueryStats {
+        idf,
+        avg_doc_length: if total_docs > 0 { total_tokens as f64 / total_docs as f64 } else { 0.0 },
+    });
+    let stats = Box::into_raw(stats);
+    check(api.xSetAuxdata.ok_or(ffi::SQLITE_ERROR)?(fts, stats as *mut c_void, Some(drop_query_stats)))?;
+    Ok(stats)
+}
+
+unsafe fn bm25_score(
+    api: &ffi::Fts5ExtensionApi,
+    fts: *mut ffi::Fts5Context,
+    args: &[*mut ffi::sqlite3_value],
+) -> Result<f64, c_int> {
+    let arg = |i: usize, default: f64| args.get(i).map(|v| ffi::sqlite3_value_double(*v)).unwrap_or(default);
+    let k1 = arg(0, 1.2);
+    let b = arg(1, 0.75);
+
+    let stats = &*query_stats(api, fts)?;
+
+    // 当前分片中每个短语的（按列加权的）词频
+    let mut freq = vec![0.0f64; stats.idf.len()];
+    let mut inst_count: c_int = 0;
+    check(api.xInstCount.ok_or(ffi::SQLITE_ERROR)?(fts, &mut inst_count))?;
+    let inst = api.xInst.ok_or(ffi::SQLITE_ERROR)?;
+    for i in 0..inst_count {
+        let (mut phrase, mut column, mut offset) = (0, 0, 0);
+        check(inst(fts, i, &mut phrase, &mut column, &mut offset))?;
+        if let Some(f) = freq.get_mut(phrase as usize) {
+            *f += arg(2 + column as usize, 1.0);
+        }
+    }
+
+    // 文档长度按 token 计，而不是字节
+    let mut doc_length: c_int = 0;
+    check(api.xColumnSize.ok_or(ffi::SQLITE_ERROR)?(fts, -1, &mut doc_length))?;
+    let length_norm = if stats.avg_doc_length > 0.0 {
+        1.0 - b + b * doc_length as f64 / stats.avg_doc_length
+    } else {
+        1.0
+    };
+
+    Ok(stats
+        .idf
+        .iter()
+        .zip(freq)
+        .map(|(idf, f)| idf * (f * (k1 + 1.0)) / (f + k1 * length_norm))
+        .sum())
+}
+
+unsafe extern "C" fn bm25_function(
+    api: *const ffi::Fts5ExtensionApi,
+    fts: *mut ffi::Fts5Context,
+    ctx: *mut ffi::sqlite3_context,
+    n_val: c_int,
+    ap_val: *mut *mut ffi::sqlite3_value,
+) {
+    let args = if n_val > 0 && !ap_val.is_null() {
+        std::slice::from_raw_parts(ap_val, n_val as usize)
+    } else {
+        &[]
+    };
+    match bm25_score(&*api, fts, args) {
+        Ok(score) => ffi::sqlite3_result_double(ctx, score),
+        Err(rc) => ffi::sqlite3_result_error_code(ctx, rc),
+    }
+}
+
+fn check(rc: c_int) -> Result<(), c_int> {
+    if rc == ffi::SQLITE_OK {
+        Ok(())
+    } else {
+        Err(rc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{DatabaseConnection, DatabaseOperations};
+    use crate::models::DocumentChunk;
+    use tempfile::TempDir;
+
+    fn create_test_db(texts: &[&str]) -> (TempDir, DatabaseConnection) {
+        let dir = TempDir::new().unwrap();
+        let mut db = DatabaseConnection::new(dir.path().join("vectors.sqlite"), 3).unwrap();
+        let mut ops = DatabaseOperations::new(&mut db);
+        for (index, text) in texts.iter().enumerate() {
+            ops.insert_chunk(&DocumentChunk {
+                id: None,
+                book_title: "Test Book".to_string(),
+                book_author: "Test Author".to_string(),
+                md_file_path: "test.md".to_string(),
+                file_order_in_book: 1,
+                related_chapter_titles: String::new(),
+                chunk_text: text.to_string(),
+                chunk_order_in_file: index,
+                total_chunks_in_file: texts.len(),
+                embedding: vec![1.0, 0.0, 0.0],
+                global_chunk_index: index,
+            })
+            .unwrap();
+        }
+        (dir, db)
+    }
+
+    /// 命中的分片正文，按 rowid 排序
+    fn matching(db: &DatabaseConnection, query: &str) -> Vec<String> {
+        let segmenter = Segmenter::new(&fts_segmenter_config(db.connection()).unwrap());
+        let match_query = fts_match_query(query, &segmenter).unwrap();
+        let mut stmt = db
+            .connection()
+            .prepare(&format!(
+                "SELECT dc.chunk_text FROM {0} JOIN document_chunks dc ON dc.id = {0}.rowid WHERE {0} MATCH ?1 ORDER BY dc.id",
+                FTS_TABLE
+            ))
+            .unwrap();
+        let rows = stmt.query_map([match_query], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<Vec<String>>>().unwrap()
+    }
+
+    #[test]
+    fn test_match_whole_words_only() {
+        let (_dir, db) = create_test_db(&["Start the engine.", "The Art of War", "artful dodger", "ART!"]);
+        assert!(fts_index_ready(db.connection()).unwrap());
+
+        // 按词匹配：art 不命中 start，也不命中 artful
+        assert_eq!(matching(&db, "art"), vec!["The Art of War", "ART!"]);
+        assert_eq!(matching(&db, "engine war"), vec!["Start the engine.", "The Art of War"]);
+    }
+
+    #[test]
+    fn test_match_cjk_bigrams() {
+        let (_dir, db) = create_test_db(&["数据库的索引", "数据结构", "图书馆"]);
+
+        assert_eq!(matching(&db, "数据库"), vec!["数据库的索引", "数据结构"]);
+        assert_eq!(matching(&db, "据库"), vec!["数据库的索引"]);
+        // 单字用前缀查询，命中以它开头的二元组
+        assert_eq!(matching(&db, "图"), vec!["图书馆"]);
+        assert_eq!(matching(&db, "馆"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_fts_match_query() {
+        let segmenter = Segmenter::new(&SegmenterConfig::default());
+        assert_eq!(fts_match_query("Art art, \"war\"", &segmenter).as_deref(), Some(r#""art" OR "war""#));
+        assert_eq!(fts_match_query("书", &segmenter).as_deref(), Some(r#""书" *"#));
+        assert_eq!(fts_match_query(" ,.! ", &segmenter), None);
+    }
+
+    #[test]
+    fn test_configure_fts_table() {
+        let (_dir, db) = create_test_db(&["Start the engine."]);
+        let config = SegmenterConfig::with_stopwords(&["en".to_string()]).unwrap();
+        configure_fts_table(db.connection(), &config).unwrap();
+        assert_eq!(fts_segmenter_config(db.connection()).unwrap(), config);
+        // 重建后已有分片仍可检索
+        assert_eq!(matching(&db, "engine"), vec!["Start the engine."]);
+
+        // 其他分词器建立的旧索引需要重新索引
+        db.connection()
+            .execute_batch(&format!(
+                "DROP TABLE {0}; CREATE VIRTUAL TABLE {0} USING fts5(chunk_text, tokenize='unicode61')",
+                FTS_TABLE
+            ))
+            .unwrap();
+        assert!(!fts_index_ready(db.connection()).unwrap());
+    }
+}
@@ -39,7 +39,7 @@ impl<'a> HybridSearch<'a> {
     /// 纯BM25搜索
     fn bm25_only_search(&self, query: &str, limit: usize, config: &HybridSearchConfig) -> Result<Vec<SearchResult>> {
         let bm25_search = BM25Search::new(self.db, config.k1, config.b);
-        let bm25_results = bm25_search.search(query, limit)?;
+        let bm25_results = bm25_search.search_normalized(query, limit)?;
         
         // 转换为SearchResult格式
         Ok(bm25_results.into_iter().map(|r| {
@@ -85,48 +85,50 @@ impl<'a> HybridSearch<'a> {
         bm25_results: Vec<SearchResult>,
         config: &HybridSearchConfig,
     ) -> Result<Vec<HybridSearchResult>> {
-        // 1. 归一化分数
+        // 1. 归一化分数（加权求和使用），名次即在各自结果列表中的位置（RRF 使用）
         let normalized_vector = self.normalize_scores(&vector_results);
         let normalized_bm25 = self.normalize_scores(&bm25_results);
 
-        // 2. 创建结果映射
-        let mut vector_map: HashMap<i64, f32> = HashMap::new();
-        let mut bm25_map: HashMap<i64, f32> = HashMap::new();
+        // 2. 创建结果映射：chunk_id -> (名次, 归一化分数)
+        let mut vector_map: HashMap<i64, (usize, f32)> = HashMap::new();
+        let mut bm25_map: HashMap<i64, (usize, f32)> = HashMap::new();
         let mut all_results: HashMap<i64, SearchResult> = HashMap::new();
 
         // 填充向量搜索结果
-        for (result, norm_score) in vector_results.into_iter().zip(normalized_vector.into_iter()) {
-            vector_map.insert(result.chunk_id, norm_score);
+        for (i, (result, norm_score)) in vector_results.into_iter().zip(normalized_vector).enumerate() {
+            vector_map.insert(result.chunk_id, (i + 1, norm_score));
             all_results.insert(result.chunk_id, result);
         }
 
-        // 填充BM25搜索结果
-        for (result, norm_score) in bm25_results.into_iter().zip(normalized_bm25.into_iter()) {
-            bm25_map.insert(result.chunk_id, norm_score);
-            all_results.insert(result.chunk_id, result);
+        // 填充BM25搜索结果，两路都命中时合并各自的名次与分数
+        for (i, (result, norm_score)) in bm25_results.into_iter().zip(normalized_bm25).enumerate() {
+            bm25_map.insert(result.chunk_id, (i + 1, norm_score));
+            match all_results.get_mut(&result.chunk_id) {
+                Some(existing) => {
+                    existing.components.bm25_rank = result.components.bm25_rank;
+                    existing.components.bm25_score = result.components.bm25_score;
+                }
+                None => {
+                    all_results.insert(result.chunk_id, result);
+                }
+            }
         }
 
-        // 3. 计算混合分数
-        let mut hybrid_results = Vec::new();
-        for (chunk_id, search_result) in all_results {
-            let vector_score = vector_map.get(&chunk_id).copied();
-            let bm25_score = bm25_map.get(&chunk_id).copied();
-
-            // 计算加权合并分数
-            let combined_score = self.calculate_combined_score(
-                vector_score,
-                bm25_score,
-                config,
-            );
-
-            hybrid_results.push(HybridSearchResult {
-                combined_score,
+        // 3. 按融合方式计算混合分数
+        let mut hybrid_results: Vec<HybridSearchResult> = all_results
+            .into_iter()
+            .map(|(chunk_id, search_result)| HybridSearchResult {
+                combined_score: config.fuse(vector_map.get(&chunk_id).copied(), bm25_map.get(&chunk_id).copied()),
                 search_result,
-            });
-        }
+            })
+            .collect();
 
         // 4. 按合并分数排序
-        hybrid_results.sort_by(|a, b| b.combined_score.partial_cmp(&a.combined_score).unwrap());
+        hybrid_results.sort_by(|a, b| {
+            b.combined_score
+                .total_cmp(&a.combined_score)
+                .then_with(|| a.search_result.global_chunk_index.cmp(&b.search_result.global_chunk_index))
+        });
 
         Ok(hybrid_results)
     }
@@ -153,33 +155,6 @@ impl<'a> HybridSearch<'a> {
             .collect()
     }
 
-    /// 计算加权合并分数
-    fn calculate_combined_score(
-        &self,
-        vector_score: Option<f32>,
-        bm25_score: Option<f32>,
-        config: &HybridSearchConfig,
-    ) -> f32 {
-        match (vector_score, bm25_score) {
-            (Some(v_score), Some(b_score)) => {
-                // 两种搜索都有结果，使用加权平均
-                config.vector_weight * v_score + config.bm25_weight * b_score
-            }
-            (Some(v_score), None) => {
-                // 只有向量搜索有结果
-                config.vector_weight * v_score
-            }
-            (None, Some(b_score)) => {
-                // 只有BM25搜索有结果
-                config.bm25_weight * b_score
-            }
-            (None, None) => {
-                // 不应该发生，但返回0分
-                0.0
-            }
-        }
-    }
-
     // 移除了未使用的get_search_stats方法
 }
 
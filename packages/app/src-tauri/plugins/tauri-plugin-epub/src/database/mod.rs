@@ -2,14 +2,21 @@ pub mod connection;
 pub mod operations;
 pub mod search;
 pub mod bm25;
+pub mod fts;
 pub mod hybrid;
+pub mod reuse;
+pub mod checkpoint;
+pub mod diversity;
 
 // Re-export public types for convenience
 pub use connection::*;
 pub use operations::*;
 pub use search::*;
 pub use bm25::*;
+pub use fts::*;
 pub use hybrid::*;
+pub use reuse::*;
+pub use checkpoint::*;
 
 // Backward compatibility wrapper
 use anyhow::{Result};
@@ -48,10 +55,36 @@ impl VectorDatabase {
 
 
 
-    /// 批量插入文档块
-    pub fn insert_chunks_batch(&mut self, chunks: &[DocumentChunk]) -> Result<Vec<i64>> {
+    /// 写入索引元信息（向量模型、维度、EPUB 哈希）
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.db.set_meta(key, value)
+    }
+
+    /// 按指定分词配置建立关键词全文索引（配置不同时重建）
+    pub fn configure_keyword_index(&self, config: &crate::text::SegmenterConfig) -> Result<()> {
+        configure_fts_table(self.db.connection(), config)
+    }
+
+    /// 删除索引元信息
+    pub fn remove_meta(&self, key: &str) -> Result<()> {
+        self.db.connection().execute("DELETE FROM index_meta WHERE key = ?1", [key])?;
+        Ok(())
+    }
+
+    /// 已入库分片的最大 id，空库为 0
+    pub fn max_chunk_id(&self) -> Result<i64> {
+        let id = self.db.connection().query_row(
+            "SELECT COALESCE(MAX(id), 0) FROM document_chunks",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(id)
+    }
+
+    /// 批量插入文档块，并在同一事务中记录索引检查点
+    pub fn insert_chunks_with_checkpoint(&mut self, chunks: &[DocumentChunk], checkpoint: &IndexCheckpoint) -> Result<Vec<i64>> {
         let mut ops = DatabaseOperations::new(&mut self.db);
-        ops.insert_chunks_batch(chunks)
+        ops.insert_chunks_with_checkpoint(chunks, checkpoint)
     }
 
     /// 执行向量相似性搜索
@@ -76,7 +109,7 @@ impl VectorDatabase {
     pub fn bm25_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
         let config = crate::models::HybridSearchConfig::default();
         let bm25_search = BM25Search::new(&self.db, config.k1, config.b);
-        let bm25_results = bm25_search.search(query, limit)?;
+        let bm25_results = bm25_search.search_normalized(query, limit)?;
 
         // 转换为SearchResult格式
         Ok(bm25_results.into_iter().map(|r| {
@@ -122,6 +155,16 @@ impl VectorDatabase {
         }
     }
 
+    /// 合并相邻命中并用 MMR 去除冗余结果
+    pub fn diversify(
+        &self,
+        results: Vec<SearchResult>,
+        limit: usize,
+        config: &crate::models::DiversityConfig,
+    ) -> Vec<SearchResult> {
+        diversity::diversify(&self.db, results, limit, config)
+    }
+
     pub fn get_chunk_with_context(
         &self,
         chunk_id: i64,
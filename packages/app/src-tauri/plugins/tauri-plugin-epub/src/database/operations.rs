@@ -1,9 +1,15 @@
 use anyhow::Result;
 use rusqlite::params;
+use sha1::{Digest, Sha1};
 
-use crate::database::DatabaseConnection;
+use crate::database::{DatabaseConnection, IndexCheckpoint, FTS_TABLE, META_CHECKPOINT};
 use crate::models::DocumentChunk;
 
+/// 分片内容哈希（SHA-1 十六进制）
+pub fn chunk_content_hash(text: &str) -> String {
+    format!("{:x}", Sha1::digest(text.as_bytes()))
+}
+
 /// 数据库操作管理器
 pub struct DatabaseOperations<'a> {
     db: &'a mut DatabaseConnection,
@@ -15,25 +21,27 @@ impl<'a> DatabaseOperations<'a> {
         Self { db }
     }
 
-    /// 插入单个文档块
+    /// 插入单个文档块；chunk.id 为 Some 时沿用该 id（重新索引时保持未变分片的 id 不变）
     pub fn insert_chunk(&mut self, chunk: &DocumentChunk) -> Result<i64> {
         // 插入文档分块元数据
         let chunk_id = self.db.connection_mut().query_row(
             r#"
             INSERT INTO document_chunks (
-                book_title, book_author, md_file_path, file_order_in_book,
-                related_chapter_titles, chunk_text, chunk_order_in_file,
+                id, book_title, book_author, md_file_path, file_order_in_book,
+                related_chapter_titles, chunk_text, content_hash, chunk_order_in_file,
                 total_chunks_in_file, global_chunk_index
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
             RETURNING id
             "#,
             params![
+                chunk.id,
                 chunk.book_title,
                 chunk.book_author,
                 chunk.md_file_path,
                 chunk.file_order_in_book,
                 chunk.related_chapter_titles,
                 chunk.chunk_text,
+                chunk_content_hash(&chunk.chunk_text),
                 chunk.chunk_order_in_file,
                 chunk.total_chunks_in_file,
                 chunk.global_chunk_index,
@@ -41,6 +49,15 @@ impl<'a> DatabaseOperations<'a> {
             |row| row.get(0),
         )?;
 
+        // 写入全文索引
+        self.db.connection_mut().execute(
+            &format!(
+                "INSERT INTO {} (rowid, chunk_text, related_chapter_titles) VALUES (?1, ?2, ?3)",
+                FTS_TABLE
+            ),
+            params![chunk_id, chunk.chunk_text, chunk.related_chapter_titles],
+        )?;
+
         // 插入向量数据
         self.insert_embedding(chunk_id, &chunk.embedding)?;
 
@@ -76,20 +93,25 @@ impl<'a> DatabaseOperations<'a> {
         Ok(())
     }
 
-    /// 批量插入文档块
-    pub fn insert_chunks_batch(&mut self, chunks: &[DocumentChunk]) -> Result<Vec<i64>> {
-        if chunks.is_empty() {
-            return Ok(vec![]);
-        }
+    /// 批量插入并在同一事务中写入索引检查点；插入失败时整批回滚，检查点也不会前移
+    pub fn insert_chunks_with_checkpoint(&mut self, chunks: &[DocumentChunk], checkpoint: &IndexCheckpoint) -> Result<Vec<i64>> {
+        let json = serde_json::to_string(checkpoint)?;
+        self.in_transaction(|ops| {
+            let ids = ops.insert_chunks_batch_inner(chunks)?;
+            ops.db.set_meta(META_CHECKPOINT, &json)?;
+            Ok(ids)
+        })
+    }
 
+    fn in_transaction<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
         self.db.begin_transaction()?;
-        
-        let result = self.insert_chunks_batch_inner(chunks);
-        
+
+        let result = f(self);
+
         match result {
-            Ok(ids) => {
+            Ok(value) => {
                 self.db.commit_transaction()?;
-                Ok(ids)
+                Ok(value)
             }
             Err(e) => {
                 self.db.rollback_transaction()?;
@@ -114,14 +136,15 @@ impl<'a> DatabaseOperations<'a> {
 mod tests {
     use super::*;
     use crate::database::DatabaseConnection;
-    use tempfile::NamedTempFile;
+    use tempfile::TempDir;
 
-    fn create_test_db() -> DatabaseConnection {
-        let temp_file = NamedTempFile::new().unwrap();
-        DatabaseConnection::new(temp_file.path(), 384).unwrap()
+    fn create_test_db() -> (TempDir, DatabaseConnection) {
+        let dir = TempDir::new().unwrap();
+        let db = DatabaseConnection::new(dir.path().join("vectors.sqlite"), 3).unwrap();
+        (dir, db)
     }
 
-    fn create_test_chunk() -> DocumentChunk {
+    fn create_test_chunk(index: usize, text: &str) -> DocumentChunk {
         DocumentChunk {
             id: None,
             book_title: "Test Book".to_string(),
@@ -129,54 +152,65 @@ mod tests {
             md_file_path: "test.md".to_string(),
             file_order_in_book: 1,
             related_chapter_titles: "Chapter 1".to_string(),
-            chunk_text: "This is a test chunk.".to_string(),
-            chunk_order_in_file: 0,
-            total_chunks_in_file: 1,
-            global_chunk_index: 0,
-            created_at: "2023-01-01 00:00:00".to_string(),
+            chunk_text: text.to_string(),
+            chunk_order_in_file: index,
+            total_chunks_in_file: 2,
+            embedding: vec![1.0, 0.0, index as f32],
+            global_chunk_index: index,
         }
     }
 
-    #[test]
-    fn test_insert_and_retrieve_chunk() {
-        let mut db = create_test_db();
-        let mut ops = DatabaseOperations::new(&mut db);
-        
-        let chunk = create_test_chunk();
-        let chunk_id = ops.insert_chunk(&chunk).unwrap();
-        assert!(chunk_id > 0);
-
-        let chunks = ops.get_book_chunks("Test Book", "Test Author").unwrap();
-        assert_eq!(chunks.len(), 1);
-        assert_eq!(chunks[0].chunk_text, "This is a test chunk.");
+    fn count(db: &DatabaseConnection, table: &str) -> i64 {
+        db.connection()
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
     }
 
     #[test]
-    fn test_book_exists() {
-        let mut db = create_test_db();
+    fn test_insert_chunk() {
+        let (_dir, mut db) = create_test_db();
         let mut ops = DatabaseOperations::new(&mut db);
-        
-        assert!(!ops.book_exists("Test Book", "Test Author").unwrap());
-        
-        let chunk = create_test_chunk();
-        ops.insert_chunk(&chunk).unwrap();
-        
-        assert!(ops.book_exists("Test Book", "Test Author").unwrap());
+
+        let chunk_id = ops.insert_chunk(&create_test_chunk(0, "This is a test chunk.")).unwrap();
+        assert!(chunk_id > 0);
+        // 指定 id 时沿用
+        let mut kept = create_test_chunk(1, "Another chunk.");
+        kept.id = Some(42);
+        assert_eq!(ops.insert_chunk(&kept).unwrap(), 42);
+
+        assert_eq!(count(&db, "document_chunks"), 2);
+        assert_eq!(count(&db, FTS_TABLE), 2);
+        let hash: String = db
+            .connection()
+            .query_row("SELECT content_hash FROM document_chunks WHERE id = ?1", [chunk_id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(hash, chunk_content_hash("This is a test chunk."));
     }
 
     #[test]
-    fn test_delete_book() {
-        let mut db = create_test_db();
-        let mut ops = DatabaseOperations::new(&mut db);
-        
-        let chunk = create_test_chunk();
-        ops.insert_chunk(&chunk).unwrap();
-        
-        assert!(ops.book_exists("Test Book", "Test Author").unwrap());
-        
-        let deleted_count = ops.delete_book("Test Book", "Test Author").unwrap();
-        assert_eq!(deleted_count, 1);
-        
-        assert!(!ops.book_exists("Test Book", "Test Author").unwrap());
+    fn test_checkpoint_written_with_batch() {
+        let (_dir, mut db) = create_test_db();
+        let checkpoint = IndexCheckpoint {
+            position: 2,
+            global_chunk_index: 2,
+            processed_chunks: 2,
+            ..Default::default()
+        };
+        let chunks = [create_test_chunk(0, "First."), create_test_chunk(1, "Second.")];
+        let ids = DatabaseOperations::new(&mut db).insert_chunks_with_checkpoint(&chunks, &checkpoint).unwrap();
+        assert_eq!(ids.len(), 2);
+        let saved: IndexCheckpoint = serde_json::from_str(&db.get_meta(META_CHECKPOINT).unwrap()).unwrap();
+        assert_eq!(saved.position, 2);
+
+        // 插入失败时整批回滚，检查点不前移
+        let mut duplicate = create_test_chunk(2, "Third.");
+        duplicate.id = Some(ids[0]);
+        let next = IndexCheckpoint { position: 3, ..checkpoint };
+        assert!(DatabaseOperations::new(&mut db)
+            .insert_chunks_with_checkpoint(&[create_test_chunk(3, "Fourth."), duplicate], &next)
+            .is_err());
+        assert_eq!(count(&db, "document_chunks"), 2);
+        let saved: IndexCheckpoint = serde_json::from_str(&db.get_meta(META_CHECKPOINT).unwrap()).unwrap();
+        assert_eq!(saved.position, 2);
     }
 }
@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+use crate::database::{chunk_content_hash, DatabaseConnection};
+
+/// index_meta 中的键
+pub const META_EMBEDDING_MODEL: &str = "embedding_model";
+pub const META_EMBEDDING_DIMENSION: &str = "embedding_dimension";
+pub const META_EPUB_HASH: &str = "epub_hash";
+
+/// 上一次索引的结果：按分片内容哈希复用向量与分片 id
+pub struct PreviousIndex {
+    db: DatabaseConnection,
+    ids_by_hash: HashMap<String, VecDeque<i64>>,
+    max_id: i64,
+}
+
+impl PreviousIndex {
+    /// 打开已有的向量库；不存在，或向量模型、维度与本次不同时返回 None
+    pub fn open<P: AsRef<Path>>(db_path: P, model_name: &str, dimension: usize) -> Result<Option<Self>> {
+        let db_path = db_path.as_ref();
+        if !db_path.exists() {
+            return Ok(None);
+        }
+
+        let db = DatabaseConnection::open_existing(db_path, dimension)?;
+        let same_model = db.get_meta(META_EMBEDDING_MODEL).as_deref() == Some(model_name);
+        let same_dimension = db.get_meta(META_EMBEDDING_DIMENSION) == Some(dimension.to_string());
+        if !same_model || !same_dimension {
+            log::info!("已有向量库的模型或维度与本次不同（或未记录），不复用向量");
+            return Ok(None);
+        }
+
+        let mut ids_by_hash: HashMap<String, VecDeque<i64>> = HashMap::new();
+        let mut max_id = 0;
+        {
+            let mut stmt = db
+                .connection()
+                .prepare("SELECT id, content_hash, chunk_text FROM document_chunks ORDER BY global_chunk_index")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (id, hash, text) = row.context("Failed to load previous chunk")?;
+                let hash = hash.unwrap_or_else(|| chunk_content_hash(&text));
+                ids_by_hash.entry(hash).or_default().push_back(id);
+                max_id = max_id.max(id);
+            }
+        }
+
+        Ok(Some(Self { db, ids_by_hash, max_id }))
+    }
+
+    /// 取出一个内容相同的旧分片及其向量；同一内容的多个分片按原顺序依次取用
+    pub fn take(&mut self, content_hash: &str) -> Option<(i64, Vec<f32>)> {
+        let id = self.ids_by_hash.get_mut(content_hash)?.pop_front()?;
+        match self.db.chunk_embedding(id) {
+            Ok(embedding) => Some((id, embedding)),
+            Err(e) => {
+                log::warn!("读取旧向量失败 (chunk {}): {}，将重新向量化", id, e);
+                None
+            }
+        }
+    }
+
+    /// 跳过一个内容相同的旧分片而不读取向量；续传时已入库的分片用它保持取用顺序
+    pub fn skip(&mut self, content_hash: &str) -> Option<i64> {
+        self.ids_by_hash.get_mut(content_hash)?.pop_front()
+    }
+
+    /// 旧分片的最大 id；新分片的 id 从其后分配，避免与沿用的 id 冲突
+    pub fn max_id(&self) -> i64 {
+        self.max_id
+    }
+}
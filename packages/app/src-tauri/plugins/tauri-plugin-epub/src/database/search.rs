@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use rusqlite::params;
 
 use crate::database::{DatabaseConnection};
-use crate::models::{SearchResult};
+use crate::models::{ScoreComponents, SearchResult};
 
 /// 数据库搜索管理器
 pub struct DatabaseSearch<'a> {
@@ -17,11 +17,16 @@ impl<'a> DatabaseSearch<'a> {
 
     /// 执行向量相似性搜索
     pub fn vector_search(&self, query_embedding: &[f32], limit: usize) -> Result<Vec<SearchResult>> {
-        if self.db.supports_vector_search() {
-            self.vector_search_with_sqlite_vec(query_embedding, limit)
+        let mut results = if self.db.supports_vector_search() {
+            self.vector_search_with_sqlite_vec(query_embedding, limit)?
         } else {
-            self.vector_search_fallback(query_embedding, limit)
+            self.vector_search_fallback(query_embedding, limit)?
+        };
+        for (i, result) in results.iter_mut().enumerate() {
+            result.components.vector_rank = Some(i + 1);
+            result.components.vector_score = Some(result.similarity_score);
         }
+        Ok(results)
     }
 
     // 移除了未使用的hybrid_search方法，功能已在VectorDatabase中实现
@@ -71,6 +76,8 @@ impl<'a> DatabaseSearch<'a> {
                 total_chunks_in_file: row.get(8)?,
                 global_chunk_index: row.get(9)?,
                 similarity_score: (1.0 - row.get::<_, f64>(11)?) as f32, // 转换距离为相似度
+                components: ScoreComponents::default(),
+                merged_chunk_ids: Vec::new(),
             })
         })?;
 
@@ -119,6 +126,8 @@ impl<'a> DatabaseSearch<'a> {
                 total_chunks_in_file: row.get(8)?,
                 global_chunk_index: row.get(9)?,
                 similarity_score: similarity as f32,
+                components: ScoreComponents::default(),
+                merged_chunk_ids: Vec::new(),
             })
         })?;
 
@@ -170,6 +179,8 @@ impl<'a> DatabaseSearch<'a> {
                 total_chunks_in_file: row.get(8)?,
                 global_chunk_index: row.get(9)?,
                 similarity_score: 1.0, // 文本搜索不计算相似度分数
+                components: ScoreComponents::default(),
+                merged_chunk_ids: Vec::new(),
             })
         })?;
 
@@ -218,11 +229,11 @@ impl<'a> DatabaseSearch<'a> {
 mod tests {
     use super::*;
     use crate::database::{DatabaseConnection, DatabaseOperations};
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
-    fn create_test_db_with_data() -> (DatabaseConnection, i64) {
-        let temp_file = NamedTempFile::new().unwrap();
-        let mut db = DatabaseConnection::new(temp_file.path(), 384).unwrap();
+    fn create_test_db_with_data() -> (TempDir, DatabaseConnection, i64) {
+        let dir = TempDir::new().unwrap();
+        let mut db = DatabaseConnection::new(dir.path().join("vectors.sqlite"), 3).unwrap();
         
         let chunk = crate::models::DocumentChunk {
             id: None,
@@ -234,24 +245,24 @@ mod tests {
             chunk_text: "This is a test chunk about artificial intelligence.".to_string(),
             chunk_order_in_file: 0,
             total_chunks_in_file: 1,
+            embedding: vec![1.0, 0.0, 0.0],
             global_chunk_index: 0,
-            created_at: "2023-01-01 00:00:00".to_string(),
         };
         
         let mut ops = DatabaseOperations::new(&mut db);
         let chunk_id = ops.insert_chunk(&chunk).unwrap();
         
-        (db, chunk_id)
+        (dir, db, chunk_id)
     }
 
     #[test]
     fn test_text_search() {
-        let (db, _) = create_test_db_with_data();
+        let (_dir, db, _) = create_test_db_with_data();
         let search = DatabaseSearch::new(&db);
         
         let results = search.text_search("artificial intelligence", 10).unwrap();
         assert_eq!(results.len(), 1);
-        assert!(results[0].chunk.chunk_text.contains("artificial intelligence"));
+        assert!(results[0].chunk_text.contains("artificial intelligence"));
     }
 
     #[test]
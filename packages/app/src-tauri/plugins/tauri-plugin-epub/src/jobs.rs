@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// 索引任务状态；interrupted 表示上次运行（例如应用退出前）留下了可续传的检查点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexJobStatus {
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+impl IndexJobStatus {
+    fn is_active(self) -> bool {
+        matches!(self, Self::Running | Self::Paused)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobSignal {
+    Run,
+    Pause,
+    Cancel,
+}
+
+/// 索引被取消；流水线以此错误结束，调用方可用 downcast 识别
+#[derive(Debug)]
+pub struct IndexCancelled;
+
+impl std::fmt::Display for IndexCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "indexing cancelled")
+    }
+}
+
+impl std::error::Error for IndexCancelled {}
+
+/// 流水线一侧的任务控制：每个分片前调用 checkpoint
+#[derive(Clone)]
+pub struct JobControl {
+    signal: Arc<watch::Sender<JobSignal>>,
+}
+
+impl std::fmt::Debug for JobControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobControl").field("signal", &*self.signal.borrow()).finish()
+    }
+}
+
+impl JobControl {
+    fn new() -> Self {
+        let (tx, _rx) = watch::channel(JobSignal::Run);
+        Self { signal: Arc::new(tx) }
+    }
+
+    fn send(&self, signal: JobSignal) {
+        self.signal.send_replace(signal);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.signal.borrow() == JobSignal::Pause
+    }
+
+    /// 暂停时等待继续；已取消时返回 IndexCancelled
+    pub async fn checkpoint(&self) -> Result<(), IndexCancelled> {
+        let mut rx = self.signal.subscribe();
+        loop {
+            let signal = *rx.borrow_and_update();
+            match signal {
+                JobSignal::Run => return Ok(()),
+                JobSignal::Cancel => return Err(IndexCancelled),
+                JobSignal::Pause => {}
+            }
+            if rx.changed().await.is_err() {
+                return Err(IndexCancelled);
+            }
+        }
+    }
+}
+
+/// 返回给前端的任务信息
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexJobInfo {
+    pub job_id: String,
+    pub book_id: String,
+    pub status: IndexJobStatus,
+    pub current: usize,
+    pub total: usize,
+    pub percent: f32,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+struct IndexJob {
+    info: IndexJobInfo,
+    control: Option<JobControl>, // 中断的任务没有正在运行的流水线
+}
+
+/// 取消任务后调用方还需要做的事
+pub enum CancelAction {
+    /// 已通知正在运行的流水线，由它清理临时库
+    Signalled,
+    /// 没有运行中的流水线，需要删除该书的检查点（临时向量库）
+    DiscardCheckpoint,
+}
+
+/// 插件内的索引任务表，每本书同时最多一个运行中的任务
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, IndexJob>>,
+}
+
+impl JobManager {
+    /// 为一次索引创建任务；同一本书已有运行中或暂停的任务时报错
+    pub fn start(&self, book_id: &str) -> Result<(IndexJobInfo, JobControl), String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs
+            .values()
+            .find(|job| job.info.book_id == book_id && job.info.status.is_active())
+        {
+            return Err(format!("book is already being indexed (job {})", job.info.job_id));
+        }
+        // 同一本书只保留最近一次任务
+        jobs.retain(|_, job| job.info.book_id != book_id);
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let control = JobControl::new();
+        let info = IndexJobInfo {
+            job_id: uuid::Uuid::new_v4().to_string(),
+            book_id: book_id.to_string(),
+            status: IndexJobStatus::Running,
+            current: 0,
+            total: 0,
+            percent: 0.0,
+            error: None,
+            started_at: now,
+            updated_at: now,
+        };
+        jobs.insert(
+            info.job_id.clone(),
+            IndexJob {
+                info: info.clone(),
+                control: Some(control.clone()),
+            },
+        );
+        Ok((info, control))
+    }
+
+    /// 记录磁盘上发现的中断索引；该书已有任务记录时不重复添加
+    pub fn register_interrupted(&self, book_id: &str, processed: usize, percent: f32, updated_at: i64) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.values().any(|job| job.info.book_id == book_id) {
+            return;
+        }
+        let info = IndexJobInfo {
+            job_id: uuid::Uuid::new_v4().to_string(),
+            book_id: book_id.to_string(),
+            status: IndexJobStatus::Interrupted,
+            current: processed,
+            total: 0,
+            percent,
+            error: None,
+            started_at: updated_at,
+            updated_at,
+        };
+        jobs.insert(info.job_id.clone(), IndexJob { info, control: None });
+    }
+
+    pub fn update_progress(&self, job_id: &str, current: usize, total: usize, percent: f32) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            job.info.current = current;
+            job.info.total = total;
+            job.info.percent = percent;
+            job.info.updated_at = chrono::Utc::now().timestamp_millis();
+        }
+    }
+
+    /// 流水线结束后记录最终状态
+    pub fn finish(&self, job_id: &str, status: IndexJobStatus, error: Option<String>) -> Option<IndexJobInfo> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(job_id)?;
+        job.info.status = status;
+        job.info.error = error;
+        job.info.updated_at = chrono::Utc::now().timestamp_millis();
+        if status == IndexJobStatus::Completed {
+            job.info.percent = 100.0;
+        }
+        job.control = None;
+        Some(job.info.clone())
+    }
+
+    pub fn pause(&self, job_id: &str) -> Result<IndexJobInfo, String> {
+        self.signal(job_id, |status| match status {
+            IndexJobStatus::Running | IndexJobStatus::Paused => Ok((JobSignal::Pause, IndexJobStatus::Paused)),
+            _ => Err(format!("job is not running ({:?})", status)),
+        })
+    }
+
+    pub fn resume(&self, job_id: &str) -> Result<IndexJobInfo, String> {
+        self.signal(job_id, |status| match status {
+            IndexJobStatus::Running | IndexJobStatus::Paused => Ok((JobSignal::Run, IndexJobStatus::Running)),
+            IndexJobStatus::Interrupted | IndexJobStatus::Failed => {
+                Err("job is not running; call index_epub again to continue from its checkpoint".to_string())
+            }
+            _ => Err(format!("job is not paused ({:?})", status)),
+        })
+    }
+
+    pub fn cancel(&self, job_id: &str) -> Result<(IndexJobInfo, CancelAction), String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(job_id).ok_or_else(|| format!("job not found: {}", job_id))?;
+        let action = match (job.info.status, &job.control) {
+            (status, Some(control)) if status.is_active() => {
+                control.send(JobSignal::Cancel);
+                CancelAction::Signalled
+            }
+            (IndexJobStatus::Interrupted | IndexJobStatus::Failed, _) => CancelAction::DiscardCheckpoint,
+            (status, _) => return Err(format!("job already finished ({:?})", status)),
+        };
+        job.info.status = IndexJobStatus::Cancelled;
+        job.info.updated_at = chrono::Utc::now().timestamp_millis();
+        Ok((job.info.clone(), action))
+    }
+
+    /// 所有任务，最近开始的在前
+    pub fn list(&self) -> Vec<IndexJobInfo> {
+        let mut jobs: Vec<IndexJobInfo> = self.jobs.lock().unwrap().values().map(|job| job.info.clone()).collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.started_at));
+        jobs
+    }
+
+    fn signal<F>(&self, job_id: &str, transition: F) -> Result<IndexJobInfo, String>
+    where
+        F: FnOnce(IndexJobStatus) -> Result<(JobSignal, IndexJobStatus), String>,
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(job_id).ok_or_else(|| format!("job not found: {}", job_id))?;
+        let (signal, status) = transition(job.info.status)?;
+        if let Some(control) = &job.control {
+            control.send(signal);
+        }
+        job.info.status = status;
+        job.info.updated_at = chrono::Utc::now().timestamp_millis();
+        Ok(job.info.clone())
+    }
+}
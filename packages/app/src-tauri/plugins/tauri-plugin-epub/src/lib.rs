@@ -5,6 +5,9 @@ use tauri::{
 
 mod commands;
 mod state;
+mod jobs;
+mod queue;
+mod library_search;
 
 // Data models
 mod models;
@@ -20,6 +23,12 @@ mod database;
 // Core modules
 mod pipeline;
 
+// Location helpers shared with the host app
+pub mod cfi;
+
+// Token counting shared with the host app (o200k_base)
+pub use text::tokenizer::TextTokenizer;
+
 pub use state::EpubState;
 
 /// Initializes the EPUB plugin.
@@ -28,7 +37,19 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
         .invoke_handler(tauri::generate_handler![
             commands::parse_epub,
             commands::index_epub,
+            commands::pause_index,
+            commands::resume_index,
+            commands::cancel_index,
+            commands::list_index_jobs,
+            commands::enqueue_index,
+            commands::remove_from_index_queue,
+            commands::set_index_priority,
+            commands::clear_index_queue,
+            commands::start_index_queue,
+            commands::stop_index_queue,
+            commands::get_index_queue,
             commands::search_db,
+            commands::search_library,
             commands::convert_to_mdbook,
             commands::parse_toc,
             commands::get_chunk_with_context,
@@ -37,7 +58,12 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
         ])
         .setup(|app, _api| {
             // Initialize and manage plugin state
-            app.manage(EpubState::default());
+            let state = EpubState::default();
+            match app.path().app_data_dir() {
+                Ok(dir) => state.queue.load(dir.join("index-queue.json")),
+                Err(e) => log::warn!("index queue will not be persisted: {}", e),
+            }
+            app.manage(state);
             Ok(())
         })
         .build()
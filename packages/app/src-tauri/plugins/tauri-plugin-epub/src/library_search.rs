@@ -0,0 +1,542 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+use crate::database::{
+    fts_index_ready, BM25Search, DatabaseConnection, DatabaseSearch, META_EMBEDDING_DIMENSION, META_EMBEDDING_MODEL,
+};
+use crate::models::{HybridSearchConfig, ScoreComponents, SearchMode, SearchResult, VectorizerConfig};
+use crate::text::TextVectorizer;
+
+/// 同时打开搜索的书籍数
+const LIBRARY_SEARCH_CONCURRENCY: usize = 4;
+
+/// 已完成索引的书
+#[derive(Debug, Clone)]
+pub struct IndexedBook {
+    pub book_id: String,
+    pub db_path: PathBuf,
+}
+
+/// 某本书没有完整参与搜索的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSearchStatus {
+    KeywordOnly, // 向量模型或维度与查询不同，只做了关键词搜索
+    VectorOnly,  // 关键词索引缺失或为旧版本（需重新索引），只做了向量搜索
+    Skipped,     // 未参与搜索（纯向量模式下模型不匹配，或打开失败）
+    NotIndexed,  // 指定的书还没有索引
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BookSearchNotice {
+    pub book_id: String,
+    pub status: BookSearchStatus,
+    pub reason: String,
+}
+
+/// 全库搜索命中；result.similarity_score 是跨书校准后的分数。
+/// result.components 中是原始分数与全库名次
+#[derive(Debug, Clone)]
+pub struct LibraryHit {
+    pub book_id: String,
+    pub result: SearchResult,
+}
+
+#[derive(Debug, Clone)]
+pub struct LibrarySearchOutcome {
+    pub hits: Vec<LibraryHit>,
+    pub searched_books: usize,
+    pub notices: Vec<BookSearchNotice>,
+}
+
+/// 单本书的原始结果，分数尚未校准
+#[derive(Debug, Default)]
+struct BookHits {
+    vector: Vec<SearchResult>,
+    bm25: Vec<SearchResult>,
+    notice: Option<BookSearchNotice>,
+}
+
+/// 列出参与搜索的书；book_ids 为 None 时是 books 目录下所有已索引的书。
+/// 按标签（书架）筛选时先用 books_with_tag 解析成 book_ids
+pub fn resolve_books(books_dir: &Path, book_ids: Option<&[String]>) -> Result<(Vec<IndexedBook>, Vec<BookSearchNotice>)> {
+    let mut books = Vec::new();
+    let mut notices = Vec::new();
+
+    match book_ids {
+        Some(ids) => {
+            for book_id in ids {
+                let valid = !book_id.is_empty() && !book_id.contains(['/', '\\']) && book_id != "..";
+                let db_path = books_dir.join(book_id).join("vectors.sqlite");
+                if valid && db_path.exists() {
+                    books.push(IndexedBook { book_id: book_id.clone(), db_path });
+                } else {
+                    notices.push(BookSearchNotice {
+                        book_id: book_id.clone(),
+                        status: BookSearchStatus::NotIndexed,
+                        reason: "book has no vector index".to_string(),
+                    });
+                }
+            }
+        }
+        None => {
+            if !books_dir.exists() {
+                return Ok((books, notices));
+            }
+            for entry in std::fs::read_dir(books_dir).context("Failed to read books directory")? {
+                let entry = entry?;
+                let db_path = entry.path().join("vectors.sqlite");
+                if db_path.exists() {
+                    books.push(IndexedBook {
+                        book_id: entry.file_name().to_string_lossy().into_owned(),
+                        db_path,
+                    });
+                }
+            }
+        }
+    }
+
+    books.sort_by(|a, b| a.book_id.cmp(&b.book_id));
+    books.dedup_by(|a, b| a.book_id == b.book_id);
+    Ok((books, notices))
+}
+
+/// 带有某个标签的书。标签可以是名称或 id；books.tags 中既可能存标签 id，也可能存名称，两者都匹配
+pub fn books_with_tag(app_db_path: &Path, tag: &str) -> Result<Vec<String>> {
+    let conn = Connection::open_with_flags(app_db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context("Failed to open app database")?;
+    let known: Option<(String, String)> = conn
+        .query_row("SELECT id, name FROM tags WHERE name = ?1 OR id = ?1", [tag], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+
+    let mut patterns = vec![format!("%\"{}\"%", tag)];
+    if let Some((id, name)) = known {
+        patterns.extend([id, name].into_iter().filter(|v| v != tag).map(|v| format!("%\"{}\"%", v)));
+    }
+    let conditions = vec!["tags LIKE ?"; patterns.len()].join(" OR ");
+    let mut stmt = conn.prepare(&format!("SELECT id FROM books WHERE {} ORDER BY id", conditions))?;
+    let book_ids = stmt
+        .query_map(rusqlite::params_from_iter(&patterns), |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(book_ids)
+}
+
+/// 在多本书中搜索：查询只向量化一次，各书分别检索后统一校准分数、取全局前 limit 个
+pub async fn search_library(
+    books: Vec<IndexedBook>,
+    query: &str,
+    limit: usize,
+    vectorizer: VectorizerConfig,
+    config: HybridSearchConfig,
+) -> Result<LibrarySearchOutcome> {
+    let model_name = vectorizer.model_name.clone();
+    let embedding = if config.mode == SearchMode::BM25Only || books.is_empty() {
+        None
+    } else {
+        let mut v = TextVectorizer::new(vectorizer).await?;
+        Some(v.vectorize_text(query).await.context("Failed to vectorize query")?)
+    };
+
+    search_books(books, query, embedding, &model_name, limit, config).await
+}
+
+/// 用已经算好的查询向量在多本书中搜索
+pub async fn search_books(
+    books: Vec<IndexedBook>,
+    query: &str,
+    embedding: Option<Vec<f32>>,
+    model_name: &str,
+    limit: usize,
+    config: HybridSearchConfig,
+) -> Result<LibrarySearchOutcome> {
+    let searched_books = books.len();
+    let query: Arc<str> = Arc::from(query);
+    let model_name: Arc<str> = Arc::from(model_name);
+    let embedding = embedding.map(Arc::new);
+    let config = Arc::new(config);
+    // 与单本书的混合搜索一致，每一路各取 limit * 2 个候选
+    let per_book_limit = limit.max(1) * 2;
+
+    let mut book_hits: Vec<(String, BookHits)> = Vec::with_capacity(books.len());
+    let mut pending = books.into_iter();
+    let mut join_set = JoinSet::new();
+    loop {
+        while join_set.len() < LIBRARY_SEARCH_CONCURRENCY {
+            let Some(book) = pending.next() else { break };
+            let query = query.clone();
+            let model_name = model_name.clone();
+            let embedding = embedding.clone();
+            let config = config.clone();
+            join_set.spawn_blocking(move || {
+                let embedding = embedding.as_deref().map(Vec::as_slice);
+                let hits = search_book(&book, &query, embedding, &model_name, per_book_limit, &config)
+                    .unwrap_or_else(|e| {
+                        log::warn!("全库搜索跳过书籍 {}: {:#}", book.book_id, e);
+                        BookHits {
+                            notice: Some(BookSearchNotice {
+                                book_id: book.book_id.clone(),
+                                status: BookSearchStatus::Skipped,
+                                reason: format!("{:#}", e),
+                            }),
+                            ..Default::default()
+                        }
+                    });
+                (book.book_id, hits)
+            });
+        }
+        match join_set.join_next().await {
+            Some(joined) => book_hits.push(joined.context("Library search task panicked")?),
+            None => break,
+        }
+    }
+
+    let mut notices: Vec<BookSearchNotice> = book_hits.iter_mut().filter_map(|(_, hits)| hits.notice.take()).collect();
+    notices.sort_by(|a, b| a.book_id.cmp(&b.book_id));
+
+    Ok(LibrarySearchOutcome {
+        hits: merge_hits(book_hits, limit, &config),
+        searched_books,
+        notices,
+    })
+}
+
+/// 检查书的向量库是否与查询向量来自同一模型和维度；未记录元信息的旧库视为兼容，由检索本身报错
+fn vector_mismatch(db: &DatabaseConnection, model_name: &str, dimension: usize) -> Option<String> {
+    if let Some(indexed_model) = db.get_meta(META_EMBEDDING_MODEL) {
+        if indexed_model != model_name {
+            return Some(format!("indexed with embedding model {}, query uses {}", indexed_model, model_name));
+        }
+    }
+    if let Some(indexed_dimension) = db.get_meta(META_EMBEDDING_DIMENSION).and_then(|d| d.parse::<usize>().ok()) {
+        if indexed_dimension != dimension {
+            return Some(format!("indexed with dimension {}, query has {}", indexed_dimension, dimension));
+        }
+    }
+    None
+}
+
+fn search_book(
+    book: &IndexedBook,
+    query: &str,
+    embedding: Option<&[f32]>,
+    model_name: &str,
+    limit: usize,
+    config: &HybridSearchConfig,
+) -> Result<BookHits> {
+    let dimension = embedding.map(|e| e.len()).unwrap_or(1024);
+    let db = DatabaseConnection::open_existing(&book.db_path, dimension)?;
+    let mut hits = BookHits::default();
+
+    let embedding = embedding.filter(|_| config.mode != SearchMode::BM25Only);
+    if let Some(embedding) = embedding {
+        let vector = match vector_mismatch(&db, model_name, dimension) {
+            Some(reason) => Err(reason),
+            None => DatabaseSearch::new(&db).vector_search(embedding, limit).map_err(|e| format!("{:#}", e)),
+        };
+        match vector {
+            Ok(results) => hits.vector = results,
+            Err(reason) => {
+                let status = if config.mode == SearchMode::Hybrid {
+                    BookSearchStatus::KeywordOnly
+                } else {
+                    BookSearchStatus::Skipped
+                };
+                log::info!("书籍 {} 的向量检索不可用（{}），{:?}", book.book_id, reason, status);
+                hits.notice = Some(BookSearchNotice {
+                    book_id: book.book_id.clone(),
+                    status,
+                    reason,
+                });
+                if status == BookSearchStatus::Skipped {
+                    return Ok(hits);
+                }
+            }
+        }
+    }
+
+    if config.mode != SearchMode::VectorOnly {
+        if !fts_index_ready(db.connection())? {
+            let vector_available = config.mode == SearchMode::Hybrid && hits.notice.is_none();
+            let status = if vector_available {
+                BookSearchStatus::VectorOnly
+            } else {
+                BookSearchStatus::Skipped
+            };
+            log::info!("书籍 {} 的关键词索引缺失或为旧版本，{:?}", book.book_id, status);
+            hits.notice = Some(BookSearchNotice {
+                book_id: book.book_id.clone(),
+                status,
+                reason: "keyword index is missing or outdated, re-index the book".to_string(),
+            });
+            return Ok(hits);
+        }
+        let bm25 = BM25Search::new(&db, config.k1, config.b);
+        hits.bm25 = bm25
+            .search(query, limit)?
+            .into_iter()
+            .map(|r| {
+                let mut result = r.search_result;
+                result.similarity_score = r.score;
+                result
+            })
+            .collect();
+    }
+
+    Ok(hits)
+}
+
+/// 一组结果的 min-max 区间；所有分数相同时返回 None
+fn score_range<'a>(scores: impl Iterator<Item = &'a SearchResult>) -> Option<(f32, f32)> {
+    let (min, max) = scores.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), r| {
+        (min.min(r.similarity_score), max.max(r.similarity_score))
+    });
+    (max > min).then_some((min, max))
+}
+
+fn normalize(score: f32, range: Option<(f32, f32)>) -> f32 {
+    match range {
+        Some((min, max)) => (score - min) / (max - min),
+        None => 1.0,
+    }
+}
+
+/// BM25 饱和常数：原始分数 s 换算为 s / (s + c)，等于 c 时为 0.5
+const BM25_SATURATION: f32 = 5.0;
+
+/// 把 BM25 原始分数换算到 [0, 1)。不用书内 min-max，否则每本书的第一名都是 1.0，
+/// 只有一条弱命中的书也会排在最前
+fn calibrate_bm25(score: f32) -> f32 {
+    let score = score.max(0.0);
+    score / (score + BM25_SATURATION)
+}
+
+/// 把各书某一路的结果按分数排成全库名次（从 1 开始），值为 (名次, 原始分数)
+fn global_ranking(
+    book_hits: &[(String, BookHits)],
+    channel: fn(&BookHits) -> &[SearchResult],
+) -> HashMap<(String, i64), (usize, f32)> {
+    let mut all: Vec<(&str, &SearchResult)> = Vec::new();
+    for (book_id, hits) in book_hits {
+        all.extend(channel(hits).iter().map(|r| (book_id.as_str(), r)));
+    }
+    all.sort_by(|(a_book, a), (b_book, b)| {
+        b.similarity_score
+            .total_cmp(&a.similarity_score)
+            .then_with(|| a_book.cmp(b_book))
+            .then_with(|| a.global_chunk_index.cmp(&b.global_chunk_index))
+    });
+    all.into_iter()
+        .enumerate()
+        .map(|(i, (book_id, r))| ((book_id.to_string(), r.chunk_id), (i + 1, r.similarity_score)))
+        .collect()
+}
+
+/// 合并各书结果。向量分数由同一模型下的向量距离换算而来，可以跨书比较，使用全库 min-max；
+/// BM25 分数依赖各书自己的词频统计，只经固定的饱和变换压到 [0, 1)，弱命中在任何书里都是低分。
+/// 两路名次都是按分数排出的全库名次
+fn merge_hits(book_hits: Vec<(String, BookHits)>, limit: usize, config: &HybridSearchConfig) -> Vec<LibraryHit> {
+    let vector_range = score_range(book_hits.iter().flat_map(|(_, h)| h.vector.iter()));
+    let vector_ranks = global_ranking(&book_hits, |h| &h.vector);
+    let bm25_ranks = global_ranking(&book_hits, |h| &h.bm25);
+
+    let mut merged: HashMap<(String, i64), LibraryHit> = HashMap::new();
+    for (book_id, hits) in book_hits {
+        for result in hits.vector.into_iter().chain(hits.bm25) {
+            merged
+                .entry((book_id.clone(), result.chunk_id))
+                .or_insert_with(|| LibraryHit {
+                    book_id: book_id.clone(),
+                    result,
+                });
+        }
+    }
+
+    let mut hits: Vec<LibraryHit> = merged
+        .into_iter()
+        .map(|(key, mut hit)| {
+            let vector = vector_ranks.get(&key).copied();
+            let bm25 = bm25_ranks.get(&key).copied();
+            hit.result.components = ScoreComponents {
+                vector_rank: vector.map(|(rank, _)| rank),
+                vector_score: vector.map(|(_, score)| score),
+                bm25_rank: bm25.map(|(rank, _)| rank),
+                bm25_score: bm25.map(|(_, score)| score),
+                rerank_score: None,
+            };
+            let vector_norm = vector.map(|(rank, score)| (rank, normalize(score, vector_range)));
+            let bm25_norm = bm25.map(|(rank, score)| (rank, calibrate_bm25(score)));
+            hit.result.similarity_score = match config.mode {
+                SearchMode::VectorOnly => vector.map_or(0.0, |(_, score)| score),
+                SearchMode::BM25Only => bm25_norm.map_or(0.0, |(_, score)| score),
+                SearchMode::Hybrid => config.fuse(vector_norm, bm25_norm),
+            };
+            hit
+        })
+        .collect();
+
+    hits.sort_by(|a, b| {
+        b.result
+            .similarity_score
+            .total_cmp(&a.result.similarity_score)
+            .then_with(|| a.book_id.cmp(&b.book_id))
+            .then_with(|| a.result.global_chunk_index.cmp(&b.result.global_chunk_index))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn result(chunk_id: i64, score: f32) -> SearchResult {
+        SearchResult {
+            chunk_id,
+            book_title: String::new(),
+            book_author: String::new(),
+            md_file_path: String::new(),
+            file_order_in_book: 0,
+            related_chapter_titles: String::new(),
+            chunk_text: String::new(),
+            chunk_order_in_file: 0,
+            total_chunks_in_file: 1,
+            global_chunk_index: chunk_id as usize,
+            similarity_score: score,
+            components: ScoreComponents::default(),
+            merged_chunk_ids: Vec::new(),
+        }
+    }
+
+    fn book(book_id: &str, vector: &[(i64, f32)], bm25: &[(i64, f32)]) -> (String, BookHits) {
+        let hits = BookHits {
+            vector: vector.iter().map(|&(id, score)| result(id, score)).collect(),
+            bm25: bm25.iter().map(|&(id, score)| result(id, score)).collect(),
+            notice: None,
+        };
+        (book_id.to_string(), hits)
+    }
+
+    fn order(hits: &[LibraryHit]) -> Vec<(&str, i64)> {
+        hits.iter().map(|h| (h.book_id.as_str(), h.result.chunk_id)).collect()
+    }
+
+    #[test]
+    fn test_merge_bm25_weak_hit_not_promoted() {
+        let config = HybridSearchConfig { mode: SearchMode::BM25Only, ..Default::default() };
+        // b 只有一条弱命中，不应因书内归一化排到 a 的强命中前面
+        let books = vec![book("a", &[], &[(1, 12.0), (2, 10.0)]), book("b", &[], &[(7, 1.0)])];
+        let hits = merge_hits(books, 10, &config);
+
+        assert_eq!(order(&hits), vec![("a", 1), ("a", 2), ("b", 7)]);
+        assert!((hits[2].result.similarity_score - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(hits[2].result.components.bm25_rank, Some(3));
+        assert_eq!(hits[2].result.components.bm25_score, Some(1.0));
+    }
+
+    #[test]
+    fn test_merge_hybrid_combines_channels() {
+        let config = HybridSearchConfig::default();
+        let books = vec![
+            book("a", &[(1, 0.9)], &[(1, 8.0)]),
+            book("b", &[(2, 0.5)], &[(3, 0.5)]),
+            book("c", &[(4, 0.7)], &[]),
+        ];
+        let hits = merge_hits(books, 3, &config);
+
+        assert_eq!(order(&hits), vec![("a", 1), ("c", 4), ("b", 3)]);
+        let top = &hits[0].result.components;
+        assert_eq!((top.vector_rank, top.bm25_rank), (Some(1), Some(1)));
+        // 向量全库 min-max 后为 1.0，BM25 为 8 / 13
+        let expected = config.vector_weight + config.bm25_weight * 8.0 / 13.0;
+        assert!((hits[0].result.similarity_score - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_merge_limit_and_vector_only() {
+        let config = HybridSearchConfig { mode: SearchMode::VectorOnly, ..Default::default() };
+        let books = vec![book("a", &[(1, 0.4), (2, 0.8)], &[]), book("b", &[(1, 0.6)], &[])];
+        let hits = merge_hits(books, 2, &config);
+
+        // 纯向量模式使用原始分数；不同书中相同的分片 id 是不同的结果
+        assert_eq!(order(&hits), vec![("a", 2), ("b", 1)]);
+        assert_eq!(hits[1].result.similarity_score, 0.6);
+        assert_eq!(hits[1].result.components.vector_rank, Some(2));
+    }
+
+    #[test]
+    fn test_resolve_books() {
+        let dir = TempDir::new().unwrap();
+        for book_id in ["b", "a"] {
+            std::fs::create_dir(dir.path().join(book_id)).unwrap();
+            std::fs::write(dir.path().join(book_id).join("vectors.sqlite"), b"").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("c")).unwrap();
+
+        let (books, notices) = resolve_books(dir.path(), None).unwrap();
+        let ids: Vec<&str> = books.iter().map(|b| b.book_id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert!(notices.is_empty());
+
+        let requested: Vec<String> = ["b", "c", "../a", "..", "b"].iter().map(|s| s.to_string()).collect();
+        let (books, notices) = resolve_books(dir.path(), Some(&requested)).unwrap();
+        let ids: Vec<&str> = books.iter().map(|b| b.book_id.as_str()).collect();
+        assert_eq!(ids, vec!["b"]);
+        let skipped: Vec<&str> = notices.iter().map(|n| n.book_id.as_str()).collect();
+        assert_eq!(skipped, vec!["c", "../a", ".."]);
+        assert!(notices.iter().all(|n| n.status == BookSearchStatus::NotIndexed));
+
+        let (books, notices) = resolve_books(&dir.path().join("missing"), None).unwrap();
+        assert!(books.is_empty() && notices.is_empty());
+    }
+
+    #[test]
+    fn test_books_with_tag() {
+        let dir = TempDir::new().unwrap();
+        let app_db = dir.path().join("app.db");
+        let conn = Connection::open(&app_db).unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE books (id TEXT PRIMARY KEY, tags TEXT);
+            INSERT INTO tags VALUES ('t1', 'history'), ('t2', 'fiction');
+            INSERT INTO books VALUES
+                ('by-id', '["t1"]'),
+                ('by-name', '["history","t2"]'),
+                ('other', '["t2"]'),
+                ('prefix', '["history-of-art"]'),
+                ('untagged', NULL);
+            "#,
+        )
+        .unwrap();
+        drop(conn);
+
+        assert_eq!(books_with_tag(&app_db, "history").unwrap(), vec!["by-id", "by-name"]);
+        assert_eq!(books_with_tag(&app_db, "t1").unwrap(), vec!["by-id", "by-name"]);
+        assert_eq!(books_with_tag(&app_db, "fiction").unwrap(), vec!["by-name", "other"]);
+        // 未登记的标签名只按名称匹配
+        assert_eq!(books_with_tag(&app_db, "history-of-art").unwrap(), vec!["prefix"]);
+        assert!(books_with_tag(&app_db, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_vector_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let db = DatabaseConnection::new(dir.path().join("vectors.sqlite"), 3).unwrap();
+        // 未记录元信息的旧库视为兼容
+        assert_eq!(vector_mismatch(&db, "model-a", 3), None);
+
+        db.set_meta(META_EMBEDDING_MODEL, "model-a").unwrap();
+        db.set_meta(META_EMBEDDING_DIMENSION, "3").unwrap();
+        assert_eq!(vector_mismatch(&db, "model-a", 3), None);
+        assert!(vector_mismatch(&db, "model-b", 3).unwrap().contains("model-b"));
+        assert!(vector_mismatch(&db, "model-a", 4).unwrap().contains("dimension 3"));
+    }
+}
@@ -1,8 +1,17 @@
+use crate::jobs::JobControl;
+use crate::text::{
+    SegmenterConfig, DEFAULT_EMBEDDING_BATCH_SIZE, DEFAULT_EMBEDDING_BATCH_TOKENS, DEFAULT_EMBEDDING_CONCURRENCY,
+    DEFAULT_RERANK_TIMEOUT_MS, DEFAULT_RERANK_TOP_N,
+};
+
 /// 处理选项配置
 #[derive(Debug, Clone)]
 pub struct ProcessOptions {
     pub batch_size: Option<usize>,
     pub vectorizer: VectorizerConfig,
+    pub embedding: EmbeddingBatchConfig,
+    pub keyword: SegmenterConfig, // 关键词检索的分词配置
+    pub control: Option<JobControl>, // 暂停、取消索引任务
 }
 
 /// 向量化器配置
@@ -13,6 +22,46 @@ pub struct VectorizerConfig {
     pub api_key: Option<String>,
 }
 
+/// 重排（cross-encoder）配置，接口兼容 llama-server 的 /rerank 与 Jina、Cohere 风格的 /v1/rerank
+#[derive(Debug, Clone)]
+pub struct RerankerConfig {
+    pub rerank_url: String,
+    pub model_name: String, // llama-server 可留空
+    pub api_key: Option<String>,
+    pub top_n: usize,       // 参与重排的候选数
+    pub timeout_ms: u64,    // 超时后保持原有顺序
+}
+
+impl RerankerConfig {
+    pub fn new(rerank_url: String, model_name: String, api_key: Option<String>) -> Self {
+        Self {
+            rerank_url,
+            model_name,
+            api_key,
+            top_n: DEFAULT_RERANK_TOP_N,
+            timeout_ms: DEFAULT_RERANK_TIMEOUT_MS,
+        }
+    }
+}
+
+/// 批量向量化配置
+#[derive(Debug, Clone)]
+pub struct EmbeddingBatchConfig {
+    pub batch_size: usize,       // 每个请求最多的分片数
+    pub max_batch_tokens: usize, // 每个请求的 token 上限
+    pub concurrency: usize,      // 同时进行的请求数
+}
+
+impl Default for EmbeddingBatchConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_EMBEDDING_BATCH_SIZE,
+            max_batch_tokens: DEFAULT_EMBEDDING_BATCH_TOKENS,
+            concurrency: DEFAULT_EMBEDDING_CONCURRENCY,
+        }
+    }
+}
+
 /// API相关的DTO结构
 use serde::Serialize;
 use super::ProcessReport;
@@ -28,6 +77,7 @@ pub struct ParsedBook {
 pub struct IndexResult {
     pub success: bool,
     pub message: String,
+    pub job_id: Option<String>,
     pub report: Option<ProcessReportDto>,
 }
 
@@ -45,7 +95,9 @@ pub struct ProcessReportDto {
     pub book_title: String,
     pub book_author: String,
     pub total_chunks: usize,
+    pub reused_chunks: usize,
     pub vector_dimension: usize,
+    pub mdbook_converted: bool,
 }
 
 impl From<ProcessReport> for ProcessReportDto {
@@ -55,7 +107,9 @@ impl From<ProcessReport> for ProcessReportDto {
             book_title: r.book_title,
             book_author: r.book_author,
             total_chunks: r.total_chunks,
+            reused_chunks: r.reused_chunks,
             vector_dimension: r.vector_dimension,
+            mdbook_converted: r.mdbook_converted,
         }
     }
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::ScoreComponents;
+
 /// 文档分片数据结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentChunk {
@@ -40,4 +42,8 @@ pub struct SearchResult {
     pub total_chunks_in_file: usize,
     pub global_chunk_index: usize,
     pub similarity_score: f32,
+    #[serde(default)]
+    pub components: ScoreComponents, // 各路检索的名次与分数
+    #[serde(default)]
+    pub merged_chunk_ids: Vec<i64>, // 相邻分片合并成段落时，段落内按阅读顺序的全部分片 id；未合并时为空
 }
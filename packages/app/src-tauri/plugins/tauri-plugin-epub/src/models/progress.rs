@@ -19,7 +19,9 @@ pub struct ProcessReport {
     pub book_title: String,
     pub book_author: String,
     pub total_chunks: usize,
+    pub reused_chunks: usize, // 内容未变、沿用已有向量的分片数
     pub vector_dimension: usize,
+    pub mdbook_converted: bool, // EPUB 未变化时跳过 mdbook 转换
 }
 
 /// 错误统计数据结构
@@ -31,6 +31,31 @@ impl std::str::FromStr for SearchMode {
     }
 }
 
+/// 混合搜索中两路结果的融合方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum FusionStrategy {
+    #[serde(rename = "weighted")]
+    #[default]
+    WeightedSum,   // 两路分数各自 min-max 归一化后加权求和
+    #[serde(rename = "rrf")]
+    Rrf,           // 倒数排名融合：Σ 1/(k + 名次)，只看名次，不受分数尺度影响
+}
+
+impl std::str::FromStr for FusionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "weighted" => Ok(FusionStrategy::WeightedSum),
+            "rrf" => Ok(FusionStrategy::Rrf),
+            _ => Err(format!("Invalid fusion strategy: {}", s)),
+        }
+    }
+}
+
+/// RRF 常数 k 的默认值
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
 /// 混合搜索配置
 #[derive(Debug, Clone)]
 pub struct HybridSearchConfig {
@@ -39,6 +64,8 @@ pub struct HybridSearchConfig {
     pub bm25_weight: f32,      // BM25搜索权重 (0.0-1.0)
     pub k1: f32,               // BM25参数k1 (默认1.2)
     pub b: f32,                // BM25参数b (默认0.75)
+    pub fusion: FusionStrategy,
+    pub rrf_k: f32,            // RRF 常数 k (默认60)
 }
 
 impl Default for HybridSearchConfig {
@@ -49,10 +76,57 @@ impl Default for HybridSearchConfig {
             bm25_weight: 0.3,      // BM25搜索权重30%
             k1: 1.2,               // BM25标准参数
             b: 0.75,               // BM25标准参数
+            fusion: FusionStrategy::WeightedSum,
+            rrf_k: DEFAULT_RRF_K,
         }
     }
 }
 
+impl HybridSearchConfig {
+    /// 融合一条结果在两路检索中的（名次, 归一化分数）；名次从 1 开始，未命中的一路为 None
+    pub fn fuse(&self, vector: Option<(usize, f32)>, bm25: Option<(usize, f32)>) -> f32 {
+        match self.fusion {
+            FusionStrategy::WeightedSum => {
+                self.vector_weight * vector.map_or(0.0, |(_, score)| score)
+                    + self.bm25_weight * bm25.map_or(0.0, |(_, score)| score)
+            }
+            FusionStrategy::Rrf => [vector, bm25]
+                .into_iter()
+                .flatten()
+                .map(|(rank, _)| 1.0 / (self.rrf_k + rank as f32))
+                .sum(),
+        }
+    }
+}
+
+/// MMR 中相关度的默认权重，其余为冗余惩罚
+pub const DEFAULT_MMR_LAMBDA: f32 = 0.7;
+
+/// 去冗余时取 limit 的多少倍作为候选
+pub const MMR_CANDIDATE_FACTOR: usize = 3;
+
+/// 搜索结果去冗余配置：相邻分片合并为段落，再用最大边际相关（MMR）挑选结果
+#[derive(Debug, Clone)]
+pub struct DiversityConfig {
+    pub lambda: f32, // 1.0 只看相关度，0.0 只看差异度
+}
+
+impl Default for DiversityConfig {
+    fn default() -> Self {
+        Self { lambda: DEFAULT_MMR_LAMBDA }
+    }
+}
+
+/// 一条结果在各路检索中的名次（从 1 开始）与原始分数，用于调试排序
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScoreComponents {
+    pub vector_rank: Option<usize>,
+    pub vector_score: Option<f32>,
+    pub bm25_rank: Option<usize>,
+    pub bm25_score: Option<f32>,
+    pub rerank_score: Option<f32>, // 重排模型给出的相关度，未重排时为 None
+}
+
 // 移除了未使用的impl块
 
 /// 混合搜索结果
@@ -62,13 +136,6 @@ pub struct HybridSearchResult {
     pub search_result: SearchResult,  // 原始搜索结果
 }
 
-/// BM25统计信息
-#[derive(Debug, Clone)]
-pub struct BM25Stats {
-    pub total_docs: usize,
-    pub avg_doc_length: f32,
-}
-
 // 移除了未使用的DocumentTermFreq结构体
 
 /// BM25搜索结果
@@ -77,3 +144,88 @@ pub struct BM25SearchResult {
     pub score: f32,
     pub search_result: SearchResult,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(fusion: FusionStrategy) -> HybridSearchConfig {
+        HybridSearchConfig { fusion, ..Default::default() }
+    }
+
+    /// 名称、向量一路与 BM25 一路的（名次, 归一化分数）
+    type Hit = (&'static str, Option<(usize, f32)>, Option<(usize, f32)>);
+
+    /// 按融合分数从高到低排列的名称
+    fn order(config: &HybridSearchConfig, hits: &[Hit]) -> Vec<&'static str> {
+        let mut scored: Vec<(&str, f32)> = hits
+            .iter()
+            .map(|(name, vector, bm25)| (*name, config.fuse(*vector, *bm25)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_rrf_rank_order() {
+        let rrf = config(FusionStrategy::Rrf);
+        // 两路都排第三，胜过只在一路排第一
+        let hits = [
+            ("vector_top", Some((1, 1.0)), None),
+            ("both", Some((3, 0.2)), Some((3, 0.1))),
+            ("bm25_top", None, Some((1, 1.0))),
+            ("bm25_second", None, Some((2, 0.9))),
+        ];
+        assert_eq!(order(&rrf, &hits), vec!["both", "vector_top", "bm25_top", "bm25_second"]);
+
+        // 只看名次，不看分数
+        assert_eq!(rrf.fuse(Some((2, 0.0)), None), rrf.fuse(Some((2, 1.0)), None));
+        assert!((rrf.fuse(Some((1, 0.5)), Some((4, 0.5))) - (1.0 / 61.0 + 1.0 / 64.0)).abs() < 1e-6);
+
+        let small_k = HybridSearchConfig { rrf_k: 1.0, ..rrf };
+        assert!((small_k.fuse(Some((1, 0.0)), None) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_single_source_hits() {
+        for fusion in [FusionStrategy::WeightedSum, FusionStrategy::Rrf] {
+            let config = config(fusion);
+            assert_eq!(config.fuse(None, None), 0.0);
+            assert!(config.fuse(Some((1, 1.0)), None) > 0.0, "{:?}", fusion);
+            assert!(config.fuse(None, Some((1, 1.0))) > 0.0, "{:?}", fusion);
+            // 只命中一路的结果低于同名次、同分数下两路都命中的结果
+            assert!(config.fuse(Some((1, 1.0)), Some((1, 1.0))) > config.fuse(Some((1, 1.0)), None));
+        }
+
+        let weighted = config(FusionStrategy::WeightedSum);
+        assert!((weighted.fuse(Some((5, 0.5)), None) - 0.35).abs() < 1e-6);
+        assert!((weighted.fuse(None, Some((5, 0.5))) - 0.15).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_weighted_fusion_is_default() {
+        assert_eq!(FusionStrategy::default(), FusionStrategy::WeightedSum);
+        let config = HybridSearchConfig::default();
+        assert_eq!(config.fusion, FusionStrategy::WeightedSum);
+        assert_eq!(config.rrf_k, DEFAULT_RRF_K);
+
+        // 加权求和只看分数，不看名次；未命中的一路记 0 分
+        assert_eq!(config.fuse(Some((1, 0.5)), Some((9, 0.5))), config.fuse(Some((9, 0.5)), Some((1, 0.5))));
+        assert!((config.fuse(Some((1, 1.0)), Some((1, 1.0))) - 1.0).abs() < 1e-6);
+        let hits = [
+            ("vector_only", Some((1, 1.0)), None),
+            ("both_half", Some((2, 0.5)), Some((1, 0.5))),
+            ("bm25_only", None, Some((2, 1.0))),
+        ];
+        assert_eq!(order(&config, &hits), vec!["vector_only", "both_half", "bm25_only"]);
+    }
+
+    #[test]
+    fn test_parse_fusion_strategy() {
+        assert_eq!("weighted".parse::<FusionStrategy>(), Ok(FusionStrategy::WeightedSum));
+        assert_eq!("RRF".parse::<FusionStrategy>(), Ok(FusionStrategy::Rrf));
+        assert!("sum".parse::<FusionStrategy>().is_err());
+        assert_eq!("Hybrid".parse::<SearchMode>(), Ok(SearchMode::Hybrid));
+        assert!("keyword".parse::<SearchMode>().is_err());
+    }
+}
@@ -2,15 +2,26 @@ use anyhow::{Context, Result};
 use std::fs;
 use std::path::Path;
 
-use crate::database::VectorDatabase;
+use crate::database::{
+    chunk_content_hash, IndexCheckpoint, InterruptedIndex, PreviousIndex, VectorDatabase, META_CHECKPOINT,
+    META_EMBEDDING_DIMENSION, META_EMBEDDING_MODEL, META_EPUB_HASH,
+};
+use crate::jobs::IndexCancelled;
 use crate::epub::EpubReader;
-use crate::text::{TextVectorizer, MAX_CHUNK_TOKENS, MIN_CHUNK_TOKENS};
+use crate::text::{Reranker, TextVectorizer, MAX_CHUNK_TOKENS, MIN_CHUNK_TOKENS};
 use crate::epub::{parse_toc_file, find_toc_ncx_in_mdbook, parse_nav_md_file, find_nav_md_in_mdbook, flatten_toc};
 use crate::models::{
     DocumentChunk, EpubContent, ProcessOptions, ProcessReport, ProgressUpdate,
     ErrorStats, VectorizerConfig, BookMetadataFile, AuthorField, FlatTocNode
 };
 use epub2mdbook::convert_epub_to_mdbook;
+use sha1::{Digest, Sha1};
+
+/// 索引过程中写入的临时向量库，完成后替换 vectors.sqlite；中断时保留检查点
+pub const TMP_DB_FILE: &str = "vectors.sqlite.tmp";
+
+/// mdbook 目录中记录转换来源 EPUB 哈希的文件
+const EPUB_HASH_FILE: &str = ".epub-hash";
 
 /// Core pipeline: book_dir -> locate book.epub -> parse -> write chapters -> vectorize -> persist to SQLite
 pub async fn process_epub_to_db<P: AsRef<Path>, F>(
@@ -57,29 +68,22 @@ where
         fs::create_dir_all(&mdbook_dir).context("Failed to create mdbook directory")?;
     }
 
-    // Check if conversion is needed (compare file timestamps)
-    // let need_conversion = if mdbook_dir.join("book").join("src").exists() {
-    //     let epub_time = epub_path.metadata()
-    //         .map(|m| m.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH))
-    //         .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
-
-    //     let mdbook_time = mdbook_dir.metadata()
-    //         .map(|m| m.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH))
-    //         .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
-
-    //     epub_time > mdbook_time
-    // } else {
-    //     true
-    // };
-    let need_conversion = true;
+    // EPUB 内容没有变化且 mdbook 已存在时跳过转换
+    let epub_hash = file_hash(&epub_path)?;
+    let hash_file = mdbook_dir.join(EPUB_HASH_FILE);
+    let need_conversion = !mdbook_dir.join("book").join("src").exists()
+        || fs::read_to_string(&hash_file).ok().as_deref().map(str::trim) != Some(epub_hash.as_str());
 
     if need_conversion {
-        log::info!("Converting EPUB to MDBook (EPUB is newer or MDBook doesn't exist)");
+        log::info!("Converting EPUB to MDBook (EPUB changed or MDBook doesn't exist)");
         convert_epub_to_mdbook(&epub_path, &mdbook_dir, true)
             .map_err(|e| anyhow::anyhow!("Failed to convert EPUB to MDBook: {}", e))?;
+        if let Err(e) = fs::write(&hash_file, &epub_hash) {
+            log::warn!("写入 EPUB 哈希失败：{}", e);
+        }
         log::info!("EPUB to MDBook conversion completed");
     } else {
-        log::info!("MDBook is up-to-date, skipping conversion");
+        log::info!("EPUB unchanged (hash {}), skipping MDBook conversion", epub_hash);
     }
 
     // Step 2: Parse and flatten TOC (支持 nav.md 和 toc.ncx)
@@ -168,20 +172,43 @@ where
         .with_context(|| "Failed to detect embedding dimension")?;
     log::info!("检测到实际向量维度: {}", actual_dimension);
 
-    // 直接删除数据库文件，不管是否存在
-    log::info!("删除现有数据库文件（如果存在）");
-    if db_path.exists() {
-        std::fs::remove_file(&db_path)
-            .with_context(|| format!("Failed to remove database file: {:?}", db_path))?;
-        log::info!("数据库文件已删除: {:?}", db_path);
-    } else {
-        log::info!("数据库文件不存在，将创建新文件");
+    // 内容未变的分片沿用旧库中的向量与 id，模型或维度变化时全部重新向量化
+    let mut previous = match PreviousIndex::open(&db_path, &opts.vectorizer.model_name, actual_dimension) {
+        Ok(previous) => previous,
+        Err(e) => {
+            log::warn!("读取已有向量库失败，将全部重新向量化：{}", e);
+            None
+        }
+    };
+    // 先写入临时文件，完成后再替换旧库；中途失败时旧索引仍然可用。
+    // 同一 EPUB、同一模型的中断索引从最后提交的批次继续
+    let tmp_db_path = book_dir.join(TMP_DB_FILE);
+    let resume = match InterruptedIndex::read(&tmp_db_path) {
+        Ok(Some(interrupted)) if interrupted.matches(&epub_hash, &opts.vectorizer.model_name, actual_dimension) => {
+            Some(interrupted.checkpoint)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("读取索引检查点失败，将重新开始：{}", e);
+            None
+        }
+    };
+    if resume.is_none() && tmp_db_path.exists() {
+        std::fs::remove_file(&tmp_db_path)
+            .with_context(|| format!("Failed to remove temporary database file: {:?}", tmp_db_path))?;
+    }
+    if let Some(checkpoint) = &resume {
+        log::info!(
+            "从检查点继续索引：已处理 {} 个分片，已入库 {} 个",
+            checkpoint.position,
+            checkpoint.processed_chunks
+        );
     }
 
     // 使用检测到的维度创建数据库
-    log::info!("Opening vector database at: {:?} with dimension: {}", db_path, actual_dimension);
-    let mut db = VectorDatabase::new(&db_path, actual_dimension)
-        .with_context(|| format!("Failed to open/create database at {:?} with dimension {}", db_path, actual_dimension))?;
+    log::info!("Opening vector database at: {:?} with dimension: {}", tmp_db_path, actual_dimension);
+    let mut db = VectorDatabase::new(&tmp_db_path, actual_dimension)
+        .with_context(|| format!("Failed to open/create database at {:?} with dimension {}", tmp_db_path, actual_dimension))?;
     log::info!("Vector database opened successfully");
     if let Err(e) = db.initialize_vec_table() {
         log::warn!(
@@ -189,11 +216,27 @@ where
             e
         );
     }
+    db.configure_keyword_index(&opts.keyword)?;
+    db.set_meta(META_EMBEDDING_MODEL, &opts.vectorizer.model_name)?;
+    db.set_meta(META_EMBEDDING_DIMENSION, &actual_dimension.to_string())?;
+    db.set_meta(META_EPUB_HASH, &epub_hash)?;
+
+    // 新分片的 id 排在旧分片（以及续传前已入库的分片）之后，沿用的 id 不会冲突
+    let mut next_chunk_id = match &previous {
+        Some(p) => Some(p.max_id().max(db.max_chunk_id()?) + 1),
+        None => None,
+    };
     let batch_size = opts.batch_size.unwrap_or(10);
 
     // 流水线处理：逐个文件处理，立即向量化和入库
-    let mut global_chunk_index = 0;
-    let mut total_processed_chunks = 0;
+    let checkpoint = resume.unwrap_or_default();
+    let resume_position = checkpoint.position;
+    let mut global_chunk_index = checkpoint.global_chunk_index;
+    let mut total_processed_chunks = checkpoint.processed_chunks;
+    let mut reused_chunks = checkpoint.reused_chunks;
+    let mut percent = checkpoint.percent;
+    // 按遍历顺序给每个分片编号，续传时跳过检查点之前的分片
+    let mut position = 0;
 
     // 错误统计
     let mut error_stats = ErrorStats::new();
@@ -270,102 +313,156 @@ where
             total_chunks_in_file
         );
 
-        // 立即处理该文件的所有分片：分片→向量化→批量入库
-        let mut file_batch: Option<Vec<DocumentChunk>> = None;
-        // 以“等长估计”给每个 chunk 一个起始位置，用于分配区间
+        // 立即处理该文件的所有分片：分片→批量向量化→批量入库
+        let mut file_batch: Vec<DocumentChunk> = Vec::new();
+        // (遍历序号, 文件内序号, 内容哈希, 内容)
+        let mut pending = Vec::new();
         for (chunk_index, chunk_content) in chunks.into_iter().enumerate() {
             if chunk_content.trim().is_empty() { continue; }
 
-            // 立即向量化和处理这个分片
-            log::debug!(
-                "向量化分片 {}/{} (文件: {})",
-                chunk_index + 1,
-                total_chunks_in_file,
-                md_src
-            );
-
-            let embedding = match vectorizer.vectorize_text(&chunk_content).await {
-                Ok(emb) => emb,
-                Err(e) => {
-                    log::error!("向量化失败 (文件: {}, 分片: {}): {}", md_src, chunk_index, e);
-                    error_stats.add_chunk_error();
-                    continue; // 跳过这个分片，继续处理其他分片
+            let content_hash = chunk_content_hash(&chunk_content);
+            position += 1;
+            if position <= resume_position {
+                // 已在上次运行中入库；保持旧分片的取用顺序
+                if let Some(p) = previous.as_mut() {
+                    p.skip(&content_hash);
                 }
-            };
-
-            // 创建DocumentChunk并添加到批次中
-            // 存储绝对路径而不是相对路径，以便正确解析图片路径
-            let absolute_md_path = md_file_path.to_string_lossy().to_string();
-            let chunk = DocumentChunk {
-                id: None,
-                book_title: epub_content.title.clone(),
-                book_author: epub_content.author.clone(),
-                md_file_path: absolute_md_path,
-                file_order_in_book: min_play_order,
-                related_chapter_titles: related_chapter_titles.clone(),
-                chunk_text: chunk_content,
-                chunk_order_in_file: chunk_index,
-                total_chunks_in_file: total_chunks_in_file,
-                embedding,
-                global_chunk_index,
-            };
+                continue;
+            }
+            pending.push((position, chunk_index, content_hash, chunk_content));
+        }
 
-            // 添加到当前文件的批次中
-            if file_batch.is_none() {
-                file_batch = Some(Vec::new());
+        // 每次取出可以同时向量化的一组分片
+        let window_size = (opts.embedding.batch_size * opts.embedding.concurrency).max(1);
+        let mut pending = pending.into_iter().peekable();
+        while pending.peek().is_some() {
+            let window: Vec<_> = pending.by_ref().take(window_size).collect();
+
+            if let Some(control) = &opts.control {
+                // 暂停前先提交已向量化的分片，暂停期间退出应用也不会丢失
+                if control.is_paused() && !file_batch.is_empty() {
+                    let committed = IndexCheckpoint {
+                        position: window[0].0 - 1,
+                        global_chunk_index,
+                        processed_chunks: total_processed_chunks + file_batch.len(),
+                        reused_chunks,
+                        percent,
+                    };
+                    total_processed_chunks += commit_batch(&mut db, &file_batch, &committed, md_src, &mut error_stats);
+                    file_batch.clear();
+                }
+                if control.checkpoint().await.is_err() {
+                    log::info!("索引已取消，删除临时向量库");
+                    drop(db);
+                    let _ = fs::remove_file(&tmp_db_path);
+                    return Err(IndexCancelled.into());
+                }
             }
-            file_batch.as_mut().unwrap().push(chunk);
-            global_chunk_index += 1;
-
-            // 如果批次达到大小，立即入库
-            if file_batch.as_ref().unwrap().len() >= batch_size {
-                if let Err(e) = db.insert_chunks_batch(file_batch.as_ref().unwrap()) {
-                    log::error!("批量入库失败 (文件: {}): {}", md_src, e);
-                    error_stats.add_db_error();
-                } else {
-                    log::debug!("批量入库成功：{} 个分片", file_batch.as_ref().unwrap().len());
+
+            // 内容未变的分片沿用旧向量，其余分片批量向量化
+            let mut embeddings: Vec<Option<ChunkEmbedding>> = window
+                .iter()
+                .map(|(_, _, content_hash, _)| previous.as_mut().and_then(|p| p.take(content_hash)))
+                .map(|reused| reused.map(|(id, embedding)| ChunkEmbedding { chunk_id: Some(id), embedding, reused: true }))
+                .collect();
+
+            let missing: Vec<usize> = (0..window.len()).filter(|&i| embeddings[i].is_none()).collect();
+            if !missing.is_empty() {
+                log::debug!("向量化 {} 个分片 (文件: {})", missing.len(), md_src);
+                let texts: Vec<String> = missing.iter().map(|&i| window[i].3.clone()).collect();
+                let results = vectorizer.vectorize_texts(&texts, &opts.embedding).await;
+                for (i, result) in missing.into_iter().zip(results) {
+                    match result {
+                        Ok(embedding) => {
+                            let id = next_chunk_id;
+                            next_chunk_id = next_chunk_id.map(|n| n + 1);
+                            embeddings[i] = Some(ChunkEmbedding { chunk_id: id, embedding, reused: false });
+                        }
+                        Err(e) => {
+                            log::error!("向量化失败 (文件: {}, 分片: {}): {}", md_src, window[i].1, e);
+                            error_stats.add_chunk_error(); // 跳过这个分片，继续处理其他分片
+                        }
+                    }
                 }
-                total_processed_chunks += file_batch.as_ref().unwrap().len();
-                file_batch = Some(Vec::new());
             }
 
-            // 发送进度更新
-            if let Some(cb) = on_progress.as_mut() {
-                let file_progress = ((chunk_index + 1) as f32 / total_chunks_in_file as f32) * 100.0;
-                let overall_progress = ((file_index as f32 + file_progress / 100.0) / total_files as f32) * 100.0;
-
-                // 估算总分片数：已处理的分片数 + 当前文件剩余分片数 + 剩余文件的估算分片数
-                let remaining_chunks_in_current_file = total_chunks_in_file - (chunk_index + 1);
-                let remaining_files = total_files - (file_index + 1);
-                let estimated_chunks_per_file = if file_index > 0 {
-                    (total_processed_chunks + chunk_index + 1) / (file_index + 1)
-                } else {
-                    total_chunks_in_file
-                };
-                let estimated_total = total_processed_chunks + chunk_index + 1 + remaining_chunks_in_current_file + (remaining_files * estimated_chunks_per_file);
-
-                cb(ProgressUpdate {
-                    current: total_processed_chunks + chunk_index + 1,
-                    total: estimated_total,
-                    percent: overall_progress,
-                    md_file_path: md_src.to_string(),
-                    chunk_index,
+            for ((chunk_position, chunk_index, _, chunk_content), embedding) in window.into_iter().zip(embeddings) {
+                let Some(ChunkEmbedding { chunk_id, embedding, reused }) = embedding else { continue };
+                if reused {
+                    reused_chunks += 1;
+                }
+
+                // 创建DocumentChunk并添加到批次中
+                // 存储绝对路径而不是相对路径，以便正确解析图片路径
+                let absolute_md_path = md_file_path.to_string_lossy().to_string();
+                let chunk = DocumentChunk {
+                    id: chunk_id,
+                    book_title: epub_content.title.clone(),
+                    book_author: epub_content.author.clone(),
+                    md_file_path: absolute_md_path,
+                    file_order_in_book: min_play_order,
                     related_chapter_titles: related_chapter_titles.clone(),
-                });
+                    chunk_text: chunk_content,
+                    chunk_order_in_file: chunk_index,
+                    total_chunks_in_file: total_chunks_in_file,
+                    embedding,
+                    global_chunk_index,
+                };
+
+                // 添加到当前文件的批次中
+                file_batch.push(chunk);
+                global_chunk_index += 1;
+
+                let file_progress = ((chunk_index + 1) as f32 / total_chunks_in_file as f32) * 100.0;
+                percent = ((file_index as f32 + file_progress / 100.0) / total_files as f32) * 100.0;
+
+                // 如果批次达到大小，立即入库
+                if file_batch.len() >= batch_size {
+                    let committed = IndexCheckpoint {
+                        position: chunk_position,
+                        global_chunk_index,
+                        processed_chunks: total_processed_chunks + file_batch.len(),
+                        reused_chunks,
+                        percent,
+                    };
+                    total_processed_chunks += commit_batch(&mut db, &file_batch, &committed, md_src, &mut error_stats);
+                    file_batch.clear();
+                }
+
+                // 发送进度更新
+                if let Some(cb) = on_progress.as_mut() {
+                    // 估算总分片数：已处理的分片数 + 当前文件剩余分片数 + 剩余文件的估算分片数
+                    let remaining_chunks_in_current_file = total_chunks_in_file - (chunk_index + 1);
+                    let remaining_files = total_files - (file_index + 1);
+                    let estimated_chunks_per_file = if file_index > 0 {
+                        (total_processed_chunks + chunk_index + 1) / (file_index + 1)
+                    } else {
+                        total_chunks_in_file
+                    };
+                    let estimated_total = total_processed_chunks + chunk_index + 1 + remaining_chunks_in_current_file + (remaining_files * estimated_chunks_per_file);
+
+                    cb(ProgressUpdate {
+                        current: total_processed_chunks + chunk_index + 1,
+                        total: estimated_total,
+                        percent,
+                        md_file_path: md_src.to_string(),
+                        chunk_index,
+                        related_chapter_titles: related_chapter_titles.clone(),
+                    });
+                }
             }
         }
 
         // 处理该文件剩余的分片
-        if let Some(batch) = file_batch {
-            if !batch.is_empty() {
-                if let Err(e) = db.insert_chunks_batch(&batch) {
-                    log::error!("最终批量入库失败 (文件: {}): {}", md_src, e);
-                    error_stats.add_db_error();
-                } else {
-                    log::debug!("最终批量入库成功：{} 个分片", batch.len());
-                }
-                total_processed_chunks += batch.len();
-            }
+        if !file_batch.is_empty() {
+            let committed = IndexCheckpoint {
+                position,
+                global_chunk_index,
+                processed_chunks: total_processed_chunks + file_batch.len(),
+                reused_chunks,
+                percent,
+            };
+            total_processed_chunks += commit_batch(&mut db, &file_batch, &committed, md_src, &mut error_stats);
         }
 
         log::info!(
@@ -392,17 +489,67 @@ where
         }
     }
 
-    log::info!("流水线处理完成：共计 {} 个分片已处理", total_processed_chunks);
+    // 关闭两个连接后用新库替换旧库
+    db.remove_meta(META_CHECKPOINT)?;
+    drop(db);
+    drop(previous);
+    fs::rename(&tmp_db_path, &db_path)
+        .with_context(|| format!("Failed to replace database file: {:?}", db_path))?;
+
+    log::info!(
+        "流水线处理完成：共计 {} 个分片已处理，其中 {} 个沿用已有向量",
+        total_processed_chunks,
+        reused_chunks
+    );
 
     Ok(ProcessReport {
         db_path,
         book_title: epub_content.title,
         book_author: epub_content.author,
         total_chunks: total_processed_chunks,
+        reused_chunks,
         vector_dimension: actual_dimension,
+        mdbook_converted: need_conversion,
     })
 }
 
+/// 待入库分片的向量
+struct ChunkEmbedding {
+    /// 写入的分片 id；沿用旧向量时保留原 id
+    chunk_id: Option<i64>,
+    embedding: Vec<f32>,
+    /// 是否沿用了旧索引中的向量
+    reused: bool,
+}
+
+/// 批量入库，并在同一事务中记录检查点。返回成功入库的分片数；
+/// 入库失败时检查点不前移、不计入已处理分片，只计入错误统计
+fn commit_batch(
+    db: &mut VectorDatabase,
+    batch: &[DocumentChunk],
+    checkpoint: &IndexCheckpoint,
+    md_src: &str,
+    error_stats: &mut ErrorStats,
+) -> usize {
+    match db.insert_chunks_with_checkpoint(batch, checkpoint) {
+        Ok(_) => {
+            log::debug!("批量入库成功：{} 个分片", batch.len());
+            batch.len()
+        }
+        Err(e) => {
+            log::error!("批量入库失败 (文件: {}): {}", md_src, e);
+            error_stats.add_db_error();
+            0
+        }
+    }
+}
+
+/// 文件内容的 SHA-1 十六进制
+fn file_hash(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read file: {:?}", path))?;
+    Ok(format!("{:x}", Sha1::digest(&bytes)))
+}
+
 // 移除了未使用的search_db函数
 
 /// 支持混合搜索模式的数据库搜索
@@ -415,6 +562,10 @@ pub async fn search_db_with_mode<P: AsRef<Path>>(
     search_mode: &str,
     vector_weight: Option<f32>,
     bm25_weight: Option<f32>,
+    fusion: crate::models::FusionStrategy,
+    rrf_k: Option<f32>,
+    reranker: Option<crate::models::RerankerConfig>,
+    diversity: Option<crate::models::DiversityConfig>,
 ) -> Result<Vec<crate::models::SearchResult>> {
     let db_path = book_dir.as_ref().join("vectors.sqlite");
 
@@ -428,16 +579,30 @@ pub async fn search_db_with_mode<P: AsRef<Path>>(
         .unwrap_or(crate::models::SearchMode::Hybrid);
 
     // 创建搜索配置（使用简化的配置管理器）
-    let config = if vector_weight.is_some() || bm25_weight.is_some() {
+    let mut config = if vector_weight.is_some() || bm25_weight.is_some() {
         // 使用自定义权重
         crate::config::create_custom_hybrid_config(Some(mode.clone()), vector_weight, bm25_weight)
     } else {
         // 使用智能推荐配置
         crate::config::get_smart_hybrid_config(query)
     };
+    config.mode = mode.clone();
+    config.fusion = fusion;
+    if let Some(k) = rrf_k {
+        config.rrf_k = k;
+    }
+
+    // 重排、去冗余时多取候选，最后再截断到 limit
+    let mut candidates = limit;
+    if diversity.is_some() {
+        candidates = limit * crate::models::MMR_CANDIDATE_FACTOR;
+    }
+    if let Some(r) = &reranker {
+        candidates = candidates.max(r.top_n);
+    }
 
     // 根据搜索模式执行相应的搜索
-    match mode {
+    let (db, results) = match mode {
         crate::models::SearchMode::BM25Only => {
             // 对于BM25Only模式，不需要向量化
             log::info!("执行BM25搜索: {}", db_path.display());
@@ -446,7 +611,8 @@ pub async fn search_db_with_mode<P: AsRef<Path>>(
             let db = VectorDatabase::open_for_search(&db_path, 1024)
                 .context("Open database failed")?;
 
-            db.search_with_mode(query, None, limit, &config)
+            let results = db.search_with_mode(query, None, candidates, &config)?;
+            (db, results)
         }
         _ => {
             // 需要向量化的模式
@@ -463,9 +629,20 @@ pub async fn search_db_with_mode<P: AsRef<Path>>(
             let embedding = v.vectorize_text(query).await?;
 
             // 使用新的搜索接口
-            db.search_with_mode(query, Some(&embedding), limit, &config)
+            let results = db.search_with_mode(query, Some(&embedding), candidates, &config)?;
+            (db, results)
         }
+    };
+
+    let mut results = match reranker {
+        Some(reranker) => Reranker::new(reranker).rerank(query, results).await,
+        None => results,
+    };
+    if let Some(diversity) = &diversity {
+        results = db.diversify(results, limit, diversity);
     }
+    results.truncate(limit);
+    Ok(results)
 }
 
 fn sanitize_filename(name: &str) -> String {
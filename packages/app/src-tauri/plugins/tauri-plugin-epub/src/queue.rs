@@ -0,0 +1,532 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::Notify;
+
+use crate::commands::run_index;
+use crate::models::{EmbeddingBatchConfig, VectorizerConfig};
+use crate::state::EpubState;
+use crate::text::SegmenterConfig;
+
+/// 默认同时索引的书籍数
+pub const DEFAULT_QUEUE_CONCURRENCY: usize = 1;
+
+/// 队列中书籍的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueItemStatus {
+    Queued,
+    Running,
+    Failed,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueItem {
+    pub book_id: String,
+    pub priority: i32, // 越大越先索引，相同优先级按入队顺序
+    pub status: QueueItemStatus,
+    pub error: Option<String>,
+    pub job_id: Option<String>,
+    pub percent: f32,
+    pub enqueued_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+/// 队列整体进度，随 epub://index-queue 事件发送
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSummary {
+    pub active: bool, // 是否正在调度
+    pub concurrency: usize,
+    pub total: usize,
+    pub queued: usize,
+    pub running: usize,
+    pub failed: usize,
+    pub done: usize,
+    pub percent: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    #[serde(flatten)]
+    pub summary: QueueSummary,
+    pub items: Vec<QueueItem>,
+}
+
+/// 调度时使用的向量化配置；不落盘（含 API key），重启后需重新启动队列
+#[derive(Debug, Clone)]
+pub struct QueueRunConfig {
+    pub vectorizer: VectorizerConfig,
+    pub embedding: EmbeddingBatchConfig,
+    pub keyword: SegmenterConfig,
+}
+
+/// 落盘的队列状态
+#[derive(Serialize, Deserialize)]
+struct QueueFile {
+    concurrency: usize,
+    items: Vec<QueueItem>,
+}
+
+#[derive(Default)]
+struct QueueInner {
+    path: Option<PathBuf>,
+    concurrency: usize,
+    items: Vec<QueueItem>,
+    config: Option<QueueRunConfig>,
+    dispatching: bool,
+}
+
+/// 多本书的后台索引队列，状态保存在 $AppData/index-queue.json
+#[derive(Default)]
+pub struct IndexQueue {
+    inner: Mutex<QueueInner>,
+    notify: Notify,
+}
+
+impl IndexQueue {
+    /// 读取上次保存的队列；上次退出时仍在索引的书重新排队，并从检查点继续
+    pub fn load(&self, path: PathBuf) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.concurrency = DEFAULT_QUEUE_CONCURRENCY;
+        match fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str::<QueueFile>(&text) {
+                Ok(file) => {
+                    inner.concurrency = file.concurrency.max(1);
+                    inner.items = file.items;
+                    for item in &mut inner.items {
+                        if item.status == QueueItemStatus::Running {
+                            item.status = QueueItemStatus::Queued;
+                            item.job_id = None;
+                        }
+                    }
+                    log::info!("已恢复索引队列：{} 本书", inner.items.len());
+                }
+                Err(e) => log::warn!("索引队列文件无效，忽略：{}", e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("读取索引队列失败：{}", e),
+        }
+        inner.path = Some(path);
+    }
+
+    /// 加入队列；已在队列中的书更新优先级，失败或已完成的重新排队
+    pub fn enqueue(&self, book_ids: &[String], priority: i32) -> Vec<QueueItem> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut changed = Vec::new();
+
+        for book_id in book_ids {
+            if book_id.trim().is_empty() {
+                continue;
+            }
+            match inner.items.iter_mut().find(|item| &item.book_id == book_id) {
+                Some(item) if item.status == QueueItemStatus::Running => {
+                    item.priority = priority;
+                    changed.push(item.clone());
+                }
+                Some(item) => {
+                    item.priority = priority;
+                    if item.status != QueueItemStatus::Queued {
+                        item.status = QueueItemStatus::Queued;
+                        item.error = None;
+                        item.job_id = None;
+                        item.percent = 0.0;
+                        item.enqueued_at = now;
+                        item.started_at = None;
+                        item.finished_at = None;
+                    }
+                    changed.push(item.clone());
+                }
+                None => {
+                    let item = QueueItem {
+                        book_id: book_id.clone(),
+                        priority,
+                        status: QueueItemStatus::Queued,
+                        error: None,
+                        job_id: None,
+                        percent: 0.0,
+                        enqueued_at: now,
+                        started_at: None,
+                        finished_at: None,
+                    };
+                    changed.push(item.clone());
+                    inner.items.push(item);
+                }
+            }
+        }
+
+        save(&inner);
+        drop(inner);
+        self.notify.notify_one();
+        changed
+    }
+
+    /// 移出队列；正在索引的书需要先取消其任务
+    pub fn remove(&self, book_id: &str) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.items.iter().find(|item| item.book_id == book_id) {
+            None => return Err(format!("book not in queue: {}", book_id)),
+            Some(item) if item.status == QueueItemStatus::Running => {
+                return Err("book is being indexed; cancel its job first".to_string())
+            }
+            Some(_) => {}
+        }
+        inner.items.retain(|item| item.book_id != book_id);
+        save(&inner);
+        Ok(())
+    }
+
+    pub fn set_priority(&self, book_id: &str, priority: i32) -> Result<QueueItem, String> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner
+            .items
+            .iter_mut()
+            .find(|item| item.book_id == book_id)
+            .ok_or_else(|| format!("book not in queue: {}", book_id))?;
+        item.priority = priority;
+        let item = item.clone();
+        save(&inner);
+        Ok(item)
+    }
+
+    /// 删除已完成的书，失败的保留以便重试
+    pub fn clear_done(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.items.len();
+        inner.items.retain(|item| item.status != QueueItemStatus::Done);
+        save(&inner);
+        before - inner.items.len()
+    }
+
+    /// 开始（或更新配置后继续）调度；返回是否需要启动调度任务
+    pub fn start(&self, config: QueueRunConfig, concurrency: Option<usize>) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(concurrency) = concurrency {
+            inner.concurrency = concurrency.max(1);
+        }
+        inner.config = Some(config);
+        save(&inner);
+        let spawn = !inner.dispatching;
+        inner.dispatching = true;
+        drop(inner);
+        self.notify.notify_one();
+        spawn
+    }
+
+    /// 停止调度新的书，正在索引的书继续完成
+    pub fn stop(&self) {
+        self.inner.lock().unwrap().config = None;
+        self.notify.notify_one();
+    }
+
+    /// 在并发上限内取出下一批要索引的书并标记为 running；已停止时返回 None
+    fn take_next(&self) -> Option<(QueueRunConfig, Vec<String>)> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(config) = inner.config.clone() else {
+            inner.dispatching = false;
+            return None;
+        };
+
+        let running = inner
+            .items
+            .iter()
+            .filter(|item| item.status == QueueItemStatus::Running)
+            .count();
+        let mut queued: Vec<usize> = (0..inner.items.len())
+            .filter(|&i| inner.items[i].status == QueueItemStatus::Queued)
+            .collect();
+        queued.sort_by_key(|&i| (std::cmp::Reverse(inner.items[i].priority), inner.items[i].enqueued_at));
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut started = Vec::new();
+        for i in queued.into_iter().take(inner.concurrency.max(1).saturating_sub(running)) {
+            let item = &mut inner.items[i];
+            item.status = QueueItemStatus::Running;
+            item.started_at = Some(now);
+            item.percent = 0.0;
+            started.push(item.book_id.clone());
+        }
+        if !started.is_empty() {
+            save(&inner);
+        }
+        Some((config, started))
+    }
+
+    /// 记录正在索引的书对应的任务，便于前端暂停或取消
+    pub fn attach_job(&self, book_id: &str, job_id: &str) -> Option<QueueItem> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = running_item(&mut inner, book_id)?;
+        item.job_id = Some(job_id.to_string());
+        Some(item.clone())
+    }
+
+    /// 更新队列中正在索引的书的进度；不在队列中的书返回 None
+    pub fn update_progress(&self, book_id: &str, percent: f32) -> Option<QueueItem> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = running_item(&mut inner, book_id)?;
+        item.percent = percent;
+        Some(item.clone())
+    }
+
+    fn finish(&self, book_id: &str, error: Option<String>) -> Option<QueueItem> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = running_item(&mut inner, book_id)?;
+        item.status = if error.is_some() {
+            QueueItemStatus::Failed
+        } else {
+            QueueItemStatus::Done
+        };
+        if error.is_none() {
+            item.percent = 100.0;
+        }
+        item.error = error;
+        item.finished_at = Some(chrono::Utc::now().timestamp_millis());
+        let item = item.clone();
+        save(&inner);
+        Some(item)
+    }
+
+    pub fn summary(&self) -> QueueSummary {
+        summarize(&self.inner.lock().unwrap())
+    }
+
+    /// 队列全部内容，按调度顺序排列（运行中、排队、失败、完成）
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let inner = self.inner.lock().unwrap();
+        let mut items = inner.items.clone();
+        items.sort_by_key(|item| {
+            let rank = match item.status {
+                QueueItemStatus::Running => 0,
+                QueueItemStatus::Queued => 1,
+                QueueItemStatus::Failed => 2,
+                QueueItemStatus::Done => 3,
+            };
+            (rank, std::cmp::Reverse(item.priority), item.enqueued_at)
+        });
+        QueueSnapshot {
+            summary: summarize(&inner),
+            items,
+        }
+    }
+}
+
+fn running_item<'a>(inner: &'a mut QueueInner, book_id: &str) -> Option<&'a mut QueueItem> {
+    inner
+        .items
+        .iter_mut()
+        .find(|item| item.book_id == book_id && item.status == QueueItemStatus::Running)
+}
+
+fn summarize(inner: &QueueInner) -> QueueSummary {
+    let count = |status| inner.items.iter().filter(|item| item.status == status).count();
+    let total = inner.items.len();
+    let finished = count(QueueItemStatus::Done) + count(QueueItemStatus::Failed);
+    let running_progress: f32 = inner
+        .items
+        .iter()
+        .filter(|item| item.status == QueueItemStatus::Running)
+        .map(|item| item.percent / 100.0)
+        .sum();
+
+    QueueSummary {
+        active: inner.config.is_some(),
+        concurrency: inner.concurrency,
+        total,
+        queued: count(QueueItemStatus::Queued),
+        running: count(QueueItemStatus::Running),
+        failed: count(QueueItemStatus::Failed),
+        done: count(QueueItemStatus::Done),
+        percent: if total == 0 {
+            0.0
+        } else {
+            (finished as f32 + running_progress) / total as f32 * 100.0
+        },
+    }
+}
+
+fn save(inner: &QueueInner) {
+    let Some(path) = &inner.path else { return };
+    let file = QueueFile {
+        concurrency: inner.concurrency,
+        items: inner.items.clone(),
+    };
+    let result = serde_json::to_string_pretty(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        log::warn!("保存索引队列失败：{}", e);
+    }
+}
+
+/// 发送队列整体进度与单本书的状态
+pub fn emit_queue<R: Runtime>(app: &AppHandle<R>, item: Option<QueueItem>) {
+    if let Some(item) = item {
+        let _ = app.emit("epub://index-queue-item", item);
+    }
+    let summary = app.state::<EpubState>().queue.summary();
+    let _ = app.emit("epub://index-queue", summary);
+}
+
+/// 调度任务：有空位就启动排队中的书，书完成或队列变化时再检查一次，停止后退出
+pub fn spawn_dispatcher<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        log::info!("索引队列开始调度");
+        loop {
+            let state = app.state::<EpubState>();
+            let Some((config, books)) = state.queue.take_next() else {
+                break;
+            };
+
+            for book_id in books {
+                let app = app.clone();
+                let config = config.clone();
+                tauri::async_runtime::spawn(async move {
+                    let result = run_index(&app, &book_id, config.vectorizer, config.embedding, config.keyword).await;
+                    let error = match result {
+                        Ok(result) if result.success => None,
+                        Ok(result) => Some(result.message),
+                        Err(e) => Some(e),
+                    };
+                    if let Some(e) = &error {
+                        log::warn!("索引队列：{} 索引失败：{}", book_id, e);
+                    }
+                    let state = app.state::<EpubState>();
+                    let item = state.queue.finish(&book_id, error);
+                    emit_queue(&app, item);
+                    state.queue.notify.notify_one();
+                });
+            }
+
+            state.queue.notify.notified().await;
+        }
+        log::info!("索引队列已停止调度");
+        emit_queue(&app, None);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn run_config() -> QueueRunConfig {
+        QueueRunConfig {
+            vectorizer: VectorizerConfig {
+                embeddings_url: "http://localhost".to_string(),
+                model_name: "test".to_string(),
+                api_key: None,
+            },
+            embedding: EmbeddingBatchConfig::default(),
+            keyword: SegmenterConfig::default(),
+        }
+    }
+
+    fn ids(books: &[&str]) -> Vec<String> {
+        books.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn item(queue: &IndexQueue, book_id: &str) -> QueueItem {
+        let inner = queue.inner.lock().unwrap();
+        inner.items.iter().find(|item| item.book_id == book_id).unwrap().clone()
+    }
+
+    fn set_enqueued_at(queue: &IndexQueue, book_id: &str, enqueued_at: i64) {
+        let mut inner = queue.inner.lock().unwrap();
+        inner.items.iter_mut().find(|item| item.book_id == book_id).unwrap().enqueued_at = enqueued_at;
+    }
+
+    #[test]
+    fn test_take_next_order_and_concurrency() {
+        let queue = IndexQueue::default();
+        queue.enqueue(&ids(&["low", "late", "early"]), 0);
+        queue.set_priority("late", 5).unwrap();
+        queue.set_priority("early", 5).unwrap();
+        set_enqueued_at(&queue, "low", 1);
+        set_enqueued_at(&queue, "late", 3);
+        set_enqueued_at(&queue, "early", 2);
+
+        // 未启动时不调度
+        assert!(queue.take_next().is_none());
+
+        assert!(queue.start(run_config(), Some(2)));
+        let (_, started) = queue.take_next().unwrap();
+        assert_eq!(started, vec!["early", "late"]);
+        assert_eq!(item(&queue, "early").status, QueueItemStatus::Running);
+
+        // 并发已满
+        let (_, started) = queue.take_next().unwrap();
+        assert!(started.is_empty());
+
+        queue.finish("late", None).unwrap();
+        let (_, started) = queue.take_next().unwrap();
+        assert_eq!(started, vec!["low"]);
+
+        queue.stop();
+        assert!(queue.take_next().is_none());
+        // 停止后再次启动需要新的调度任务
+        assert!(queue.start(run_config(), None));
+    }
+
+    #[test]
+    fn test_load_requeues_running() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("index-queue.json");
+
+        let queue = IndexQueue::default();
+        queue.load(path.clone());
+        queue.enqueue(&ids(&["a", "b"]), 0);
+        queue.start(run_config(), Some(3));
+        queue.take_next().unwrap();
+        queue.attach_job("a", "job-1").unwrap();
+        queue.finish("b", None).unwrap();
+
+        // 模拟退出后重新启动
+        let restored = IndexQueue::default();
+        restored.load(path);
+        let a = item(&restored, "a");
+        assert_eq!(a.status, QueueItemStatus::Queued);
+        assert_eq!(a.job_id, None);
+        assert_eq!(item(&restored, "b").status, QueueItemStatus::Done);
+        let summary = restored.summary();
+        assert_eq!(summary.concurrency, 3);
+        assert!(!summary.active);
+
+        let invalid = dir.path().join("invalid.json");
+        fs::write(&invalid, "not json").unwrap();
+        let empty = IndexQueue::default();
+        empty.load(invalid);
+        assert_eq!(empty.summary().total, 0);
+        assert_eq!(empty.summary().concurrency, DEFAULT_QUEUE_CONCURRENCY);
+    }
+
+    #[test]
+    fn test_enqueue_resets_finished() {
+        let queue = IndexQueue::default();
+        queue.enqueue(&ids(&["failed", "done", "running", " "]), 0);
+        queue.start(run_config(), Some(3));
+        queue.take_next().unwrap();
+        queue.update_progress("failed", 40.0).unwrap();
+        queue.finish("failed", Some("boom".to_string())).unwrap();
+        queue.finish("done", None).unwrap();
+        queue.update_progress("running", 30.0).unwrap();
+
+        let changed = queue.enqueue(&ids(&["failed", "done", "running"]), 7);
+        assert_eq!(changed.len(), 3);
+        for book_id in ["failed", "done"] {
+            let item = item(&queue, book_id);
+            assert_eq!(item.status, QueueItemStatus::Queued);
+            assert_eq!(item.priority, 7);
+            assert_eq!((item.error, item.percent, item.started_at, item.finished_at), (None, 0.0, None, None));
+        }
+
+        // 正在索引的书只更新优先级
+        let running = item(&queue, "running");
+        assert_eq!(running.status, QueueItemStatus::Running);
+        assert_eq!((running.priority, running.percent), (7, 30.0));
+        assert_eq!(queue.summary().total, 3);
+    }
+}
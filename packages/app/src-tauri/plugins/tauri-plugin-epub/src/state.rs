@@ -1,9 +1,10 @@
-use std::collections::HashMap;
-use tokio::sync::Mutex;
+use crate::jobs::JobManager;
+use crate::queue::IndexQueue;
 
 #[derive(Default)]
 pub struct EpubState {
-    // Example: track simple statuses by a book ID or file path
-    pub statuses: Mutex<HashMap<String, String>>,
+    // 索引任务：暂停、继续、取消与进度查询
+    pub jobs: JobManager,
+    // 多本书的后台索引队列
+    pub queue: IndexQueue,
 }
-
@@ -10,3 +10,20 @@ pub const MIN_CHUNK_TOKENS: usize = 50;
 /// 分片重叠比例（20%）
 pub const CHUNK_OVERLAP_RATIO: f32 = 0.2;
 
+/// 每个向量化请求最多包含的分片数
+pub const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 16;
+
+/// 每个向量化请求的 token 上限，本地服务通常受 batch 大小限制
+pub const DEFAULT_EMBEDDING_BATCH_TOKENS: usize = 8192;
+
+/// 同时进行的向量化请求数
+pub const DEFAULT_EMBEDDING_CONCURRENCY: usize = 2;
+
+/// 遇到限流（429）时单个请求的最大尝试次数
+pub const EMBEDDING_MAX_ATTEMPTS: u32 = 5;
+
+/// 交给重排模型的候选数
+pub const DEFAULT_RERANK_TOP_N: usize = 20;
+
+/// 重排请求的超时时间（毫秒），超时后保持原有顺序
+pub const DEFAULT_RERANK_TIMEOUT_MS: u64 = 3000;
@@ -3,6 +3,8 @@ pub mod tokenizer;
 pub mod sanitizer;
 pub mod vectorizer;
 pub mod constants;
+pub mod segmenter;
+pub mod reranker;
 
 // Re-export public types for convenience
 pub use chunker::*;
@@ -10,3 +12,5 @@ pub use tokenizer::*;
 pub use sanitizer::*;
 pub use vectorizer::*;
 pub use constants::*;
+pub use segmenter::*;
+pub use reranker::*;
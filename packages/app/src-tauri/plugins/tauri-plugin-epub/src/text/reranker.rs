@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::models::{RerankerConfig, SearchResult};
+
+#[derive(Serialize)]
+struct RerankRequest<'a> {
+    #[serde(skip_serializing_if = "str::is_empty")]
+    model: &'a str,
+    query: &'a str,
+    documents: Vec<&'a str>,
+    top_n: usize,
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankItem>,
+}
+
+#[derive(Deserialize)]
+struct RerankItem {
+    index: usize,
+    relevance_score: f32,
+}
+
+/// 交叉编码器重排：把检索结果的前 top_n 个候选交给 /rerank 接口重新打分排序
+pub struct Reranker {
+    client: Client,
+    config: RerankerConfig,
+}
+
+impl Reranker {
+    pub fn new(config: RerankerConfig) -> Self {
+        Self {
+            client: Client::new(),
+            config,
+        }
+    }
+
+    /// 重排前 top_n 个结果，之后的结果保持原位。请求失败或超时时返回原有顺序，不影响搜索本身
+    pub async fn rerank(&self, query: &str, mut results: Vec<SearchResult>) -> Vec<SearchResult> {
+        let top_n = self.config.top_n.min(results.len());
+        if top_n < 2 {
+            return results;
+        }
+
+        let scores = match self.score(query, &results[..top_n]).await {
+            Ok(scores) => scores,
+            Err(e) => {
+                log::warn!("重排失败，保持原有顺序: {:#}", e);
+                return results;
+            }
+        };
+
+        let rest = results.split_off(top_n);
+        let mut candidates: Vec<(Option<f32>, SearchResult)> = scores.into_iter().zip(results).collect();
+        // 稳定排序：接口没有返回分数的候选排在有分数的之后，并保持原有相对顺序
+        candidates.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => b.total_cmp(a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+
+        candidates
+            .into_iter()
+            .map(|(score, mut result)| {
+                result.components.rerank_score = score;
+                result
+            })
+            .chain(rest)
+            .collect()
+    }
+
+    /// 请求重排接口，返回与 candidates 一一对应的相关度
+    async fn score(&self, query: &str, candidates: &[SearchResult]) -> Result<Vec<Option<f32>>> {
+        let mut req = self
+            .client
+            .post(&self.config.rerank_url)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .json(&RerankRequest {
+                model: &self.config.model_name,
+                query,
+                documents: candidates.iter().map(|r| r.chunk_text.as_str()).collect(),
+                top_n: candidates.len(),
+            });
+        if let Some(k) = &self.config.api_key {
+            req = req.header("Authorization", format!("Bearer {}", k));
+        }
+
+        let response = req.send().await.context("Failed to send request to rerank API")?;
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Rerank API error: {}", error_text);
+        }
+        let response: RerankResponse = response.json().await.context("Failed to parse rerank API response")?;
+
+        let mut scores = vec![None; candidates.len()];
+        for item in response.results {
+            let slot = scores
+                .get_mut(item.index)
+                .with_context(|| format!("Rerank API returned out-of-range index {}", item.index))?;
+            *slot = Some(item.relevance_score);
+        }
+        Ok(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ScoreComponents;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn result(text: &str, index: usize) -> SearchResult {
+        SearchResult {
+            chunk_id: index as i64,
+            book_title: "Book".to_string(),
+            book_author: "Author".to_string(),
+            md_file_path: "chapter.md".to_string(),
+            file_order_in_book: 1,
+            related_chapter_titles: "Chapter".to_string(),
+            chunk_text: text.to_string(),
+            chunk_order_in_file: index,
+            total_chunks_in_file: 4,
+            global_chunk_index: index,
+            similarity_score: 1.0 - index as f32 * 0.1,
+            components: ScoreComponents::default(),
+            merged_chunk_ids: Vec::new(),
+        }
+    }
+
+    /// 只处理一个请求的本地重排服务：按文本长度打分，delay 模拟慢服务
+    async fn stub_server(status: &'static str, delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let body = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf).to_string();
+                if let Some(pos) = text.find("\r\n\r\n") {
+                    let len = text
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if buf.len() >= pos + 4 + len {
+                        break text[pos + 4..].to_string();
+                    }
+                }
+            };
+            let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let results: Vec<serde_json::Value> = request["documents"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(i, d)| serde_json::json!({ "index": i, "relevance_score": d.as_str().unwrap().len() as f32 }))
+                .collect();
+            let response = serde_json::json!({ "results": results }).to_string();
+            tokio::time::sleep(delay).await;
+            let _ = socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    )
+                    .as_bytes(),
+                )
+                .await;
+        });
+        format!("http://{}/rerank", addr)
+    }
+
+    fn reranker(url: String, top_n: usize) -> Reranker {
+        let mut config = RerankerConfig::new(url, String::new(), None);
+        config.top_n = top_n;
+        config.timeout_ms = 500;
+        Reranker::new(config)
+    }
+
+    fn texts(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.chunk_text.as_str()).collect()
+    }
+
+    fn candidates() -> Vec<SearchResult> {
+        ["a", "ccc", "bb", "dddd"].iter().enumerate().map(|(i, t)| result(t, i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_rerank_reorders_top_n() {
+        let url = stub_server("200 OK", Duration::ZERO).await;
+        let results = reranker(url, 3).rerank("query", candidates()).await;
+        assert_eq!(texts(&results), ["ccc", "bb", "a", "dddd"]);
+        assert_eq!(results[0].components.rerank_score, Some(3.0));
+        assert_eq!(results[3].components.rerank_score, None);
+    }
+
+    #[tokio::test]
+    async fn test_rerank_timeout_keeps_order() {
+        let url = stub_server("200 OK", Duration::from_secs(2)).await;
+        let results = reranker(url, 4).rerank("query", candidates()).await;
+        assert_eq!(texts(&results), ["a", "ccc", "bb", "dddd"]);
+        assert!(results.iter().all(|r| r.components.rerank_score.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_rerank_error_keeps_order() {
+        let url = stub_server("500 Internal Server Error", Duration::ZERO).await;
+        let results = reranker(url, 4).rerank("query", candidates()).await;
+        assert_eq!(texts(&results), ["a", "ccc", "bb", "dddd"]);
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 关键词检索（FTS5）使用的分词器名称
+pub const SEGMENTER_NAME: &str = "epub";
+
+/// 中日韩文字的切分方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CjkMode {
+    /// 相邻两字组成一个词（“数据库” -> “数据”“据库”），单字片段保留单字
+    #[default]
+    Bigram,
+    /// 每个字一个词
+    Unigram,
+}
+
+/// 关键词分词配置；索引与查询使用同一配置，保存在全文索引表的 tokenize 参数中
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SegmenterConfig {
+    pub cjk: CjkMode,
+    pub stopwords: Vec<String>, // 启用停用词表的语言，目前支持 en、zh
+}
+
+impl SegmenterConfig {
+    /// 停用词语言去重、排序后的配置；不支持的语言返回错误
+    pub fn with_stopwords(stopwords: &[String]) -> Result<Self> {
+        let mut languages: Vec<String> = stopwords.iter().map(|l| l.trim().to_lowercase()).collect();
+        languages.sort();
+        languages.dedup();
+        if let Some(unknown) = languages.iter().find(|l| stopword_list(l).is_none()) {
+            return Err(anyhow!("unsupported stop-word language: {}", unknown));
+        }
+        Ok(Self {
+            cjk: CjkMode::default(),
+            stopwords: languages,
+        })
+    }
+
+    /// FTS5 tokenize 参数，例如 `epub cjk bigram stopwords en zh`
+    pub fn to_args(&self) -> String {
+        let mut args = vec![SEGMENTER_NAME.to_string(), "cjk".to_string()];
+        args.push(
+            match self.cjk {
+                CjkMode::Bigram => "bigram",
+                CjkMode::Unigram => "unigram",
+            }
+            .to_string(),
+        );
+        if !self.stopwords.is_empty() {
+            args.push("stopwords".to_string());
+            args.extend(self.stopwords.iter().cloned());
+        }
+        args.join(" ")
+    }
+
+    /// 解析 tokenize 参数（不含分词器名称本身）
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<Self> {
+        let mut cjk = CjkMode::default();
+        let mut stopwords = Vec::new();
+        let mut iter = args.iter().map(|a| a.as_ref());
+        while let Some(arg) = iter.next() {
+            match arg {
+                "cjk" => {
+                    cjk = match iter.next() {
+                        Some("bigram") => CjkMode::Bigram,
+                        Some("unigram") => CjkMode::Unigram,
+                        other => return Err(anyhow!("invalid cjk mode: {:?}", other)),
+                    }
+                }
+                "stopwords" => stopwords.extend(iter.by_ref().map(str::to_string)),
+                other => return Err(anyhow!("unknown tokenizer option: {}", other)),
+            }
+        }
+        let mut config = Self::with_stopwords(&stopwords)?;
+        config.cjk = cjk;
+        Ok(config)
+    }
+}
+
+/// 分出的词及其在原文中的字节区间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 关键词分词器：中日韩文字按字或二元组切分，其他文字按 Unicode 词边界（UAX #29）切分，
+/// 统一转小写并去掉变音符号
+pub struct Segmenter {
+    cjk: CjkMode,
+    stopwords: HashSet<&'static str>,
+}
+
+impl Segmenter {
+    pub fn new(config: &SegmenterConfig) -> Self {
+        let stopwords = config
+            .stopwords
+            .iter()
+            .filter_map(|l| stopword_list(l))
+            .flat_map(|list| list.iter().copied())
+            .collect();
+        Self { cjk: config.cjk, stopwords }
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        // 连续的中日韩字符（字节位置, 字符），遇到其他文字或标点时整体切分
+        let mut run: Vec<(usize, char)> = Vec::new();
+
+        for (offset, word) in text.split_word_bound_indices() {
+            if word.chars().all(is_cjk) {
+                for (i, c) in word.char_indices() {
+                    if self.is_stopword(c.encode_utf8(&mut [0; 4])) {
+                        self.flush_cjk(&mut run, &mut tokens);
+                    } else {
+                        run.push((offset + i, c));
+                    }
+                }
+                continue;
+            }
+
+            self.flush_cjk(&mut run, &mut tokens);
+            if !word.chars().any(char::is_alphanumeric) {
+                continue;
+            }
+            let normalized = normalize_word(word);
+            if !normalized.is_empty() && !self.is_stopword(&normalized) {
+                tokens.push(Token {
+                    text: normalized,
+                    start: offset,
+                    end: offset + word.len(),
+                });
+            }
+        }
+        self.flush_cjk(&mut run, &mut tokens);
+
+        tokens
+    }
+
+    fn is_stopword(&self, word: &str) -> bool {
+        !self.stopwords.is_empty() && self.stopwords.contains(word)
+    }
+
+    fn flush_cjk(&self, run: &mut Vec<(usize, char)>, tokens: &mut Vec<Token>) {
+        let token = |chars: &[(usize, char)]| {
+            let (start, _) = chars[0];
+            let (last, c) = chars[chars.len() - 1];
+            Token {
+                text: chars.iter().map(|(_, c)| *c).collect(),
+                start,
+                end: last + c.len_utf8(),
+            }
+        };
+        match (self.cjk, run.len()) {
+            (_, 0) => {}
+            (CjkMode::Unigram, _) | (CjkMode::Bigram, 1) => tokens.extend(run.chunks(1).map(token)),
+            (CjkMode::Bigram, _) => tokens.extend(run.windows(2).map(token)),
+        }
+        run.clear();
+    }
+}
+
+impl Default for Segmenter {
+    fn default() -> Self {
+        Self::new(&SegmenterConfig::default())
+    }
+}
+
+/// 汉字、假名与谚文
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // 平假名、片假名
+        | 0x31F0..=0x31FF   // 片假名音标扩展
+        | 0x3400..=0x4DBF   // 中日韩统一表意文字扩展 A
+        | 0x4E00..=0x9FFF   // 中日韩统一表意文字
+        | 0xAC00..=0xD7AF   // 谚文音节
+        | 0x1100..=0x11FF   // 谚文字母
+        | 0x3130..=0x318F   // 谚文兼容字母
+        | 0xF900..=0xFAFF   // 兼容表意文字
+        | 0x20000..=0x2EBEF // 扩展 B-F
+    )
+}
+
+/// 小写并去掉变音符号（café -> cafe），全角字母数字转为半角
+fn normalize_word(word: &str) -> String {
+    word.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn stopword_list(language: &str) -> Option<&'static [&'static str]> {
+    match language {
+        "en" => Some(EN_STOPWORDS),
+        "zh" => Some(ZH_STOPWORDS),
+        _ => None,
+    }
+}
+
+const EN_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "had", "has", "have", "he", "her",
+    "his", "i", "if", "in", "into", "is", "it", "its", "of", "on", "or", "she", "so", "such", "that", "the",
+    "their", "then", "there", "these", "they", "this", "to", "was", "were", "which", "will", "with",
+];
+
+/// 中文停用字：作为分隔处理，不参与二元组
+const ZH_STOPWORDS: &[&str] = &[
+    "的", "了", "着", "过", "是", "在", "和", "与", "及", "或", "也", "都", "就", "而", "之", "其", "这", "那",
+    "吗", "呢", "吧", "啊",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(segmenter: &Segmenter, text: &str) -> Vec<String> {
+        segmenter.tokenize(text).into_iter().map(|t| t.text).collect()
+    }
+
+    #[test]
+    fn test_cjk_bigrams() {
+        let segmenter = Segmenter::default();
+        assert_eq!(words(&segmenter, "数据库设计"), ["数据", "据库", "库设", "设计"]);
+        assert_eq!(words(&segmenter, "书。"), ["书"]);
+    }
+
+    #[test]
+    fn test_mixed_scripts() {
+        let segmenter = Segmenter::default();
+        assert_eq!(words(&segmenter, "用Rust写Café"), ["用", "rust", "写", "cafe"]);
+
+        let tokens = segmenter.tokenize("Rust语言");
+        assert_eq!(&"Rust语言"[tokens[1].start..tokens[1].end], "语言");
+    }
+
+    #[test]
+    fn test_stopwords() {
+        let config = SegmenterConfig::with_stopwords(&["zh".into(), "en".into()]).unwrap();
+        let segmenter = Segmenter::new(&config);
+        assert_eq!(words(&segmenter, "The art of 我的书"), ["art", "我", "书"]);
+        assert!(SegmenterConfig::with_stopwords(&["xx".into()]).is_err());
+    }
+
+    #[test]
+    fn test_config_args_roundtrip() {
+        let config = SegmenterConfig::with_stopwords(&["zh".into()]).unwrap();
+        let args = config.to_args();
+        assert_eq!(args, "epub cjk bigram stopwords zh");
+        let parsed: Vec<&str> = args.split_whitespace().skip(1).collect();
+        assert_eq!(SegmenterConfig::from_args(&parsed).unwrap(), config);
+    }
+}
@@ -1,10 +1,14 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use jan_utils::calculate_exponential_backoff_delay;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use tiktoken_rs::o200k_base;
+use tokio::task::JoinSet;
 
-use crate::models::VectorizerConfig;
-use crate::text::MAX_CHUNK_TOKENS;
+use crate::models::{EmbeddingBatchConfig, VectorizerConfig};
+use crate::text::{EMBEDDING_MAX_ATTEMPTS, MAX_CHUNK_TOKENS};
 
 #[derive(Serialize)]
 struct OpenAIEmbeddingRequest {
@@ -16,7 +20,7 @@ struct OpenAIEmbeddingRequest {
 #[derive(Serialize)]
 struct OllamaEmbeddingRequest {
     model: String,
-    input: String,
+    input: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -31,16 +35,136 @@ struct OllamaEmbeddingResponse {
 
 #[derive(Deserialize)]
 struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
     embedding: Vec<f32>,
 }
 
 // Removed Usage: only `data` is required
 
-pub struct TextVectorizer {
+/// 向量化 API 的连接信息，可复制到并发的请求任务中
+#[derive(Clone)]
+struct EmbeddingClient {
     client: Client,
     api_key: Option<String>,
     model_name: String,
     embeddings_url: String,
+}
+
+impl EmbeddingClient {
+    /// 判断是否为 Ollama API（根据 URL 结尾）
+    fn is_ollama(&self) -> bool {
+        self.embeddings_url.ends_with("/api/embed")
+    }
+
+    /// 发送一次向量化请求，返回与 inputs 顺序一致的向量；被限流（429）时指数退避后重试
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let is_ollama = self.is_ollama();
+        let mut attempt = 1;
+
+        loop {
+            let mut req = self.client
+                .post(&self.embeddings_url)
+                .header("Content-Type", "application/json");
+
+            if is_ollama {
+                // Ollama /api/embed 同样接受数组形式的 input
+                req = req.json(&OllamaEmbeddingRequest {
+                    model: self.model_name.clone(),
+                    input: inputs.to_vec(),
+                });
+            } else {
+                req = req.json(&OpenAIEmbeddingRequest {
+                    input: inputs.to_vec(),
+                    model: self.model_name.clone(),
+                    encoding_format: "float".to_string(),
+                });
+            }
+
+            if let Some(k) = &self.api_key {
+                req = req.header("Authorization", format!("Bearer {}", k));
+            }
+
+            let response = req
+                .send()
+                .await
+                .context("Failed to send request to embedding API")?;
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS && attempt < EMBEDDING_MAX_ATTEMPTS {
+                // 服务端给出 Retry-After 时至少等待这么久
+                let retry_after_ms = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(|secs| secs * 1000)
+                    .unwrap_or(0);
+                let delay = calculate_exponential_backoff_delay(attempt).max(retry_after_ms);
+                log::warn!(
+                    "向量化请求被限流 (429)，{} ms 后重试（第 {}/{} 次）",
+                    delay,
+                    attempt,
+                    EMBEDDING_MAX_ATTEMPTS - 1
+                );
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                attempt += 1;
+                continue;
+            }
+
+            if !response.status().is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                anyhow::bail!("Embedding API error: {}", error_text);
+            }
+
+            let embeddings = if is_ollama {
+                let embedding_response: OllamaEmbeddingResponse = response
+                    .json()
+                    .await
+                    .context("Failed to parse Ollama embedding API response")?;
+                embedding_response.embeddings
+            } else {
+                let mut embedding_response: OpenAIEmbeddingResponse = response
+                    .json()
+                    .await
+                    .context("Failed to parse OpenAI embedding API response")?;
+                embedding_response.data.sort_by_key(|d| d.index);
+                embedding_response.data.into_iter().map(|d| d.embedding).collect::<Vec<_>>()
+            };
+
+            if embeddings.len() != inputs.len() {
+                anyhow::bail!(
+                    "Embedding API returned {} embeddings for {} inputs",
+                    embeddings.len(),
+                    inputs.len()
+                );
+            }
+            return Ok(embeddings);
+        }
+    }
+
+    /// 批量请求；整批失败时逐条重试，单条失败只影响该分片
+    async fn embed_batch(&self, inputs: Vec<String>) -> Vec<Result<Vec<f32>>> {
+        match self.embed(&inputs).await {
+            Ok(embeddings) => embeddings.into_iter().map(Ok).collect(),
+            Err(e) if inputs.len() > 1 => {
+                log::warn!("批量向量化失败（{} 个分片），逐条重试: {}", inputs.len(), e);
+                let mut results = Vec::with_capacity(inputs.len());
+                for input in &inputs {
+                    let result = self
+                        .embed(std::slice::from_ref(input))
+                        .await
+                        .map(|mut embeddings| embeddings.remove(0));
+                    results.push(result);
+                }
+                results
+            }
+            Err(e) => vec![Err(e)],
+        }
+    }
+}
+
+pub struct TextVectorizer {
+    client: EmbeddingClient,
     tokenizer: tiktoken_rs::CoreBPE,
     embedding_dimension: Option<usize>, // 缓存检测到的维度
 }
@@ -52,14 +176,15 @@ impl TextVectorizer {
     pub async fn new(config: VectorizerConfig) -> Result<Self> {
         log::info!("初始化嵌入 API 向量化器: embeddings_url={}, model={}", config.embeddings_url, config.model_name);
 
-        let client = Client::new();
         let tokenizer = o200k_base().context("Failed to initialize tiktoken tokenizer")?;
 
         Ok(Self {
-            client,
-            api_key: config.api_key,
-            model_name: config.model_name,
-            embeddings_url: config.embeddings_url,
+            client: EmbeddingClient {
+                client: Client::new(),
+                api_key: config.api_key,
+                model_name: config.model_name,
+                embeddings_url: config.embeddings_url,
+            },
             tokenizer,
             embedding_dimension: None, // 初始化时未知，首次调用时检测
         })
@@ -67,96 +192,91 @@ impl TextVectorizer {
 
     /// 将文本转换为向量
     pub async fn vectorize_text(&mut self, text: &str) -> Result<Vec<f32>> {
-        // 使用统一的 token 限制配置
-        let tokens = self.tokenizer.encode_with_special_tokens(text);
-        let processed_text = if tokens.len() > MAX_CHUNK_TOKENS {
-            log::warn!(
-                "文本过长 ({} tokens)，按 token 截断到 {} tokens",
-                tokens.len(),
-                MAX_CHUNK_TOKENS
-            );
-            let preview = text.chars().take(120).collect::<String>();
-            log::debug!("原文本预览(120)：{}", preview);
-            let clipped = &tokens[..MAX_CHUNK_TOKENS];
-            // 将截断后的 token 反解码为字符串
-            self.tokenizer.decode(clipped.to_vec())
-                .unwrap_or_else(|_| text.chars().take(1000).collect::<String>())
-        } else {
-            text.to_string()
-        };
-
-        // 判断是否为 Ollama API（根据 URL 结尾）
-        let is_ollama = self.embeddings_url.ends_with("/api/embed");
-
-        let mut req = self.client
-            .post(&self.embeddings_url)
-            .header("Content-Type", "application/json");
-
-        if is_ollama {
-            // Ollama 格式：input 是字符串
-            let request = OllamaEmbeddingRequest {
-                model: self.model_name.clone(),
-                input: processed_text,
-            };
-            req = req.json(&request);
-        } else {
-            // OpenAI 格式：input 是数组
-            let request = OpenAIEmbeddingRequest {
-                input: vec![processed_text],
-                model: self.model_name.clone(),
-                encoding_format: "float".to_string(),
-            };
-            req = req.json(&request);
-        }
-
-        if let Some(k) = &self.api_key {
-            req = req.header("Authorization", format!("Bearer {}", k));
-        }
-
-        let response = req
-            .send()
-            .await
-            .context("Failed to send request to embedding API")?;
+        let (processed_text, _) = self.prepare_text(text);
+        let embedding = self.client.embed(&[processed_text]).await?.remove(0);
+        self.remember_dimension(&embedding);
+        Ok(embedding)
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Embedding API error: {}", error_text);
-        }
+    /// 批量向量化：按分片数与 token 预算分批，最多 concurrency 个请求同时进行。
+    /// 结果与 texts 一一对应，失败的分片对应 Err
+    pub async fn vectorize_texts(
+        &mut self,
+        texts: &[String],
+        config: &EmbeddingBatchConfig,
+    ) -> Vec<Result<Vec<f32>>> {
+        let prepared: Vec<(String, usize)> = texts.iter().map(|t| self.prepare_text(t)).collect();
+        let token_counts: Vec<usize> = prepared.iter().map(|(_, tokens)| *tokens).collect();
+        let mut batches = plan_batches(&token_counts, config.batch_size, config.max_batch_tokens).into_iter();
+        log::debug!(
+            "批量向量化 {} 个分片，分为 {} 个请求，并发 {}",
+            texts.len(),
+            batches.len(),
+            config.concurrency
+        );
 
-        let embedding = if is_ollama {
-            // 解析 Ollama 响应
-            let embedding_response: OllamaEmbeddingResponse = response
-                .json()
-                .await
-                .context("Failed to parse Ollama embedding API response")?;
+        let mut results: Vec<Option<Result<Vec<f32>>>> = texts.iter().map(|_| None).collect();
+        let mut tasks = JoinSet::new();
+        loop {
+            while tasks.len() < config.concurrency.max(1) {
+                let Some(batch) = batches.next() else { break };
+                let client = self.client.clone();
+                let inputs: Vec<String> = batch.iter().map(|&i| prepared[i].0.clone()).collect();
+                tasks.spawn(async move {
+                    let embeddings = client.embed_batch(inputs).await;
+                    (batch, embeddings)
+                });
+            }
 
-            if embedding_response.embeddings.is_empty() {
-                anyhow::bail!("No embeddings returned from Ollama API");
+            let Some(joined) = tasks.join_next().await else { break };
+            match joined {
+                Ok((batch, embeddings)) => {
+                    for (i, embedding) in batch.into_iter().zip(embeddings) {
+                        results[i] = Some(embedding);
+                    }
+                }
+                Err(e) => log::error!("向量化任务异常退出: {}", e),
             }
+        }
 
-            embedding_response.embeddings[0].clone()
-        } else {
-            // 解析 OpenAI 响应
-            let embedding_response: OpenAIEmbeddingResponse = response
-                .json()
-                .await
-                .context("Failed to parse OpenAI embedding API response")?;
+        let results: Vec<Result<Vec<f32>>> = results
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(anyhow::anyhow!("Embedding task aborted"))))
+            .collect();
+        if let Some(Ok(embedding)) = results.iter().find(|r| r.is_ok()) {
+            self.remember_dimension(embedding);
+        }
+        results
+    }
 
-            if embedding_response.data.is_empty() {
-                anyhow::bail!("No embeddings returned from OpenAI API");
-            }
+    /// 使用统一的 token 限制配置截断文本，返回文本及其 token 数
+    fn prepare_text(&self, text: &str) -> (String, usize) {
+        let tokens = self.tokenizer.encode_with_special_tokens(text);
+        if tokens.len() <= MAX_CHUNK_TOKENS {
+            return (text.to_string(), tokens.len());
+        }
 
-            embedding_response.data[0].embedding.clone()
-        };
+        log::warn!(
+            "文本过长 ({} tokens)，按 token 截断到 {} tokens",
+            tokens.len(),
+            MAX_CHUNK_TOKENS
+        );
+        let preview = text.chars().take(120).collect::<String>();
+        log::debug!("原文本预览(120)：{}", preview);
+        let clipped = &tokens[..MAX_CHUNK_TOKENS];
+        // 将截断后的 token 反解码为字符串
+        let clipped_text = self.tokenizer.decode(clipped.to_vec())
+            .unwrap_or_else(|_| text.chars().take(1000).collect::<String>());
+        (clipped_text, MAX_CHUNK_TOKENS)
+    }
 
-        // 首次调用时检测并缓存维度
+    /// 首次调用时检测并缓存维度
+    fn remember_dimension(&mut self, embedding: &[f32]) {
         if self.embedding_dimension.is_none() {
             let detected_dimension = embedding.len();
             log::info!("检测到向量维度: {}", detected_dimension);
             self.embedding_dimension = Some(detected_dimension);
         }
-
-        Ok(embedding)
     }
 
 
@@ -172,3 +292,65 @@ impl TextVectorizer {
         Ok(test_embedding.len())
     }
 }
+
+/// 按顺序把分片分成请求：每批不超过 max_items 个分片、max_tokens 个 token；
+/// 单个分片超过 token 预算时独占一批
+fn plan_batches(token_counts: &[usize], max_items: usize, max_tokens: usize) -> Vec<Vec<usize>> {
+    let mut batches = Vec::new();
+    let mut current = Vec::new();
+    let mut tokens = 0;
+
+    for (i, &count) in token_counts.iter().enumerate() {
+        if !current.is_empty() && (current.len() >= max_items.max(1) || tokens + count > max_tokens) {
+            batches.push(std::mem::take(&mut current));
+            tokens = 0;
+        }
+        current.push(i);
+        tokens += count;
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_batches_item_limit() {
+        assert_eq!(plan_batches(&[1; 5], 2, 100), vec![vec![0, 1], vec![2, 3], vec![4]]);
+        assert_eq!(plan_batches(&[1; 3], 3, 100), vec![vec![0, 1, 2]]);
+        // max_items 为 0 时按 1 处理
+        assert_eq!(plan_batches(&[1; 2], 0, 100), vec![vec![0], vec![1]]);
+        assert!(plan_batches(&[], 4, 100).is_empty());
+    }
+
+    #[test]
+    fn test_plan_batches_token_budget() {
+        // 恰好等于预算时仍在同一批
+        assert_eq!(plan_batches(&[40, 60, 1], 10, 100), vec![vec![0, 1], vec![2]]);
+        assert_eq!(plan_batches(&[40, 61, 30, 30], 10, 100), vec![vec![0], vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn test_plan_batches_oversized_item_gets_own_batch() {
+        assert_eq!(plan_batches(&[500], 10, 100), vec![vec![0]]);
+        assert_eq!(
+            plan_batches(&[10, 500, 10, 20], 10, 100),
+            vec![vec![0], vec![1], vec![2, 3]]
+        );
+        assert_eq!(plan_batches(&[200, 300], 10, 100), vec![vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn test_plan_batches_keeps_order() {
+        let counts = [30, 80, 5, 5, 5, 90, 10];
+        let batches = plan_batches(&counts, 3, 100);
+        assert_eq!(batches, vec![vec![0], vec![1, 2, 3], vec![4, 5], vec![6]]);
+        let flattened: Vec<usize> = batches.concat();
+        assert_eq!(flattened, (0..counts.len()).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,388 @@
+use super::koreader::{self, KoAnnotation, Sidecar};
+use super::kobo;
+use super::library::{self, apply_progress, get_notes, insert_notes, LibraryIndex};
+use super::models::*;
+use super::{reanchor, readwise, web_annotation};
+use crate::core::books::models::BookNote;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_epub::cfi::{Cfi, EpubLocator};
+
+/// 从 KOReader sidecar 导入标注与阅读进度。
+/// `path` 可以是单个 `metadata.*.lua` 文件，也可以是包含 `.sdr` 目录的文件夹（如设备根目录）。
+#[tauri::command]
+pub async fn import_koreader_annotations(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<AnnotationImportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let mut library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+    let mut report = AnnotationImportReport::new("koreader");
+
+    let sidecars = koreader::find_sidecars(Path::new(&path));
+    if sidecars.is_empty() {
+        return Err(format!("未找到 KOReader 标注文件: {}", path));
+    }
+
+    for sidecar_path in sidecars {
+        let sidecar = match Sidecar::read(&sidecar_path) {
+            Ok(sidecar) => sidecar,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+
+        let Some(book) = library.find(sidecar.partial_md5(), &sidecar.title(), &sidecar.authors())
+        else {
+            report.unmatched_books.push(sidecar.title());
+            continue;
+        };
+        report.matched_books += 1;
+
+        // EpubLocator 不跨 await 持有
+        let converted = EpubLocator::open(&book.file_path).map(|mut locator| {
+            let notes: Vec<ImportedNote> = sidecar
+                .annotations()
+                .iter()
+                .filter_map(|a| koreader::to_imported(a, &mut locator, &mut report))
+                .collect();
+            (notes, sidecar.progress(Some(&mut locator)))
+        });
+        let (notes, progress) = match converted {
+            Ok(result) => result,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", book.title, e));
+                (Vec::new(), sidecar.progress(None))
+            }
+        };
+
+        insert_notes(&db_pool, &book.id, notes, &mut report).await?;
+        apply_progress(&db_pool, &book.id, &progress, &mut report).await?;
+    }
+
+    Ok(report)
+}
+
+/// 从挂载的 Kobo 设备数据库（`.kobo/KoboReader.sqlite`）导入标注与阅读进度
+#[tauri::command]
+pub async fn import_kobo_annotations(
+    app_handle: AppHandle,
+    db_path: String,
+) -> Result<AnnotationImportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let mut library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+    let mut report = AnnotationImportReport::new("kobo");
+
+    let db_path = PathBuf::from(&db_path);
+    let kobo_pool = kobo::open(&db_path).await?;
+    // 设备挂载根目录：<mount>/.kobo/KoboReader.sqlite
+    let mount_root = db_path
+        .parent()
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf);
+
+    for kobo_book in kobo::books(&kobo_pool).await? {
+        let bookmarks = kobo::bookmarks(&kobo_pool, &kobo_book.volume_id).await?;
+        if bookmarks.is_empty() && kobo_book.read_status == 0 {
+            continue;
+        }
+
+        let md5 = mount_root
+            .as_deref()
+            .and_then(|root| kobo::device_file(root, &kobo_book.volume_id))
+            .and_then(|file| library::partial_md5(&file));
+        let Some(book) = library.find(md5.as_deref(), &kobo_book.title, &kobo_book.author) else {
+            report.unmatched_books.push(kobo_book.title.clone());
+            continue;
+        };
+        report.matched_books += 1;
+
+        let converted = EpubLocator::open(&book.file_path).map(|mut locator| {
+            let notes: Vec<ImportedNote> = bookmarks
+                .iter()
+                .filter_map(|b| kobo::to_imported(b, &mut locator, &mut report))
+                .collect();
+            (notes, kobo::progress(&kobo_book, Some(&mut locator)))
+        });
+        let (notes, progress) = match converted {
+            Ok(result) => result,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", book.title, e));
+                (Vec::new(), kobo::progress(&kobo_book, None))
+            }
+        };
+
+        insert_notes(&db_pool, &book.id, notes, &mut report).await?;
+        apply_progress(&db_pool, &book.id, &progress, &mut report).await?;
+    }
+
+    kobo_pool.close().await;
+    Ok(report)
+}
+
+/// 把书库中的标注写成 KOReader sidecar。
+/// 目标目录中已有同一本书的 sidecar 时合并写回；有同一本书的 EPUB 时写到它旁边；
+/// 否则在目标目录下新建 `<书名>.sdr/metadata.epub.lua`。
+#[tauri::command]
+pub async fn export_koreader_annotations(
+    app_handle: AppHandle,
+    target_dir: String,
+    book_ids: Option<Vec<String>>,
+) -> Result<KoreaderExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let mut library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+    let mut report = KoreaderExportReport::default();
+
+    let target = PathBuf::from(&target_dir);
+    std::fs::create_dir_all(&target).map_err(|e| format!("创建目录失败: {}", e))?;
+
+    let mut sidecars: Vec<Sidecar> = koreader::find_sidecars(&target)
+        .iter()
+        .filter_map(|path| match Sidecar::read(path) {
+            Ok(sidecar) => Some(sidecar),
+            Err(e) => {
+                report.errors.push(e);
+                None
+            }
+        })
+        .collect();
+    let epubs: Vec<(PathBuf, Option<String>)> = koreader::find_epubs(&target)
+        .into_iter()
+        .map(|path| {
+            let md5 = library::partial_md5(&path);
+            (path, md5)
+        })
+        .collect();
+
+    let books: Vec<LibraryBook> = library
+        .books()
+        .iter()
+        .filter(|b| book_ids.as_ref().is_none_or(|ids| ids.contains(&b.id)))
+        .cloned()
+        .collect();
+
+    for book in books {
+        let notes = get_notes(&db_pool, &book.id).await?;
+        if notes.is_empty() {
+            continue;
+        }
+        let md5 = library.md5_of(&book.id);
+
+        let existing = sidecars.iter().position(|s| {
+            (md5.is_some() && s.partial_md5() == md5.as_deref())
+                || (library::normalize(&s.title()) == library::normalize(&book.title)
+                    && library::authors_match(&s.authors(), &book.author))
+        });
+        let mut sidecar = match existing {
+            Some(index) => sidecars.swap_remove(index),
+            None => {
+                let path = epubs
+                    .iter()
+                    .find(|(_, epub_md5)| md5.is_some() && *epub_md5 == md5)
+                    .map(|(path, _)| path.with_extension("sdr"))
+                    .unwrap_or_else(|| target.join(format!("{}.sdr", sanitize_file_name(&book.title))))
+                    .join("metadata.epub.lua");
+                Sidecar::create(path, &book, md5.clone())
+            }
+        };
+
+        let mut locator = match EpubLocator::open(&book.file_path) {
+            Ok(locator) => locator,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", book.title, e));
+                report.skipped_notes += notes.len() as i64;
+                continue;
+            }
+        };
+
+        let mut incoming: Vec<KoAnnotation> = Vec::new();
+        for note in &notes {
+            match koreader::from_book_note(note, &mut locator) {
+                Ok(annotation) => incoming.push(annotation),
+                Err(e) => {
+                    report.errors.push(e);
+                    report.skipped_notes += 1;
+                }
+            }
+        }
+
+        let (merged, added) =
+            koreader::merge_annotations(sidecar.annotations(), incoming, &mut locator);
+        sidecar.set_annotations(&merged);
+        sidecar.write()?;
+
+        report.exported_notes += added as i64;
+        report.written_files.push(sidecar.path.display().to_string());
+    }
+
+    Ok(report)
+}
+
+/// 导出为 Readwise CSV（Highlight, Book Title, Book Author, Note, Location, Date）
+#[tauri::command]
+pub async fn export_readwise_csv(
+    app_handle: AppHandle,
+    file_path: String,
+    book_ids: Option<Vec<String>>,
+) -> Result<AnnotationExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+
+    let entries = collect_notes(&db_pool, &library, book_ids.as_deref()).await?;
+    let (csv, count) = readwise::to_csv(&entries);
+    std::fs::write(&file_path, csv).map_err(|e| format!("写入文件失败: {}", e))?;
+
+    Ok(AnnotationExportReport {
+        file_path,
+        exported_notes: count as i64,
+    })
+}
+
+/// 导出为 W3C Web Annotation（JSON-LD AnnotationCollection）
+#[tauri::command]
+pub async fn export_web_annotations(
+    app_handle: AppHandle,
+    file_path: String,
+    book_ids: Option<Vec<String>>,
+) -> Result<AnnotationExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+
+    let entries = collect_notes(&db_pool, &library, book_ids.as_deref()).await?;
+    let (collection, count) = web_annotation::to_collection(&entries);
+    let content = serde_json::to_string_pretty(&collection)
+        .map_err(|e| format!("序列化标注失败: {}", e))?;
+    std::fs::write(&file_path, content).map_err(|e| format!("写入文件失败: {}", e))?;
+
+    Ok(AnnotationExportReport {
+        file_path,
+        exported_notes: count as i64,
+    })
+}
+
+/// 导入 W3C Web Annotation JSON-LD；保留原 id、时间戳与上下文，可与导出往返
+#[tauri::command]
+pub async fn import_web_annotations(
+    app_handle: AppHandle,
+    file_path: String,
+) -> Result<AnnotationImportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let app_data_dir = get_app_data_dir(&app_handle)?;
+    let mut library = LibraryIndex::load(&db_pool, &app_data_dir).await?;
+    let mut report = AnnotationImportReport::new("web-annotation");
+
+    let content =
+        std::fs::read_to_string(&file_path).map_err(|e| format!("读取文件失败: {}", e))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("解析 JSON 失败: {}", e))?;
+    let (annotations, errors) = web_annotation::from_json(&value);
+    report.errors.extend(errors);
+
+    // 按书分组：优先用导出时的书籍 id，其次用书名/作者匹配
+    let mut grouped: Vec<(String, Vec<ImportedNote>)> = Vec::new();
+    for annotation in annotations {
+        let book_id = annotation
+            .book_id
+            .as_deref()
+            .and_then(|id| library.get(id))
+            .map(|b| b.id.clone())
+            .or_else(|| {
+                library
+                    .find(None, &annotation.book_title, &annotation.book_author)
+                    .map(|b| b.id)
+            });
+        let Some(book_id) = book_id else {
+            if !report.unmatched_books.contains(&annotation.book_title) {
+                report.unmatched_books.push(annotation.book_title);
+            }
+            continue;
+        };
+
+        match grouped.iter_mut().find(|(id, _)| *id == book_id) {
+            Some((_, notes)) => notes.push(annotation.note),
+            None => grouped.push((book_id, vec![annotation.note])),
+        }
+    }
+
+    report.matched_books = grouped.len() as i64;
+    for (book_id, notes) in grouped {
+        insert_notes(&db_pool, &book_id, notes, &mut report).await?;
+    }
+
+    Ok(report)
+}
+
+/// 用标注保存的引文与上下文重新定位一本书的全部标注，修复 CFI 漂移
+#[tauri::command]
+pub async fn reanchor_book_notes(
+    app_handle: AppHandle,
+    book_id: String,
+) -> Result<ReanchorReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let file_path: Option<String> = sqlx::query_scalar("SELECT file_path FROM books WHERE id = ?")
+        .bind(&book_id)
+        .fetch_optional(&db_pool)
+        .await
+        .map_err(|e| format!("查询书籍失败: {}", e))?;
+    let file_path = file_path.ok_or_else(|| "书籍不存在".to_string())?;
+    let epub_path = get_app_data_dir(&app_handle)?.join(file_path);
+
+    reanchor::reanchor_book(&db_pool, &book_id, &epub_path).await
+}
+
+/// 按书收集笔记，书内按 CFI 先后排序
+async fn collect_notes(
+    db_pool: &SqlitePool,
+    library: &LibraryIndex,
+    book_ids: Option<&[String]>,
+) -> Result<Vec<(LibraryBook, Vec<BookNote>)>, String> {
+    let mut entries = Vec::new();
+    for book in library.books() {
+        if book_ids.is_some_and(|ids| !ids.contains(&book.id)) {
+            continue;
+        }
+        let mut notes = get_notes(db_pool, &book.id).await?;
+        if notes.is_empty() {
+            continue;
+        }
+        notes.sort_by_cached_key(|n| n.cfi.parse::<Cfi>().map(|c| c.sort_key()).unwrap_or_default());
+        entries.push((book.clone(), notes));
+    }
+    Ok(entries)
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let trimmed = cleaned.trim().trim_matches('.');
+    if trimmed.is_empty() {
+        "untitled".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn get_app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("获取应用目录失败: {}", e))
+}
+
+async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
+    let db_path = get_app_data_dir(app_handle)?.join("database").join("app.db");
+    let db_url = format!("sqlite:{}", db_path.display());
+
+    SqlitePool::connect(&db_url)
+        .await
+        .map_err(|e| format!("数据库连接失败: {}", e))
+}
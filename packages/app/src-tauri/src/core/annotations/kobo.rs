@@ -0,0 +1,268 @@
+use super::models::*;
+use sqlx::{Row, SqlitePool};
+use std::path::{Path, PathBuf};
+use tauri_plugin_epub::cfi::{parse_steps, EpubLocator, TextPosition};
+
+/// Kobo 设备上的一本书（content 表中 ContentType = 6 的记录）
+#[derive(Debug, Clone)]
+pub struct KoboBook {
+    pub volume_id: String,
+    pub title: String,
+    pub author: String,
+    pub percent_read: f64,
+    pub read_status: i64, // 0 未读 / 1 在读 / 2 读完
+    pub last_read_at: Option<i64>,
+    pub chapter_bookmarked: Option<String>,
+}
+
+/// Bookmark 表中的一条高亮/笔记/书签
+#[derive(Debug, Clone)]
+pub struct KoboBookmark {
+    pub content_id: String,
+    pub start_path: Option<String>,
+    pub start_offset: i64,
+    pub end_path: Option<String>,
+    pub end_offset: i64,
+    pub text: Option<String>,
+    pub annotation: Option<String>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub kind: Option<String>, // highlight | note | dogear
+    pub color: Option<i64>,
+}
+
+/// 以只读方式打开 KoboReader.sqlite
+pub async fn open(db_path: &Path) -> Result<SqlitePool, String> {
+    if !db_path.is_file() {
+        return Err(format!("找不到 Kobo 数据库: {}", db_path.display()));
+    }
+    let db_url = format!("sqlite:{}?mode=ro", db_path.display());
+    SqlitePool::connect(&db_url)
+        .await
+        .map_err(|e| format!("打开 Kobo 数据库失败: {}", e))
+}
+
+pub async fn books(pool: &SqlitePool) -> Result<Vec<KoboBook>, String> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ContentID, Title, Attribution, ___PercentRead, ReadStatus, DateLastRead, ChapterIDBookmarked
+        FROM content
+        WHERE ContentType = 6
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("查询 Kobo 书籍失败: {}", e))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| KoboBook {
+            volume_id: row.try_get("ContentID").unwrap_or_default(),
+            title: row.try_get::<Option<String>, _>("Title").ok().flatten().unwrap_or_default(),
+            author: row
+                .try_get::<Option<String>, _>("Attribution")
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+            percent_read: row
+                .try_get::<Option<i64>, _>("___PercentRead")
+                .ok()
+                .flatten()
+                .unwrap_or(0) as f64,
+            read_status: row.try_get::<Option<i64>, _>("ReadStatus").ok().flatten().unwrap_or(0),
+            last_read_at: row
+                .try_get::<Option<String>, _>("DateLastRead")
+                .ok()
+                .flatten()
+                .and_then(|d| parse_timestamp(&d)),
+            chapter_bookmarked: row
+                .try_get::<Option<String>, _>("ChapterIDBookmarked")
+                .ok()
+                .flatten(),
+        })
+        .collect())
+}
+
+pub async fn bookmarks(pool: &SqlitePool, volume_id: &str) -> Result<Vec<KoboBookmark>, String> {
+    // 旧固件没有 Type / Color 列，按需补 NULL
+    let columns: Vec<String> = sqlx::query("PRAGMA table_info(Bookmark)")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("读取 Kobo 表结构失败: {}", e))?
+        .iter()
+        .map(|row| row.get::<String, _>("name"))
+        .collect();
+    let optional = |name: &str| {
+        if columns.iter().any(|c| c == name) {
+            name.to_string()
+        } else {
+            format!("NULL AS {}", name)
+        }
+    };
+
+    let query = format!(
+        r#"
+        SELECT ContentID, StartContainerPath, StartOffset, EndContainerPath, EndOffset,
+               Text, Annotation, DateCreated, DateModified, {}, {}
+        FROM Bookmark
+        WHERE VolumeID = ? AND (Hidden IS NULL OR Hidden = 'false' OR Hidden = 0)
+        ORDER BY DateCreated ASC
+        "#,
+        optional("Type"),
+        optional("Color")
+    );
+
+    let rows = sqlx::query(&query)
+        .bind(volume_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("查询 Kobo 标注失败: {}", e))?;
+
+    let text = |row: &sqlx::sqlite::SqliteRow, name: &str| {
+        row.try_get::<Option<String>, _>(name).ok().flatten()
+    };
+    Ok(rows
+        .iter()
+        .map(|row| KoboBookmark {
+            content_id: text(row, "ContentID").unwrap_or_default(),
+            start_path: text(row, "StartContainerPath"),
+            start_offset: row.try_get::<Option<i64>, _>("StartOffset").ok().flatten().unwrap_or(0),
+            end_path: text(row, "EndContainerPath"),
+            end_offset: row.try_get::<Option<i64>, _>("EndOffset").ok().flatten().unwrap_or(0),
+            text: text(row, "Text").filter(|t| !t.trim().is_empty()),
+            annotation: text(row, "Annotation").filter(|t| !t.trim().is_empty()),
+            created_at: text(row, "DateCreated").and_then(|d| parse_timestamp(&d)),
+            updated_at: text(row, "DateModified").and_then(|d| parse_timestamp(&d)),
+            kind: text(row, "Type"),
+            color: row.try_get::<Option<i64>, _>("Color").ok().flatten(),
+        })
+        .collect())
+}
+
+/// 由 VolumeID（`file:///mnt/onboard/...`）推出书在挂载目录下的实际路径
+pub fn device_file(mount_root: &Path, volume_id: &str) -> Option<PathBuf> {
+    let relative = volume_id
+        .strip_prefix("file:///mnt/onboard/")
+        .or_else(|| volume_id.strip_prefix("/mnt/onboard/"))?;
+    let path = mount_root.join(relative);
+    path.is_file().then_some(path)
+}
+
+pub fn progress(book: &KoboBook, locator: Option<&mut EpubLocator>) -> ImportedProgress {
+    let status = match book.read_status {
+        2 => Some("completed".to_string()),
+        1 => Some("reading".to_string()),
+        _ => None,
+    };
+    let location = match (locator, book.chapter_bookmarked.as_deref()) {
+        (Some(locator), Some(chapter)) => {
+            chapter_href(chapter).and_then(|href| locator.spine_index_for_href(href)).map(|i| locator.chapter_cfi(i))
+        }
+        _ => None,
+    };
+
+    ImportedProgress {
+        percent: Some(if book.read_status == 2 { 1.0 } else { book.percent_read / 100.0 }),
+        status,
+        location,
+        last_read_at: book.last_read_at,
+    }
+}
+
+/// 把一条 Kobo 标注换算为本地笔记
+pub fn to_imported(
+    bookmark: &KoboBookmark,
+    locator: &mut EpubLocator,
+    report: &mut AnnotationImportReport,
+) -> Option<ImportedNote> {
+    let spine_index = chapter_href(&bookmark.content_id).and_then(|href| locator.spine_index_for_href(href))?;
+    let is_bookmark = bookmark.kind.as_deref() == Some("dogear") || bookmark.text.is_none();
+
+    let start = resolve(locator, spine_index, bookmark.start_path.as_deref(), bookmark.start_offset);
+    let end = resolve(locator, spine_index, bookmark.end_path.as_deref(), bookmark.end_offset);
+    let cfi = match (start, end) {
+        (Some(start), Some(end)) => locator.range_cfi(spine_index, start, end).ok(),
+        (Some(start), None) => locator.range_cfi(spine_index, start, start).ok(),
+        _ => None,
+    }
+    .or_else(|| {
+        // kepub 的 `kobo.x.y` span 在原始 EPUB 中不存在，按原文查找
+        let text = bookmark.text.as_deref()?;
+        locator.find_text_cfi(spine_index, text).ok().flatten()
+    })
+    .unwrap_or_else(|| {
+        report.approximate_locations += 1;
+        locator.chapter_cfi(spine_index)
+    });
+
+    let created_at = bookmark
+        .created_at
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    Some(ImportedNote {
+        r#type: if is_bookmark { "bookmark" } else { "annotation" }.to_string(),
+        cfi,
+        text: if is_bookmark { None } else { bookmark.text.clone() },
+        style: (!is_bookmark).then(|| "highlight".to_string()),
+        color: (!is_bookmark).then(|| color_name(bookmark.color).to_string()),
+        note: bookmark.annotation.clone().unwrap_or_default(),
+        created_at,
+        updated_at: bookmark.updated_at.unwrap_or(created_at),
+        ..Default::default()
+    })
+}
+
+/// 从 ContentID 中取出章节资源路径，兼容
+/// `file:///...epub!OEBPS!Text/ch1.xhtml`、`...!!OEBPS/ch1.xhtml` 与 `book.epub#(3)OEBPS/ch1.xhtml`
+fn chapter_href(content_id: &str) -> Option<&str> {
+    let tail = if let Some(index) = content_id.rfind(')') {
+        content_id.get(index + 1..)?
+    } else {
+        content_id.rsplit('!').next()?
+    };
+    let tail = tail.split('#').next().unwrap_or(tail);
+    (!tail.is_empty()).then_some(tail)
+}
+
+/// 解析 StartContainerPath / EndContainerPath
+fn resolve(
+    locator: &mut EpubLocator,
+    spine_index: usize,
+    path: Option<&str>,
+    offset: i64,
+) -> Option<TextPosition> {
+    let path = path?.trim();
+    let offset = offset.max(0) as usize;
+    let document = locator.content(spine_index).ok()?;
+
+    if let Some(id) = path.strip_prefix("span#") {
+        // kepub 的 span id，点号经过转义：span#kobo\.12\.3
+        return document.resolve_element_id(&id.replace('\\', ""), offset).ok();
+    }
+
+    // `point(/1/4/2/6/1:12)`：/1 是文档根，之后的部分与 CFI 相同
+    let inner = path.strip_prefix("point(")?.strip_suffix(')')?;
+    let inner = inner.strip_prefix("/1").unwrap_or(inner);
+    let steps = parse_steps(inner).ok()?;
+    document.resolve_steps(&steps).ok()
+}
+
+fn color_name(color: Option<i64>) -> &'static str {
+    match color {
+        Some(1) => "red",
+        Some(2) => "blue",
+        Some(3) => "green",
+        _ => "yellow",
+    }
+}
+
+/// Kobo 的时间戳是 UTC，格式随固件不同（带或不带毫秒/时区）
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(dt.timestamp_millis());
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), format).ok())
+        .map(|naive| naive.and_utc().timestamp_millis())
+}
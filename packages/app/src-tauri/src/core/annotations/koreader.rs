@@ -0,0 +1,424 @@
+use super::lua::{self, LuaValue};
+use super::models::*;
+use crate::core::books::models::BookNote;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use std::path::{Path, PathBuf};
+use tauri_plugin_epub::cfi::{EpubLocator, XPointer};
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// sidecar 中的一条标注（统一新版 annotations 与旧版 highlight/bookmarks 格式）
+#[derive(Debug, Clone, Default)]
+pub struct KoAnnotation {
+    pub text: Option<String>,
+    pub note: Option<String>,
+    pub pos0: Option<String>,
+    pub pos1: Option<String>,
+    pub page: Option<String>, // 书签位置（XPointer）
+    pub chapter: Option<String>,
+    pub datetime: Option<String>,
+    pub datetime_updated: Option<String>,
+    pub drawer: Option<String>,
+    pub color: Option<String>,
+}
+
+impl KoAnnotation {
+    fn from_lua(value: &LuaValue) -> Self {
+        let string = |key: &str| value.get_str(key).map(|s| s.to_string());
+        Self {
+            text: string("text"),
+            note: string("note"),
+            pos0: string("pos0"),
+            pos1: string("pos1"),
+            page: string("page"),
+            chapter: string("chapter"),
+            datetime: string("datetime"),
+            datetime_updated: string("datetime_updated"),
+            drawer: string("drawer"),
+            color: string("color"),
+        }
+    }
+
+    fn to_lua(&self) -> LuaValue {
+        let string = |v: &Option<String>| v.clone().map(LuaValue::String).unwrap_or(LuaValue::Nil);
+        LuaValue::table(vec![
+            ("chapter", string(&self.chapter)),
+            ("color", string(&self.color)),
+            ("datetime", string(&self.datetime)),
+            ("datetime_updated", string(&self.datetime_updated)),
+            ("drawer", string(&self.drawer)),
+            ("note", string(&self.note)),
+            ("page", string(&self.page)),
+            ("pos0", string(&self.pos0)),
+            ("pos1", string(&self.pos1)),
+            ("text", string(&self.text)),
+        ])
+    }
+
+    fn is_bookmark(&self) -> bool {
+        self.pos0.is_none()
+    }
+
+    /// 判断两条标注是否指向同一处
+    fn same_as(&self, other: &KoAnnotation) -> bool {
+        if self.is_bookmark() != other.is_bookmark() {
+            return false;
+        }
+        if self.is_bookmark() {
+            return self.page.is_some() && self.page == other.page;
+        }
+        (self.pos0 == other.pos0 && self.pos1 == other.pos1)
+            || (self.text.is_some() && self.text == other.text)
+    }
+}
+
+/// 一个 KOReader sidecar 文件（`*.sdr/metadata.*.lua`）
+pub struct Sidecar {
+    pub path: PathBuf,
+    pub data: LuaValue,
+}
+
+impl Sidecar {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("读取文件失败 {}: {}", path.display(), e))?;
+        let data = lua::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            data,
+        })
+    }
+
+    /// 为尚不存在 sidecar 的书创建一个新的
+    pub fn create(path: PathBuf, book: &LibraryBook, md5: Option<String>) -> Self {
+        let doc_props = LuaValue::table(vec![
+            ("title", LuaValue::string(&book.title)),
+            ("authors", LuaValue::string(&book.author)),
+        ]);
+        let data = LuaValue::table(vec![
+            ("doc_props", doc_props),
+            (
+                "partial_md5_checksum",
+                md5.map(LuaValue::String).unwrap_or(LuaValue::Nil),
+            ),
+        ]);
+        Self { path, data }
+    }
+
+    pub fn write(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        }
+        std::fs::write(&self.path, lua::serialize(&self.data))
+            .map_err(|e| format!("写入文件失败 {}: {}", self.path.display(), e))
+    }
+
+    pub fn title(&self) -> String {
+        self.data
+            .get("doc_props")
+            .and_then(|p| p.get_str("title"))
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| {
+                // 没有元数据时退回到 `<书名>.sdr` 目录名
+                self.path
+                    .parent()
+                    .and_then(|p| p.file_stem())
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+    }
+
+    pub fn authors(&self) -> String {
+        self.data
+            .get("doc_props")
+            .and_then(|p| p.get_str("authors"))
+            .map(|s| s.replace('\n', ", "))
+            .unwrap_or_default()
+    }
+
+    pub fn partial_md5(&self) -> Option<&str> {
+        self.data.get_str("partial_md5_checksum")
+    }
+
+    /// 读出全部标注，兼容旧版 highlight + bookmarks 结构
+    pub fn annotations(&self) -> Vec<KoAnnotation> {
+        if let Some(annotations) = self.data.get("annotations") {
+            return annotations.values().into_iter().map(KoAnnotation::from_lua).collect();
+        }
+
+        let mut result: Vec<KoAnnotation> = Vec::new();
+        if let Some(highlight) = self.data.get("highlight") {
+            for page in highlight.values() {
+                for item in page.values() {
+                    result.push(KoAnnotation::from_lua(item));
+                }
+            }
+        }
+
+        if let Some(bookmarks) = self.data.get("bookmarks") {
+            for bookmark in bookmarks.values() {
+                let text = bookmark.get_str("text");
+                if bookmark.get_bool("highlighted") == Some(true) {
+                    // 旧版把用户笔记放在书签的 text 里；自动生成的 text 会包含高亮原文
+                    let highlighted = bookmark.get_str("notes").unwrap_or_default();
+                    let note = text.filter(|t| !t.is_empty() && !t.contains(highlighted));
+                    let pos0 = bookmark.get_str("pos0");
+                    if let (Some(note), Some(item)) = (
+                        note,
+                        result.iter_mut().find(|h| h.pos0.as_deref() == pos0 && pos0.is_some()),
+                    ) {
+                        item.note = Some(note.to_string());
+                    }
+                } else {
+                    let mut item = KoAnnotation::from_lua(bookmark);
+                    item.text = None;
+                    item.pos0 = None;
+                    item.pos1 = None;
+                    result.push(item);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// 写回标注；旧版结构保留原样，KOReader 优先读取 annotations
+    pub fn set_annotations(&mut self, annotations: &[KoAnnotation]) {
+        let values = annotations.iter().map(KoAnnotation::to_lua).collect();
+        self.data.set("annotations", LuaValue::array(values));
+        self.data.set("annotations_externally_modified", LuaValue::Bool(true));
+    }
+
+    pub fn progress(&self, locator: Option<&mut EpubLocator>) -> ImportedProgress {
+        let summary = self.data.get("summary");
+        let status = summary.and_then(|s| s.get_str("status")).and_then(|s| match s {
+            "complete" | "finished" => Some("completed".to_string()),
+            "reading" | "abandoned" => Some("reading".to_string()),
+            _ => None,
+        });
+        let last_read_at = summary
+            .and_then(|s| s.get_str("modified"))
+            .and_then(|d| parse_datetime(&format!("{} 00:00:00", d)));
+        let location = match (locator, self.data.get_str("last_xpointer")) {
+            (Some(locator), Some(xpointer)) => locator.xpointer_to_cfi(xpointer, None).ok(),
+            _ => None,
+        };
+
+        ImportedProgress {
+            percent: self.data.get_f64("percent_finished"),
+            status,
+            location,
+            last_read_at,
+        }
+    }
+}
+
+/// 在目录下递归查找 sidecar 文件；传入文件时直接返回该文件
+pub fn find_sidecars(root: &Path) -> Vec<PathBuf> {
+    if root.is_file() {
+        return vec![root.to_path_buf()];
+    }
+
+    let mut result = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let in_sdr = dir
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("sdr"));
+            if in_sdr && name.starts_with("metadata.") && name.ends_with(".lua") {
+                result.push(path);
+            }
+        }
+    }
+    result.sort();
+    result
+}
+
+/// 在目录下递归查找 EPUB 文件（导出时用于把 sidecar 放到书旁边）
+pub fn find_epubs(root: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("sdr")) {
+                    pending.push(path);
+                }
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+            {
+                result.push(path);
+            }
+        }
+    }
+    result
+}
+
+/// 把一条 KOReader 标注换算为本地笔记；无 XPointer 的（如 PDF 页码）返回 None
+pub fn to_imported(
+    annotation: &KoAnnotation,
+    locator: &mut EpubLocator,
+    report: &mut AnnotationImportReport,
+) -> Option<ImportedNote> {
+    let anchor = annotation.pos0.as_deref().or(annotation.page.as_deref())?;
+    let spine_index = anchor.parse::<XPointer>().ok()?.spine_index();
+
+    let cfi = locator
+        .xpointer_to_cfi(anchor, annotation.pos1.as_deref())
+        .ok()
+        .or_else(|| {
+            let text = annotation.text.as_deref()?;
+            locator.find_text_cfi(spine_index, text).ok().flatten()
+        })
+        .unwrap_or_else(|| {
+            report.approximate_locations += 1;
+            locator.chapter_cfi(spine_index)
+        });
+
+    let created_at = annotation
+        .datetime
+        .as_deref()
+        .and_then(parse_datetime)
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let updated_at = annotation
+        .datetime_updated
+        .as_deref()
+        .and_then(parse_datetime)
+        .unwrap_or(created_at);
+
+    let is_bookmark = annotation.is_bookmark();
+    Some(ImportedNote {
+        r#type: if is_bookmark { "bookmark" } else { "annotation" }.to_string(),
+        cfi,
+        text: if is_bookmark { None } else { annotation.text.clone() },
+        style: (!is_bookmark).then(|| style_from_drawer(annotation.drawer.as_deref()).to_string()),
+        color: (!is_bookmark).then(|| color_from_koreader(annotation.color.as_deref()).to_string()),
+        note: annotation.note.clone().unwrap_or_default(),
+        created_at,
+        updated_at,
+        ..Default::default()
+    })
+}
+
+/// 把本地笔记换算为 KOReader 标注
+pub fn from_book_note(note: &BookNote, locator: &mut EpubLocator) -> Result<KoAnnotation, String> {
+    let (pos0, pos1) = locator
+        .cfi_to_xpointers(&note.cfi)
+        .map_err(|e| format!("无法定位 {}: {}", note.cfi, e))?;
+
+    let mut annotation = KoAnnotation {
+        page: Some(pos0.clone()),
+        datetime: Some(format_datetime(note.created_at)),
+        datetime_updated: (note.updated_at != note.created_at)
+            .then(|| format_datetime(note.updated_at)),
+        ..Default::default()
+    };
+    if note.r#type != "bookmark" {
+        annotation.pos0 = Some(pos0);
+        annotation.pos1 = Some(pos1);
+        annotation.text = note.text.clone();
+        annotation.note = Some(note.note.clone()).filter(|n| !n.is_empty());
+        annotation.drawer = Some(drawer_from_style(note.style.as_deref()).to_string());
+        annotation.color = Some(color_to_koreader(note.color.as_deref()).to_string());
+    }
+    Ok(annotation)
+}
+
+/// 合并标注：已有条目保留，新条目追加后按书中位置排序
+pub fn merge_annotations(
+    existing: Vec<KoAnnotation>,
+    incoming: Vec<KoAnnotation>,
+    locator: &mut EpubLocator,
+) -> (Vec<KoAnnotation>, usize) {
+    let mut merged = existing;
+    let mut added = 0;
+    for annotation in incoming {
+        if !merged.iter().any(|a| a.same_as(&annotation)) {
+            merged.push(annotation);
+            added += 1;
+        }
+    }
+
+    let mut keyed: Vec<((usize, usize, usize), KoAnnotation)> = merged
+        .into_iter()
+        .map(|a| {
+            let key = a
+                .pos0
+                .as_deref()
+                .or(a.page.as_deref())
+                .and_then(|xp| locator.xpointer_order(xp).ok())
+                .unwrap_or((usize::MAX, 0, 0));
+            (key, a)
+        })
+        .collect();
+    keyed.sort_by_key(|(key, _)| *key);
+    (keyed.into_iter().map(|(_, a)| a).collect(), added)
+}
+
+fn parse_datetime(value: &str) -> Option<i64> {
+    let naive = NaiveDateTime::parse_from_str(value.trim(), DATETIME_FORMAT).ok()?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.timestamp_millis())
+}
+
+fn format_datetime(millis: i64) -> String {
+    Local
+        .timestamp_millis_opt(millis)
+        .single()
+        .unwrap_or_else(Local::now)
+        .format(DATETIME_FORMAT)
+        .to_string()
+}
+
+fn style_from_drawer(drawer: Option<&str>) -> &'static str {
+    match drawer {
+        Some("underscore") => "underline",
+        Some("strikeout") => "squiggly",
+        _ => "highlight",
+    }
+}
+
+fn drawer_from_style(style: Option<&str>) -> &'static str {
+    match style {
+        Some("underline") => "underscore",
+        Some("squiggly") => "strikeout",
+        _ => "lighten",
+    }
+}
+
+fn color_from_koreader(color: Option<&str>) -> &'static str {
+    match color {
+        Some("red") => "red",
+        Some("green") | Some("olive") => "green",
+        Some("blue") | Some("cyan") => "blue",
+        Some("purple") => "violet",
+        _ => "yellow",
+    }
+}
+
+fn color_to_koreader(color: Option<&str>) -> &'static str {
+    match color {
+        Some("red") => "red",
+        Some("green") => "green",
+        Some("blue") => "blue",
+        Some("violet") => "purple",
+        _ => "yellow",
+    }
+}
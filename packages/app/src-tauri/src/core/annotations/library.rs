@@ -0,0 +1,278 @@
+use super::models::*;
+use crate::core::books::models::BookNote;
+use md5::{Digest, Md5};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// KOReader 的 partial MD5：依次在 0、1K、4K、16K…1G 处各读取 1KB 计算摘要
+pub fn partial_md5(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Md5::new();
+    let mut buffer = [0u8; 1024];
+
+    for i in -1i32..=10 {
+        let offset = if i < 0 { 0 } else { 1024u64 << (2 * i) };
+        if file.seek(SeekFrom::Start(offset)).is_err() {
+            break;
+        }
+        let mut read = 0;
+        while read < buffer.len() {
+            match file.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => return None,
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// 书名/作者比较前的归一化：小写、去掉标点与空白
+pub fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+pub fn authors_match(a: &str, b: &str) -> bool {
+    let a = normalize(a);
+    let b = normalize(b);
+    a.is_empty() || b.is_empty() || a.contains(&b) || b.contains(&a)
+}
+
+/// 书库索引：按内容摘要或书名/作者查找书籍
+pub struct LibraryIndex {
+    books: Vec<LibraryBook>,
+    md5_cache: HashMap<String, Option<String>>,
+}
+
+impl LibraryIndex {
+    pub async fn load(pool: &SqlitePool, app_data_dir: &Path) -> Result<Self, String> {
+        let rows = sqlx::query("SELECT id, title, author, file_path FROM books")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("查询书籍列表失败: {}", e))?;
+
+        let books = rows
+            .iter()
+            .map(|row| LibraryBook {
+                id: row.get("id"),
+                title: row.get("title"),
+                author: row.get("author"),
+                file_path: app_data_dir.join(row.get::<String, _>("file_path")),
+            })
+            .collect();
+
+        Ok(Self {
+            books,
+            md5_cache: HashMap::new(),
+        })
+    }
+
+    pub fn books(&self) -> &[LibraryBook] {
+        &self.books
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LibraryBook> {
+        self.books.iter().find(|b| b.id == id)
+    }
+
+    /// 书库文件的 partial MD5（带缓存）
+    pub fn md5_of(&mut self, id: &str) -> Option<String> {
+        if let Some(cached) = self.md5_cache.get(id) {
+            return cached.clone();
+        }
+        let digest = self.get(id).and_then(|b| partial_md5(&b.file_path));
+        self.md5_cache.insert(id.to_string(), digest.clone());
+        digest
+    }
+
+    /// 先按摘要匹配，失败后按书名 + 作者匹配（书名唯一时忽略作者）
+    pub fn find(&mut self, md5: Option<&str>, title: &str, author: &str) -> Option<LibraryBook> {
+        if let Some(md5) = md5.filter(|m| !m.is_empty()) {
+            let ids: Vec<String> = self.books.iter().map(|b| b.id.clone()).collect();
+            for id in ids {
+                if self.md5_of(&id).as_deref() == Some(md5) {
+                    return self.get(&id).cloned();
+                }
+            }
+        }
+
+        let title = normalize(title);
+        if title.is_empty() {
+            return None;
+        }
+        let candidates: Vec<&LibraryBook> = self
+            .books
+            .iter()
+            .filter(|b| normalize(&b.title) == title)
+            .collect();
+
+        candidates
+            .iter()
+            .find(|b| authors_match(&b.author, author))
+            .or_else(|| if candidates.len() == 1 { candidates.first() } else { None })
+            .map(|b| (*b).clone())
+    }
+}
+
+/// 写入导入的标注，跳过同一 id 或同一位置且文字相同的重复条目。
+/// 不能只按文字去重：书中重复出现的同一句话在不同位置各自是独立的标注
+pub async fn insert_notes(
+    pool: &SqlitePool,
+    book_id: &str,
+    notes: Vec<ImportedNote>,
+    report: &mut AnnotationImportReport,
+) -> Result<(), String> {
+    for note in notes {
+        let existing: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM book_notes WHERE id = ? OR (book_id = ? AND type = ? AND cfi = ? AND text IS ?)",
+        )
+        .bind(&note.id)
+        .bind(book_id)
+        .bind(&note.r#type)
+        .bind(&note.cfi)
+        .bind(&note.text)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+
+        if existing > 0 {
+            report.skipped_duplicates += 1;
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO book_notes (id, book_id, type, cfi, text, style, color, note, context_before, context_after, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(note.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()))
+        .bind(book_id)
+        .bind(&note.r#type)
+        .bind(&note.cfi)
+        .bind(&note.text)
+        .bind(&note.style)
+        .bind(&note.color)
+        .bind(&note.note)
+        .bind(&note.context_before)
+        .bind(&note.context_after)
+        .bind(note.created_at)
+        .bind(note.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("创建笔记失败: {}", e))?;
+
+        report.imported_notes += 1;
+    }
+
+    Ok(())
+}
+
+/// 合并阅读进度：只在外部进度更靠后（或已读完）时覆盖
+pub async fn apply_progress(
+    pool: &SqlitePool,
+    book_id: &str,
+    progress: &ImportedProgress,
+    report: &mut AnnotationImportReport,
+) -> Result<(), String> {
+    let Some(row) = sqlx::query(
+        "SELECT status, progress_current, progress_total FROM book_status WHERE book_id = ?",
+    )
+    .bind(book_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("查询书籍状态失败: {}", e))?
+    else {
+        return Ok(());
+    };
+
+    let current_status: String = row.get("status");
+    let current: i64 = row.try_get("progress_current").unwrap_or(0);
+    let total: i64 = row.try_get("progress_total").unwrap_or(0);
+    let current_ratio = if total > 0 { current as f64 / total as f64 } else { 0.0 };
+
+    let percent = progress.percent.unwrap_or(0.0).clamp(0.0, 1.0);
+    let completed = progress.status.as_deref() == Some("completed");
+    let moves_forward = percent > current_ratio + f64::EPSILON;
+    if !(moves_forward || completed && current_status != "completed") {
+        return Ok(());
+    }
+
+    let total = if total > 0 { total } else { 100 };
+    let new_current = if moves_forward {
+        (percent * total as f64).round() as i64
+    } else {
+        current
+    };
+    let status = match progress.status.as_deref() {
+        Some(status) => status.to_string(),
+        None if percent >= 1.0 => "completed".to_string(),
+        None if percent > 0.0 => "reading".to_string(),
+        None => current_status,
+    };
+    let now = chrono::Utc::now().timestamp_millis();
+    let last_read_at = progress.last_read_at.unwrap_or(now);
+
+    sqlx::query(
+        r#"
+        UPDATE book_status SET
+            status = ?,
+            progress_current = ?,
+            progress_total = ?,
+            location = COALESCE(?, location),
+            last_read_at = MAX(COALESCE(last_read_at, 0), ?),
+            started_at = COALESCE(started_at, ?),
+            completed_at = CASE WHEN ? = 'completed' THEN COALESCE(completed_at, ?) ELSE completed_at END,
+            updated_at = ?
+        WHERE book_id = ?
+        "#,
+    )
+    .bind(&status)
+    .bind(new_current)
+    .bind(total)
+    .bind(if moves_forward { progress.location.clone() } else { None })
+    .bind(last_read_at)
+    .bind(last_read_at)
+    .bind(&status)
+    .bind(last_read_at)
+    .bind(now)
+    .bind(book_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("更新书籍状态失败: {}", e))?;
+
+    report.updated_progress += 1;
+    Ok(())
+}
+
+/// 读取一本书的全部标注
+pub async fn get_notes(db_pool: &SqlitePool, book_id: &str) -> Result<Vec<BookNote>, String> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, anchor_status, created_at, updated_at
+        FROM book_notes
+        WHERE book_id = ?1
+        ORDER BY created_at ASC
+        "#,
+    )
+    .bind(book_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("查询笔记失败: {}", e))?;
+
+    rows.iter()
+        .map(BookNote::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))
+}
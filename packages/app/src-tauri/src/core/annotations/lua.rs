@@ -0,0 +1,600 @@
+// KOReader sidecar（metadata.*.lua）使用的 Lua 表子集：读取与写回
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaKey {
+    Index(i64),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(Vec<(LuaKey, LuaValue)>),
+}
+
+impl LuaValue {
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        match self {
+            LuaValue::Table(entries) => entries.iter().find_map(|(k, v)| match k {
+                LuaKey::Name(name) if name == key => Some(v),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(LuaValue::String(s)) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.get(key) {
+            Some(LuaValue::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key) {
+            Some(LuaValue::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// 表中的所有值（按数组下标排序，命名键保持原顺序）
+    pub fn values(&self) -> Vec<&LuaValue> {
+        match self {
+            LuaValue::Table(entries) => {
+                let mut items: Vec<&(LuaKey, LuaValue)> = entries.iter().collect();
+                items.sort_by_key(|(k, _)| match k {
+                    LuaKey::Index(i) => *i,
+                    LuaKey::Name(_) => i64::MAX,
+                });
+                items.into_iter().map(|(_, v)| v).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// 设置命名键（不存在则追加）
+    pub fn set(&mut self, key: &str, value: LuaValue) {
+        if let LuaValue::Table(entries) = self {
+            if let Some(entry) = entries
+                .iter_mut()
+                .find(|(k, _)| matches!(k, LuaKey::Name(name) if name == key))
+            {
+                entry.1 = value;
+            } else {
+                entries.push((LuaKey::Name(key.to_string()), value));
+            }
+        }
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        LuaValue::String(value.into())
+    }
+
+    /// 由 Vec 构建 Lua 数组（下标从 1 开始）
+    pub fn array(values: Vec<LuaValue>) -> Self {
+        LuaValue::Table(
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (LuaKey::Index(i as i64 + 1), v))
+                .collect(),
+        )
+    }
+
+    /// 由键值对构建 Lua 表，跳过 Nil
+    pub fn table(fields: Vec<(&str, LuaValue)>) -> Self {
+        LuaValue::Table(
+            fields
+                .into_iter()
+                .filter(|(_, v)| *v != LuaValue::Nil)
+                .map(|(k, v)| (LuaKey::Name(k.to_string()), v))
+                .collect(),
+        )
+    }
+}
+
+/// 解析 `return { ... }` 形式的 Lua 文件
+pub fn parse(input: &str) -> Result<LuaValue, String> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    parser.skip_ws();
+    if parser.consume_word("return") {
+        parser.skip_ws();
+    }
+    let value = parser.parse_value()?;
+    parser.skip_ws();
+    Ok(value)
+}
+
+/// 按 KOReader 的 dump 风格序列化（键排序、4 空格缩进）
+pub fn serialize(value: &LuaValue) -> String {
+    let mut out = String::from("-- we can read Lua syntax here!\nreturn ");
+    write_value(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Lua 解析失败（位置 {}）: {}", self.pos, message)
+    }
+
+    fn skip_ws(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.pos += 1,
+                Some('-') if self.peek_at(1) == Some('-') => {
+                    self.pos += 2;
+                    if self.peek() == Some('[') {
+                        if let Some(level) = self.long_bracket_level() {
+                            let _ = self.read_long_string(level);
+                            continue;
+                        }
+                    }
+                    while let Some(c) = self.peek() {
+                        self.pos += 1;
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn consume_word(&mut self, word: &str) -> bool {
+        let end = self.pos + word.chars().count();
+        if end > self.chars.len() {
+            return false;
+        }
+        let candidate: String = self.chars[self.pos..end].iter().collect();
+        let boundary = self
+            .chars
+            .get(end)
+            .is_none_or(|c| !(c.is_alphanumeric() || *c == '_'));
+        if candidate == word && boundary {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<LuaValue, String> {
+        self.skip_ws();
+        match self.peek() {
+            Some('{') => self.parse_table(),
+            Some('"') | Some('\'') => self.parse_quoted().map(LuaValue::String),
+            Some('[') => {
+                let level = self
+                    .long_bracket_level()
+                    .ok_or_else(|| self.error("unexpected '['"))?;
+                self.read_long_string(level).map(LuaValue::String)
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => self.parse_number(),
+            Some(_) => {
+                if self.consume_word("true") {
+                    Ok(LuaValue::Bool(true))
+                } else if self.consume_word("false") {
+                    Ok(LuaValue::Bool(false))
+                } else if self.consume_word("nil") {
+                    Ok(LuaValue::Nil)
+                } else {
+                    Err(self.error("unexpected token"))
+                }
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_table(&mut self) -> Result<LuaValue, String> {
+        self.pos += 1; // '{'
+        let mut entries = Vec::new();
+        let mut next_index = 1i64;
+
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    break;
+                }
+                None => return Err(self.error("unterminated table")),
+                _ => {}
+            }
+
+            let key = if self.peek() == Some('[') && self.long_bracket_level().is_none() {
+                self.pos += 1;
+                let key_value = self.parse_value()?;
+                self.skip_ws();
+                if self.peek() != Some(']') {
+                    return Err(self.error("expected ']'"));
+                }
+                self.pos += 1;
+                self.expect_assign()?;
+                Some(match key_value {
+                    LuaValue::String(s) => LuaKey::Name(s),
+                    LuaValue::Number(n) => LuaKey::Index(n as i64),
+                    _ => return Err(self.error("unsupported table key")),
+                })
+            } else {
+                self.peek_identifier_assign().map(LuaKey::Name)
+            };
+
+            let value = self.parse_value()?;
+            let key = key.unwrap_or_else(|| {
+                let k = LuaKey::Index(next_index);
+                next_index += 1;
+                k
+            });
+            if value != LuaValue::Nil {
+                entries.push((key, value));
+            }
+
+            self.skip_ws();
+            match self.peek() {
+                Some(',') | Some(';') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+
+        Ok(LuaValue::Table(entries))
+    }
+
+    /// 识别 `name = value` 形式的字段
+    fn peek_identifier_assign(&mut self) -> Option<String> {
+        let start = self.pos;
+        let first = self.peek()?;
+        if !(first.is_alphabetic() || first == '_') {
+            return None;
+        }
+        let mut end = start;
+        while end < self.chars.len() && (self.chars[end].is_alphanumeric() || self.chars[end] == '_') {
+            end += 1;
+        }
+        let name: String = self.chars[start..end].iter().collect();
+        self.pos = end;
+        self.skip_ws();
+        if self.peek() == Some('=') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            Some(name)
+        } else {
+            self.pos = start;
+            None
+        }
+    }
+
+    fn expect_assign(&mut self) -> Result<(), String> {
+        self.skip_ws();
+        if self.peek() == Some('=') {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error("expected '='"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<LuaValue, String> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some('0') && matches!(self.peek_at(1), Some('x') | Some('X')) {
+            self.pos += 2;
+            let digits_start = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                self.pos += 1;
+            }
+            let digits: String = self.chars[digits_start..self.pos].iter().collect();
+            let value = i64::from_str_radix(&digits, 16).map_err(|_| self.error("invalid hex number"))?;
+            let negative = self.chars[start] == '-';
+            return Ok(LuaValue::Number(if negative { -value as f64 } else { value as f64 }));
+        }
+        while let Some(c) = self.peek() {
+            let is_exponent_sign = (c == '-' || c == '+')
+                && matches!(self.chars.get(self.pos.wrapping_sub(1)), Some('e') | Some('E'));
+            if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || is_exponent_sign {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        raw.parse::<f64>()
+            .map(LuaValue::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut out = String::new();
+
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            if c == quote {
+                break;
+            }
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+
+            let escaped = self.peek().ok_or_else(|| self.error("bad escape"))?;
+            self.pos += 1;
+            match escaped {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'a' => out.push('\u{07}'),
+                'b' => out.push('\u{08}'),
+                'f' => out.push('\u{0C}'),
+                'v' => out.push('\u{0B}'),
+                '\n' => out.push('\n'),
+                'z' => {
+                    while self.peek().is_some_and(|c| c.is_whitespace()) {
+                        self.pos += 1;
+                    }
+                }
+                'x' => {
+                    let hex: String = self.chars[self.pos..(self.pos + 2).min(self.chars.len())].iter().collect();
+                    self.pos += hex.len();
+                    let byte = u8::from_str_radix(&hex, 16).map_err(|_| self.error("bad \\x escape"))?;
+                    self.push_byte(&mut out, byte)?;
+                }
+                d if d.is_ascii_digit() => {
+                    let mut digits = d.to_string();
+                    while digits.len() < 3 && self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        digits.push(self.peek().unwrap_or('0'));
+                        self.pos += 1;
+                    }
+                    let byte: u8 = digits.parse().map_err(|_| self.error("bad decimal escape"))?;
+                    self.push_byte(&mut out, byte)?;
+                }
+                other => out.push(other),
+            }
+        }
+
+        // 十进制转义可能拆开了多字节 UTF-8 字符，这里重新拼合
+        Ok(self.finish_bytes(out))
+    }
+
+    /// Lua 的 `\ddd` 转义按字节写入；先以私有区字符暂存，最后统一解码
+    fn push_byte(&self, out: &mut String, byte: u8) -> Result<(), String> {
+        if byte < 0x80 {
+            out.push(byte as char);
+        } else {
+            out.push(char::from_u32(0xF0000 + byte as u32).ok_or_else(|| self.error("bad byte"))?);
+        }
+        Ok(())
+    }
+
+    fn finish_bytes(&self, s: String) -> String {
+        if !s.chars().any(|c| (0xF0080..=0xF00FF).contains(&(c as u32))) {
+            return s;
+        }
+        let mut bytes = Vec::with_capacity(s.len());
+        for c in s.chars() {
+            let code = c as u32;
+            if (0xF0080..=0xF00FF).contains(&code) {
+                bytes.push((code - 0xF0000) as u8);
+            } else {
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// 若当前位置是长括号 `[[` / `[==[`，返回其等级
+    fn long_bracket_level(&self) -> Option<usize> {
+        if self.peek() != Some('[') {
+            return None;
+        }
+        let mut level = 0;
+        while self.peek_at(1 + level) == Some('=') {
+            level += 1;
+        }
+        if self.peek_at(1 + level) == Some('[') {
+            Some(level)
+        } else {
+            None
+        }
+    }
+
+    fn read_long_string(&mut self, level: usize) -> Result<String, String> {
+        self.pos += level + 2;
+        // 紧跟开括号的换行不计入内容
+        if self.peek() == Some('\n') {
+            self.pos += 1;
+        }
+        let closing: Vec<char> = std::iter::once(']')
+            .chain(std::iter::repeat_n('=', level))
+            .chain(std::iter::once(']'))
+            .collect();
+        let start = self.pos;
+        while self.pos + closing.len() <= self.chars.len() {
+            if self.chars[self.pos..self.pos + closing.len()] == closing[..] {
+                let value: String = self.chars[start..self.pos].iter().collect();
+                self.pos += closing.len();
+                return Ok(value);
+            }
+            self.pos += 1;
+        }
+        Err(self.error("unterminated long string"))
+    }
+}
+
+fn write_value(out: &mut String, value: &LuaValue, depth: usize) {
+    match value {
+        LuaValue::Nil => out.push_str("nil"),
+        LuaValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        LuaValue::Number(n) => {
+            if n.fract() == 0.0 && n.abs() < 1e15 {
+                out.push_str(&format!("{}", *n as i64));
+            } else {
+                out.push_str(&format!("{}", n));
+            }
+        }
+        LuaValue::String(s) => write_string(out, s),
+        LuaValue::Table(entries) => {
+            out.push_str("{\n");
+            let mut sorted: Vec<&(LuaKey, LuaValue)> = entries.iter().collect();
+            sorted.sort_by(|(a, _), (b, _)| match (a, b) {
+                (LuaKey::Index(x), LuaKey::Index(y)) => x.cmp(y),
+                (LuaKey::Index(_), LuaKey::Name(_)) => std::cmp::Ordering::Less,
+                (LuaKey::Name(_), LuaKey::Index(_)) => std::cmp::Ordering::Greater,
+                (LuaKey::Name(x), LuaKey::Name(y)) => x.cmp(y),
+            });
+            let indent = "    ".repeat(depth + 1);
+            for (key, val) in sorted {
+                out.push_str(&indent);
+                match key {
+                    LuaKey::Index(i) => out.push_str(&format!("[{}] = ", i)),
+                    LuaKey::Name(name) => {
+                        out.push('[');
+                        write_string(out, name);
+                        out.push_str("] = ");
+                    }
+                }
+                write_value(out, val, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&"    ".repeat(depth));
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            // 固定写三位：后面紧跟数字时，"\1" 与 "2" 会被读成 "\12"
+            c if (c as u32) < 0x20 || c == '\u{7F}' => out.push_str(&format!("\\{:03}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 序列化后再解析，内容不变；序列化会按键排序，因此比较两次序列化的结果
+    fn assert_round_trip(value: &LuaValue) {
+        let out = serialize(value);
+        let parsed = parse(&out).expect("serialized output parses");
+        assert_eq!(serialize(&parsed), out);
+    }
+
+    #[test]
+    fn test_round_trip_nested_table() {
+        let value = LuaValue::table(vec![
+            ("doc_pages", LuaValue::Number(312.0)),
+            ("percent_finished", LuaValue::Number(0.4375)),
+            ("offset", LuaValue::Number(-12.0)),
+            ("hidden", LuaValue::Bool(false)),
+            (
+                "annotations",
+                LuaValue::array(vec![LuaValue::table(vec![
+                    ("text", LuaValue::string("中文「引号」 and \"quotes\"")),
+                    ("note", LuaValue::string("line 1\nline 2\r\n\\path")),
+                    ("pos0", LuaValue::string("/body/DocFragment[12]/body/p[3]/text().17")),
+                ])]),
+            ),
+        ]);
+        assert_round_trip(&value);
+        let parsed = parse(&serialize(&value)).unwrap();
+        let annotation = parsed.get("annotations").unwrap().values()[0];
+        assert_eq!(annotation.get_str("note"), Some("line 1\nline 2\r\n\\path"));
+        assert_eq!(annotation.get_str("text"), Some("中文「引号」 and \"quotes\""));
+        assert_eq!(parsed.get_f64("offset"), Some(-12.0));
+        assert_eq!(parsed.get_bool("hidden"), Some(false));
+    }
+
+    #[test]
+    fn test_control_characters_use_three_digit_escapes() {
+        let value = LuaValue::string("a\u{1}2\u{0}7\tb\u{7F}9");
+        let out = serialize(&value);
+        assert!(out.contains(r#""a\0012\0007\009b\1279""#), "{}", out);
+        assert_eq!(parse(&out).unwrap(), value);
+    }
+
+    #[test]
+    fn test_parse_koreader_sidecar() {
+        let input = r#"-- we can read Lua syntax here!
+return {
+    ["annotations"] = {
+        [1] = {
+            ["chapter"] = "Chapter 1",
+            ["text"] = "caf\195\169 \x41",
+        },
+        [2] = {
+            ["text"] = [[long
+string]],
+        },
+    },
+    ["summary"] = { status = 'reading'; modified = "2024-01-02" },
+    ["percent_finished"] = 1.5e-1,
+    ["flags"] = { true, nil, 0x1F },
+}
+"#;
+        let value = parse(input).unwrap();
+        let annotations = value.get("annotations").unwrap().values();
+        assert_eq!(annotations[0].get_str("text"), Some("café A"));
+        assert_eq!(annotations[1].get_str("text"), Some("long\nstring"));
+        assert_eq!(value.get("summary").unwrap().get_str("status"), Some("reading"));
+        assert_eq!(value.get_f64("percent_finished"), Some(0.15));
+        // nil 元素不保留，但会占用数组下标
+        let flags = value.get("flags").unwrap();
+        assert_eq!(
+            flags,
+            &LuaValue::Table(vec![
+                (LuaKey::Index(1), LuaValue::Bool(true)),
+                (LuaKey::Index(3), LuaValue::Number(31.0)),
+            ])
+        );
+        assert_round_trip(&value);
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let err = parse("return { [\"a\"] = 1").unwrap_err();
+        assert!(err.contains("位置 18") && err.contains("expected ',' or '}'"), "{}", err);
+        assert!(parse("return { \"unterminated }").is_err());
+    }
+}
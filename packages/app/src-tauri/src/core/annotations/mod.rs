@@ -0,0 +1,9 @@
+pub mod commands;
+pub mod kobo;
+pub mod koreader;
+pub mod library;
+pub mod lua;
+pub mod models;
+pub mod reanchor;
+pub mod readwise;
+pub mod web_annotation;
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+/// 标注导入结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AnnotationImportReport {
+    pub source: String, // 'koreader' | 'kobo'
+    #[serde(rename = "matchedBooks")]
+    pub matched_books: i64,
+    #[serde(rename = "importedNotes")]
+    pub imported_notes: i64,
+    #[serde(rename = "skippedDuplicates")]
+    pub skipped_duplicates: i64,
+    // 无法精确定位、只定位到章节的条目数
+    #[serde(rename = "approximateLocations")]
+    pub approximate_locations: i64,
+    #[serde(rename = "updatedProgress")]
+    pub updated_progress: i64,
+    #[serde(rename = "unmatchedBooks")]
+    pub unmatched_books: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl AnnotationImportReport {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// KOReader sidecar 导出结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct KoreaderExportReport {
+    #[serde(rename = "writtenFiles")]
+    pub written_files: Vec<String>,
+    #[serde(rename = "exportedNotes")]
+    pub exported_notes: i64,
+    #[serde(rename = "skippedNotes")]
+    pub skipped_notes: i64,
+    pub errors: Vec<String>,
+}
+
+/// 单文件导出结果（Readwise CSV / Web Annotation JSON-LD）
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AnnotationExportReport {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    #[serde(rename = "exportedNotes")]
+    pub exported_notes: i64,
+}
+
+/// 标注重定位结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReanchorReport {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub total: i64,
+    pub unchanged: i64,
+    pub relocated: i64,
+    // 已改写位置但匹配不完全可信，标记为 fuzzy
+    pub fuzzy: i64,
+    // 找不到对应文本，保留原位置并标记为 lost
+    pub lost: i64,
+    #[serde(rename = "flaggedNoteIds")]
+    pub flagged_note_ids: Vec<String>,
+}
+
+/// 从外部来源读出的一条标注（已换算为 CFI）
+#[derive(Debug, Clone, Default)]
+pub struct ImportedNote {
+    pub id: Option<String>, // 来源中保留的原始 id（JSON-LD 往返导入）
+    pub r#type: String, // bookmark | annotation | excerpt
+    pub cfi: String,
+    pub text: Option<String>,
+    pub style: Option<String>,
+    pub color: Option<String>,
+    pub note: String,
+    pub context_before: Option<String>,
+    pub context_after: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// 从外部阅读器读出的阅读进度
+#[derive(Debug, Clone, Default)]
+pub struct ImportedProgress {
+    pub percent: Option<f64>, // 0.0 - 1.0
+    pub status: Option<String>, // unread | reading | completed
+    pub location: Option<String>,
+    pub last_read_at: Option<i64>,
+}
+
+/// 书库中的一本书（用于与外部阅读器中的书匹配）
+#[derive(Debug, Clone)]
+pub struct LibraryBook {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub file_path: std::path::PathBuf,
+}
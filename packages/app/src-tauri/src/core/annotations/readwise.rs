@@ -0,0 +1,58 @@
+use super::models::LibraryBook;
+use crate::core::books::models::BookNote;
+use chrono::{Local, TimeZone};
+
+const HEADER: [&str; 6] = ["Highlight", "Book Title", "Book Author", "Note", "Location", "Date"];
+
+/// 生成 Readwise 可导入的 CSV。书签没有文本，不导出；
+/// Location 为高亮在书中的先后序号（调用方需按 CFI 顺序传入）。
+pub fn to_csv(entries: &[(LibraryBook, Vec<BookNote>)]) -> (String, usize) {
+    let mut out = String::new();
+    push_row(&mut out, &HEADER);
+
+    let mut count = 0;
+    for (book, notes) in entries {
+        let highlights = notes
+            .iter()
+            .filter(|n| n.r#type != "bookmark")
+            .filter(|n| n.text.as_deref().is_some_and(|t| !t.trim().is_empty()));
+
+        for (index, note) in highlights.enumerate() {
+            let location = (index + 1).to_string();
+            let date = Local
+                .timestamp_millis_opt(note.created_at)
+                .single()
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            push_row(
+                &mut out,
+                &[
+                    note.text.as_deref().unwrap_or_default(),
+                    &book.title,
+                    &book.author,
+                    &note.note,
+                    &location,
+                    &date,
+                ],
+            );
+            count += 1;
+        }
+    }
+
+    (out, count)
+}
+
+fn push_row(out: &mut String, fields: &[&str]) {
+    let row: Vec<String> = fields.iter().map(|f| escape_field(f)).collect();
+    out.push_str(&row.join(","));
+    out.push_str("\r\n");
+}
+
+/// RFC 4180：含逗号、引号或换行的字段用双引号包裹，内部引号加倍
+fn escape_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,137 @@
+// 书籍文件替换（如换成勘误版）后，依据标注保存的引文与上下文重新定位 CFI
+
+use super::library::get_notes;
+use super::models::ReanchorReport;
+use crate::core::books::models::BookNote;
+use sqlx::SqlitePool;
+use std::path::Path;
+use tauri_plugin_epub::cfi::{collapse_whitespace, AnchorQuery, Cfi, EpubLocator};
+
+pub const STATUS_FUZZY: &str = "fuzzy";
+pub const STATUS_LOST: &str = "lost";
+
+/// 单条标注的重定位结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnchorOutcome {
+    Unchanged,
+    Relocated(String),
+    Fuzzy(String),
+    Lost,
+}
+
+/// 重新定位一本书的全部标注并写回数据库。
+/// 原位置文本仍一致的保持不变；可信匹配直接改写 CFI；不可信的改写后标记 fuzzy；找不到的保留原 CFI 并标记 lost。
+pub async fn reanchor_book(
+    pool: &SqlitePool,
+    book_id: &str,
+    epub_path: &Path,
+) -> Result<ReanchorReport, String> {
+    let notes = get_notes(pool, book_id).await?;
+    let mut report = ReanchorReport {
+        book_id: book_id.to_string(),
+        total: notes.len() as i64,
+        ..Default::default()
+    };
+    if notes.is_empty() {
+        return Ok(report);
+    }
+
+    // EpubLocator 不跨 await 持有
+    let outcomes: Vec<AnchorOutcome> = {
+        let mut locator = EpubLocator::open(epub_path).map_err(|e| format!("打开书籍文件失败: {}", e))?;
+        notes.iter().map(|note| locate(&mut locator, note)).collect()
+    };
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("开启事务失败: {}", e))?;
+
+    for (note, outcome) in notes.iter().zip(outcomes) {
+        let (cfi, status) = match &outcome {
+            AnchorOutcome::Unchanged => {
+                report.unchanged += 1;
+                (note.cfi.as_str(), None)
+            }
+            AnchorOutcome::Relocated(cfi) if *cfi == note.cfi => {
+                report.unchanged += 1;
+                (note.cfi.as_str(), None)
+            }
+            AnchorOutcome::Relocated(cfi) => {
+                report.relocated += 1;
+                (cfi.as_str(), None)
+            }
+            AnchorOutcome::Fuzzy(cfi) => {
+                report.fuzzy += 1;
+                report.flagged_note_ids.push(note.id.clone());
+                (cfi.as_str(), Some(STATUS_FUZZY))
+            }
+            AnchorOutcome::Lost => {
+                report.lost += 1;
+                report.flagged_note_ids.push(note.id.clone());
+                (note.cfi.as_str(), Some(STATUS_LOST))
+            }
+        };
+        if cfi == note.cfi && status == note.anchor_status.as_deref() {
+            continue;
+        }
+
+        // 位置修正不算用户编辑，不更新 updated_at
+        sqlx::query("UPDATE book_notes SET cfi = ?, anchor_status = ? WHERE id = ?")
+            .bind(cfi)
+            .bind(status)
+            .bind(&note.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("更新笔记位置失败: {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("提交事务失败: {}", e))?;
+
+    Ok(report)
+}
+
+/// 在新版 EPUB 中为一条标注寻找位置
+pub fn locate(locator: &mut EpubLocator, note: &BookNote) -> AnchorOutcome {
+    let text = note.text.as_deref().filter(|t| !t.trim().is_empty());
+
+    let Some(text) = text else {
+        // 书签没有引文：原 CFI 仍可解析就保留，否则退回到同一章节开头
+        if locator.text_at(&note.cfi).ok().flatten().is_some() {
+            return AnchorOutcome::Unchanged;
+        }
+        return match note.cfi.parse::<Cfi>().ok().and_then(|c| c.spine_index()) {
+            Some(spine_index) if spine_index < locator.spine_len() => {
+                AnchorOutcome::Fuzzy(locator.chapter_cfi(spine_index))
+            }
+            _ => AnchorOutcome::Lost,
+        };
+    };
+
+    let current = locator.text_at(&note.cfi).ok().flatten();
+    if current.is_some_and(|t| collapse_whitespace(&t) == collapse_whitespace(text)) {
+        return AnchorOutcome::Unchanged;
+    }
+
+    let context = |key: &str| {
+        note.context
+            .as_ref()
+            .and_then(|c| c.get(key))
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+    };
+    let query = AnchorQuery {
+        exact: text,
+        prefix: context("before"),
+        suffix: context("after"),
+        hint: Some(&note.cfi),
+    };
+
+    match locator.anchor(&query) {
+        Ok(Some(found)) if found.is_confident() => AnchorOutcome::Relocated(found.cfi),
+        Ok(Some(found)) => AnchorOutcome::Fuzzy(found.cfi),
+        Ok(None) | Err(_) => AnchorOutcome::Lost,
+    }
+}
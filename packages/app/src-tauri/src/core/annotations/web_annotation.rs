@@ -0,0 +1,454 @@
+// W3C Web Annotation Data Model（JSON-LD）导出与导入
+// https://www.w3.org/TR/annotation-model/
+
+use super::models::{ImportedNote, LibraryBook};
+use crate::core::books::models::BookNote;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+
+const ANNO_CONTEXT: &str = "http://www.w3.org/ns/anno.jsonld";
+const SAGEREAD_NS: &str = "https://sageread.app/ns#";
+const CFI_SPEC: &str = "http://www.idpf.org/epub/linking/cfi/epub-cfi.html";
+const BOOK_URN_PREFIX: &str = "urn:sageread:book:";
+const UUID_URN_PREFIX: &str = "urn:uuid:";
+
+const STYLES: [&str; 3] = ["highlight", "underline", "squiggly"];
+const COLORS: [&str; 5] = ["red", "yellow", "green", "blue", "violet"];
+
+/// JSON-LD 中解析出的一条标注及其所属书籍信息
+#[derive(Debug, Clone)]
+pub struct ParsedAnnotation {
+    pub book_id: Option<String>,
+    pub book_title: String,
+    pub book_author: String,
+    pub note: ImportedNote,
+}
+
+/// 生成包含全部标注的 AnnotationCollection
+pub fn to_collection(entries: &[(LibraryBook, Vec<BookNote>)]) -> (Value, usize) {
+    let items: Vec<Value> = entries
+        .iter()
+        .flat_map(|(book, notes)| notes.iter().map(move |note| to_annotation(book, note)))
+        .collect();
+    let total = items.len();
+
+    let collection = json!({
+        "@context": [ANNO_CONTEXT, { "sageread": SAGEREAD_NS }],
+        "id": format!("{}{}", UUID_URN_PREFIX, uuid::Uuid::new_v4()),
+        "type": "AnnotationCollection",
+        "label": "SageRead annotations",
+        "total": total,
+        "first": {
+            "type": "AnnotationPage",
+            "startIndex": 0,
+            "items": items,
+        },
+    });
+    (collection, total)
+}
+
+fn to_annotation(book: &LibraryBook, note: &BookNote) -> Value {
+    let context = |key: &str| {
+        note.context
+            .as_ref()
+            .and_then(|c| c.get(key))
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+    };
+
+    let mut selectors = Vec::new();
+    if let Some(text) = note.text.as_deref() {
+        let mut quote = json!({ "type": "TextQuoteSelector", "exact": text });
+        if let Some(prefix) = context("before") {
+            quote["prefix"] = json!(prefix);
+        }
+        if let Some(suffix) = context("after") {
+            quote["suffix"] = json!(suffix);
+        }
+        selectors.push(quote);
+    }
+    selectors.push(json!({
+        "type": "FragmentSelector",
+        "conformsTo": CFI_SPEC,
+        "value": note.cfi,
+    }));
+
+    let mut target = json!({
+        "source": format!("{}{}", BOOK_URN_PREFIX, book.id),
+        "selector": selectors,
+        "sageread:bookTitle": book.title,
+        "sageread:bookAuthor": book.author,
+    });
+    let style_class: Vec<&str> = [note.style.as_deref(), note.color.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !style_class.is_empty() {
+        target["styleClass"] = json!(style_class.join(" "));
+    }
+
+    let motivation = match note.r#type.as_str() {
+        "bookmark" => "bookmarking",
+        _ if !note.note.is_empty() => "commenting",
+        _ => "highlighting",
+    };
+
+    let mut annotation = json!({
+        "id": format!("{}{}", UUID_URN_PREFIX, note.id),
+        "type": "Annotation",
+        "motivation": motivation,
+        "created": format_time(note.created_at),
+        "modified": format_time(note.updated_at),
+        "sageread:noteType": note.r#type,
+        "target": target,
+    });
+    if !note.note.is_empty() {
+        annotation["body"] = json!({
+            "type": "TextualBody",
+            "value": note.note,
+            "format": "text/plain",
+            "purpose": "commenting",
+        });
+    }
+    annotation
+}
+
+/// 解析 JSON-LD：支持 AnnotationCollection、AnnotationPage、Annotation 数组或单条 Annotation
+pub fn from_json(value: &Value) -> (Vec<ParsedAnnotation>, Vec<String>) {
+    let mut items = Vec::new();
+    collect_items(value, &mut items);
+
+    let mut parsed = Vec::new();
+    let mut errors = Vec::new();
+    for item in items {
+        match parse_annotation(item) {
+            Ok(annotation) => parsed.push(annotation),
+            Err(e) => errors.push(e),
+        }
+    }
+    (parsed, errors)
+}
+
+fn collect_items<'a>(value: &'a Value, items: &mut Vec<&'a Value>) {
+    match value {
+        Value::Array(values) => values.iter().for_each(|v| collect_items(v, items)),
+        Value::Object(object) => match type_of(object).as_deref() {
+            Some("AnnotationCollection") => {
+                if let Some(first) = object.get("first") {
+                    collect_items(first, items);
+                }
+            }
+            Some("AnnotationPage") => {
+                if let Some(page_items) = object.get("items") {
+                    collect_items(page_items, items);
+                }
+            }
+            _ => items.push(value),
+        },
+        _ => {}
+    }
+}
+
+fn parse_annotation(value: &Value) -> Result<ParsedAnnotation, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| "标注格式无效".to_string())?;
+    let id = object.get("id").and_then(|v| v.as_str());
+    let label = id.unwrap_or("(无 id)");
+
+    let target = match object.get("target") {
+        Some(Value::Array(targets)) => targets.first(),
+        other => other,
+    }
+    .ok_or_else(|| format!("标注缺少 target: {}", label))?;
+
+    let mut cfi = None;
+    let mut quote: Option<&Map<String, Value>> = None;
+    let selectors = match target.get("selector") {
+        Some(Value::Array(selectors)) => selectors.iter().collect(),
+        Some(selector) => vec![selector],
+        None => Vec::new(),
+    };
+    for selector in selectors.into_iter().filter_map(|s| s.as_object()) {
+        match type_of(selector).as_deref() {
+            Some("TextQuoteSelector") => quote = Some(selector),
+            Some("FragmentSelector") => {
+                let value = selector.get("value").and_then(|v| v.as_str());
+                let is_cfi = selector.get("conformsTo").and_then(|v| v.as_str()) == Some(CFI_SPEC)
+                    || value.is_some_and(|v| v.starts_with("epubcfi("));
+                if is_cfi {
+                    cfi = value.map(|v| v.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    let cfi = cfi.ok_or_else(|| format!("标注缺少 EPUB CFI 选择器: {}", label))?;
+
+    let quote_field = |key: &str| {
+        quote
+            .and_then(|q| q.get(key))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+
+    let motivation = first_str(object.get("motivation"));
+    let r#type = object
+        .get("sageread:noteType")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| match motivation.as_deref() {
+            Some("bookmarking") => "bookmark".to_string(),
+            _ => "annotation".to_string(),
+        });
+
+    let style_class = target
+        .get("styleClass")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let style = style_class
+        .split_whitespace()
+        .find(|s| STYLES.contains(s))
+        .map(|s| s.to_string());
+    let color = style_class
+        .split_whitespace()
+        .find(|s| COLORS.contains(s))
+        .map(|s| s.to_string());
+
+    let created_at = parse_time(object.get("created")).unwrap_or_else(|| Utc::now().timestamp_millis());
+    let updated_at = parse_time(object.get("modified")).unwrap_or(created_at);
+
+    let book_id = target
+        .get("source")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.strip_prefix(BOOK_URN_PREFIX))
+        .map(|s| s.to_string());
+    let target_str = |key: &str| {
+        target
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    Ok(ParsedAnnotation {
+        book_id,
+        book_title: target_str("sageread:bookTitle"),
+        book_author: target_str("sageread:bookAuthor"),
+        note: ImportedNote {
+            id: id
+                .and_then(|s| s.strip_prefix(UUID_URN_PREFIX))
+                .map(|s| s.to_string()),
+            r#type,
+            cfi,
+            text: quote_field("exact"),
+            style,
+            color,
+            note: body_text(object.get("body")),
+            context_before: quote_field("prefix"),
+            context_after: quote_field("suffix"),
+            created_at,
+            updated_at,
+        },
+    })
+}
+
+/// 合并评论类 body 的文本；标签等其他用途的 body 不计入笔记
+fn body_text(body: Option<&Value>) -> String {
+    let bodies: Vec<&Value> = match body {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    bodies
+        .into_iter()
+        .filter_map(|b| match b {
+            Value::String(s) => Some(s.clone()),
+            Value::Object(object) => {
+                let purpose = first_str(object.get("purpose"));
+                if matches!(purpose.as_deref(), None | Some("commenting") | Some("describing")) {
+                    object.get("value").and_then(|v| v.as_str()).map(|s| s.to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn type_of(object: &Map<String, Value>) -> Option<String> {
+    first_str(object.get("type").or_else(|| object.get("@type")))
+}
+
+fn first_str(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(values) => values.iter().find_map(|v| v.as_str()).map(|s| s.to_string()),
+        _ => None,
+    }
+}
+
+fn format_time(millis: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_time(value: Option<&Value>) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value?.as_str()?)
+        .ok()
+        .map(|dt| dt.timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn book(id: &str, title: &str) -> LibraryBook {
+        LibraryBook {
+            id: id.to_string(),
+            title: title.to_string(),
+            author: "作者 & Co.".to_string(),
+            file_path: PathBuf::new(),
+        }
+    }
+
+    fn note(id: &str, r#type: &str, cfi: &str) -> BookNote {
+        BookNote {
+            id: id.to_string(),
+            book_id: String::new(),
+            r#type: r#type.to_string(),
+            cfi: cfi.to_string(),
+            text: None,
+            style: None,
+            color: None,
+            note: String::new(),
+            context: None,
+            anchor_status: None,
+            created_at: 0,
+            updated_at: 1,
+        }
+    }
+
+    /// 导入结果还原为书库中的标注，与 book_notes 读出时的形式一致
+    fn to_book_note(parsed: &ParsedAnnotation) -> BookNote {
+        let imported = &parsed.note;
+        let context = (imported.context_before.is_some() || imported.context_after.is_some()).then(|| {
+            json!({ "before": imported.context_before, "after": imported.context_after })
+        });
+        BookNote {
+            id: imported.id.clone().unwrap(),
+            book_id: parsed.book_id.clone().unwrap(),
+            r#type: imported.r#type.clone(),
+            cfi: imported.cfi.clone(),
+            text: imported.text.clone(),
+            style: imported.style.clone(),
+            color: imported.color.clone(),
+            note: imported.note.clone(),
+            context,
+            anchor_status: None,
+            created_at: imported.created_at,
+            updated_at: imported.updated_at,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let entries = vec![
+            (
+                book("book-1", "第一本书"),
+                vec![
+                    BookNote {
+                        text: Some("「引号」 and \"quotes\"\nsecond line".to_string()),
+                        style: Some("underline".to_string()),
+                        color: Some("violet".to_string()),
+                        note: "评论\n\nwith <html> & more".to_string(),
+                        context: Some(json!({ "before": "before ", "after": " after" })),
+                        created_at: 1_700_000_000_123,
+                        updated_at: 1_700_000_500_456,
+                        ..note("8f1c2a4e-0000-4000-8000-000000000001", "annotation", "epubcfi(/6/4!/4/2,/1:0,/1:12)")
+                    },
+                    BookNote {
+                        text: Some("plain excerpt".to_string()),
+                        style: Some("highlight".to_string()),
+                        color: Some("yellow".to_string()),
+                        created_at: 1_700_000_001_000,
+                        updated_at: 1_700_000_001_000,
+                        ..note("8f1c2a4e-0000-4000-8000-000000000002", "excerpt", "epubcfi(/6/8!/4/2/1:5)")
+                    },
+                ],
+            ),
+            (
+                book("book-2", "Second"),
+                vec![note("8f1c2a4e-0000-4000-8000-000000000003", "bookmark", "epubcfi(/6/10!/4/2)")],
+            ),
+        ];
+
+        let (collection, total) = to_collection(&entries);
+        assert_eq!(total, 3);
+        let text = serde_json::to_string_pretty(&collection).unwrap();
+        let (parsed, errors) = from_json(&serde_json::from_str(&text).unwrap());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(parsed.len(), 3);
+
+        let originals = entries.iter().flat_map(|(book, notes)| notes.iter().map(move |n| (book, n)));
+        for ((book, original), parsed) in originals.zip(&parsed) {
+            assert_eq!(parsed.book_id.as_deref(), Some(book.id.as_str()));
+            assert_eq!(parsed.book_title, book.title);
+            assert_eq!(parsed.book_author, book.author);
+
+            let restored = to_book_note(parsed);
+            assert_eq!(
+                serde_json::to_value(&restored).unwrap(),
+                serde_json::to_value(BookNote { book_id: book.id.clone(), ..original.clone() }).unwrap()
+            );
+        }
+
+        // 再次导出的标注与第一次完全相同
+        let restored: Vec<(LibraryBook, Vec<BookNote>)> = entries
+            .iter()
+            .map(|(book, _)| {
+                let notes = parsed
+                    .iter()
+                    .filter(|p| p.book_id.as_deref() == Some(book.id.as_str()))
+                    .map(to_book_note)
+                    .collect();
+                (book.clone(), notes)
+            })
+            .collect();
+        let (again, _) = to_collection(&restored);
+        assert_eq!(again["first"], collection["first"]);
+    }
+
+    #[test]
+    fn test_import_foreign_annotations() {
+        let value = json!([
+            {
+                "@type": ["Annotation"],
+                "motivation": ["bookmarking"],
+                "target": [{ "selector": { "type": "FragmentSelector", "value": "epubcfi(/6/2!/4)" } }],
+            },
+            {
+                "type": "Annotation",
+                "body": [
+                    "first",
+                    { "type": "TextualBody", "value": "tag", "purpose": "tagging" },
+                    { "type": "TextualBody", "value": "second" },
+                ],
+                "target": { "selector": [{ "type": "TextQuoteSelector", "exact": "quote" }] },
+            },
+        ]);
+        let (parsed, errors) = from_json(&value);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].note.r#type, "bookmark");
+        assert_eq!(parsed[0].note.cfi, "epubcfi(/6/2!/4)");
+        assert_eq!(parsed[0].book_id, None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("CFI"), "{}", errors[0]);
+
+        assert_eq!(body_text(value[1].get("body")), "first\n\nsecond");
+    }
+}
@@ -1,4 +1,6 @@
 use super::models::*;
+use crate::core::annotations::models::ReanchorReport;
+use crate::core::annotations::reanchor::reanchor_book;
 use sqlx::{Row, SqlitePool};
 use std::fs;
 use tauri::{AppHandle, Manager};
@@ -247,6 +249,61 @@ pub async fn update_book(
         .ok_or_else(|| "更新后无法找到书籍".to_string())
 }
 
+/// 用新版本（如勘误版）替换书籍文件，并重新定位该书的全部标注
+#[tauri::command]
+pub async fn replace_book_file(
+    app_handle: AppHandle,
+    id: String,
+    temp_file_path: String,
+) -> Result<ReanchorReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let book = get_book_by_id(app_handle.clone(), id.clone())
+        .await?
+        .ok_or_else(|| "书籍不存在".to_string())?;
+
+    let extension = std::path::Path::new(&temp_file_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    if extension.as_deref() != Some(book.format.to_lowercase().as_str()) {
+        return Err(format!("文件格式与原书不一致，应为 {}", book.format));
+    }
+
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("获取应用目录失败: {}", e))?;
+    let book_path = app_data_dir.join(&book.file_path);
+    let backup_path = book_path.with_extension("bak");
+
+    // 先备份旧文件，重定位失败时还原
+    fs::rename(&book_path, &backup_path).map_err(|e| format!("备份书籍文件失败: {}", e))?;
+    if let Err(e) = fs::rename(&temp_file_path, &book_path) {
+        let _ = fs::rename(&backup_path, &book_path);
+        return Err(format!("移动书籍文件失败: {}", e));
+    }
+
+    let report = match reanchor_book(&db_pool, &id, &book_path).await {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = fs::remove_file(&book_path);
+            let _ = fs::rename(&backup_path, &book_path);
+            return Err(e);
+        }
+    };
+    let _ = fs::remove_file(&backup_path);
+
+    let file_size = fs::metadata(&book_path).map(|m| m.len() as i64).unwrap_or(book.file_size);
+    sqlx::query("UPDATE books SET file_size = ?, updated_at = ? WHERE id = ?")
+        .bind(file_size)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(&id)
+        .execute(&db_pool)
+        .await
+        .map_err(|e| format!("更新书籍信息失败: {}", e))?;
+
+    Ok(report)
+}
+
 #[tauri::command]
 pub async fn delete_book(app_handle: AppHandle, id: String) -> Result<(), String> {
     let db_pool = get_db_pool(&app_handle).await?;
@@ -830,7 +887,7 @@ pub async fn get_book_notes(
 
     let rows = sqlx::query(
         r#"
-        SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, created_at, updated_at
+        SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, anchor_status, created_at, updated_at
         FROM book_notes
         WHERE book_id = ?1
         ORDER BY created_at ASC
@@ -882,6 +939,7 @@ pub async fn update_book_note(
             note = COALESCE(?, note),
             context_before = COALESCE(?, context_before),
             context_after = COALESCE(?, context_after),
+            anchor_status = CASE WHEN ? IS NULL THEN anchor_status ELSE NULL END,
             updated_at = ?
         WHERE id = ?
         "#,
@@ -894,6 +952,7 @@ pub async fn update_book_note(
     .bind(&update_data.note)
     .bind(&context_before)
     .bind(&context_after)
+    .bind(&update_data.cfi) // 手动调整位置后清除重定位标记
     .bind(now)
     .bind(&id);
 
@@ -909,7 +968,7 @@ pub async fn update_book_note(
     // 查询更新后的笔记
     let row = sqlx::query(
         r#"
-        SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, created_at, updated_at
+        SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, anchor_status, created_at, updated_at
         FROM book_notes
         WHERE id = ?1
         "#
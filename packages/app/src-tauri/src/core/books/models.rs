@@ -275,6 +275,9 @@ pub struct BookNote {
     pub note: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context: Option<serde_json::Value>,
+    // 书籍文件替换后的重定位状态：fuzzy | lost，正常时为空
+    #[serde(rename = "anchorStatus", default, skip_serializing_if = "Option::is_none")]
+    pub anchor_status: Option<String>,
     #[serde(rename = "createdAt")]
     pub created_at: i64,
     #[serde(rename = "updatedAt")]
@@ -330,6 +333,7 @@ impl BookNote {
             color,
             note,
             context,
+            anchor_status: None,
             created_at: now,
             updated_at: now,
         }
@@ -360,6 +364,7 @@ impl BookNote {
             color: row.try_get("color")?,
             note: row.try_get("note")?,
             context,
+            anchor_status: row.try_get("anchor_status").unwrap_or(None),
             created_at: row.try_get("created_at")?,
             updated_at: row.try_get("updated_at")?,
         })
@@ -1,16 +1,6 @@
 use sqlx::{migrate::MigrateDatabase, Sqlite, SqlitePool};
 use std::fs;
 use tauri::{AppHandle, Manager};
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
-
-#[derive(Deserialize, Serialize, Debug)]
-struct DefaultSkill {
-    name: String,
-    content: String,
-    is_system: bool,
-    is_active: bool,
-}
 
 pub async fn initialize(app_handle: &AppHandle) -> Result<SqlitePool, Box<dyn std::error::Error>> {
     let app_data_dir = app_handle
@@ -43,42 +33,161 @@ pub async fn initialize(app_handle: &AppHandle) -> Result<SqlitePool, Box<dyn st
         .await?;
     println!("Database schema initialized.");
 
-    if is_new_db {
-        initialize_default_skills(&pool).await?;
-    }
+    migrate_columns(&pool).await?;
+    migrate_thread_messages(&pool).await?;
+    migrate_thread_branches(&pool).await?;
+    sync_search_index(&pool).await?;
+    sync_note_links(&pool).await?;
+    sync_default_skills(&pool).await?;
 
     Ok(pool)
 }
 
-async fn initialize_default_skills(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
-    let default_skills_json = include_str!("./default-skills.json");
-    let default_skills: Vec<DefaultSkill> = serde_json::from_str(default_skills_json)?;
-
-    println!("Initializing {} default skills...", default_skills.len());
-
-    for skill in default_skills {
-        let skill_id = Uuid::new_v4().to_string();
-        let now = chrono::Utc::now().timestamp_millis();
-
-        sqlx::query(
-            r#"
-            INSERT INTO skills (id, name, content, is_active, is_system, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(&skill_id)
-        .bind(&skill.name)
-        .bind(&skill.content)
-        .bind(if skill.is_active { 1 } else { 0 })
-        .bind(if skill.is_system { 1 } else { 0 })
-        .bind(now)
-        .bind(now)
-        .execute(pool)
+/// schema.sql 只会创建缺失的表；旧数据库中已存在的表需要补上后来新增的列
+async fn migrate_columns(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let columns = [
+        ("book_notes", "anchor_status", "TEXT"),
+        ("threads", "active_leaf_id", "TEXT"),
+        ("skills", "parameters", "TEXT"),
+    ];
+
+    for (table, column, definition) in columns {
+        let exists: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?",
+            table
+        ))
+        .bind(column)
+        .fetch_one(pool)
         .await?;
+        if exists > 0 {
+            continue;
+        }
+
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+        println!("Column added: {}.{}", table, column);
+    }
+
+    Ok(())
+}
+
+/// 旧版本把对话消息整体存放在 threads.messages 中，拆分到 thread_messages 后清空原字段
+async fn migrate_thread_messages(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let thread_ids: Vec<String> =
+        sqlx::query_scalar("SELECT id FROM threads WHERE messages != '[]'")
+            .fetch_all(pool)
+            .await?;
 
-        println!("✅ Default skill initialized: {}", skill.name);
+    for thread_id in &thread_ids {
+        crate::core::threads::messages::migrate_legacy_messages(pool, thread_id).await?;
     }
+    if !thread_ids.is_empty() {
+        println!("Thread messages migrated: {} threads", thread_ids.len());
+    }
+
+    Ok(())
+}
+
+/// 分支功能之前的对话消息是线性的：按 seq 依次串成一条分支，末条消息作为当前分支末端
+async fn migrate_thread_branches(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let thread_ids: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM threads t
+        WHERE t.active_leaf_id IS NULL
+          AND EXISTS (SELECT 1 FROM thread_messages m WHERE m.thread_id = t.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM thread_messages m WHERE m.thread_id = t.id AND m.parent_id IS NOT NULL
+          )
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for thread_id in &thread_ids {
+        crate::core::threads::branches::link_linear_messages(pool, thread_id).await?;
+    }
+    if !thread_ids.is_empty() {
+        println!("Thread branches migrated: {} threads", thread_ids.len());
+    }
+
+    Ok(())
+}
+
+/// 旧数据库升级后 note_links 为空，为已有的含 [[...]] 的笔记补建链接
+async fn sync_note_links(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let existing: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM note_links")
+        .fetch_one(pool)
+        .await?;
+    if existing > 0 {
+        return Ok(());
+    }
+
+    let note_ids: Vec<String> =
+        sqlx::query_scalar("SELECT id FROM notes WHERE content LIKE '%[[%]]%'")
+            .fetch_all(pool)
+            .await?;
+    for note_id in &note_ids {
+        crate::core::notes::links::sync_note_links(pool, note_id).await?;
+    }
+    if !note_ids.is_empty() {
+        println!("Note links synced: {} notes", note_ids.len());
+    }
+
+    Ok(())
+}
+
+/// 全文索引由触发器维护；旧数据库首次升级或索引条数与源表不一致时整体重建
+async fn sync_search_index(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let targets = [
+        (
+            "book_notes_fts",
+            "book_notes",
+            "INSERT INTO book_notes_fts(rowid, text, note) SELECT rowid, text, note FROM book_notes",
+        ),
+        (
+            "notes_fts",
+            "notes",
+            "INSERT INTO notes_fts(rowid, title, content) SELECT rowid, title, content FROM notes",
+        ),
+        (
+            "threads_fts",
+            "threads",
+            "INSERT INTO threads_fts(rowid, title) SELECT rowid, title FROM threads",
+        ),
+        (
+            "thread_messages_fts",
+            "thread_messages",
+            "INSERT INTO thread_messages_fts(rowid, content) SELECT rowid, content FROM thread_messages",
+        ),
+    ];
+
+    for (fts_table, source_table, rebuild_sql) in targets {
+        let indexed: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", fts_table))
+            .fetch_one(pool)
+            .await?;
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", source_table))
+            .fetch_one(pool)
+            .await?;
+        if indexed == total {
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(&format!("DELETE FROM {}", fts_table))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(rebuild_sql).execute(&mut *tx).await?;
+        tx.commit().await?;
+        println!("Search index rebuilt: {} ({} rows)", fts_table, total);
+    }
+
+    Ok(())
+}
 
-    println!("Default skills initialization completed.");
+/// 安装新增的内置技能，并升级用户未修改过的内置技能
+async fn sync_default_skills(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    crate::core::skills::defaults::sync_default_skills(pool).await?;
+    println!("Default skills synchronized.");
     Ok(())
 }
@@ -1,8 +1,11 @@
+pub mod annotations;
 pub mod books;
 pub mod database;
 pub mod fonts;
 pub mod llama;
 pub mod notes;
+pub mod review;
+pub mod search;
 pub mod skills;
 pub mod state;
 pub mod tags;
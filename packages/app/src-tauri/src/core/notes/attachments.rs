@@ -0,0 +1,197 @@
+// 笔记附件：文件保存在应用数据目录的 notes/{note_id}/ 下，同一笔记内按内容哈希去重
+
+use super::models::{NoteAttachment, NoteAttachmentCleanupReport};
+use sha1::{Digest, Sha1};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// 附件目录，相对于应用数据目录
+pub const ATTACHMENTS_DIR: &str = "notes";
+/// 新上传但尚未写入笔记的附件在该时长内不会被清理（1 天）
+const ORPHAN_GRACE_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// 保存附件；同一笔记中内容相同的文件只保存一份，直接返回已有记录
+pub async fn store_attachment(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    note_id: &str,
+    file_name: &str,
+    data: &[u8],
+) -> Result<NoteAttachment, String> {
+    let exists: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM notes WHERE id = ?")
+        .bind(note_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+    if exists == 0 {
+        return Err("笔记不存在".to_string());
+    }
+
+    let hash = format!("{:x}", Sha1::digest(data));
+    let extension = file_extension(file_name);
+    let stored_name = match &extension {
+        Some(ext) => format!("{}.{}", hash, ext),
+        None => hash.clone(),
+    };
+    let path = format!("{}/{}/{}", ATTACHMENTS_DIR, note_id, stored_name);
+
+    let full_path = app_data_dir.join(&path);
+    if !full_path.exists() {
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("创建附件目录失败: {}", e))?;
+        }
+        fs::write(&full_path, data).map_err(|e| format!("保存附件失败: {}", e))?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO note_attachments (id, note_id, hash, file_name, mime_type, size, path, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(note_id)
+    .bind(&hash)
+    .bind(file_name)
+    .bind(mime_type(extension.as_deref()))
+    .bind(data.len() as i64)
+    .bind(&path)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await
+    .map_err(|e| format!("保存附件记录失败: {}", e))?;
+
+    let row = sqlx::query("SELECT * FROM note_attachments WHERE note_id = ? AND hash = ?")
+        .bind(note_id)
+        .bind(&hash)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("查询附件失败: {}", e))?;
+
+    NoteAttachment::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+/// 删除笔记的附件目录
+pub fn remove_note_attachments(app_data_dir: &Path, note_id: &str) -> Result<(), String> {
+    let dir = app_data_dir.join(ATTACHMENTS_DIR).join(note_id);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("删除附件失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 清理附件：删除笔记及其历史版本中都不再引用的附件记录，再删除磁盘上没有对应记录的文件与目录
+pub async fn cleanup_attachments(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+) -> Result<NoteAttachmentCleanupReport, String> {
+    let mut report = NoteAttachmentCleanupReport::default();
+    let cutoff = chrono::Utc::now().timestamp_millis() - ORPHAN_GRACE_MS;
+
+    // 引用地址中包含文件哈希，按哈希判断是否仍被引用
+    let unreferenced = sqlx::query(
+        r#"
+        SELECT a.id FROM note_attachments a
+        WHERE a.created_at < ?
+          AND NOT EXISTS (
+              SELECT 1 FROM notes n WHERE n.id = a.note_id AND instr(n.content, a.hash) > 0
+          )
+          AND NOT EXISTS (
+              SELECT 1 FROM note_revisions r WHERE r.note_id = a.note_id AND instr(r.content, a.hash) > 0
+          )
+        "#,
+    )
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("查询附件失败: {}", e))?;
+
+    for row in &unreferenced {
+        sqlx::query("DELETE FROM note_attachments WHERE id = ?")
+            .bind(row.get::<String, _>("id"))
+            .execute(pool)
+            .await
+            .map_err(|e| format!("删除附件记录失败: {}", e))?;
+        report.removed_records += 1;
+    }
+
+    let known: HashSet<String> = sqlx::query_scalar("SELECT path FROM note_attachments")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("查询附件失败: {}", e))?
+        .into_iter()
+        .collect();
+
+    let root = app_data_dir.join(ATTACHMENTS_DIR);
+    let Ok(note_dirs) = fs::read_dir(&root) else {
+        return Ok(report);
+    };
+    for note_dir in note_dirs.flatten() {
+        let note_path = note_dir.path();
+        if !note_path.is_dir() {
+            continue;
+        }
+        let note_id = note_dir.file_name().to_string_lossy().to_string();
+
+        for file in fs::read_dir(&note_path).into_iter().flatten().flatten() {
+            let relative = format!(
+                "{}/{}/{}",
+                ATTACHMENTS_DIR,
+                note_id,
+                file.file_name().to_string_lossy()
+            );
+            if known.contains(&relative) {
+                continue;
+            }
+            let size = file.metadata().map(|m| m.len() as i64).unwrap_or(0);
+            if fs::remove_file(file.path()).is_ok() {
+                report.removed_files += 1;
+                report.freed_bytes += size;
+            }
+        }
+
+        // 目录已空时一并删除；remove_dir 对非空目录会失败，忽略即可
+        let _ = fs::remove_dir(&note_path);
+    }
+
+    Ok(report)
+}
+
+/// 取文件扩展名（小写，仅保留字母数字）
+fn file_extension(file_name: &str) -> Option<String> {
+    let (_, ext) = file_name.rsplit_once('.')?;
+    let ext: String = ext
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(10)
+        .collect::<String>()
+        .to_lowercase();
+    (!ext.is_empty()).then_some(ext)
+}
+
+fn mime_type(extension: Option<&str>) -> &'static str {
+    match extension.unwrap_or_default() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
@@ -1,5 +1,11 @@
+use super::attachments::{cleanup_attachments, remove_note_attachments, store_attachment};
+use super::links::{link_context, parse_links, rename_note_links, resolve_links, sync_note_links};
+use super::markdown::{export_folder, import_folder};
 use super::models::*;
-use sqlx::SqlitePool;
+use super::revisions::{diff_lines, snapshot_note, AI_REVISION_REASON};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use tauri::{AppHandle, Manager};
 use uuid::Uuid;
 
@@ -37,6 +43,8 @@ pub async fn create_note(app_handle: AppHandle, data: CreateNoteData) -> Result<
     .await
     .map_err(|e| format!("创建笔记失败: {}", e))?;
 
+    sync_note_links(&db_pool, &id).await?;
+
     Ok(Note::new(
         id,
         data.book_id,
@@ -56,6 +64,25 @@ pub async fn update_note(app_handle: AppHandle, data: UpdateNoteData) -> Result<
     let db_pool = get_db_pool(&app_handle).await?;
     let now = chrono::Utc::now().timestamp_millis();
 
+    // 改名前的标题，用于改写其他笔记中指向本笔记的链接
+    let old_title: Option<String> = if data.title.is_some() {
+        sqlx::query_scalar("SELECT title FROM notes WHERE id = ?")
+            .bind(&data.id)
+            .fetch_optional(&db_pool)
+            .await
+            .map_err(|e| format!("查询笔记失败: {}", e))?
+            .flatten()
+    } else {
+        None
+    };
+
+    // 修改标题或内容前保存历史版本；AI 改写总是单独保存
+    if data.title.is_some() || data.content.is_some() {
+        let ai = data.source.as_deref() == Some(AI_REVISION_REASON);
+        let reason = if ai { AI_REVISION_REASON } else { "edit" };
+        snapshot_note(&db_pool, &data.id, reason, ai).await?;
+    }
+
     // 构建动态更新查询
     let mut has_updates = false;
     let mut query_builder = sqlx::QueryBuilder::new("UPDATE notes SET ");
@@ -63,7 +90,7 @@ pub async fn update_note(app_handle: AppHandle, data: UpdateNoteData) -> Result<
 
     if let Some(book_id_opt) = &data.book_id {
         has_updates = true;
-        separated.push("book_id = ").push_bind(book_id_opt.clone());
+        separated.push("book_id = ").push_bind_unseparated(book_id_opt.clone());
     }
 
     if let Some(book_meta_opt) = &data.book_meta {
@@ -73,24 +100,24 @@ pub async fn update_note(app_handle: AppHandle, data: UpdateNoteData) -> Result<
         } else {
             None
         };
-        separated.push("book_meta = ").push_bind(book_meta_json);
+        separated.push("book_meta = ").push_bind_unseparated(book_meta_json);
     }
 
     if let Some(title_opt) = &data.title {
         has_updates = true;
-        separated.push("title = ").push_bind(title_opt.clone());
+        separated.push("title = ").push_bind_unseparated(title_opt.clone());
     }
 
     if let Some(content_opt) = &data.content {
         has_updates = true;
-        separated.push("content = ").push_bind(content_opt.clone());
+        separated.push("content = ").push_bind_unseparated(content_opt.clone());
     }
 
     if !has_updates {
         return Err("没有需要更新的字段".to_string());
     }
 
-    separated.push("updated_at = ").push_bind(now);
+    separated.push("updated_at = ").push_bind_unseparated(now);
 
     query_builder.push(" WHERE id = ").push_bind(&data.id);
 
@@ -105,6 +132,15 @@ pub async fn update_note(app_handle: AppHandle, data: UpdateNoteData) -> Result<
         return Err("笔记不存在".to_string());
     }
 
+    if let (Some(old_title), Some(Some(new_title))) = (&old_title, &data.title) {
+        if old_title.trim() != new_title.trim() {
+            rename_note_links(&db_pool, &data.id, new_title).await?;
+        }
+    }
+    if data.title.is_some() || data.content.is_some() {
+        sync_note_links(&db_pool, &data.id).await?;
+    }
+
     // 获取更新后的笔记
     get_note_by_id(app_handle, data.id.clone())
         .await?
@@ -125,6 +161,12 @@ pub async fn delete_note(app_handle: AppHandle, id: String) -> Result<(), String
         return Err("笔记不存在".to_string());
     }
 
+    // 附件记录随外键级联删除，这里删除磁盘上的文件
+    remove_note_attachments(&get_app_data_dir(&app_handle)?, &id)?;
+
+    // 指向被删笔记的链接变为未解析（或改指向同名笔记）
+    resolve_links(&db_pool).await?;
+
     Ok(())
 }
 
@@ -185,6 +227,428 @@ pub async fn get_notes(
     notes.map_err(|e| format!("转换查询结果失败: {}", e))
 }
 
+/// 从本地文件添加附件
+#[tauri::command]
+pub async fn add_note_attachment(
+    app_handle: AppHandle,
+    note_id: String,
+    file_path: String,
+) -> Result<NoteAttachment, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let path = std::path::Path::new(&file_path);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("无效的文件路径".to_string())?;
+    let data = std::fs::read(path).map_err(|e| format!("读取文件失败: {}", e))?;
+
+    store_attachment(&db_pool, &get_app_data_dir(&app_handle)?, &note_id, &file_name, &data).await
+}
+
+/// 保存粘贴或拖入的文件内容作为附件
+#[tauri::command]
+pub async fn save_note_attachment_data(
+    app_handle: AppHandle,
+    note_id: String,
+    file_name: String,
+    data: Vec<u8>,
+) -> Result<NoteAttachment, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    store_attachment(&db_pool, &get_app_data_dir(&app_handle)?, &note_id, &file_name, &data).await
+}
+
+#[tauri::command]
+pub async fn get_note_attachments(
+    app_handle: AppHandle,
+    note_id: String,
+) -> Result<Vec<NoteAttachment>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let rows = sqlx::query("SELECT * FROM note_attachments WHERE note_id = ? ORDER BY created_at ASC")
+        .bind(&note_id)
+        .fetch_all(&db_pool)
+        .await
+        .map_err(|e| format!("查询附件失败: {}", e))?;
+
+    rows.iter()
+        .map(NoteAttachment::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn delete_note_attachment(app_handle: AppHandle, id: String) -> Result<(), String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let path: String = sqlx::query_scalar("SELECT path FROM note_attachments WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&db_pool)
+        .await
+        .map_err(|e| format!("查询附件失败: {}", e))?
+        .ok_or("附件不存在".to_string())?;
+
+    sqlx::query("DELETE FROM note_attachments WHERE id = ?")
+        .bind(&id)
+        .execute(&db_pool)
+        .await
+        .map_err(|e| format!("删除附件失败: {}", e))?;
+
+    let full_path = get_app_data_dir(&app_handle)?.join(&path);
+    if full_path.exists() {
+        std::fs::remove_file(&full_path).map_err(|e| format!("删除附件文件失败: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// 清理不再被引用的附件以及磁盘上的孤立文件
+#[tauri::command]
+pub async fn cleanup_note_attachments(
+    app_handle: AppHandle,
+) -> Result<NoteAttachmentCleanupReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    cleanup_attachments(&db_pool, &get_app_data_dir(&app_handle)?).await
+}
+
+/// 将所有笔记导出为 Markdown 文件夹（增量）
+#[tauri::command]
+pub async fn export_notes_markdown(
+    app_handle: AppHandle,
+    dir_path: String,
+) -> Result<NoteMarkdownExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    export_folder(&db_pool, &get_app_data_dir(&app_handle)?, std::path::Path::new(&dir_path)).await
+}
+
+/// 从 Markdown 文件夹导入笔记，已有笔记按 id 或路径更新
+#[tauri::command]
+pub async fn import_notes_markdown(
+    app_handle: AppHandle,
+    dir_path: String,
+) -> Result<NoteMarkdownImportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    import_folder(&db_pool, &get_app_data_dir(&app_handle)?, std::path::Path::new(&dir_path)).await
+}
+
+/// 获取笔记的历史版本（最新在前）
+#[tauri::command]
+pub async fn get_note_revisions(
+    app_handle: AppHandle,
+    note_id: String,
+) -> Result<Vec<NoteRevision>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let rows = sqlx::query(
+        "SELECT * FROM note_revisions WHERE note_id = ? ORDER BY created_at DESC",
+    )
+    .bind(&note_id)
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询笔记版本失败: {}", e))?;
+
+    rows.iter()
+        .map(NoteRevision::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+/// 逐行对比两个版本；不指定 to_revision_id 时与笔记当前内容对比
+#[tauri::command]
+pub async fn diff_note_revisions(
+    app_handle: AppHandle,
+    from_revision_id: String,
+    to_revision_id: Option<String>,
+) -> Result<NoteRevisionDiff, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let from = get_revision(&db_pool, &from_revision_id).await?;
+
+    let (new_title, new_content) = match &to_revision_id {
+        Some(id) => {
+            let to = get_revision(&db_pool, id).await?;
+            if to.note_id != from.note_id {
+                return Err("只能对比同一笔记的版本".to_string());
+            }
+            (to.title, to.content)
+        }
+        None => {
+            let row = sqlx::query("SELECT title, content FROM notes WHERE id = ?")
+                .bind(&from.note_id)
+                .fetch_optional(&db_pool)
+                .await
+                .map_err(|e| format!("查询笔记失败: {}", e))?
+                .ok_or("笔记不存在".to_string())?;
+            (row.get("title"), row.get("content"))
+        }
+    };
+
+    let lines = diff_lines(
+        from.content.as_deref().unwrap_or_default(),
+        new_content.as_deref().unwrap_or_default(),
+    );
+    let added = lines.iter().filter(|l| l.kind == "insert").count() as i64;
+    let removed = lines.iter().filter(|l| l.kind == "delete").count() as i64;
+
+    Ok(NoteRevisionDiff {
+        from_revision_id,
+        to_revision_id,
+        old_title: from.title,
+        new_title,
+        lines,
+        added,
+        removed,
+    })
+}
+
+/// 将笔记恢复到某个历史版本；恢复前的内容会另存为一个版本，恢复操作本身也可撤销
+#[tauri::command]
+pub async fn restore_note_revision(
+    app_handle: AppHandle,
+    revision_id: String,
+) -> Result<Note, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let revision = get_revision(&db_pool, &revision_id).await?;
+    let old_title: Option<String> = sqlx::query_scalar("SELECT title FROM notes WHERE id = ?")
+        .bind(&revision.note_id)
+        .fetch_optional(&db_pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?
+        .ok_or("笔记不存在".to_string())?;
+
+    snapshot_note(&db_pool, &revision.note_id, "restore", true).await?;
+
+    sqlx::query("UPDATE notes SET title = ?, content = ?, updated_at = ? WHERE id = ?")
+        .bind(&revision.title)
+        .bind(&revision.content)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(&revision.note_id)
+        .execute(&db_pool)
+        .await
+        .map_err(|e| format!("恢复笔记失败: {}", e))?;
+
+    if let (Some(old_title), Some(new_title)) = (&old_title, &revision.title) {
+        if old_title.trim() != new_title.trim() {
+            rename_note_links(&db_pool, &revision.note_id, new_title).await?;
+        }
+    }
+    sync_note_links(&db_pool, &revision.note_id).await?;
+
+    get_note_by_id(app_handle, revision.note_id)
+        .await?
+        .ok_or("恢复后获取笔记失败".to_string())
+}
+
+/// 获取指向某个笔记、书籍或书摘的反向链接。target_type：note | book | highlight
+#[tauri::command]
+pub async fn get_note_backlinks(
+    app_handle: AppHandle,
+    target_type: String,
+    target_id: String,
+) -> Result<Vec<NoteBacklink>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    resolve_links(&db_pool).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT l.id AS link_id, l.source_note_id, n.title AS source_title, n.content AS source_content,
+               l.target_type, l.target_id, l.target_text, l.alias, n.updated_at
+        FROM note_links l
+        JOIN notes n ON n.id = l.source_note_id
+        WHERE l.target_type = ? AND l.target_id = ?
+        ORDER BY n.updated_at DESC
+        "#,
+    )
+    .bind(&target_type)
+    .bind(&target_id)
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询反向链接失败: {}", e))?;
+
+    Ok(rows_to_backlinks(&rows))
+}
+
+/// 获取所有未能解析到目标的链接
+#[tauri::command]
+pub async fn get_unresolved_note_links(app_handle: AppHandle) -> Result<Vec<NoteBacklink>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    resolve_links(&db_pool).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT l.id AS link_id, l.source_note_id, n.title AS source_title, n.content AS source_content,
+               l.target_type, l.target_id, l.target_text, l.alias, n.updated_at
+        FROM note_links l
+        JOIN notes n ON n.id = l.source_note_id
+        WHERE l.target_id IS NULL
+        ORDER BY l.target_type, l.target_text, n.updated_at DESC
+        "#,
+    )
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询未解析链接失败: {}", e))?;
+
+    Ok(rows_to_backlinks(&rows))
+}
+
+/// 导出笔记、书籍与书摘之间的关系图（节点与边）
+#[tauri::command]
+pub async fn get_note_graph(app_handle: AppHandle) -> Result<NoteGraph, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    resolve_links(&db_pool).await?;
+
+    let notes = sqlx::query("SELECT id, title, book_id FROM notes ORDER BY created_at ASC")
+        .fetch_all(&db_pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+    let links = sqlx::query("SELECT source_note_id, target_type, target_id, target_text FROM note_links")
+        .fetch_all(&db_pool)
+        .await
+        .map_err(|e| format!("查询笔记链接失败: {}", e))?;
+    let highlights = sqlx::query(
+        r#"
+        SELECT id, book_id, text FROM book_notes
+        WHERE id IN (SELECT target_id FROM note_links WHERE target_type = 'highlight')
+        "#,
+    )
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询书摘失败: {}", e))?;
+    let books = sqlx::query(
+        r#"
+        SELECT id, title FROM books
+        WHERE id IN (SELECT book_id FROM notes WHERE book_id IS NOT NULL)
+           OR id IN (SELECT target_id FROM note_links WHERE target_type = 'book')
+           OR id IN (
+               SELECT book_id FROM book_notes
+               WHERE id IN (SELECT target_id FROM note_links WHERE target_type = 'highlight')
+           )
+        "#,
+    )
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询书籍失败: {}", e))?;
+
+    let mut graph = NoteGraph::default();
+    let mut edge_index: HashMap<(String, String, String), usize> = HashMap::new();
+    let mut add_edge = |graph: &mut NoteGraph, source: String, target: String, kind: &str| {
+        let key = (source.clone(), target.clone(), kind.to_string());
+        match edge_index.get(&key) {
+            Some(&index) => graph.edges[index].weight += 1,
+            None => {
+                edge_index.insert(key, graph.edges.len());
+                graph.edges.push(NoteGraphEdge {
+                    source,
+                    target,
+                    kind: kind.to_string(),
+                    weight: 1,
+                });
+            }
+        }
+    };
+
+    for row in &notes {
+        let id: String = row.get("id");
+        let title: Option<String> = row.get("title");
+        graph.nodes.push(NoteGraphNode {
+            id: format!("note:{}", id),
+            kind: "note".to_string(),
+            label: title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| "未命名笔记".to_string()),
+            entity_id: Some(id.clone()),
+            resolved: true,
+        });
+        if let Some(book_id) = row.get::<Option<String>, _>("book_id") {
+            add_edge(&mut graph, format!("note:{}", id), format!("book:{}", book_id), "book");
+        }
+    }
+    for row in &books {
+        let id: String = row.get("id");
+        graph.nodes.push(NoteGraphNode {
+            id: format!("book:{}", id),
+            kind: "book".to_string(),
+            label: row.get("title"),
+            entity_id: Some(id),
+            resolved: true,
+        });
+    }
+    for row in &highlights {
+        let id: String = row.get("id");
+        let book_id: String = row.get("book_id");
+        let text: Option<String> = row.get("text");
+        let label: String = text.unwrap_or_default().chars().take(40).collect();
+        graph.nodes.push(NoteGraphNode {
+            id: format!("highlight:{}", id),
+            kind: "highlight".to_string(),
+            label,
+            entity_id: Some(id.clone()),
+            resolved: true,
+        });
+        add_edge(&mut graph, format!("highlight:{}", id), format!("book:{}", book_id), "highlight");
+    }
+
+    for row in &links {
+        let source: String = row.get("source_note_id");
+        let target_type: String = row.get("target_type");
+        let target_text: String = row.get("target_text");
+        let target = match row.get::<Option<String>, _>("target_id") {
+            Some(id) => format!("{}:{}", target_type, id),
+            None => {
+                let id = format!("unresolved:{}:{}", target_type, target_text.to_lowercase());
+                if !graph.nodes.iter().any(|n| n.id == id) {
+                    graph.nodes.push(NoteGraphNode {
+                        id: id.clone(),
+                        kind: target_type.clone(),
+                        label: target_text.clone(),
+                        entity_id: None,
+                        resolved: false,
+                    });
+                }
+                id
+            }
+        };
+        add_edge(&mut graph, format!("note:{}", source), target, "link");
+    }
+
+    Ok(graph)
+}
+
+fn rows_to_backlinks(rows: &[sqlx::sqlite::SqliteRow]) -> Vec<NoteBacklink> {
+    rows.iter()
+        .map(|row| {
+            let content: Option<String> = row.get("source_content");
+            let target_type: String = row.get("target_type");
+            let target_text: String = row.get("target_text");
+            let context = content.as_deref().and_then(|content| {
+                parse_links(content)
+                    .iter()
+                    .find(|l| l.target_type == target_type && l.target.eq_ignore_ascii_case(&target_text))
+                    .map(|l| link_context(content, l))
+            });
+
+            NoteBacklink {
+                link_id: row.get("link_id"),
+                source_note_id: row.get("source_note_id"),
+                source_title: row.get("source_title"),
+                target_type,
+                target_id: row.get("target_id"),
+                target_text,
+                alias: row.get("alias"),
+                context,
+                updated_at: row.get("updated_at"),
+            }
+        })
+        .collect()
+}
+
+async fn get_revision(db_pool: &SqlitePool, id: &str) -> Result<NoteRevision, String> {
+    let row = sqlx::query("SELECT * FROM note_revisions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("查询笔记版本失败: {}", e))?
+        .ok_or("版本不存在".to_string())?;
+
+    NoteRevision::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
 async fn execute_normal_query(
     db_pool: &SqlitePool,
     opts: &NoteQueryOptions,
@@ -205,13 +669,15 @@ async fn execute_normal_query(
     query_builder.build().fetch_all(db_pool).await
 }
 
-async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
-    let app_data_dir = app_handle
+fn get_app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    app_handle
         .path()
         .app_data_dir()
-        .map_err(|e| format!("获取应用目录失败: {}", e))?;
+        .map_err(|e| format!("获取应用目录失败: {}", e))
+}
 
-    let db_path = app_data_dir.join("database").join("app.db");
+async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
+    let db_path = get_app_data_dir(app_handle)?.join("database").join("app.db");
     let db_url = format!("sqlite:{}", db_path.display());
 
     SqlitePool::connect(&db_url)
@@ -0,0 +1,327 @@
+// 笔记中的双链：[[笔记标题]]、[[book:书名]]、[[highlight:书摘id]]，可带 |显示文字，笔记链接可带 #小节
+
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use uuid::Uuid;
+
+/// 内容中解析出的一个链接
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedLink {
+    pub target_type: String, // note | book | highlight
+    pub target: String,
+    pub alias: Option<String>,
+    pub span: Range<usize>,        // 整个 [[...]] 的字节范围
+    pub target_span: Range<usize>, // 目标文字（不含前缀、#小节与别名）的字节范围
+}
+
+/// 解析 Markdown 中的链接，跳过代码块与行内代码
+pub fn parse_links(content: &str) -> Vec<ParsedLink> {
+    let mut links = Vec::new();
+    let mut in_fence = false;
+    let mut line_start = 0;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            parse_line(line, line_start, &mut links);
+        }
+        line_start += line.len();
+    }
+    links
+}
+
+fn parse_line(line: &str, offset: usize, links: &mut Vec<ParsedLink>) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    let mut in_code = false;
+
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            in_code = !in_code;
+            i += 1;
+            continue;
+        }
+        if in_code || !bytes[i..].starts_with(b"[[") {
+            i += 1;
+            continue;
+        }
+        let Some(close) = line[i + 2..].find("]]") else {
+            break;
+        };
+        let inner_start = i + 2;
+        let inner_end = inner_start + close;
+        if let Some(link) = parse_inner(&line[inner_start..inner_end], offset + inner_start) {
+            links.push(ParsedLink {
+                span: offset + i..offset + inner_end + 2,
+                ..link
+            });
+        }
+        i = inner_end + 2;
+    }
+}
+
+fn parse_inner(inner: &str, offset: usize) -> Option<ParsedLink> {
+    if inner.contains("[[") {
+        return None;
+    }
+    let (target_part, alias) = match inner.find('|') {
+        Some(index) => (&inner[..index], Some(inner[index + 1..].trim().to_string())),
+        None => (inner, None),
+    };
+
+    let lower = target_part.to_lowercase();
+    let (target_type, rest_start) = if lower.trim_start().starts_with("book:") {
+        ("book", target_part.find(':')? + 1)
+    } else if lower.trim_start().starts_with("highlight:") {
+        ("highlight", target_part.find(':')? + 1)
+    } else {
+        ("note", 0)
+    };
+    let mut rest = &target_part[rest_start..];
+    if target_type == "note" {
+        // [[标题#小节]] 只按标题匹配
+        rest = rest.split('#').next().unwrap_or_default();
+    }
+
+    let leading = rest.len() - rest.trim_start().len();
+    let target = rest.trim();
+    if target.is_empty() {
+        return None;
+    }
+    let start = offset + rest_start + leading;
+
+    Some(ParsedLink {
+        target_type: target_type.to_string(),
+        target: target.to_string(),
+        alias: alias.filter(|a| !a.is_empty()),
+        span: 0..0,
+        target_span: start..start + target.len(),
+    })
+}
+
+/// 把目标文字属于 `targets`（已小写）的笔记链接改为 `new_title`（保留 #小节 与别名）；没有改动时返回 None
+pub fn rename_link_targets(content: &str, targets: &HashSet<String>, new_title: &str) -> Option<String> {
+    let spans: Vec<Range<usize>> = parse_links(content)
+        .into_iter()
+        .filter(|l| l.target_type == "note" && targets.contains(&l.target.to_lowercase()))
+        .map(|l| l.target_span)
+        .collect();
+    if spans.is_empty() {
+        return None;
+    }
+
+    let mut result = content.to_string();
+    for span in spans.into_iter().rev() {
+        result.replace_range(span, new_title.trim());
+    }
+    Some(result)
+}
+
+/// 链接所在行，作为反向链接的上下文
+pub fn link_context(content: &str, link: &ParsedLink) -> String {
+    let start = content[..link.span.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = content[link.span.end..]
+        .find('\n')
+        .map_or(content.len(), |i| link.span.end + i);
+    content[start..end].trim().to_string()
+}
+
+/// 重新解析一条笔记的链接并写入 note_links
+pub async fn sync_note_links(pool: &SqlitePool, note_id: &str) -> Result<(), String> {
+    let content: Option<String> = sqlx::query_scalar("SELECT content FROM notes WHERE id = ?")
+        .bind(note_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?
+        .flatten();
+    let links = parse_links(content.as_deref().unwrap_or_default());
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("开启事务失败: {}", e))?;
+    sqlx::query("DELETE FROM note_links WHERE source_note_id = ?")
+        .bind(note_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("更新笔记链接失败: {}", e))?;
+
+    for link in links {
+        sqlx::query(
+            "INSERT INTO note_links (id, source_note_id, target_type, target_text, alias, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(note_id)
+        .bind(&link.target_type)
+        .bind(&link.target)
+        .bind(&link.alias)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("更新笔记链接失败: {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("提交事务失败: {}", e))?;
+
+    resolve_links(pool).await
+}
+
+/// 解析尚未指向有效目标的链接（目标后来才创建、改名或已被删除的情况）
+pub async fn resolve_links(pool: &SqlitePool) -> Result<(), String> {
+    let statements = [
+        r#"
+        UPDATE note_links SET target_id = (
+            SELECT id FROM notes WHERE lower(trim(notes.title)) = lower(note_links.target_text)
+            ORDER BY updated_at DESC LIMIT 1
+        )
+        WHERE target_type = 'note'
+          AND (target_id IS NULL OR NOT EXISTS (SELECT 1 FROM notes WHERE id = note_links.target_id))
+        "#,
+        r#"
+        UPDATE note_links SET target_id = COALESCE(
+            (SELECT id FROM books WHERE lower(trim(books.title)) = lower(note_links.target_text)
+             ORDER BY updated_at DESC LIMIT 1),
+            (SELECT id FROM books WHERE id = note_links.target_text)
+        )
+        WHERE target_type = 'book'
+          AND (target_id IS NULL OR NOT EXISTS (SELECT 1 FROM books WHERE id = note_links.target_id))
+        "#,
+        r#"
+        UPDATE note_links SET target_id = (
+            SELECT id FROM book_notes WHERE id = note_links.target_text
+        )
+        WHERE target_type = 'highlight'
+          AND (target_id IS NULL OR NOT EXISTS (SELECT 1 FROM book_notes WHERE id = note_links.target_id))
+        "#,
+    ];
+
+    for sql in statements {
+        sqlx::query(sql)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("解析笔记链接失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 笔记改名后，改写其他笔记中指向它的链接文字。
+/// 只改写 note_links 中解析到这条笔记的链接：同名的其他笔记的链接不受影响，
+/// 改名前就已指向它的旧标题链接也一并更新
+pub async fn rename_note_links(pool: &SqlitePool, note_id: &str, new_title: &str) -> Result<(), String> {
+    if new_title.trim().is_empty() {
+        return Ok(());
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT n.id, n.content, l.target_text
+        FROM note_links l
+        JOIN notes n ON n.id = l.source_note_id
+        WHERE l.target_type = 'note' AND l.target_id = ?
+        "#,
+    )
+    .bind(note_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("查询笔记链接失败: {}", e))?;
+
+    // 来源笔记 -> (内容, 指向这条笔记的链接文字)
+    let mut sources: HashMap<String, (Option<String>, HashSet<String>)> = HashMap::new();
+    for row in rows {
+        let target_text: String = row.get("target_text");
+        sources
+            .entry(row.get("id"))
+            .or_insert_with(|| (row.get("content"), HashSet::new()))
+            .1
+            .insert(target_text.to_lowercase());
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    for (source_id, (content, targets)) in sources {
+        let Some(updated) = content.and_then(|c| rename_link_targets(&c, &targets, new_title)) else {
+            continue;
+        };
+
+        sqlx::query("UPDATE notes SET content = ?, updated_at = ? WHERE id = ?")
+            .bind(&updated)
+            .bind(now)
+            .bind(&source_id)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("更新笔记链接失败: {}", e))?;
+        sync_note_links(pool, &source_id).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(content: &str) -> Vec<(String, String, Option<String>)> {
+        parse_links(content)
+            .into_iter()
+            .map(|l| (l.target_type, l.target, l.alias))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_link_types() {
+        let links = targets("见 [[笔记 A]]、[[Book: 三体 ]] 与 [[highlight:abc-123|这段话]]");
+        assert_eq!(
+            links,
+            [
+                ("note".to_string(), "笔记 A".to_string(), None),
+                ("book".to_string(), "三体".to_string(), None),
+                ("highlight".to_string(), "abc-123".to_string(), Some("这段话".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_section_and_alias() {
+        let content = "[[Title#Section|shown]]";
+        let links = parse_links(content);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].target, "Title");
+        assert_eq!(links[0].alias.as_deref(), Some("shown"));
+        assert_eq!(&content[links[0].target_span.clone()], "Title");
+        assert_eq!(links[0].span, 0..content.len());
+    }
+
+    #[test]
+    fn test_parse_skips_code_and_invalid_links() {
+        let content = "`[[inline]]` [[ ]] [[a [[b]]\n```\n[[fenced]]\n```\n[[after]] [[unclosed";
+        let links = targets(content);
+        assert_eq!(
+            links.iter().map(|(_, t, _)| t.as_str()).collect::<Vec<_>>(),
+            ["after"]
+        );
+    }
+
+    #[test]
+    fn test_rename_only_matching_targets() {
+        let content = "[[Old]] [[old#Part|alias]] [[Other]] [[book:Old]]";
+        let set: HashSet<String> = ["old".to_string()].into();
+        assert_eq!(
+            rename_link_targets(content, &set, " New ").as_deref(),
+            Some("[[New]] [[New#Part|alias]] [[Other]] [[book:Old]]")
+        );
+        let none: HashSet<String> = ["missing".to_string()].into();
+        assert_eq!(rename_link_targets(content, &none, "New"), None);
+    }
+
+    #[test]
+    fn test_link_context_is_the_whole_line() {
+        let content = "first\n  see [[Target]] here  \nlast";
+        let link = &parse_links(content)[0];
+        assert_eq!(link_context(content, link), "see [[Target]] here");
+    }
+}
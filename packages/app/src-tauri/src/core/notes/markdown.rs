@@ -0,0 +1,695 @@
+// 笔记与 Markdown 文件夹互相转换：每条笔记一个 .md 文件，front-matter 保存 id、关联书籍与时间。
+// 文件夹中的清单文件记录每条笔记上次导出的路径、更新时间与文件哈希，用于增量导出和按路径匹配导入。
+
+use super::attachments::{store_attachment, ATTACHMENTS_DIR};
+use super::links::sync_note_links;
+use super::models::{BookMeta, Note, NoteMarkdownExportReport, NoteMarkdownImportReport};
+use super::revisions::snapshot_note;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// 导出清单文件名
+pub const MANIFEST_FILE: &str = ".sageread-notes.json";
+/// 导出文件夹中存放附件的子目录
+pub const ATTACHMENTS_FOLDER: &str = "_attachments";
+const ATTACHMENT_SCHEME: &str = "attachment://";
+const MAX_FILE_STEM_CHARS: usize = 80;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExportManifest {
+    pub notes: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    pub path: String, // 相对于导出文件夹，使用 / 分隔
+    #[serde(default)]
+    pub title: Option<String>, // 标题变化时才按新标题重命名文件
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+    pub hash: String,
+}
+
+impl ExportManifest {
+    pub fn load(dir: &Path) -> Self {
+        fs::read_to_string(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| format!("序列化导出清单失败: {}", e))?;
+        fs::write(dir.join(MANIFEST_FILE), text).map_err(|e| format!("写入导出清单失败: {}", e))
+    }
+}
+
+/// 从 Markdown 文件解析出的笔记
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarkdownNote {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub book_id: Option<String>,
+    pub book_title: Option<String>,
+    pub book_author: Option<String>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub content: String,
+}
+
+/// 生成带 front-matter 的 Markdown 文本
+pub fn render_note(note: &Note) -> String {
+    let mut out = String::from("---\n");
+    push_field(&mut out, "id", Some(&note.id));
+    push_field(&mut out, "title", note.title.as_deref());
+    push_field(&mut out, "book_id", note.book_id.as_deref());
+    if let Some(meta) = &note.book_meta {
+        push_field(&mut out, "book_title", Some(&meta.title));
+        push_field(&mut out, "book_author", Some(&meta.author));
+    }
+    push_field(&mut out, "created", Some(&format_time(note.created_at)));
+    push_field(&mut out, "updated", Some(&format_time(note.updated_at)));
+    out.push_str("---\n\n");
+
+    let content = note.content.as_deref().unwrap_or_default();
+    out.push_str(&content.replace(ATTACHMENT_SCHEME, &format!("{}/", ATTACHMENTS_FOLDER)));
+    // 文件总以换行结尾；解析时只去掉这一个换行，正文自身的结尾空白原样保留
+    out.push('\n');
+    out
+}
+
+/// 解析 Markdown 文本；没有 front-matter 标题时依次取一级标题、文件名作为标题
+pub fn parse_note(text: &str, file_stem: &str) -> MarkdownNote {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut note = MarkdownNote::default();
+    let mut body = text.as_str();
+
+    if let Some(rest) = text.strip_prefix("---\n") {
+        // (front-matter 结束位置, 正文开始位置)
+        let bounds = if rest.starts_with("---\n") || rest == "---" {
+            Some((0, 4))
+        } else {
+            rest.find("\n---\n")
+                .map(|i| (i, i + 5))
+                .or_else(|| rest.strip_suffix("\n---").map(|r| (r.len(), rest.len())))
+        };
+        if let Some((end, body_start)) = bounds {
+            for line in rest[..end].lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = parse_value(value);
+                if value.is_empty() {
+                    continue;
+                }
+                match key.trim() {
+                    "id" => note.id = Some(value),
+                    "title" => note.title = Some(value),
+                    "book_id" => note.book_id = Some(value),
+                    "book_title" => note.book_title = Some(value),
+                    "book_author" => note.book_author = Some(value),
+                    "created" | "created_at" => note.created_at = parse_time(&value),
+                    "updated" | "updated_at" => note.updated_at = parse_time(&value),
+                    _ => {}
+                }
+            }
+            body = rest.get(body_start..).unwrap_or_default();
+        }
+    }
+
+    // 导出时在 front-matter 后加了一个空行，文件末尾加了一个换行
+    let body = body.strip_prefix('\n').unwrap_or(body);
+    note.content = body.strip_suffix('\n').unwrap_or(body).to_string();
+
+    if note.title.is_none() {
+        note.title = note
+            .content
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .or_else(|| Some(file_stem.to_string()).filter(|s| !s.is_empty()));
+    }
+    note
+}
+
+/// 由标题生成文件名（不含扩展名），去掉文件系统不允许的字符
+pub fn file_stem(title: Option<&str>) -> String {
+    let stem: String = title
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .take(MAX_FILE_STEM_CHARS)
+        .collect();
+    let stem = stem.trim().trim_matches('.').trim();
+    if stem.is_empty() {
+        "未命名笔记".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha1::digest(text.as_bytes()))
+}
+
+/// 增量导出：自上次导出后未修改的笔记跳过；文件在外部被修改过时不覆盖，需要先导入
+pub async fn export_folder(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    dir: &Path,
+) -> Result<NoteMarkdownExportReport, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("创建导出目录失败: {}", e))?;
+    let mut report = NoteMarkdownExportReport {
+        dir_path: dir.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+
+    let rows = sqlx::query("SELECT * FROM notes ORDER BY created_at ASC")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+    let notes = rows
+        .iter()
+        .map(Note::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))?;
+
+    let mut manifest = ExportManifest::load(dir);
+    let note_ids: HashSet<&str> = notes.iter().map(|n| n.id.as_str()).collect();
+
+    // 已删除的笔记：文件未在外部修改过才删除
+    let removed: Vec<String> = manifest
+        .notes
+        .keys()
+        .filter(|id| !note_ids.contains(id.as_str()))
+        .cloned()
+        .collect();
+    for id in removed {
+        let Some(entry) = manifest.notes.remove(&id) else {
+            continue;
+        };
+        let path = dir.join(&entry.path);
+        if file_hash(&path).as_deref() == Some(entry.hash.as_str()) && fs::remove_file(&path).is_ok() {
+            report.removed_files += 1;
+        }
+        let _ = fs::remove_dir_all(dir.join(ATTACHMENTS_FOLDER).join(&id));
+    }
+
+    let mut used_paths: HashSet<String> = manifest.notes.values().map(|e| e.path.to_lowercase()).collect();
+
+    for note in &notes {
+        let entry = manifest.notes.get(&note.id).cloned();
+        if let Some(entry) = &entry {
+            if entry.updated_at == note.updated_at && dir.join(&entry.path).exists() {
+                report.skipped_notes += 1;
+                continue;
+            }
+            if file_hash(&dir.join(&entry.path)).is_some_and(|hash| hash != entry.hash) {
+                report
+                    .errors
+                    .push(format!("{}: 文件已在外部修改，请先导入", entry.path));
+                continue;
+            }
+            used_paths.remove(&entry.path.to_lowercase());
+        }
+
+        let path = note_path(dir, note, entry.as_ref(), &used_paths);
+        let text = render_note(note);
+        if let Err(e) = fs::write(dir.join(&path), &text) {
+            report.errors.push(format!("{}: 写入失败: {}", path, e));
+            if let Some(entry) = &entry {
+                used_paths.insert(entry.path.to_lowercase());
+            }
+            continue;
+        }
+        if let Some(entry) = &entry {
+            if entry.path != path {
+                let _ = fs::remove_file(dir.join(&entry.path));
+            }
+        }
+        if let Err(e) = export_attachments(app_data_dir, dir, note) {
+            report.errors.push(format!("{}: {}", path, e));
+        }
+
+        used_paths.insert(path.to_lowercase());
+        manifest.notes.insert(
+            note.id.clone(),
+            ManifestEntry {
+                path,
+                title: note.title.clone(),
+                updated_at: note.updated_at,
+                hash: content_hash(&text),
+            },
+        );
+        report.exported_notes += 1;
+    }
+
+    manifest.save(dir)?;
+    Ok(report)
+}
+
+/// 导入文件夹中的 .md 文件：按 front-matter 中的 id、再按清单中的路径匹配已有笔记，匹配不到则新建
+pub async fn import_folder(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    dir: &Path,
+) -> Result<NoteMarkdownImportReport, String> {
+    if !dir.is_dir() {
+        return Err("目录不存在".to_string());
+    }
+    let mut report = NoteMarkdownImportReport {
+        dir_path: dir.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+
+    let mut manifest = ExportManifest::load(dir);
+    let ids_by_path: HashMap<String, String> = manifest
+        .notes
+        .iter()
+        .map(|(id, entry)| (entry.path.clone(), id.clone()))
+        .collect();
+
+    let mut files = Vec::new();
+    collect_markdown_files(dir, &mut files);
+    files.sort();
+
+    for file in files {
+        let relative = relative_path(dir, &file);
+        match import_file(pool, app_data_dir, dir, &file, &relative, &ids_by_path).await {
+            Ok((outcome, id, entry)) => {
+                match outcome {
+                    ImportOutcome::Created => report.created_notes += 1,
+                    ImportOutcome::Updated => report.updated_notes += 1,
+                    ImportOutcome::Unchanged => report.unchanged_notes += 1,
+                }
+                manifest.notes.insert(id, entry);
+            }
+            Err(e) => report.errors.push(format!("{}: {}", relative, e)),
+        }
+    }
+
+    manifest.save(dir)?;
+    Ok(report)
+}
+
+enum ImportOutcome {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+async fn import_file(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    dir: &Path,
+    file: &Path,
+    relative: &str,
+    ids_by_path: &HashMap<String, String>,
+) -> Result<(ImportOutcome, String, ManifestEntry), String> {
+    let text = fs::read_to_string(file).map_err(|e| format!("读取文件失败: {}", e))?;
+    let stem = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let parsed = parse_note(&text, &stem);
+
+    let mut existing = None;
+    for candidate in [parsed.id.as_ref(), ids_by_path.get(relative)].into_iter().flatten() {
+        let row = sqlx::query("SELECT * FROM notes WHERE id = ?")
+            .bind(candidate)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| format!("查询笔记失败: {}", e))?;
+        if let Some(row) = row {
+            existing = Some(Note::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e))?);
+            break;
+        }
+    }
+
+    // 书籍不在书库中时只保留书籍信息
+    let book: Option<(String, String, String)> = match &parsed.book_id {
+        Some(book_id) => sqlx::query_as("SELECT id, title, author FROM books WHERE id = ?")
+            .bind(book_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| format!("查询书籍失败: {}", e))?,
+        None => None,
+    };
+    let book_id = book.as_ref().map(|(id, _, _)| id.clone());
+    // 手写的 front-matter 可能只有 book_id，此时书籍信息取自书库
+    let book_meta = match &parsed.book_title {
+        Some(title) => Some(BookMeta {
+            title: title.clone(),
+            author: parsed.book_author.clone().unwrap_or_default(),
+        }),
+        None => book.map(|(_, title, author)| BookMeta { title, author }),
+    };
+    let book_meta_json = match &book_meta {
+        Some(meta) => Some(serde_json::to_string(meta).map_err(|e| format!("序列化书籍信息失败: {}", e))?),
+        None => None,
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let (outcome, note_id) = match existing {
+        Some(note) => {
+            let content = import_attachments(pool, app_data_dir, dir, file, &note.id, &parsed.content).await?;
+            let unchanged = note.title == parsed.title
+                && note.content.as_deref().unwrap_or_default() == content
+                && note.book_id == book_id
+                && note.book_meta.as_ref().map(|m| (&m.title, &m.author))
+                    == book_meta.as_ref().map(|m| (&m.title, &m.author));
+            if unchanged {
+                (ImportOutcome::Unchanged, note.id)
+            } else {
+                snapshot_note(pool, &note.id, "import", true).await?;
+                sqlx::query(
+                    "UPDATE notes SET title = ?, content = ?, book_id = ?, book_meta = ?, updated_at = ? WHERE id = ?",
+                )
+                .bind(&parsed.title)
+                .bind(&content)
+                .bind(&book_id)
+                .bind(&book_meta_json)
+                .bind(now)
+                .bind(&note.id)
+                .execute(pool)
+                .await
+                .map_err(|e| format!("更新笔记失败: {}", e))?;
+                (ImportOutcome::Updated, note.id)
+            }
+        }
+        None => {
+            let id = parsed.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+            sqlx::query(
+                r#"
+                INSERT INTO notes (id, book_id, book_meta, title, content, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(&book_id)
+            .bind(&book_meta_json)
+            .bind(&parsed.title)
+            .bind(&parsed.content)
+            .bind(parsed.created_at.unwrap_or(now))
+            .bind(parsed.updated_at.unwrap_or(now))
+            .execute(pool)
+            .await
+            .map_err(|e| format!("创建笔记失败: {}", e))?;
+
+            // 附件需要笔记已存在才能保存
+            let content = import_attachments(pool, app_data_dir, dir, file, &id, &parsed.content).await?;
+            if content != parsed.content {
+                sqlx::query("UPDATE notes SET content = ? WHERE id = ?")
+                    .bind(&content)
+                    .bind(&id)
+                    .execute(pool)
+                    .await
+                    .map_err(|e| format!("更新笔记失败: {}", e))?;
+            }
+            (ImportOutcome::Created, id)
+        }
+    };
+
+    sync_note_links(pool, &note_id).await?;
+
+    let row = sqlx::query("SELECT * FROM notes WHERE id = ?")
+        .bind(&note_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+    let note = Note::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e))?;
+
+    // 新建的笔记把 id 等信息写回文件，之后改名或移动也能按 id 匹配
+    let hash = if parsed.id.is_none() {
+        let rendered = render_note(&note);
+        fs::write(file, &rendered).map_err(|e| format!("写入文件失败: {}", e))?;
+        content_hash(&rendered)
+    } else {
+        content_hash(&text)
+    };
+
+    Ok((
+        outcome,
+        note_id,
+        ManifestEntry {
+            path: relative.to_string(),
+            title: note.title.clone(),
+            updated_at: note.updated_at,
+            hash,
+        },
+    ))
+}
+
+/// 把 Markdown 中指向导出文件夹附件的相对路径还原为附件地址，并保存附件文件
+async fn import_attachments(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    dir: &Path,
+    file: &Path,
+    note_id: &str,
+    content: &str,
+) -> Result<String, String> {
+    let marker = format!("{}/", ATTACHMENTS_FOLDER);
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(index) = rest.find(&marker) {
+        let end = rest[index..]
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '"' | '\'' | '>' | ']'))
+            .map_or(rest.len(), |i| index + i);
+        let reference = &rest[index..end];
+        // 允许 ../_attachments/... 形式的相对路径
+        let prefix_start = rest[..index]
+            .rfind(|c: char| c.is_whitespace() || matches!(c, '(' | '"' | '\'' | '<'))
+            .map_or(0, |i| index.min(i + 1));
+        result.push_str(&rest[..prefix_start]);
+
+        let relative_source = &rest[prefix_start..end];
+        let source = [file.parent().map(|p| p.join(relative_source)), Some(dir.join(reference))]
+            .into_iter()
+            .flatten()
+            .find(|p| p.is_file());
+
+        match source {
+            Some(source) => {
+                let data = fs::read(&source).map_err(|e| format!("读取附件失败: {}", e))?;
+                let file_name = source
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let attachment = store_attachment(pool, app_data_dir, note_id, &file_name, &data).await?;
+                result.push_str(&attachment.url);
+            }
+            None => result.push_str(relative_source),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// 复制笔记中引用的附件到导出文件夹
+fn export_attachments(app_data_dir: &Path, dir: &Path, note: &Note) -> Result<(), String> {
+    let content = note.content.as_deref().unwrap_or_default();
+    let prefix = format!("{}{}/", ATTACHMENT_SCHEME, note.id);
+    let source_dir = app_data_dir.join(ATTACHMENTS_DIR).join(&note.id);
+    let target_dir = dir.join(ATTACHMENTS_FOLDER).join(&note.id);
+
+    for (index, _) in content.match_indices(&prefix) {
+        let file_name: String = content[index + prefix.len()..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '.')
+            .collect();
+        let source = source_dir.join(&file_name);
+        let target = target_dir.join(&file_name);
+        if file_name.is_empty() || !source.is_file() || target.exists() {
+            continue;
+        }
+        fs::create_dir_all(&target_dir).map_err(|e| format!("创建附件目录失败: {}", e))?;
+        fs::copy(&source, &target).map_err(|e| format!("复制附件失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 笔记的导出路径：标题未变时沿用上次的路径（包括用户自己移动或改名的文件），重名时加上 id 前缀区分
+fn note_path(
+    dir: &Path,
+    note: &Note,
+    entry: Option<&ManifestEntry>,
+    used_paths: &HashSet<String>,
+) -> String {
+    if let Some(entry) = entry.filter(|e| e.title == note.title) {
+        return entry.path.clone();
+    }
+
+    let stem = file_stem(note.title.as_deref());
+    let short_id: String = note.id.chars().take(8).collect();
+    let path = format!("{}.md", stem);
+    let fallback = format!("{} {}.md", stem, short_id);
+    // 清单之外已存在的同名文件可能是用户自己的文件，不覆盖
+    if used_paths.contains(&path.to_lowercase()) || dir.join(&path).exists() {
+        fallback
+    } else {
+        path
+    }
+}
+
+fn collect_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name == ATTACHMENTS_FOLDER {
+            continue;
+        }
+        if path.is_dir() {
+            collect_markdown_files(&path, files);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+        {
+            files.push(path);
+        }
+    }
+}
+
+fn relative_path(dir: &Path, file: &Path) -> String {
+    file.strip_prefix(dir)
+        .unwrap_or(file)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|text| content_hash(&text))
+}
+
+fn push_field(out: &mut String, key: &str, value: Option<&str>) {
+    let Some(value) = value else {
+        return;
+    };
+    out.push_str(key);
+    out.push_str(": ");
+    out.push_str(&yaml_value(value));
+    out.push('\n');
+}
+
+/// 含特殊字符的值写成双引号字符串（JSON 字符串同时也是合法的 YAML）
+fn yaml_value(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.starts_with(['"', '\'', '[', '{', '-', '!', '&', '*', '#', '|', '>', '@', '`', '%'])
+        && !value.contains([':', '#', '\n', '\\']);
+    if plain {
+        value.to_string()
+    } else {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+fn parse_value(value: &str) -> String {
+    let value = value.trim();
+    if value.starts_with('"') {
+        if let Ok(parsed) = serde_json::from_str::<String>(value) {
+            return parsed;
+        }
+    }
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].replace("''", "'");
+    }
+    value.to_string()
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+        .unwrap_or_else(|| millis.to_string())
+}
+
+fn parse_time(value: &str) -> Option<i64> {
+    value.parse::<i64>().ok().or_else(|| {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|t| t.timestamp_millis())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(content: &str) -> Note {
+        Note {
+            id: "note-1".to_string(),
+            book_id: Some("book-1".to_string()),
+            book_meta: Some(BookMeta {
+                title: "Title: with colon".to_string(),
+                author: "Author".to_string(),
+            }),
+            title: Some("# not a heading".to_string()),
+            content: Some(content.to_string()),
+            created_at: 1_700_000_000_123,
+            updated_at: 1_700_000_100_456,
+        }
+    }
+
+    #[test]
+    fn test_render_parse_round_trip() {
+        for content in ["body", "body\n", "  indented\n\ntrailing  \n\n", "", "\nleading blank line"] {
+            let original = note(content);
+            let parsed = parse_note(&render_note(&original), "file");
+            assert_eq!(parsed.content, content);
+            assert_eq!(parsed.id.as_deref(), Some("note-1"));
+            assert_eq!(parsed.title, original.title);
+            assert_eq!(parsed.book_id.as_deref(), Some("book-1"));
+            assert_eq!(parsed.book_title.as_deref(), Some("Title: with colon"));
+            assert_eq!(parsed.book_author.as_deref(), Some("Author"));
+            assert_eq!(parsed.created_at, Some(original.created_at));
+            assert_eq!(parsed.updated_at, Some(original.updated_at));
+        }
+    }
+
+    #[test]
+    fn test_render_rewrites_attachment_links() {
+        let original = note("![img](attachment://note-1/a.png)");
+        let text = render_note(&original);
+        assert!(text.contains("](_attachments/note-1/a.png)"));
+    }
+
+    #[test]
+    fn test_parse_front_matter_with_book_id_only() {
+        let parsed = parse_note("---\nid: n\nbook_id: b\n---\n\ntext\n", "stem");
+        assert_eq!(parsed.book_id.as_deref(), Some("b"));
+        assert_eq!(parsed.book_title, None);
+        assert_eq!(parsed.content, "text");
+        assert_eq!(parsed.title.as_deref(), Some("stem"));
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        let parsed = parse_note("\u{feff}# Heading\r\nline\r\n", "stem");
+        assert_eq!(parsed.id, None);
+        assert_eq!(parsed.title.as_deref(), Some("Heading"));
+        assert_eq!(parsed.content, "# Heading\nline");
+    }
+
+    #[test]
+    fn test_parse_quoted_values() {
+        let parsed = parse_note("---\ntitle: \"a: \\\"b\\\"\"\nbook_author: 'it''s'\nupdated: 42\n---\n", "stem");
+        assert_eq!(parsed.title.as_deref(), Some("a: \"b\""));
+        assert_eq!(parsed.book_author.as_deref(), Some("it's"));
+        assert_eq!(parsed.updated_at, Some(42));
+        assert_eq!(parsed.content, "");
+    }
+}
@@ -1,2 +1,6 @@
+pub mod attachments;
 pub mod commands;
+pub mod links;
+pub mod markdown;
 pub mod models;
+pub mod revisions;
@@ -44,6 +44,189 @@ pub struct UpdateNoteData {
     pub book_meta: Option<Option<BookMeta>>, // Option<Option<BookMeta>> 支持清空book_meta
     pub title: Option<Option<String>>, // Option<Option<String>> 支持清空title
     pub content: Option<Option<String>>, // Option<Option<String>> 支持清空content
+    #[serde(default)]
+    pub source: Option<String>, // 编辑来源："ai" 表示 AI 改写，改写前总是保存历史版本
+}
+
+// 指向某个笔记、书籍或书摘的反向链接
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteBacklink {
+    #[serde(rename = "linkId")]
+    pub link_id: String,
+    #[serde(rename = "sourceNoteId")]
+    pub source_note_id: String,
+    #[serde(rename = "sourceTitle")]
+    pub source_title: Option<String>,
+    #[serde(rename = "targetType")]
+    pub target_type: String, // "note" | "book" | "highlight"
+    #[serde(rename = "targetId")]
+    pub target_id: Option<String>,
+    #[serde(rename = "targetText")]
+    pub target_text: String,
+    pub alias: Option<String>,
+    pub context: Option<String>, // 链接所在行
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+// 关系图节点，id 形如 note:<id>、book:<id>、highlight:<id>，未解析的目标为 unresolved:<类型>:<文字>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteGraphNode {
+    pub id: String,
+    pub kind: String, // "note" | "book" | "highlight"
+    pub label: String,
+    #[serde(rename = "entityId")]
+    pub entity_id: Option<String>,
+    pub resolved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteGraphEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: String, // "link" 双链 | "book" 笔记关联书籍 | "highlight" 书摘所属书籍
+    pub weight: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NoteGraph {
+    pub nodes: Vec<NoteGraphNode>,
+    pub edges: Vec<NoteGraphEdge>,
+}
+
+// 笔记的历史版本：保存修改前的标题与内容
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteRevision {
+    pub id: String,
+    #[serde(rename = "noteId")]
+    pub note_id: String,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub reason: String, // "edit" 编辑前 | "restore" 恢复前 | "import" 导入前
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+impl NoteRevision {
+    pub fn from_db_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            note_id: row.try_get("note_id")?,
+            title: row.try_get("title")?,
+            content: row.try_get("content")?,
+            reason: row.try_get("reason")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+// 逐行对比中的一行
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NoteDiffLine {
+    pub kind: String, // "equal" | "insert" | "delete"
+    #[serde(rename = "oldLine")]
+    pub old_line: Option<i64>, // 从 1 开始的行号
+    #[serde(rename = "newLine")]
+    pub new_line: Option<i64>,
+    pub text: String,
+}
+
+// 两个版本之间的对比结果，toRevisionId 为空表示与当前内容对比
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteRevisionDiff {
+    #[serde(rename = "fromRevisionId")]
+    pub from_revision_id: String,
+    #[serde(rename = "toRevisionId")]
+    pub to_revision_id: Option<String>,
+    #[serde(rename = "oldTitle")]
+    pub old_title: Option<String>,
+    #[serde(rename = "newTitle")]
+    pub new_title: Option<String>,
+    pub lines: Vec<NoteDiffLine>,
+    pub added: i64,
+    pub removed: i64,
+}
+
+// 笔记附件，文件保存在 notes/{note_id}/{hash}.{ext}，Markdown 中以 attachment://{note_id}/{hash}.{ext} 引用
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteAttachment {
+    pub id: String,
+    #[serde(rename = "noteId")]
+    pub note_id: String,
+    pub hash: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String, // 原始文件名
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub size: i64,
+    pub path: String, // 相对于应用数据目录
+    pub url: String,  // Markdown 中的引用地址
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+impl NoteAttachment {
+    pub fn from_db_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+
+        let path: String = row.try_get("path")?;
+        let note_id: String = row.try_get("note_id")?;
+        let file = path.rsplit('/').next().unwrap_or_default();
+        let url = format!("attachment://{}/{}", note_id, file);
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            note_id,
+            hash: row.try_get("hash")?,
+            file_name: row.try_get("file_name")?,
+            mime_type: row.try_get("mime_type")?,
+            size: row.try_get("size")?,
+            path,
+            url,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+// 附件清理结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NoteAttachmentCleanupReport {
+    #[serde(rename = "removedRecords")]
+    pub removed_records: i64, // 笔记及其历史版本中都不再引用的附件
+    #[serde(rename = "removedFiles")]
+    pub removed_files: i64, // 磁盘上没有对应记录的文件
+    #[serde(rename = "freedBytes")]
+    pub freed_bytes: i64,
+}
+
+// 导出 Markdown 文件夹的结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NoteMarkdownExportReport {
+    #[serde(rename = "dirPath")]
+    pub dir_path: String,
+    #[serde(rename = "exportedNotes")]
+    pub exported_notes: i64,
+    #[serde(rename = "skippedNotes")]
+    pub skipped_notes: i64, // 自上次导出后未修改
+    #[serde(rename = "removedFiles")]
+    pub removed_files: i64, // 已删除笔记对应的文件
+    pub errors: Vec<String>,
+}
+
+// 从 Markdown 文件夹导入的结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NoteMarkdownImportReport {
+    #[serde(rename = "dirPath")]
+    pub dir_path: String,
+    #[serde(rename = "createdNotes")]
+    pub created_notes: i64,
+    #[serde(rename = "updatedNotes")]
+    pub updated_notes: i64,
+    #[serde(rename = "unchangedNotes")]
+    pub unchanged_notes: i64,
+    pub errors: Vec<String>,
 }
 
 // 查询笔记时的选项
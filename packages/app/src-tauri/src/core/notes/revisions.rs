@@ -0,0 +1,318 @@
+// 笔记历史版本：更新前保存快照，短时间内的连续编辑合并为一个版本（AI 改写前后不合并）
+
+use super::models::NoteDiffLine;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+/// 距上一个版本不足该时长的编辑不再生成新版本（10 分钟）
+pub const REVISION_COALESCE_MS: i64 = 10 * 60 * 1000;
+/// AI 改写前保存的版本使用的 reason
+pub const AI_REVISION_REASON: &str = "ai";
+/// 每条笔记最多保留的版本数
+pub const MAX_REVISIONS_PER_NOTE: i64 = 50;
+/// 超过该时长的版本每天只保留最新的一个（7 天）
+const DAILY_RETENTION_AFTER_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+/// LCS 表的单元格上限（约 16 MB），超过时中间部分整体按删除 + 插入输出
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// 保存笔记当前的标题与内容作为一个版本。
+/// `force` 为 false 时，若上一个版本在合并窗口内则跳过；内容与上一个版本相同时总是跳过。
+/// AI 改写不参与合并：改写前总是保存版本，上一个版本是 AI 改写前保存的时，下一次编辑也会保存（保留 AI 的结果）。
+/// 返回是否生成了新版本。
+pub async fn snapshot_note(
+    pool: &SqlitePool,
+    note_id: &str,
+    reason: &str,
+    force: bool,
+) -> Result<bool, String> {
+    let Some(note) = sqlx::query("SELECT title, content FROM notes WHERE id = ?")
+        .bind(note_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?
+    else {
+        return Ok(false);
+    };
+    let title: Option<String> = note.get("title");
+    let content: Option<String> = note.get("content");
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let latest = sqlx::query(
+        "SELECT title, content, reason, created_at FROM note_revisions WHERE note_id = ? ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(note_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("查询笔记版本失败: {}", e))?;
+
+    if let Some(latest) = latest {
+        let unchanged = latest.get::<Option<String>, _>("title") == title
+            && latest.get::<Option<String>, _>("content") == content;
+        let recent = now - latest.get::<i64, _>("created_at") < REVISION_COALESCE_MS;
+        let coalesce = recent
+            && !force
+            && reason != AI_REVISION_REASON
+            && latest.get::<String, _>("reason") != AI_REVISION_REASON;
+        if unchanged || coalesce {
+            return Ok(false);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO note_revisions (id, note_id, title, content, reason, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(note_id)
+    .bind(&title)
+    .bind(&content)
+    .bind(reason)
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("保存笔记版本失败: {}", e))?;
+
+    prune_revisions(pool, note_id, now).await?;
+    Ok(true)
+}
+
+/// 保留策略：最近 7 天的版本全部保留，更早的每天只留最新一个；总数不超过 MAX_REVISIONS_PER_NOTE
+pub async fn prune_revisions(pool: &SqlitePool, note_id: &str, now: i64) -> Result<(), String> {
+    let rows = sqlx::query(
+        "SELECT id, created_at FROM note_revisions WHERE note_id = ? ORDER BY created_at DESC",
+    )
+    .bind(note_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("查询笔记版本失败: {}", e))?;
+
+    let mut kept = 0;
+    let mut last_day = None;
+    let mut expired = Vec::new();
+    for row in &rows {
+        let id: String = row.get("id");
+        let created_at: i64 = row.get("created_at");
+
+        let keep = if kept >= MAX_REVISIONS_PER_NOTE {
+            false
+        } else if now - created_at > DAILY_RETENTION_AFTER_MS {
+            let day = created_at.div_euclid(DAY_MS);
+            let first_of_day = last_day != Some(day);
+            last_day = Some(day);
+            first_of_day
+        } else {
+            true
+        };
+
+        if keep {
+            kept += 1;
+        } else {
+            expired.push(id);
+        }
+    }
+
+    for id in expired {
+        sqlx::query("DELETE FROM note_revisions WHERE id = ?")
+            .bind(&id)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("清理笔记版本失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 基于最长公共子序列的逐行对比；去掉相同首尾行后仍超过 MAX_LCS_CELLS 时，中间部分不做对齐
+pub fn diff_lines(old: &str, new: &str) -> Vec<NoteDiffLine> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    // 先去掉相同的首尾行，缩小 LCS 表
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+    let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+
+    // lcs[i][j]：old_mid[i..] 与 new_mid[j..] 的最长公共子序列长度。
+    // 表过大时不再对齐，lcs 为空，下面的循环先输出全部删除再输出全部插入
+    let width = new_mid.len() + 1;
+    let cells = (old_mid.len() + 1).saturating_mul(width);
+    let mut lcs = Vec::new();
+    if cells <= MAX_LCS_CELLS {
+        lcs = vec![0u32; cells];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(old_lines.len().max(new_lines.len()));
+    let line = |kind: &str, old_index: Option<usize>, new_index: Option<usize>, text: &str| NoteDiffLine {
+        kind: kind.to_string(),
+        old_line: old_index.map(|i| i as i64 + 1),
+        new_line: new_index.map(|i| i as i64 + 1),
+        text: text.to_string(),
+    };
+
+    for (i, text) in old_lines[..prefix].iter().enumerate() {
+        result.push(line("equal", Some(i), Some(i), text));
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if !lcs.is_empty() && i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            result.push(line("equal", Some(prefix + i), Some(prefix + j), old_mid[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_mid.len()
+            && (j == new_mid.len() || lcs.is_empty() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            result.push(line("delete", Some(prefix + i), None, old_mid[i]));
+            i += 1;
+        } else {
+            result.push(line("insert", None, Some(prefix + j), new_mid[j]));
+            j += 1;
+        }
+    }
+
+    for k in 0..suffix {
+        let old_index = old_lines.len() - suffix + k;
+        let new_index = new_lines.len() - suffix + k;
+        result.push(line("equal", Some(old_index), Some(new_index), old_lines[old_index]));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn kinds(diff: &[NoteDiffLine]) -> Vec<(&str, Option<i64>, Option<i64>, &str)> {
+        diff.iter()
+            .map(|l| (l.kind.as_str(), l.old_line, l.new_line, l.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("a\nb\nc\nd", "a\nc\nx\nd");
+        assert_eq!(
+            kinds(&diff),
+            vec![
+                ("equal", Some(1), Some(1), "a"),
+                ("delete", Some(2), None, "b"),
+                ("equal", Some(3), Some(2), "c"),
+                ("insert", None, Some(3), "x"),
+                ("equal", Some(4), Some(4), "d"),
+            ]
+        );
+
+        assert!(diff_lines("", "").is_empty());
+        assert_eq!(kinds(&diff_lines("", "a")), vec![("insert", None, Some(1), "a")]);
+        assert_eq!(kinds(&diff_lines("a\n", "")), vec![("delete", Some(1), None, "a")]);
+    }
+
+    #[test]
+    fn test_diff_lines_falls_back_for_large_input() {
+        // 首尾各有一行相同，中间的 LCS 表超过上限
+        let count = 2_100;
+        let old: Vec<String> = (0..count).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..count).map(|i| format!("new {}", i)).collect();
+        let old = format!("head\n{}\nshared\n{}\ntail", old.join("\n"), old.join("\n"));
+        let new = format!("head\n{}\nshared\n{}\ntail", new.join("\n"), new.join("\n"));
+        assert!((count * 2 + 2) * (count * 2 + 2) > MAX_LCS_CELLS);
+
+        let diff = diff_lines(&old, &new);
+        assert_eq!(diff.len(), 2 + (count * 2 + 1) * 2);
+        assert_eq!(kinds(&diff[..1]), vec![("equal", Some(1), Some(1), "head")]);
+        assert!(diff[1..=count * 2 + 1].iter().all(|l| l.kind == "delete"));
+        assert!(diff[count * 2 + 2..diff.len() - 1].iter().all(|l| l.kind == "insert"));
+        let last = diff.last().unwrap();
+        assert_eq!((last.kind.as_str(), last.text.as_str()), ("equal", "tail"));
+        assert_eq!(last.old_line, Some(count as i64 * 2 + 3));
+    }
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE notes (id TEXT PRIMARY KEY, title TEXT, content TEXT);
+             CREATE TABLE note_revisions (id TEXT PRIMARY KEY, note_id TEXT NOT NULL, title TEXT, content TEXT,
+                 reason TEXT NOT NULL, created_at INTEGER NOT NULL);
+             INSERT INTO notes (id, title, content) VALUES ('n', 't', 'v0');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn set_content(pool: &SqlitePool, content: &str) {
+        sqlx::query("UPDATE notes SET content = ? WHERE id = 'n'")
+            .bind(content)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn reasons(pool: &SqlitePool) -> Vec<(String, String)> {
+        sqlx::query_as("SELECT reason, content FROM note_revisions ORDER BY created_at, rowid")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_coalesces_edits() {
+        let pool = test_pool().await;
+        assert!(snapshot_note(&pool, "n", "edit", false).await.unwrap());
+        set_content(&pool, "v1").await;
+        assert!(!snapshot_note(&pool, "n", "edit", false).await.unwrap());
+        // 内容未变时即使 force 也不保存
+        set_content(&pool, "v0").await;
+        assert!(!snapshot_note(&pool, "n", "restore", true).await.unwrap());
+        assert!(!snapshot_note(&pool, "missing", "edit", true).await.unwrap());
+        assert_eq!(reasons(&pool).await, vec![("edit".to_string(), "v0".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_never_coalesces_across_ai() {
+        let pool = test_pool().await;
+        assert!(snapshot_note(&pool, "n", "edit", false).await.unwrap());
+        // 用户编辑后紧接着 AI 改写：改写前的内容要单独保存
+        set_content(&pool, "v1 user").await;
+        assert!(snapshot_note(&pool, "n", AI_REVISION_REASON, false).await.unwrap());
+        // AI 改写后紧接着用户编辑：AI 的结果也要保存
+        set_content(&pool, "v2 ai").await;
+        assert!(snapshot_note(&pool, "n", "edit", false).await.unwrap());
+        set_content(&pool, "v3 user").await;
+        assert!(!snapshot_note(&pool, "n", "edit", false).await.unwrap());
+
+        assert_eq!(
+            reasons(&pool).await,
+            vec![
+                ("edit".to_string(), "v0".to_string()),
+                ("ai".to_string(), "v1 user".to_string()),
+                ("edit".to_string(), "v2 ai".to_string()),
+            ]
+        );
+    }
+}
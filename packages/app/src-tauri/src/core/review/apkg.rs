@@ -0,0 +1,333 @@
+// Anki .apkg 导出：zip 包内含旧版（schema 11）collection.anki2 数据库与空的 media 清单
+
+use super::models::ReviewCard;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use std::io::Write;
+use std::path::Path;
+
+// 固定的笔记类型 id，重复导入时 Anki 会复用同一笔记类型
+const BASIC_MODEL_ID: i64 = 1718000000001;
+const CLOZE_MODEL_ID: i64 = 1718000000002;
+const DEFAULT_DECK_ID: i64 = 1;
+const ROOT_DECK: &str = "SageRead";
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+const ANKI_SCHEMA: &str = r#"
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+"#;
+
+/// 将卡片写入 .apkg。每本书一个子牌组（SageRead::书名）；复习中的卡片保留间隔与难度，其余作为新卡片导出。
+pub async fn write_apkg(cards: &[ReviewCard], target: &Path) -> Result<(), String> {
+    let collection_path = std::env::temp_dir().join(format!("sageread-{}.anki2", uuid::Uuid::new_v4()));
+    let result = write_collection(cards, &collection_path).await.and_then(|_| {
+        let collection =
+            std::fs::read(&collection_path).map_err(|e| format!("读取 Anki 数据库失败: {}", e))?;
+        write_package(&collection, target)
+    });
+    let _ = std::fs::remove_file(&collection_path);
+    result
+}
+
+async fn write_collection(cards: &[ReviewCard], path: &Path) -> Result<(), String> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .connect()
+        .await
+        .map_err(|e| format!("创建 Anki 数据库失败: {}", e))?;
+
+    let now = chrono::Utc::now();
+    let now_ms = now.timestamp_millis();
+    // 集合创建时间取当天零点，复习卡片的 due 以距它的天数表示
+    let crt = now.timestamp() - now.timestamp() % DAY_SECS;
+
+    let mut decks = vec![(DEFAULT_DECK_ID, "Default".to_string()), (now_ms, ROOT_DECK.to_string())];
+    let mut deck_for_book = |title: &str| -> i64 {
+        let name = format!("{}::{}", ROOT_DECK, title.replace("::", ":"));
+        match decks.iter().find(|(_, n)| *n == name) {
+            Some((id, _)) => *id,
+            None => {
+                let id = now_ms + decks.len() as i64;
+                decks.push((id, name));
+                id
+            }
+        }
+    };
+
+    let mut tx = conn.begin().await.map_err(|e| format!("开启事务失败: {}", e))?;
+    sqlx::query(ANKI_SCHEMA)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("创建 Anki 数据库失败: {}", e))?;
+
+    for (position, card) in cards.iter().enumerate() {
+        let id = now_ms + position as i64;
+        let deck_id = deck_for_book(card.book_title.as_deref().unwrap_or("Untitled"));
+        let model_id = if card.kind == "cloze" { CLOZE_MODEL_ID } else { BASIC_MODEL_ID };
+        let fields = [to_html(&card.front), to_html(&card.back)];
+        let sort_field = strip_html(&fields[0]);
+
+        sqlx::query(
+            "INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data) VALUES (?, ?, ?, ?, -1, ?, ?, ?, ?, 0, '')",
+        )
+        .bind(id)
+        .bind(&card.id)
+        .bind(model_id)
+        .bind(card.updated_at / 1000)
+        .bind(" sageread ")
+        .bind(fields.join("\u{1f}"))
+        .bind(&sort_field)
+        .bind(checksum(&sort_field))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("写入 Anki 笔记失败: {}", e))?;
+
+        // type/queue：0=新卡片，2=复习
+        let (card_type, due, interval) = if card.state == "review" {
+            (2, (card.due / 1000 - crt).div_euclid(DAY_SECS), card.interval_days)
+        } else {
+            (0, position as i64 + 1, 0)
+        };
+        sqlx::query(
+            "INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, left, odue, odid, flags, data) VALUES (?, ?, ?, 0, ?, -1, ?, ?, ?, ?, ?, ?, ?, 0, 0, 0, 0, '')",
+        )
+        .bind(id)
+        .bind(id)
+        .bind(deck_id)
+        .bind(card.updated_at / 1000)
+        .bind(card_type)
+        .bind(card_type)
+        .bind(due)
+        .bind(interval)
+        .bind((card.ease * 1000.0).round() as i64)
+        .bind(card.reps)
+        .bind(card.lapses)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("写入 Anki 卡片失败: {}", e))?;
+    }
+
+    sqlx::query(
+        "INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags) VALUES (1, ?, ?, ?, 11, 0, 0, 0, ?, ?, ?, ?, '{}')",
+    )
+    .bind(crt)
+    .bind(now_ms)
+    .bind(now_ms)
+    .bind(collection_config(cards.len()).to_string())
+    .bind(models_json(now.timestamp()).to_string())
+    .bind(decks_json(&decks, now.timestamp()).to_string())
+    .bind(deck_config_json().to_string())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("写入 Anki 集合失败: {}", e))?;
+
+    tx.commit().await.map_err(|e| format!("提交事务失败: {}", e))?;
+    conn.close().await.map_err(|e| format!("关闭 Anki 数据库失败: {}", e))
+}
+
+fn write_package(collection: &[u8], target: &Path) -> Result<(), String> {
+    let file = std::fs::File::create(target).map_err(|e| format!("创建文件失败: {}", e))?;
+    write_zip(file, collection).map_err(|e| format!("写入 apkg 失败: {}", e))
+}
+
+fn write_zip(file: std::fs::File, collection: &[u8]) -> zip::result::ZipResult<()> {
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file("collection.anki2", options)?;
+    zip.write_all(collection)?;
+    zip.start_file("media", options)?;
+    zip.write_all(b"{}")?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// 纯文本转为 Anki 字段 HTML（保留 cloze 标记）
+fn to_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+fn strip_html(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.replace("<br>", " ").chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Anki 的重复检测校验和：排序字段 SHA-1 的前 8 位十六进制
+fn checksum(sort_field: &str) -> i64 {
+    let digest = Sha1::digest(sort_field.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+fn collection_config(card_count: usize) -> Value {
+    json!({
+        "nextPos": card_count + 1,
+        "estTimes": true,
+        "activeDecks": [DEFAULT_DECK_ID],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": DEFAULT_DECK_ID,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": BASIC_MODEL_ID.to_string(),
+        "collapseTime": 1200,
+    })
+}
+
+fn models_json(modified: i64) -> Value {
+    let field = |name: &str, ord: usize| {
+        json!({ "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": [] })
+    };
+    let template = |name: &str, qfmt: &str, afmt: &str| {
+        json!({ "name": name, "ord": 0, "qfmt": qfmt, "afmt": afmt, "did": null, "bqfmt": "", "bafmt": "" })
+    };
+    let model = |id: i64, name: &str, kind: i64, fields: Vec<Value>, templates: Vec<Value>| {
+        json!({
+            "id": id,
+            "name": name,
+            "type": kind,
+            "mod": modified,
+            "usn": -1,
+            "sortf": 0,
+            "did": DEFAULT_DECK_ID,
+            "tmpls": templates,
+            "flds": fields,
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n.cloze { font-weight: bold; color: blue; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "tags": [],
+            "vers": [],
+            "req": [[0, "any", [0]]],
+        })
+    };
+
+    json!({
+        BASIC_MODEL_ID.to_string(): model(
+            BASIC_MODEL_ID,
+            "SageRead Basic",
+            0,
+            vec![field("Front", 0), field("Back", 1)],
+            vec![template("Card 1", "{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}")],
+        ),
+        CLOZE_MODEL_ID.to_string(): model(
+            CLOZE_MODEL_ID,
+            "SageRead Cloze",
+            1,
+            vec![field("Text", 0), field("Extra", 1)],
+            vec![template("Cloze", "{{cloze:Text}}", "{{cloze:Text}}<br>{{Extra}}")],
+        ),
+    })
+}
+
+fn decks_json(decks: &[(i64, String)], modified: i64) -> Value {
+    let mut result = serde_json::Map::new();
+    for (id, name) in decks {
+        result.insert(
+            id.to_string(),
+            json!({
+                "id": id,
+                "name": name,
+                "mod": modified,
+                "usn": -1,
+                "desc": "",
+                "dyn": 0,
+                "conf": 1,
+                "collapsed": false,
+                "newToday": [0, 0],
+                "revToday": [0, 0],
+                "lrnToday": [0, 0],
+                "timeToday": [0, 0],
+                "extendNew": 10,
+                "extendRev": 50,
+            }),
+        );
+    }
+    Value::Object(result)
+}
+
+fn deck_config_json() -> Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1.0, 10.0],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": false,
+                "separate": true,
+            },
+            "lapse": {
+                "delays": [10.0],
+                "mult": 0.0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0,
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "ivlFct": 1.0,
+                "maxIvl": 36500,
+                "bury": false,
+                "minSpace": 1,
+                "fuzz": 0.05,
+            },
+        }
+    })
+}
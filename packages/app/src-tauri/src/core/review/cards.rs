@@ -0,0 +1,177 @@
+// 由书摘生成卡片正反面
+
+use crate::core::books::models::BookNote;
+use std::ops::Range;
+
+/// 生成的卡片内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardContent {
+    pub kind: String,
+    pub front: String,
+    pub back: String,
+}
+
+/// 按指定类型生成卡片；书签、空书摘或缺少问题的 qa 卡片返回 None
+pub fn build_card(note: &BookNote, kind: &str, clozes: &[String]) -> Option<CardContent> {
+    if note.r#type == "bookmark" {
+        return None;
+    }
+    let text = note.text.as_deref().map(str::trim).filter(|t| !t.is_empty())?;
+    let question = note.note.trim();
+
+    let kind = match kind {
+        "cloze" | "qa" => kind,
+        _ if question.ends_with(['?', '？']) => "qa",
+        _ => "cloze",
+    };
+
+    match kind {
+        "qa" if !question.is_empty() => Some(CardContent {
+            kind: "qa".to_string(),
+            front: question.to_string(),
+            back: text.to_string(),
+        }),
+        "cloze" => Some(CardContent {
+            kind: "cloze".to_string(),
+            front: cloze_text(text, clozes)?,
+            back: question.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// 用 Anki 的 `{{c1::...}}` 语法挖空。指定片段都不在原文中时，自动挖掉最长的一个词或短句。
+pub fn cloze_text(text: &str, clozes: &[String]) -> Option<String> {
+    let mut result = text.to_string();
+    // 已挖空的区间（含标记），插入时记录而不是从文本中反推，原文中的花括号不影响判断
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for cloze in clozes.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        // 只替换与已有挖空不重叠的首次出现
+        let Some(index) = find_outside_cloze(&result, cloze, &spans) else {
+            continue;
+        };
+        let marked = format!("{{{{c1::{}}}}}", cloze);
+        let shift = marked.len() - cloze.len();
+        for span in spans.iter_mut().filter(|s| s.start > index) {
+            span.start += shift;
+            span.end += shift;
+        }
+        result.replace_range(index..index + cloze.len(), &marked);
+        spans.push(index..index + marked.len());
+    }
+    if !spans.is_empty() {
+        return Some(result);
+    }
+
+    let segment = longest_segment(text)?;
+    let index = text.find(segment)?;
+    Some(format!(
+        "{}{{{{c1::{}}}}}{}",
+        &text[..index],
+        segment,
+        &text[index + segment.len()..]
+    ))
+}
+
+fn find_outside_cloze(text: &str, needle: &str, spans: &[Range<usize>]) -> Option<usize> {
+    let mut from = 0;
+    while let Some(offset) = text[from..].find(needle) {
+        let index = from + offset;
+        let end = index + needle.len();
+        if spans.iter().all(|s| end <= s.start || index >= s.end) {
+            return Some(index);
+        }
+        // 逐字符前移，允许与上一次出现部分重叠的匹配
+        from = index + text[index..].chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// 按空白与标点切分后最长的片段（中文没有空格时即为最长的分句）
+fn longest_segment(text: &str) -> Option<&str> {
+    text.split(|c: char| c.is_whitespace() || (c.is_ascii_punctuation() && c != '-' && c != '\'') || is_cjk_punctuation(c))
+        .filter(|s| s.chars().count() >= 2)
+        .max_by_key(|s| s.chars().count())
+}
+
+fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c, '，' | '。' | '、' | '；' | '：' | '？' | '！' | '“' | '”' | '‘' | '’' | '（' | '）' | '《' | '》' | '「' | '」' | '—' | '…')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(r#type: &str, text: Option<&str>, question: &str) -> BookNote {
+        BookNote {
+            id: "n".to_string(),
+            book_id: "b".to_string(),
+            r#type: r#type.to_string(),
+            cfi: "epubcfi(/6/2!/4/2)".to_string(),
+            text: text.map(str::to_string),
+            style: None,
+            color: None,
+            note: question.to_string(),
+            context: None,
+            anchor_status: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn clozes(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cloze_marks_given_fragments() {
+        let front = cloze_text("The art of war is the art of peace", &clozes(&["art", " war ", "missing"]));
+        assert_eq!(front.as_deref(), Some("The {{c1::art}} of {{c1::war}} is the art of peace"));
+    }
+
+    #[test]
+    fn test_cloze_skips_fragments_inside_existing_cloze() {
+        let front = cloze_text("art of war, art", &clozes(&["art of war", "art"]));
+        assert_eq!(front.as_deref(), Some("{{c1::art of war}}, {{c1::art}}"));
+        // 与已有挖空部分重叠的出现也要跳过
+        let front = cloze_text("abcabc", &clozes(&["bca", "ab"]));
+        assert_eq!(front.as_deref(), Some("a{{c1::bca}}bc"));
+    }
+
+    #[test]
+    fn test_cloze_ignores_braces_in_text() {
+        let text = "fn main() { let x = {{y}}; } returns x";
+        let front = cloze_text(text, &clozes(&["main", "x"]));
+        assert_eq!(
+            front.as_deref(),
+            Some("fn {{c1::main}}() { let {{c1::x}} = {{y}}; } returns x")
+        );
+        let front = cloze_text("a }} b", &clozes(&["b"]));
+        assert_eq!(front.as_deref(), Some("a }} {{c1::b}}"));
+    }
+
+    #[test]
+    fn test_cloze_falls_back_to_longest_segment() {
+        assert_eq!(cloze_text("to be, or not", &[]).as_deref(), Some("to be, or {{c1::not}}"));
+        assert_eq!(
+            cloze_text("学而时习之，不亦说乎", &clozes(&["无关"])).as_deref(),
+            Some("{{c1::学而时习之}}，不亦说乎")
+        );
+        assert_eq!(cloze_text("a b", &[]), None);
+    }
+
+    #[test]
+    fn test_build_card_kinds() {
+        let card = build_card(&note("annotation", Some(" 答案 "), "问题？"), "auto", &[]).unwrap();
+        assert_eq!((card.kind.as_str(), card.front.as_str(), card.back.as_str()), ("qa", "问题？", "答案"));
+
+        let card = build_card(&note("annotation", Some("hello wonderful world"), "note"), "auto", &[]).unwrap();
+        assert_eq!(card.kind, "cloze");
+        assert_eq!(card.front, "hello {{c1::wonderful}} world");
+        assert_eq!(card.back, "note");
+
+        assert_eq!(build_card(&note("annotation", Some("text"), " "), "qa", &[]), None);
+        assert_eq!(build_card(&note("bookmark", Some("text"), ""), "cloze", &[]), None);
+        assert_eq!(build_card(&note("annotation", Some("  "), ""), "cloze", &[]), None);
+    }
+}
@@ -0,0 +1,341 @@
+use super::apkg::write_apkg;
+use super::cards::build_card;
+use super::models::*;
+use super::scheduler::{next_schedule, Rating, Schedule, INITIAL_EASE};
+use crate::core::books::models::BookNote;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+const CARD_COLUMNS: &str = r#"
+    c.id, c.book_id, b.title AS book_title, c.note_id, c.kind, c.front, c.back, c.state, c.due,
+    c.interval_days, c.ease, c.step, c.reps, c.lapses, c.suspended, c.last_reviewed_at,
+    c.created_at, c.updated_at
+"#;
+
+/// 由书摘生成复习卡片：cloze 挖空高亮原文，qa 以笔记为问题、原文为答案。
+/// 同一书摘同一类型只生成一张，重复调用会跳过已有卡片。
+#[tauri::command]
+pub async fn create_review_cards(
+    app_handle: AppHandle,
+    options: ReviewCardCreateOptions,
+) -> Result<Vec<ReviewCard>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let kind = options.kind.as_deref().unwrap_or("auto");
+    if !matches!(kind, "auto" | "cloze" | "qa") {
+        return Err(format!("不支持的卡片类型: {}", kind));
+    }
+    let clozes = options.clozes.unwrap_or_default();
+
+    let mut builder = sqlx::QueryBuilder::new(
+        "SELECT id, book_id, type, cfi, text, style, color, note, context_before, context_after, anchor_status, created_at, updated_at FROM book_notes WHERE type != 'bookmark'",
+    );
+    if let Some(book_id) = &options.book_id {
+        builder.push(" AND book_id = ").push_bind(book_id.clone());
+    }
+    if let Some(note_ids) = &options.note_ids {
+        if note_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        builder.push(" AND id IN (");
+        let mut separated = builder.separated(", ");
+        for id in note_ids {
+            separated.push_bind(id.clone());
+        }
+        separated.push_unseparated(")");
+    }
+    builder.push(" ORDER BY created_at ASC");
+
+    let rows = builder
+        .build()
+        .fetch_all(&db_pool)
+        .await
+        .map_err(|e| format!("查询笔记失败: {}", e))?;
+    let notes = rows
+        .iter()
+        .map(BookNote::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut created_ids = Vec::new();
+    for note in &notes {
+        let Some(content) = build_card(note, kind, &clozes) else {
+            continue;
+        };
+        let id = Uuid::new_v4().to_string();
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO review_cards (
+                id, book_id, note_id, kind, front, back, state, due, interval_days, ease,
+                step, reps, lapses, suspended, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, 'new', ?, 0, ?, 0, 0, 0, 0, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(&note.book_id)
+        .bind(&note.id)
+        .bind(&content.kind)
+        .bind(&content.front)
+        .bind(&content.back)
+        .bind(now)
+        .bind(INITIAL_EASE)
+        .bind(now)
+        .bind(now)
+        .execute(&db_pool)
+        .await
+        .map_err(|e| format!("创建卡片失败: {}", e))?;
+
+        if result.rows_affected() > 0 {
+            created_ids.push(id);
+        }
+    }
+
+    let mut cards = Vec::new();
+    for id in created_ids {
+        cards.push(get_card(&db_pool, &id).await?);
+    }
+    Ok(cards)
+}
+
+/// 获取到期卡片（含未学习的新卡片），按到期时间排序
+#[tauri::command]
+pub async fn get_due_cards(
+    app_handle: AppHandle,
+    deck: Option<ReviewDeck>,
+    limit: Option<i64>,
+) -> Result<Vec<ReviewCard>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let mut builder = deck_query(&db_pool, &deck.unwrap_or_default()).await?;
+    builder
+        .push(" AND c.suspended = 0 AND c.due <= ")
+        .push_bind(now)
+        .push(" ORDER BY c.due ASC LIMIT ")
+        .push_bind(limit.unwrap_or(100).max(1));
+
+    fetch_cards(&db_pool, builder).await
+}
+
+/// 记录一次复习并按 SM-2 计算下次到期时间。rating：1=again 2=hard 3=good 4=easy
+#[tauri::command]
+pub async fn review_card(
+    app_handle: AppHandle,
+    card_id: String,
+    rating: i64,
+    duration_ms: Option<i64>,
+) -> Result<ReviewCard, String> {
+    let rating = Rating::from_i64(rating).ok_or_else(|| format!("无效的评分: {}", rating))?;
+    let db_pool = get_db_pool(&app_handle).await?;
+    let card = get_card(&db_pool, &card_id).await?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let current = Schedule {
+        state: card.state.clone(),
+        due: card.due,
+        interval_days: card.interval_days,
+        ease: card.ease,
+        step: card.step,
+        reps: card.reps,
+        lapses: card.lapses,
+    };
+    let next = next_schedule(&current, rating, now);
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .map_err(|e| format!("开启事务失败: {}", e))?;
+
+    sqlx::query(
+        r#"
+        UPDATE review_cards
+        SET state = ?, due = ?, interval_days = ?, ease = ?, step = ?, reps = ?, lapses = ?,
+            last_reviewed_at = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(&next.state)
+    .bind(next.due)
+    .bind(next.interval_days)
+    .bind(next.ease)
+    .bind(next.step)
+    .bind(next.reps)
+    .bind(next.lapses)
+    .bind(now)
+    .bind(now)
+    .bind(&card_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("更新卡片失败: {}", e))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO review_logs (id, card_id, rating, state, interval_days, last_interval_days, ease, duration_ms, reviewed_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&card_id)
+    .bind(rating as i64)
+    .bind(&card.state)
+    .bind(next.interval_days)
+    .bind(card.interval_days)
+    .bind(next.ease)
+    .bind(duration_ms)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("写入复习记录失败: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("提交事务失败: {}", e))?;
+
+    get_card(&db_pool, &card_id).await
+}
+
+/// 获取一张卡片的复习记录（最新在前）
+#[tauri::command]
+pub async fn get_review_logs(
+    app_handle: AppHandle,
+    card_id: String,
+) -> Result<Vec<ReviewLog>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, card_id, rating, state, interval_days, last_interval_days, ease, duration_ms, reviewed_at
+        FROM review_logs
+        WHERE card_id = ?
+        ORDER BY reviewed_at DESC
+        "#,
+    )
+    .bind(&card_id)
+    .fetch_all(&db_pool)
+    .await
+    .map_err(|e| format!("查询复习记录失败: {}", e))?;
+
+    rows.iter()
+        .map(ReviewLog::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn delete_review_card(app_handle: AppHandle, id: String) -> Result<(), String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let result = sqlx::query("DELETE FROM review_cards WHERE id = ?")
+        .bind(&id)
+        .execute(&db_pool)
+        .await
+        .map_err(|e| format!("删除卡片失败: {}", e))?;
+
+    if result.rows_affected() == 0 {
+        return Err("卡片不存在".to_string());
+    }
+
+    Ok(())
+}
+
+/// 将牌组导出为 Anki .apkg
+#[tauri::command]
+pub async fn export_review_deck_apkg(
+    app_handle: AppHandle,
+    file_path: String,
+    deck: Option<ReviewDeck>,
+) -> Result<ReviewExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let mut builder = deck_query(&db_pool, &deck.unwrap_or_default()).await?;
+    builder.push(" ORDER BY b.title ASC, c.created_at ASC");
+    let cards = fetch_cards(&db_pool, builder).await?;
+    if cards.is_empty() {
+        return Err("牌组中没有卡片".to_string());
+    }
+
+    write_apkg(&cards, std::path::Path::new(&file_path)).await?;
+
+    Ok(ReviewExportReport {
+        file_path,
+        exported_cards: cards.len() as i64,
+    })
+}
+
+/// 按牌组筛选卡片的查询；标签既可能以 id 也可能以名称存放在 books.tags 中
+async fn deck_query<'a>(
+    db_pool: &SqlitePool,
+    deck: &ReviewDeck,
+) -> Result<sqlx::QueryBuilder<'a, sqlx::Sqlite>, String> {
+    let mut builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM review_cards c LEFT JOIN books b ON b.id = c.book_id WHERE 1 = 1",
+        CARD_COLUMNS
+    ));
+
+    if let Some(book_id) = &deck.book_id {
+        builder.push(" AND c.book_id = ").push_bind(book_id.clone());
+    }
+    if let Some(tag) = &deck.tag {
+        let tag_id: Option<String> = sqlx::query_scalar("SELECT id FROM tags WHERE name = ? OR id = ?")
+            .bind(tag)
+            .bind(tag)
+            .fetch_optional(db_pool)
+            .await
+            .map_err(|e| format!("查询标签失败: {}", e))?;
+
+        builder
+            .push(" AND (b.tags LIKE ")
+            .push_bind(format!("%\"{}\"%", tag));
+        if let Some(id) = tag_id {
+            builder.push(" OR b.tags LIKE ").push_bind(format!("%\"{}\"%", id));
+        }
+        builder.push(")");
+    }
+
+    Ok(builder)
+}
+
+async fn fetch_cards(
+    db_pool: &SqlitePool,
+    mut builder: sqlx::QueryBuilder<'_, sqlx::Sqlite>,
+) -> Result<Vec<ReviewCard>, String> {
+    let rows = builder
+        .build()
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("查询卡片失败: {}", e))?;
+
+    rows.iter()
+        .map(ReviewCard::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+async fn get_card(db_pool: &SqlitePool, id: &str) -> Result<ReviewCard, String> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM review_cards c LEFT JOIN books b ON b.id = c.book_id WHERE c.id = ?",
+        CARD_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| format!("查询卡片失败: {}", e))?
+    .ok_or_else(|| "卡片不存在".to_string())?;
+
+    ReviewCard::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e))
+}
+
+async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("获取应用目录失败: {}", e))?;
+
+    let db_path = app_data_dir.join("database").join("app.db");
+    let db_url = format!("sqlite:{}", db_path.display());
+
+    SqlitePool::connect(&db_url)
+        .await
+        .map_err(|e| format!("数据库连接失败: {}", e))
+}
@@ -0,0 +1,5 @@
+pub mod apkg;
+pub mod cards;
+pub mod commands;
+pub mod models;
+pub mod scheduler;
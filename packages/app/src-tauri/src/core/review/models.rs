@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+/// 复习卡片
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewCard {
+    pub id: String,
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    #[serde(rename = "bookTitle")]
+    pub book_title: Option<String>,
+    #[serde(rename = "noteId")]
+    pub note_id: String,
+    pub kind: String, // 'cloze' | 'qa'
+    pub front: String,
+    pub back: String,
+    pub state: String, // 'new' | 'learning' | 'review' | 'relearning'
+    pub due: i64,
+    #[serde(rename = "intervalDays")]
+    pub interval_days: i64,
+    pub ease: f64,
+    pub step: i64,
+    pub reps: i64,
+    pub lapses: i64,
+    pub suspended: bool,
+    #[serde(rename = "lastReviewedAt")]
+    pub last_reviewed_at: Option<i64>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+impl ReviewCard {
+    pub fn from_db_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            book_id: row.try_get("book_id")?,
+            book_title: row.try_get("book_title").unwrap_or(None),
+            note_id: row.try_get("note_id")?,
+            kind: row.try_get("kind")?,
+            front: row.try_get("front")?,
+            back: row.try_get("back")?,
+            state: row.try_get("state")?,
+            due: row.try_get("due")?,
+            interval_days: row.try_get("interval_days")?,
+            ease: row.try_get("ease")?,
+            step: row.try_get("step")?,
+            reps: row.try_get("reps")?,
+            lapses: row.try_get("lapses")?,
+            suspended: row.try_get::<i64, _>("suspended")? != 0,
+            last_reviewed_at: row.try_get("last_reviewed_at")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+/// 从书摘生成卡片的选项
+#[derive(Deserialize, Debug, Default)]
+pub struct ReviewCardCreateOptions {
+    #[serde(rename = "bookId")]
+    pub book_id: Option<String>,
+    #[serde(rename = "noteIds")]
+    pub note_ids: Option<Vec<String>>,
+    pub kind: Option<String>, // 'cloze' | 'qa' | 'auto'（默认：笔记以问号结尾时为 qa，否则为 cloze）
+    pub clozes: Option<Vec<String>>, // 指定要挖空的片段；为空时自动选择
+}
+
+/// 牌组：按书或按书籍标签筛选，均为空时为全部卡片
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ReviewDeck {
+    #[serde(rename = "bookId")]
+    pub book_id: Option<String>,
+    pub tag: Option<String>,
+}
+
+/// 一次复习记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewLog {
+    pub id: String,
+    #[serde(rename = "cardId")]
+    pub card_id: String,
+    pub rating: i64,
+    pub state: String,
+    #[serde(rename = "intervalDays")]
+    pub interval_days: i64,
+    #[serde(rename = "lastIntervalDays")]
+    pub last_interval_days: i64,
+    pub ease: f64,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: Option<i64>,
+    #[serde(rename = "reviewedAt")]
+    pub reviewed_at: i64,
+}
+
+impl ReviewLog {
+    pub fn from_db_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            card_id: row.try_get("card_id")?,
+            rating: row.try_get("rating")?,
+            state: row.try_get("state")?,
+            interval_days: row.try_get("interval_days")?,
+            last_interval_days: row.try_get("last_interval_days")?,
+            ease: row.try_get("ease")?,
+            duration_ms: row.try_get("duration_ms")?,
+            reviewed_at: row.try_get("reviewed_at")?,
+        })
+    }
+}
+
+/// Anki 导出结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReviewExportReport {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    #[serde(rename = "exportedCards")]
+    pub exported_cards: i64,
+}
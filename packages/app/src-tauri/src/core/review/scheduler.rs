@@ -0,0 +1,222 @@
+// SM-2 间隔重复调度（采用 Anki 的四档评分变体：学习阶段 + 复习间隔 × 难度系数）
+
+const MINUTE_MS: i64 = 60 * 1000;
+const DAY_MS: i64 = 24 * 60 * MINUTE_MS;
+
+/// 新卡片的学习阶段（分钟）
+const LEARNING_STEPS: [i64; 2] = [1, 10];
+/// 遗忘后的重学阶段（分钟）
+const RELEARNING_STEPS: [i64; 1] = [10];
+const GRADUATING_INTERVAL: i64 = 1;
+const EASY_INTERVAL: i64 = 4;
+const MAX_INTERVAL: i64 = 36500;
+
+pub const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+const HARD_FACTOR: f64 = 1.2;
+const EASY_BONUS: f64 = 1.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Rating {
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value {
+            1 => Some(Self::Again),
+            2 => Some(Self::Hard),
+            3 => Some(Self::Good),
+            4 => Some(Self::Easy),
+            _ => None,
+        }
+    }
+}
+
+/// 卡片的调度状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub state: String, // new | learning | review | relearning
+    pub due: i64,
+    pub interval_days: i64,
+    pub ease: f64,
+    pub step: i64,
+    pub reps: i64,
+    pub lapses: i64,
+}
+
+/// 根据评分计算下一次调度
+pub fn next_schedule(current: &Schedule, rating: Rating, now: i64) -> Schedule {
+    let mut next = Schedule {
+        reps: current.reps + 1,
+        ..current.clone()
+    };
+
+    match current.state.as_str() {
+        "review" => match rating {
+            Rating::Again => {
+                next.lapses += 1;
+                next.ease = (current.ease - 0.2).max(MIN_EASE);
+                next.interval_days = GRADUATING_INTERVAL;
+                next.state = "relearning".to_string();
+                next.step = 0;
+                next.due = now + RELEARNING_STEPS[0] * MINUTE_MS;
+            }
+            _ => {
+                let previous = current.interval_days.max(1) as f64;
+                let (interval, ease) = match rating {
+                    Rating::Hard => (previous * HARD_FACTOR, current.ease - 0.15),
+                    Rating::Good => (previous * current.ease, current.ease),
+                    _ => (previous * current.ease * EASY_BONUS, current.ease + 0.15),
+                };
+                // 间隔至少比上次多一天，避免低难度系数时停滞
+                next.interval_days = (interval.round() as i64)
+                    .max(current.interval_days + 1)
+                    .min(MAX_INTERVAL);
+                next.ease = ease.max(MIN_EASE);
+                next.due = now + next.interval_days * DAY_MS;
+            }
+        },
+        state => {
+            // new / learning / relearning：按阶段推进，走完后进入复习
+            let relearning = state == "relearning";
+            let steps: &[i64] = if relearning { &RELEARNING_STEPS } else { &LEARNING_STEPS };
+            let step = match rating {
+                Rating::Again => Some(0),
+                Rating::Hard => Some(current.step.clamp(0, steps.len() as i64 - 1)),
+                Rating::Good => Some(current.step + 1).filter(|s| *s < steps.len() as i64),
+                Rating::Easy => None,
+            };
+
+            match step {
+                Some(step) => {
+                    next.state = if relearning { "relearning" } else { "learning" }.to_string();
+                    next.step = step;
+                    next.due = now + steps[step as usize] * MINUTE_MS;
+                }
+                None => {
+                    next.interval_days = match (relearning, rating) {
+                        (true, Rating::Easy) => current.interval_days.max(1) + 1,
+                        (true, _) => current.interval_days.max(1),
+                        (false, Rating::Easy) => EASY_INTERVAL,
+                        (false, _) => GRADUATING_INTERVAL,
+                    };
+                    next.state = "review".to_string();
+                    next.step = 0;
+                    next.due = now + next.interval_days * DAY_MS;
+                }
+            }
+        }
+    }
+
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000_000;
+
+    fn card(state: &str, interval_days: i64, ease: f64, step: i64) -> Schedule {
+        Schedule {
+            state: state.to_string(),
+            due: 0,
+            interval_days,
+            ease,
+            step,
+            reps: 0,
+            lapses: 0,
+        }
+    }
+
+    #[test]
+    fn test_new_card_learning_steps() {
+        let new = card("new", 0, INITIAL_EASE, 0);
+
+        let next = next_schedule(&new, Rating::Good, NOW);
+        assert_eq!((next.state.as_str(), next.step, next.reps), ("learning", 1, 1));
+        assert_eq!(next.due, NOW + 10 * MINUTE_MS);
+
+        let next = next_schedule(&next, Rating::Good, NOW);
+        assert_eq!((next.state.as_str(), next.interval_days), ("review", GRADUATING_INTERVAL));
+        assert_eq!(next.due, NOW + DAY_MS);
+
+        let next = next_schedule(&new, Rating::Again, NOW);
+        assert_eq!((next.state.as_str(), next.step), ("learning", 0));
+        assert_eq!(next.due, NOW + MINUTE_MS);
+
+        let next = next_schedule(&card("learning", 0, INITIAL_EASE, 1), Rating::Hard, NOW);
+        assert_eq!((next.state.as_str(), next.step), ("learning", 1));
+
+        let next = next_schedule(&new, Rating::Easy, NOW);
+        assert_eq!((next.state.as_str(), next.interval_days), ("review", EASY_INTERVAL));
+        assert_eq!(next.ease, INITIAL_EASE);
+    }
+
+    #[test]
+    fn test_review_intervals() {
+        let review = card("review", 10, 2.5, 0);
+
+        let hard = next_schedule(&review, Rating::Hard, NOW);
+        assert_eq!(hard.interval_days, 12);
+        assert!((hard.ease - 2.35).abs() < 1e-9);
+
+        let good = next_schedule(&review, Rating::Good, NOW);
+        assert_eq!((good.interval_days, good.ease), (25, 2.5));
+        assert_eq!(good.due, NOW + 25 * DAY_MS);
+
+        let easy = next_schedule(&review, Rating::Easy, NOW);
+        assert_eq!(easy.interval_days, 33);
+        assert!((easy.ease - 2.65).abs() < 1e-9);
+
+        // 间隔至少增加一天，且不超过上限
+        assert_eq!(next_schedule(&card("review", 1, MIN_EASE, 0), Rating::Hard, NOW).interval_days, 2);
+        assert_eq!(next_schedule(&card("review", 30000, 2.5, 0), Rating::Good, NOW).interval_days, MAX_INTERVAL);
+    }
+
+    #[test]
+    fn test_lapse_and_relearning() {
+        let mut review = card("review", 20, 2.5, 0);
+        review.lapses = 2;
+
+        let lapsed = next_schedule(&review, Rating::Again, NOW);
+        assert_eq!((lapsed.state.as_str(), lapsed.lapses, lapsed.step), ("relearning", 3, 0));
+        assert_eq!(lapsed.interval_days, GRADUATING_INTERVAL);
+        assert!((lapsed.ease - 2.3).abs() < 1e-9);
+        assert_eq!(lapsed.due, NOW + 10 * MINUTE_MS);
+
+        let relearned = next_schedule(&lapsed, Rating::Good, NOW);
+        assert_eq!((relearned.state.as_str(), relearned.interval_days), ("review", 1));
+        assert_eq!(relearned.lapses, 3);
+
+        let relearned = next_schedule(&card("relearning", 3, 2.0, 0), Rating::Easy, NOW);
+        assert_eq!((relearned.state.as_str(), relearned.interval_days), ("review", 4));
+    }
+
+    #[test]
+    fn test_ease_floor() {
+        let mut schedule = card("review", 5, 1.4, 0);
+        for _ in 0..5 {
+            schedule = next_schedule(&schedule, Rating::Again, NOW);
+            assert!(schedule.ease >= MIN_EASE);
+            schedule.state = "review".to_string();
+        }
+        assert_eq!(schedule.ease, MIN_EASE);
+        assert_eq!(schedule.lapses, 5);
+
+        let hard = next_schedule(&card("review", 5, 1.35, 0), Rating::Hard, NOW);
+        assert_eq!(hard.ease, MIN_EASE);
+    }
+
+    #[test]
+    fn test_rating_from_i64() {
+        assert_eq!(Rating::from_i64(1), Some(Rating::Again));
+        assert_eq!(Rating::from_i64(4), Some(Rating::Easy));
+        assert_eq!(Rating::from_i64(0), None);
+        assert_eq!(Rating::from_i64(5), None);
+    }
+}
@@ -3,12 +3,38 @@ CREATE TABLE IF NOT EXISTS threads (
     book_id TEXT,
     metadata TEXT NOT NULL,
     title TEXT NOT NULL,
-    messages TEXT NOT NULL,
+    messages TEXT NOT NULL,                 -- 旧版本的消息 JSON，已迁移到 thread_messages，新数据固定为 '[]'
+    active_leaf_id TEXT,                    -- 当前分支末端的消息 id
     created_at INTEGER NOT NULL,
     updated_at INTEGER NOT NULL,
     FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE
 );
 
+-- 对话消息表 - 每条 UIMessage 一行
+CREATE TABLE IF NOT EXISTS thread_messages (
+    id TEXT NOT NULL,                       -- UIMessage id
+    thread_id TEXT NOT NULL,
+    parent_id TEXT,                         -- 父消息 id，根消息为 NULL；同一父消息下的多条消息互为分支
+    seq INTEGER NOT NULL,                   -- 写入顺序，总是大于父消息的 seq
+    role TEXT NOT NULL,                     -- user|assistant|system|summary（压缩摘要，不属于任何分支）
+    content TEXT NOT NULL,                  -- 文本片段拼接，用于检索与预览
+    parts TEXT NOT NULL,                    -- 完整的 parts JSON
+    tool_calls TEXT,                        -- 工具调用片段 JSON，没有时为 NULL
+    metadata TEXT,                          -- 消息 metadata JSON
+    input_tokens INTEGER,
+    output_tokens INTEGER,
+    total_tokens INTEGER,
+    summary_from_id TEXT,                   -- role 为 summary 时：被摘要替代的范围起点
+    summary_to_id TEXT,                     -- role 为 summary 时：被摘要替代的范围终点（含）
+    created_at INTEGER NOT NULL,
+
+    PRIMARY KEY (thread_id, id),
+    FOREIGN KEY (thread_id) REFERENCES threads(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_thread_messages_seq ON thread_messages(thread_id, seq);
+CREATE INDEX IF NOT EXISTS idx_thread_messages_parent ON thread_messages(thread_id, parent_id);
+
 CREATE TABLE IF NOT EXISTS books (
     id TEXT PRIMARY KEY NOT NULL,
     title TEXT NOT NULL,
@@ -101,6 +127,51 @@ CREATE INDEX IF NOT EXISTS idx_notes_book_id ON notes(book_id);
 CREATE INDEX IF NOT EXISTS idx_notes_updated_at ON notes(updated_at DESC);
 CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes(created_at DESC);
 
+-- 笔记链接表 - 笔记内容中的 [[...]] 双链，保存笔记时重新解析
+CREATE TABLE IF NOT EXISTS note_links (
+    id TEXT PRIMARY KEY NOT NULL,
+    source_note_id TEXT NOT NULL,          -- 链接所在笔记
+    target_type TEXT NOT NULL,             -- 目标类型: note|book|highlight
+    target_text TEXT NOT NULL,             -- 链接中书写的目标（笔记标题/书名/书摘 id）
+    target_id TEXT,                        -- 解析到的目标 id，未解析时为 NULL
+    alias TEXT,                            -- [[目标|显示文字]] 中的显示文字
+    created_at INTEGER NOT NULL,
+
+    FOREIGN KEY (source_note_id) REFERENCES notes(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_note_links_source ON note_links(source_note_id);
+CREATE INDEX IF NOT EXISTS idx_note_links_target ON note_links(target_type, target_id);
+
+-- 笔记版本表 - 更新笔记前保存的标题与内容快照
+CREATE TABLE IF NOT EXISTS note_revisions (
+    id TEXT PRIMARY KEY NOT NULL,
+    note_id TEXT NOT NULL,
+    title TEXT,
+    content TEXT,
+    reason TEXT NOT NULL,                  -- 生成原因: edit|ai|restore|import
+    created_at INTEGER NOT NULL,
+
+    FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_note_revisions_note ON note_revisions(note_id, created_at DESC);
+
+-- 笔记附件表 - 文件保存在 notes/{note_id}/ 下，同一笔记内按内容哈希去重
+CREATE TABLE IF NOT EXISTS note_attachments (
+    id TEXT PRIMARY KEY NOT NULL,
+    note_id TEXT NOT NULL,
+    hash TEXT NOT NULL,                    -- 文件内容 SHA-1
+    file_name TEXT NOT NULL,               -- 原始文件名
+    mime_type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    path TEXT NOT NULL,                    -- 相对于应用数据目录: notes/{note_id}/{hash}.{ext}
+    created_at INTEGER NOT NULL,
+
+    FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE,
+    UNIQUE(note_id, hash)
+);
+
 -- BookNote 表 - 存储书籍标注、书签、摘录等
 CREATE TABLE IF NOT EXISTS book_notes (
     id TEXT PRIMARY KEY NOT NULL,
@@ -113,6 +184,7 @@ CREATE TABLE IF NOT EXISTS book_notes (
     note TEXT NOT NULL,                    -- 用户笔记内容
     context_before TEXT,                   -- 前文上下文
     context_after TEXT,                    -- 后文上下文
+    anchor_status TEXT,                    -- 重定位状态: NULL=正常 | fuzzy=模糊匹配待确认 | lost=无法定位
     created_at INTEGER NOT NULL,           -- 创建时间戳
     updated_at INTEGER NOT NULL,           -- 更新时间戳
     
@@ -130,7 +202,8 @@ CREATE INDEX IF NOT EXISTS idx_book_notes_cfi ON book_notes(cfi);
 CREATE TABLE IF NOT EXISTS skills (
     id TEXT PRIMARY KEY NOT NULL,
     name TEXT NOT NULL UNIQUE,             -- 技能名称（如：生成思维导图）
-    content TEXT NOT NULL,                 -- 技能内容（Markdown 格式的完整说明）
+    content TEXT NOT NULL,                 -- 技能内容（Markdown 格式的完整说明，声明参数时为模板）
+    parameters TEXT,                       -- 模板参数定义（JSON 数组），为空时不作为模板渲染
     is_active INTEGER DEFAULT 1,           -- 是否启用（1=启用，0=禁用）
     is_system INTEGER DEFAULT 0,           -- 是否为系统技能（1=系统，0=用户，系统技能不可删除）
     created_at INTEGER NOT NULL,
@@ -140,4 +213,113 @@ CREATE TABLE IF NOT EXISTS skills (
 -- skills 表的索引
 CREATE INDEX IF NOT EXISTS idx_skills_name ON skills(name);
 CREATE INDEX IF NOT EXISTS idx_skills_is_active ON skills(is_active);
-CREATE INDEX IF NOT EXISTS idx_skills_updated_at ON skills(updated_at DESC);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_skills_updated_at ON skills(updated_at DESC);
+
+-- 内置技能安装记录 - 记录 default-skills.json 中每个技能已安装的版本，用于升级时判断用户是否修改过
+CREATE TABLE IF NOT EXISTS skill_defaults (
+    key TEXT PRIMARY KEY NOT NULL,         -- default-skills.json 中的 key
+    skill_id TEXT,                         -- 安装出的技能；技能被删除后不再重新安装
+    version INTEGER NOT NULL,              -- 已安装的版本
+    content_hash TEXT NOT NULL,            -- 安装时内容与参数的哈希，与当前技能不同说明用户修改过
+    updated_at INTEGER NOT NULL
+);
+-- 复习卡片表 - 由书摘生成的间隔重复卡片（SM-2 调度）
+CREATE TABLE IF NOT EXISTS review_cards (
+    id TEXT PRIMARY KEY NOT NULL,
+    book_id TEXT NOT NULL,
+    note_id TEXT NOT NULL,                 -- 来源书摘 book_notes.id
+    kind TEXT NOT NULL,                    -- 卡片类型: cloze|qa
+    front TEXT NOT NULL,                   -- 正面（cloze 为带 {{c1::...}} 标记的原文，qa 为问题）
+    back TEXT NOT NULL,                    -- 背面（cloze 为补充说明，qa 为答案）
+    state TEXT NOT NULL DEFAULT 'new',     -- 调度状态: new|learning|review|relearning
+    due INTEGER NOT NULL,                  -- 下次复习时间戳
+    interval_days INTEGER NOT NULL DEFAULT 0, -- 复习间隔（天）
+    ease REAL NOT NULL DEFAULT 2.5,        -- SM-2 难度系数
+    step INTEGER NOT NULL DEFAULT 0,       -- 学习/重学阶段序号
+    reps INTEGER NOT NULL DEFAULT 0,       -- 复习次数
+    lapses INTEGER NOT NULL DEFAULT 0,     -- 遗忘次数
+    suspended INTEGER NOT NULL DEFAULT 0,  -- 是否暂停（1=暂停）
+    last_reviewed_at INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+
+    FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE,
+    FOREIGN KEY (note_id) REFERENCES book_notes(id) ON DELETE CASCADE,
+    UNIQUE (note_id, kind)
+);
+
+CREATE INDEX IF NOT EXISTS idx_review_cards_book_id ON review_cards(book_id);
+CREATE INDEX IF NOT EXISTS idx_review_cards_due ON review_cards(due);
+
+-- 复习记录表
+CREATE TABLE IF NOT EXISTS review_logs (
+    id TEXT PRIMARY KEY NOT NULL,
+    card_id TEXT NOT NULL,
+    rating INTEGER NOT NULL,               -- 评分: 1=again 2=hard 3=good 4=easy
+    state TEXT NOT NULL,                   -- 复习前的调度状态
+    interval_days INTEGER NOT NULL,        -- 复习后的间隔
+    last_interval_days INTEGER NOT NULL,   -- 复习前的间隔
+    ease REAL NOT NULL,                    -- 复习后的难度系数
+    duration_ms INTEGER,                   -- 作答用时
+    reviewed_at INTEGER NOT NULL,
+
+    FOREIGN KEY (card_id) REFERENCES review_cards(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_review_logs_card_id ON review_logs(card_id);
+CREATE INDEX IF NOT EXISTS idx_review_logs_reviewed_at ON review_logs(reviewed_at DESC);
+
+-- 全文检索索引（FTS5）
+-- 使用 trigram 分词：中日韩文本没有空格分词，按三字组索引可做子串匹配；
+-- 少于 3 个字符的检索词无法命中 trigram，由查询端退回到 instr 扫描
+CREATE VIRTUAL TABLE IF NOT EXISTS book_notes_fts USING fts5(text, note, tokenize = 'trigram');
+CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(title, content, tokenize = 'trigram');
+CREATE VIRTUAL TABLE IF NOT EXISTS threads_fts USING fts5(title, tokenize = 'trigram');
+CREATE VIRTUAL TABLE IF NOT EXISTS thread_messages_fts USING fts5(content, tokenize = 'trigram');
+
+-- 索引行的 rowid 与源表 rowid 一致，由触发器保持同步
+CREATE TRIGGER IF NOT EXISTS book_notes_fts_ai AFTER INSERT ON book_notes BEGIN
+    INSERT INTO book_notes_fts(rowid, text, note) VALUES (new.rowid, new.text, new.note);
+END;
+CREATE TRIGGER IF NOT EXISTS book_notes_fts_ad AFTER DELETE ON book_notes BEGIN
+    DELETE FROM book_notes_fts WHERE rowid = old.rowid;
+END;
+CREATE TRIGGER IF NOT EXISTS book_notes_fts_au AFTER UPDATE OF text, note ON book_notes BEGIN
+    DELETE FROM book_notes_fts WHERE rowid = old.rowid;
+    INSERT INTO book_notes_fts(rowid, text, note) VALUES (new.rowid, new.text, new.note);
+END;
+
+CREATE TRIGGER IF NOT EXISTS notes_fts_ai AFTER INSERT ON notes BEGIN
+    INSERT INTO notes_fts(rowid, title, content) VALUES (new.rowid, new.title, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS notes_fts_ad AFTER DELETE ON notes BEGIN
+    DELETE FROM notes_fts WHERE rowid = old.rowid;
+END;
+CREATE TRIGGER IF NOT EXISTS notes_fts_au AFTER UPDATE OF title, content ON notes BEGIN
+    DELETE FROM notes_fts WHERE rowid = old.rowid;
+    INSERT INTO notes_fts(rowid, title, content) VALUES (new.rowid, new.title, new.content);
+END;
+
+-- 对话标题与各条消息分别索引，消息索引行的 rowid 与 thread_messages 的 rowid 一致，
+-- 查询时按 thread_id 归并；追加消息只写入一行
+CREATE TRIGGER IF NOT EXISTS threads_fts_ai AFTER INSERT ON threads BEGIN
+    INSERT INTO threads_fts(rowid, title) VALUES (new.rowid, new.title);
+END;
+CREATE TRIGGER IF NOT EXISTS threads_fts_ad AFTER DELETE ON threads BEGIN
+    DELETE FROM threads_fts WHERE rowid = old.rowid;
+END;
+CREATE TRIGGER IF NOT EXISTS threads_fts_au AFTER UPDATE OF title ON threads BEGIN
+    DELETE FROM threads_fts WHERE rowid = old.rowid;
+    INSERT INTO threads_fts(rowid, title) VALUES (new.rowid, new.title);
+END;
+
+CREATE TRIGGER IF NOT EXISTS thread_messages_fts_ai AFTER INSERT ON thread_messages BEGIN
+    INSERT INTO thread_messages_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
+CREATE TRIGGER IF NOT EXISTS thread_messages_fts_ad AFTER DELETE ON thread_messages BEGIN
+    DELETE FROM thread_messages_fts WHERE rowid = old.rowid;
+END;
+CREATE TRIGGER IF NOT EXISTS thread_messages_fts_au AFTER UPDATE OF content ON thread_messages BEGIN
+    DELETE FROM thread_messages_fts WHERE rowid = old.rowid;
+    INSERT INTO thread_messages_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
@@ -0,0 +1,367 @@
+use super::models::*;
+use super::query::{highlight_snippet, make_snippet, parse_query, ParsedQuery};
+use futures_util::TryStreamExt;
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use tauri::{AppHandle, Manager};
+
+/// 可检索的数据源：全文索引表，以及从索引行关联到实体表（别名 s）的 JOIN 子句。
+/// 一个实体可能对应多条索引行（对话的每条消息各占一行），查询时按实体归并
+struct SearchSource {
+    entity_type: &'static str,
+    fts_table: &'static str,
+    joins: &'static str,
+    columns: &'static [&'static str],
+    title_column: Option<&'static str>,
+}
+
+const SOURCES: [SearchSource; 4] = [
+    SearchSource {
+        entity_type: "book_note",
+        fts_table: "book_notes_fts",
+        joins: "JOIN book_notes s ON s.rowid = book_notes_fts.rowid",
+        columns: &["text", "note"],
+        title_column: None,
+    },
+    SearchSource {
+        entity_type: "note",
+        fts_table: "notes_fts",
+        joins: "JOIN notes s ON s.rowid = notes_fts.rowid",
+        columns: &["title", "content"],
+        title_column: Some("title"),
+    },
+    SearchSource {
+        entity_type: "thread",
+        fts_table: "threads_fts",
+        joins: "JOIN threads s ON s.rowid = threads_fts.rowid",
+        columns: &["title"],
+        title_column: Some("title"),
+    },
+    SearchSource {
+        entity_type: "thread",
+        fts_table: "thread_messages_fts",
+        joins: "JOIN thread_messages m ON m.rowid = thread_messages_fts.rowid JOIN threads s ON s.id = m.thread_id",
+        columns: &["content"],
+        title_column: Some("title"),
+    },
+];
+
+const SNIPPET_RADIUS: usize = 40;
+
+/// 倒数排名融合的平滑常数
+const RRF_K: f64 = 60.0;
+
+/// 在标注、笔记和对话中全文检索。
+/// 语法：空格分隔的词为 AND；"..." 为短语；word* 为前缀；tag:名称 按书籍标签过滤。
+#[tauri::command]
+pub async fn search_everything(
+    app_handle: AppHandle,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<SearchHit>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let opts = options.unwrap_or_default();
+    let parsed = parse_query(&query);
+    if parsed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let limit = opts.limit.unwrap_or(50).max(1);
+    let offset = opts.offset.unwrap_or(0).max(0);
+    let tag_patterns = resolve_tag_patterns(&db_pool, &parsed.tags).await?;
+
+    let mut ranked = Vec::new();
+    for source in SOURCES.iter().filter(|s| {
+        opts.entity_types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|t| t == s.entity_type))
+    }) {
+        let source_hits = search_source(
+            &db_pool,
+            source,
+            &parsed,
+            opts.book_id.as_deref(),
+            &tag_patterns,
+            (limit + offset) as usize,
+        )
+        .await?;
+        ranked.push(source_hits);
+    }
+
+    Ok(fuse_ranked(ranked)
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect())
+}
+
+/// 各索引的 bm25 分数尺度不同（列数与文本长度各异），不能直接比较。
+/// 按各自列表中的名次做倒数排名融合，同一实体在多个索引中命中时（对话的标题与消息）分数累加，
+/// 摘要取名次最靠前的那次命中
+fn fuse_ranked(ranked: Vec<Vec<SearchHit>>) -> Vec<SearchHit> {
+    let mut fused: Vec<(SearchHit, usize)> = Vec::new();
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+    for hits in ranked {
+        for (rank, mut hit) in hits.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+            let key = (hit.entity_type.clone(), hit.entity_id.clone());
+            match positions.get(&key) {
+                Some(&i) => {
+                    let (existing, best_rank) = &mut fused[i];
+                    hit.score = existing.score + score;
+                    if rank < *best_rank {
+                        *existing = hit;
+                        *best_rank = rank;
+                    } else {
+                        existing.score = hit.score;
+                    }
+                }
+                None => {
+                    hit.score = score;
+                    positions.insert(key, fused.len());
+                    fused.push((hit, rank));
+                }
+            }
+        }
+    }
+
+    let mut hits: Vec<SearchHit> = fused.into_iter().map(|(hit, _)| hit).collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.updated_at.cmp(&a.updated_at))
+    });
+    hits
+}
+
+/// 检索单个数据源，返回按 bm25 排序的前 limit 个实体。
+/// 一个实体的多条索引行只取排名最靠前且满足前缀条件的一条
+async fn search_source(
+    db_pool: &SqlitePool,
+    source: &SearchSource,
+    parsed: &ParsedQuery,
+    book_id: Option<&str>,
+    tag_patterns: &[Vec<String>],
+    limit: usize,
+) -> Result<Vec<SearchHit>, String> {
+    let match_expression = parsed.match_expression();
+    let short_terms = parsed.short_terms();
+    let fts = source.fts_table;
+
+    let (snippet_sql, score_sql) = if match_expression.is_some() {
+        (
+            format!("snippet({}, -1, char(1), char(2), '…', 24)", fts),
+            format!("bm25({})", fts),
+        )
+    } else {
+        ("NULL".to_string(), "0.0".to_string())
+    };
+    let title_sql = source
+        .title_column
+        .map(|c| format!("s.{}", c))
+        .unwrap_or_else(|| "NULL".to_string());
+    let text_sql: Vec<String> = source
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("{}.{} AS text{}", fts, c, i))
+        .collect();
+
+    let mut sql = format!(
+        r#"
+        SELECT s.id AS entity_id, s.book_id AS book_id, b.title AS book_title, {title} AS title,
+               {snippet} AS snippet, {score} AS score, s.updated_at AS updated_at, {texts}
+        FROM {fts}
+        {joins}
+        LEFT JOIN books b ON b.id = s.book_id
+        WHERE 1 = 1
+        "#,
+        title = title_sql,
+        snippet = snippet_sql,
+        score = score_sql,
+        texts = text_sql.join(", "),
+        fts = fts,
+        joins = source.joins,
+    );
+
+    if match_expression.is_some() {
+        sql.push_str(&format!(" AND {} MATCH ?", fts));
+    }
+    for _ in &short_terms {
+        let conditions: Vec<String> = source
+            .columns
+            .iter()
+            .map(|c| format!("instr(lower({}.{}), lower(?)) > 0", fts, c))
+            .collect();
+        sql.push_str(&format!(" AND ({})", conditions.join(" OR ")));
+    }
+    if book_id.is_some() {
+        sql.push_str(" AND s.book_id = ?");
+    }
+    for patterns in tag_patterns {
+        let conditions: Vec<&str> = patterns.iter().map(|_| r"b.tags LIKE ? ESCAPE '\'").collect();
+        sql.push_str(&format!(" AND ({})", conditions.join(" OR ")));
+    }
+    // 不在 SQL 中限制条数：前缀条件与按实体归并在读取时完成，读够 limit 个实体即停止
+    sql.push_str(" ORDER BY score ASC, s.updated_at DESC");
+
+    let mut query = sqlx::query(&sql);
+    if let Some(expression) = &match_expression {
+        query = query.bind(expression.clone());
+    }
+    for term in &short_terms {
+        for _ in source.columns {
+            query = query.bind(term.to_string());
+        }
+    }
+    if let Some(book_id) = book_id {
+        query = query.bind(book_id.to_string());
+    }
+    for pattern in tag_patterns.iter().flatten() {
+        query = query.bind(pattern.clone());
+    }
+
+    let highlight_terms: Vec<&str> = parsed.terms.iter().map(|t| t.as_str()).collect();
+    let mut rows = query.fetch(db_pool);
+    let mut hits = Vec::new();
+    let mut seen = HashSet::new();
+    while hits.len() < limit {
+        let Some(row) = rows
+            .try_next()
+            .await
+            .map_err(|e| format!("全文检索失败: {}", e))?
+        else {
+            break;
+        };
+
+        let texts: Vec<String> = (0..source.columns.len())
+            .filter_map(|i| row.try_get::<Option<String>, _>(format!("text{}", i).as_str()).ok().flatten())
+            .collect();
+        if !parsed.matches_prefixes(&texts) {
+            continue;
+        }
+        let entity_id: String = row.get("entity_id");
+        if !seen.insert(entity_id.clone()) {
+            continue;
+        }
+
+        let snippet: Option<String> = row.try_get("snippet").ok().flatten();
+        let snippet = match snippet.filter(|s| !s.is_empty()) {
+            Some(snippet) => highlight_snippet(&snippet),
+            None => {
+                // 仅有短词时没有 FTS 摘要，改为在 Rust 侧截取
+                let text = texts
+                    .iter()
+                    .find(|t| {
+                        let lower = t.to_lowercase();
+                        highlight_terms.iter().any(|term| lower.contains(&term.to_lowercase()))
+                    })
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                make_snippet(text, &highlight_terms, SNIPPET_RADIUS)
+            }
+        };
+
+        hits.push(SearchHit {
+            entity_type: source.entity_type.to_string(),
+            entity_id,
+            book_id: row.try_get("book_id").ok().flatten(),
+            book_title: row.try_get("book_title").ok().flatten(),
+            title: row.try_get("title").ok().flatten(),
+            snippet,
+            score: row.try_get("score").unwrap_or(0.0),
+            updated_at: row.try_get("updated_at").unwrap_or(0),
+        });
+    }
+    Ok(hits)
+}
+
+/// 每个标签过滤生成一组 LIKE 模式：books.tags 中既可能存标签 id，也可能存名称
+async fn resolve_tag_patterns(
+    db_pool: &SqlitePool,
+    tags: &[String],
+) -> Result<Vec<Vec<String>>, String> {
+    let mut result = Vec::new();
+    for tag in tags {
+        let mut patterns = vec![format!("%\"{}\"%", escape_like(tag))];
+        let tag_id: Option<String> = sqlx::query_scalar("SELECT id FROM tags WHERE name = ?")
+            .bind(tag)
+            .fetch_optional(db_pool)
+            .await
+            .map_err(|e| format!("查询标签失败: {}", e))?;
+        if let Some(id) = tag_id {
+            patterns.push(format!("%\"{}\"%", escape_like(&id)));
+        }
+        result.push(patterns);
+    }
+    Ok(result)
+}
+
+/// 转义 LIKE 通配符，配合 ESCAPE '\' 使用，使标签名中的 % 与 _ 按字面匹配
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("获取应用目录失败: {}", e))?;
+
+    let db_path = app_data_dir.join("database").join("app.db");
+    let db_url = format!("sqlite:{}", db_path.display());
+
+    SqlitePool::connect(&db_url)
+        .await
+        .map_err(|e| format!("数据库连接失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(entity_type: &str, entity_id: &str, snippet: &str, updated_at: i64) -> SearchHit {
+        SearchHit {
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            book_id: None,
+            book_title: None,
+            title: None,
+            snippet: snippet.to_string(),
+            score: 0.0,
+            updated_at,
+        }
+    }
+
+    fn order(hits: &[SearchHit]) -> Vec<(&str, &str)> {
+        hits.iter().map(|h| (h.entity_id.as_str(), h.snippet.as_str())).collect()
+    }
+
+    #[test]
+    fn test_fuse_ranked() {
+        let titles = vec![hit("thread", "t1", "title", 0), hit("note", "n1", "note 1", 0)];
+        let messages = vec![
+            hit("note", "n2", "note 2", 0),
+            hit("thread", "t1", "message", 0),
+            hit("thread", "t2", "other", 0),
+        ];
+        let later = vec![hit("thread", "t2", "best", 0)];
+        let fused = fuse_ranked(vec![titles, messages, later]);
+
+        // 同一实体的分数累加，摘要取名次最靠前的命中
+        assert_eq!(
+            order(&fused),
+            vec![("t1", "title"), ("t2", "best"), ("n2", "note 2"), ("n1", "note 1")]
+        );
+        let expected = 1.0 / (RRF_K + 1.0) + 1.0 / (RRF_K + 2.0);
+        assert!((fused[0].score - expected).abs() < 1e-12);
+        assert!((fused[1].score - (1.0 / (RRF_K + 3.0) + 1.0 / (RRF_K + 1.0))).abs() < 1e-12);
+
+        // 实体类型不同的相同 id 不合并；分数相同时较新的在前
+        let fused = fuse_ranked(vec![vec![hit("note", "x", "old", 1)], vec![hit("book_note", "x", "new", 2)]]);
+        assert_eq!(order(&fused), vec![("x", "new"), ("x", "old")]);
+
+        assert!(fuse_ranked(Vec::new()).is_empty());
+    }
+}
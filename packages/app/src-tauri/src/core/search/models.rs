@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+// 全局搜索的一条命中结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    #[serde(rename = "entityType")]
+    pub entity_type: String, // 'book_note' | 'note' | 'thread'
+    #[serde(rename = "entityId")]
+    pub entity_id: String,
+    #[serde(rename = "bookId")]
+    pub book_id: Option<String>,
+    #[serde(rename = "bookTitle")]
+    pub book_title: Option<String>,
+    pub title: Option<String>,
+    pub snippet: String, // 命中词以 <mark></mark> 包裹
+    pub score: f64,      // 各索引名次的倒数排名融合分数，越大越相关
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+// 全局搜索选项
+#[derive(Deserialize, Debug, Default)]
+pub struct SearchOptions {
+    #[serde(rename = "entityTypes")]
+    pub entity_types: Option<Vec<String>>,
+    #[serde(rename = "bookId")]
+    pub book_id: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
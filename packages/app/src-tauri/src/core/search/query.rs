@@ -0,0 +1,282 @@
+// 搜索语句解析：支持短语（"..."）、前缀（word*）与标签过滤（tag:名称）
+
+/// trigram 分词下可走索引的最短检索词长度
+const MIN_INDEXED_CHARS: usize = 3;
+
+/// FTS 摘要中包裹命中词的标记；摘要转义 HTML 后再替换为 <mark></mark>，避免与正文中的尖括号混淆
+pub const SNIPPET_OPEN: char = '\u{1}';
+pub const SNIPPET_CLOSE: char = '\u{2}';
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParsedQuery {
+    pub terms: Vec<String>, // 词或短语（已去掉引号与前缀通配符）
+    pub prefixes: Vec<String>, // terms 中以 * 结尾的词，需出现在词首
+    pub tags: Vec<String>,
+}
+
+impl ParsedQuery {
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// 交给 FTS5 的 MATCH 表达式，只包含 trigram 能命中的词（各词之间为 AND）。
+    /// trigram 是子串匹配，前缀词还需由 matches_prefixes 检查词首
+    pub fn match_expression(&self) -> Option<String> {
+        let parts: Vec<String> = self
+            .terms
+            .iter()
+            .filter(|t| t.chars().count() >= MIN_INDEXED_CHARS)
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" AND "))
+    }
+
+    /// 不足 3 个字符的词（常见于中文双字词），需逐行扫描
+    pub fn short_terms(&self) -> Vec<&str> {
+        self.terms
+            .iter()
+            .filter(|t| t.chars().count() < MIN_INDEXED_CHARS)
+            .map(|t| t.as_str())
+            .collect()
+    }
+
+    /// 每个前缀词都在某段文本中出现在词首：位于开头，或前一个字符不是字母数字。
+    /// 中日韩文字之间没有空格分词，视为各自成词
+    pub fn matches_prefixes(&self, texts: &[String]) -> bool {
+        self.prefixes.iter().all(|prefix| {
+            let needle: Vec<char> = prefix.chars().map(fold).collect();
+            texts.iter().any(|text| {
+                let chars: Vec<char> = text.chars().map(fold).collect();
+                (0..chars.len()).any(|pos| {
+                    chars[pos..].starts_with(&needle) && (pos == 0 || !is_word_char(chars[pos - 1]))
+                })
+            })
+        })
+    }
+}
+
+pub fn parse_query(input: &str) -> ParsedQuery {
+    let chars: Vec<char> = input.chars().collect();
+    let mut query = ParsedQuery::default();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let is_tag = chars[i..].iter().take(4).collect::<String>().eq_ignore_ascii_case("tag:");
+        if is_tag {
+            i += 4;
+        }
+
+        let (token, next) = if chars.get(i) == Some(&'"') {
+            read_quoted(&chars, i + 1)
+        } else {
+            read_word(&chars, i)
+        };
+        i = next;
+
+        let is_prefix = !is_tag && token.trim_end().ends_with('*');
+        let token = if is_tag {
+            token.trim().to_string()
+        } else {
+            token.trim().trim_end_matches('*').trim().to_string()
+        };
+        if token.is_empty() {
+            continue;
+        }
+        if is_tag {
+            query.tags.push(token);
+        } else {
+            if is_prefix {
+                query.prefixes.push(token.clone());
+            }
+            query.terms.push(token);
+        }
+    }
+
+    query
+}
+
+fn read_quoted(chars: &[char], start: usize) -> (String, usize) {
+    let mut end = start;
+    while end < chars.len() && chars[end] != '"' {
+        end += 1;
+    }
+    // 跳过结尾引号；未闭合时读到末尾
+    (chars[start..end].iter().collect(), (end + 1).min(chars.len()))
+}
+
+fn read_word(chars: &[char], start: usize) -> (String, usize) {
+    let mut end = start;
+    while end < chars.len() && !chars[end].is_whitespace() {
+        end += 1;
+    }
+    (chars[start..end].iter().collect(), end)
+}
+
+/// FTS snippet() 的结果：转义 HTML，再把 SNIPPET_OPEN/SNIPPET_CLOSE 换成 <mark></mark>
+pub fn highlight_snippet(snippet: &str) -> String {
+    let mut out = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            SNIPPET_OPEN => out.push_str("<mark>"),
+            SNIPPET_CLOSE => out.push_str("</mark>"),
+            c => push_escaped(&mut out, c),
+        }
+    }
+    out
+}
+
+/// 摘要以 HTML 渲染，正文中的特殊字符需要转义
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&#39;"),
+        c => out.push(c),
+    }
+}
+
+/// 未走 FTS 索引时在 Rust 侧生成摘要：截取首个命中词附近的文本并标记所有命中
+pub fn make_snippet(text: &str, terms: &[&str], radius: usize) -> String {
+    let chars: Vec<char> = text.chars().map(|c| if c.is_whitespace() { ' ' } else { c }).collect();
+    let lower: Vec<char> = chars.iter().map(|c| fold(*c)).collect();
+    let needles: Vec<Vec<char>> = terms
+        .iter()
+        .map(|t| t.chars().map(fold).collect::<Vec<char>>())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let find_at = |pos: usize| {
+        needles
+            .iter()
+            .find(|n| lower[pos..].starts_with(n))
+            .map(|n| n.len())
+    };
+
+    let first = (0..lower.len()).find(|&pos| find_at(pos).is_some()).unwrap_or(0);
+    let start = first.saturating_sub(radius);
+    let end = (first + radius * 2).min(chars.len());
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut pos = start;
+    while pos < end {
+        match find_at(pos) {
+            Some(len) => {
+                let stop = (pos + len).min(chars.len());
+                out.push_str("<mark>");
+                chars[pos..stop].iter().for_each(|&c| push_escaped(&mut out, c));
+                out.push_str("</mark>");
+                pos = stop;
+            }
+            None => {
+                push_escaped(&mut out, chars[pos]);
+                pos += 1;
+            }
+        }
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() && !is_cjk(c)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'   // 平假名、片假名
+        | '\u{3400}'..='\u{4DBF}' // 扩展 A
+        | '\u{4E00}'..='\u{9FFF}' // 基本汉字
+        | '\u{AC00}'..='\u{D7AF}' // 谚文音节
+        | '\u{F900}'..='\u{FAFF}' // 兼容汉字
+        | '\u{20000}'..='\u{2FFFF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query(r#"  art*  "hello  world" TAG:history 学习 tag:"science fiction" "#);
+        assert_eq!(query.terms, vec!["art", "hello  world", "学习"]);
+        assert_eq!(query.prefixes, vec!["art"]);
+        assert_eq!(query.tags, vec!["history", "science fiction"]);
+        assert_eq!(query.short_terms(), vec!["学习"]);
+        assert_eq!(query.match_expression().as_deref(), Some(r#""art" AND "hello  world""#));
+
+        // 未闭合的引号读到末尾；短语中的引号转义
+        let query = parse_query(r#"say "it's \"#);
+        assert_eq!(query.terms, vec!["say", r#"it's \"#]);
+        assert_eq!(parse_query(r#"a"b"c"#).match_expression().as_deref(), Some(r#""a""b""c""#));
+
+        // 只有通配符或空标签的词被忽略
+        let query = parse_query("* tag: ");
+        assert!(query.is_empty());
+        assert!(query.tags.is_empty());
+        assert_eq!(query.match_expression(), None);
+    }
+
+    #[test]
+    fn test_matches_prefixes() {
+        let query = parse_query("art*");
+        assert!(query.matches_prefixes(&texts(&["The ART of war"])));
+        assert!(query.matches_prefixes(&texts(&["start", "(artful)"])));
+        assert!(!query.matches_prefixes(&texts(&["start here", "smart"])));
+        assert!(!query.matches_prefixes(&[]));
+
+        // 中日韩文字之间不分词，词中任意位置都算词首
+        assert!(parse_query("中文*").matches_prefixes(&texts(&["学习中文"])));
+        assert!(!parse_query("文*").matches_prefixes(&texts(&["english文"])));
+
+        // 每个前缀词都需要命中
+        let query = parse_query("art* war*");
+        assert!(query.matches_prefixes(&texts(&["art", "war"])));
+        assert!(!query.matches_prefixes(&texts(&["art", "dwarf"])));
+        assert!(parse_query("art").matches_prefixes(&texts(&["start"])));
+    }
+
+    #[test]
+    fn test_make_snippet() {
+        assert_eq!(make_snippet("<i>ab</i>", &["ab"], 40), "&lt;i&gt;<mark>ab</mark>&lt;/i&gt;");
+
+        // 不区分大小写，标记所有命中并保留原文大小写；换行折叠为空格
+        assert_eq!(make_snippet("Ab\ncd ab", &["AB"], 40), "<mark>Ab</mark> cd <mark>ab</mark>");
+
+        // 截取首个命中词前后的文本
+        let text = "0123456789 target 0123456789";
+        assert_eq!(make_snippet(text, &["target"], 3), "…89 <mark>target</mark>…");
+
+        // 没有命中时从开头截取
+        assert_eq!(make_snippet("abcdef", &["zz", ""], 2), "abcd…");
+        assert_eq!(make_snippet("", &["a"], 2), "");
+    }
+
+    #[test]
+    fn test_highlight_snippet() {
+        assert_eq!(
+            highlight_snippet("a<b \u{1}x&y\u{2} \"q\" 'r'"),
+            "a&lt;b <mark>x&amp;y</mark> &quot;q&quot; &#39;r&#39;"
+        );
+        assert_eq!(highlight_snippet("<mark>"), "&lt;mark&gt;");
+    }
+}
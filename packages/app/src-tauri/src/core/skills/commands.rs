@@ -1,5 +1,9 @@
 use super::models::*;
-use sqlx::SqlitePool;
+use super::template::{self, Template};
+use super::{defaults, pack};
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::path::Path;
 use tauri::{AppHandle, Manager};
 use uuid::Uuid;
 
@@ -18,6 +22,9 @@ pub async fn create_skill(app_handle: AppHandle, data: SkillCreateData) -> Resul
         return Err(format!("技能 '{}' 已存在", data.name));
     }
 
+    let parameters = data.parameters.unwrap_or_default();
+    ensure_valid_template(&data.content, &parameters)?;
+
     let skill_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now().timestamp_millis();
     let is_active = data.is_active.unwrap_or(true);
@@ -25,13 +32,14 @@ pub async fn create_skill(app_handle: AppHandle, data: SkillCreateData) -> Resul
 
     sqlx::query(
         r#"
-        INSERT INTO skills (id, name, content, is_active, is_system, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO skills (id, name, content, parameters, is_active, is_system, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&skill_id)
     .bind(&data.name)
     .bind(&data.content)
+    .bind(parameters_json(&parameters)?)
     .bind(if is_active { 1 } else { 0 })
     .bind(if is_system { 1 } else { 0 })
     .bind(now)
@@ -40,7 +48,14 @@ pub async fn create_skill(app_handle: AppHandle, data: SkillCreateData) -> Resul
     .await
     .map_err(|e| format!("创建技能失败: {}", e))?;
 
-    Ok(Skill::new(skill_id, data.name, data.content, is_active, is_system))
+    Ok(Skill::new(
+        skill_id,
+        data.name,
+        data.content,
+        parameters,
+        is_active,
+        is_system,
+    ))
 }
 
 #[tauri::command]
@@ -100,6 +115,16 @@ pub async fn update_skill(
         }
     }
 
+    // 内容或参数变化时，按更新后的组合校验模板
+    if update_data.content.is_some() || update_data.parameters.is_some() {
+        let skill = get_skill_by_id(app_handle.clone(), id.clone())
+            .await?
+            .ok_or_else(|| "技能不存在".to_string())?;
+        let content = update_data.content.as_ref().unwrap_or(&skill.content);
+        let parameters = update_data.parameters.as_ref().unwrap_or(&skill.parameters);
+        ensure_valid_template(content, parameters)?;
+    }
+
     if let Some(name) = &update_data.name {
         sqlx::query("UPDATE skills SET name = ?, updated_at = ? WHERE id = ?")
             .bind(name)
@@ -120,6 +145,16 @@ pub async fn update_skill(
             .map_err(|e| format!("更新技能内容失败: {}", e))?;
     }
 
+    if let Some(parameters) = &update_data.parameters {
+        sqlx::query("UPDATE skills SET parameters = ?, updated_at = ? WHERE id = ?")
+            .bind(parameters_json(parameters)?)
+            .bind(now)
+            .bind(&id)
+            .execute(&db_pool)
+            .await
+            .map_err(|e| format!("更新技能参数失败: {}", e))?;
+    }
+
     if let Some(is_active) = update_data.is_active {
         sqlx::query("UPDATE skills SET is_active = ?, updated_at = ? WHERE id = ?")
             .bind(if is_active { 1 } else { 0 })
@@ -130,7 +165,11 @@ pub async fn update_skill(
             .map_err(|e| format!("更新技能状态失败: {}", e))?;
     }
 
-    if update_data.name.is_none() && update_data.content.is_none() && update_data.is_active.is_none() {
+    if update_data.name.is_none()
+        && update_data.content.is_none()
+        && update_data.parameters.is_none()
+        && update_data.is_active.is_none()
+    {
         sqlx::query("UPDATE skills SET updated_at = ? WHERE id = ?")
             .bind(now)
             .bind(&id)
@@ -195,6 +234,187 @@ pub async fn toggle_skill_active(app_handle: AppHandle, id: String) -> Result<Sk
         .ok_or_else(|| "更新后无法找到技能".to_string())
 }
 
+/// 校验技能模板与参数定义，返回全部问题；没有问题时为空
+#[tauri::command]
+pub async fn validate_skill_template(
+    content: String,
+    parameters: Vec<SkillParameter>,
+) -> Result<Vec<SkillTemplateIssue>, String> {
+    Ok(template::validate(&content, &parameters))
+}
+
+/// 用书籍信息、当前章节、选中文本和用户填写的值渲染技能；没有参数的技能原样返回
+#[tauri::command]
+pub async fn render_skill(
+    app_handle: AppHandle,
+    id: String,
+    context: SkillRenderContext,
+) -> Result<RenderedSkill, String> {
+    let skill = get_skill_by_id(app_handle.clone(), id)
+        .await?
+        .ok_or_else(|| "技能不存在".to_string())?;
+
+    if skill.parameters.is_empty() {
+        return Ok(RenderedSkill {
+            content: skill.content,
+            values: HashMap::new(),
+        });
+    }
+
+    let sources = collect_sources(&app_handle, &skill.parameters, &context).await?;
+    let values = template::resolve_values(&skill.parameters, &context.values, &sources)
+        .map_err(|e| format!("渲染技能失败: {}", e))?;
+    let content = Template::parse(&skill.content)
+        .and_then(|t| t.render(&values))
+        .map_err(|e| format!("渲染技能失败: {}", e))?;
+
+    Ok(RenderedSkill { content, values })
+}
+
+/// 导出技能包；未指定技能时导出全部非系统技能
+#[tauri::command]
+pub async fn export_skill_pack(
+    app_handle: AppHandle,
+    payload: ExportSkillPackPayload,
+) -> Result<SkillPackExportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+
+    let rows = sqlx::query("SELECT * FROM skills ORDER BY created_at")
+        .fetch_all(&db_pool)
+        .await
+        .map_err(|e| format!("获取技能列表失败: {}", e))?;
+    let skills = rows
+        .iter()
+        .map(Skill::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("转换查询结果失败: {}", e))?;
+
+    let selected: Vec<&Skill> = match &payload.skill_ids {
+        Some(ids) => skills.iter().filter(|s| ids.contains(&s.id)).collect(),
+        None => skills.iter().filter(|s| !s.is_system).collect(),
+    };
+    if selected.is_empty() {
+        return Err("没有可导出的技能".to_string());
+    }
+
+    let manifest = SkillPackManifest {
+        format: pack::PACK_FORMAT.to_string(),
+        format_version: pack::PACK_FORMAT_VERSION,
+        name: payload.name.unwrap_or_else(|| "技能包".to_string()),
+        version: payload.version.unwrap_or_else(|| "1.0.0".to_string()),
+        description: payload.description,
+        exported_at: chrono::Utc::now().timestamp_millis(),
+        skills: selected.iter().map(|skill| pack::pack_entry(skill)).collect(),
+    };
+    pack::write_pack(Path::new(&payload.file_path), &manifest)?;
+
+    Ok(SkillPackExportReport {
+        file_path: payload.file_path,
+        exported_skills: manifest.skills.len() as i64,
+    })
+}
+
+/// 读取技能包并列出每个技能与已有技能的冲突，供导入前选择冲突处理方式
+#[tauri::command]
+pub async fn preview_skill_pack(app_handle: AppHandle, file_path: String) -> Result<SkillPackPreview, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let manifest = pack::read_pack(Path::new(&file_path))?;
+    let skills = pack::preview(&db_pool, &manifest).await?;
+
+    Ok(SkillPackPreview {
+        name: manifest.name,
+        version: manifest.version,
+        description: manifest.description,
+        skills,
+    })
+}
+
+#[tauri::command]
+pub async fn import_skill_pack(
+    app_handle: AppHandle,
+    file_path: String,
+    conflict: Option<SkillConflictStrategy>,
+) -> Result<SkillPackImportReport, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    let manifest = pack::read_pack(Path::new(&file_path))?;
+    pack::import(&db_pool, &manifest, conflict.unwrap_or_default()).await
+}
+
+/// 有新版本但因用户修改过而没有自动升级的内置技能
+#[tauri::command]
+pub async fn get_skill_upgrades(app_handle: AppHandle) -> Result<Vec<SkillUpgrade>, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    defaults::pending_upgrades(&db_pool).await
+}
+
+/// 把内置技能更新为最新版本，覆盖用户的修改
+#[tauri::command]
+pub async fn apply_skill_upgrade(app_handle: AppHandle, id: String) -> Result<Skill, String> {
+    let db_pool = get_db_pool(&app_handle).await?;
+    defaults::apply_upgrade(&db_pool, &id).await?;
+
+    get_skill_by_id(app_handle, id)
+        .await?
+        .ok_or_else(|| "更新后无法找到技能".to_string())
+}
+
+/// 只读取参数实际用到的取值来源
+async fn collect_sources(
+    app_handle: &AppHandle,
+    parameters: &[SkillParameter],
+    context: &SkillRenderContext,
+) -> Result<HashMap<SkillParameterSource, String>, String> {
+    let used = |source| parameters.iter().any(|p| p.source == Some(source));
+    let mut sources = HashMap::new();
+
+    if let Some(chapter) = &context.chapter {
+        sources.insert(SkillParameterSource::ChapterTitle, chapter.title.clone());
+        if let Some(href) = &chapter.href {
+            sources.insert(SkillParameterSource::ChapterHref, href.clone());
+        }
+    }
+    if let Some(selection) = &context.selection {
+        sources.insert(SkillParameterSource::Selection, selection.clone());
+    }
+
+    let Some(book_id) = &context.book_id else {
+        return Ok(sources);
+    };
+
+    if used(SkillParameterSource::BookTitle) || used(SkillParameterSource::BookAuthor) {
+        let db_pool = get_db_pool(app_handle).await?;
+        let row = sqlx::query("SELECT title, author FROM books WHERE id = ?")
+            .bind(book_id)
+            .fetch_optional(&db_pool)
+            .await
+            .map_err(|e| format!("查询书籍失败: {}", e))?
+            .ok_or_else(|| "书籍不存在".to_string())?;
+        sources.insert(SkillParameterSource::BookTitle, row.get("title"));
+        sources.insert(SkillParameterSource::BookAuthor, row.get("author"));
+    }
+
+    if used(SkillParameterSource::BookMetadata) {
+        let app_data_dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("获取应用目录失败: {}", e))?;
+        let metadata_path = app_data_dir.join("books").join(book_id).join("metadata.md");
+        // 尚未生成 metadata.md 的书籍视为没有该来源
+        if let Ok(metadata) = std::fs::read_to_string(&metadata_path) {
+            sources.insert(SkillParameterSource::BookMetadata, metadata);
+        }
+    }
+
+    Ok(sources)
+}
+
+fn ensure_valid_template(content: &str, parameters: &[SkillParameter]) -> Result<(), String> {
+    match template::validate(content, parameters).first() {
+        Some(issue) => Err(format!("技能模板无效: {}", issue)),
+        None => Ok(()),
+    }
+}
+
 async fn get_db_pool(app_handle: &AppHandle) -> Result<SqlitePool, String> {
     let app_data_dir = app_handle
         .path()
@@ -0,0 +1,402 @@
+// 内置技能：default-skills.json 随应用发布，每个技能有稳定的 key 与递增的 version。
+// 启动时安装新增的内置技能，并把用户没有修改过的内置技能升级到新版本；修改过的保留用户的内容，由用户手动升级
+
+use super::models::{parameters_json, Skill, SkillParameter, SkillUpgrade};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug)]
+pub struct DefaultSkill {
+    pub key: String,
+    pub version: i64,
+    pub name: String,
+    pub content: String,
+    #[serde(default)]
+    pub parameters: Vec<SkillParameter>,
+    pub is_system: bool,
+    pub is_active: bool,
+}
+
+/// skill_defaults 中的安装记录
+struct Installed {
+    skill_id: Option<String>,
+    version: i64,
+    content_hash: String,
+}
+
+pub fn default_skills() -> Result<Vec<DefaultSkill>, String> {
+    serde_json::from_str(include_str!("../default-skills.json")).map_err(|e| format!("解析内置技能失败: {}", e))
+}
+
+/// 技能内容与参数的哈希，用于判断是否被修改过
+pub fn skill_hash(content: &str, parameters: &[SkillParameter]) -> String {
+    let parameters = parameters_json(parameters).ok().flatten().unwrap_or_default();
+    format!("{:x}", Sha1::digest(format!("{}\n{}", content, parameters).as_bytes()))
+}
+
+pub async fn sync_default_skills(pool: &SqlitePool) -> Result<(), String> {
+    let defaults = default_skills()?;
+
+    let recorded: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM skill_defaults")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let skills: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM skills")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    // 旧数据库第一次同步时还没有安装记录，找不到同名技能说明用户删除过，不再重新安装
+    let legacy = recorded == 0 && skills > 0;
+
+    for default in &defaults {
+        match load_installed(pool, &default.key).await? {
+            Some(installed) => upgrade(pool, default, installed).await?,
+            None => adopt_or_install(pool, default, legacy).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// 有新版本、但因用户修改过而没有自动升级的内置技能
+pub async fn pending_upgrades(pool: &SqlitePool) -> Result<Vec<SkillUpgrade>, String> {
+    let mut upgrades = Vec::new();
+
+    for default in default_skills()? {
+        let Some(installed) = load_installed(pool, &default.key).await? else {
+            continue;
+        };
+        if installed.version >= default.version {
+            continue;
+        }
+        let Some(skill) = load_skill(pool, installed.skill_id.as_deref()).await? else {
+            continue;
+        };
+        upgrades.push(SkillUpgrade {
+            skill_id: skill.id,
+            name: skill.name,
+            installed_version: installed.version,
+            available_version: default.version,
+            content: default.content,
+        });
+    }
+
+    Ok(upgrades)
+}
+
+/// 用最新的内置版本覆盖技能内容与参数，用户的修改会丢失
+pub async fn apply_upgrade(pool: &SqlitePool, skill_id: &str) -> Result<(), String> {
+    let key: Option<String> = sqlx::query_scalar("SELECT key FROM skill_defaults WHERE skill_id = ?")
+        .bind(skill_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("查询内置技能失败: {}", e))?;
+    let key = key.ok_or_else(|| "该技能不是内置技能".to_string())?;
+
+    let default = default_skills()?
+        .into_iter()
+        .find(|d| d.key == key)
+        .ok_or_else(|| "内置技能已不存在".to_string())?;
+
+    write_default(pool, skill_id, &default).await?;
+    record(pool, &default.key, Some(skill_id), default.version, &default_hash(&default)).await
+}
+
+async fn upgrade(pool: &SqlitePool, default: &DefaultSkill, installed: Installed) -> Result<(), String> {
+    if default.version <= installed.version {
+        return Ok(());
+    }
+
+    let Some(skill) = load_skill(pool, installed.skill_id.as_deref()).await? else {
+        // 用户已删除该技能，只记录版本
+        return record(pool, &default.key, None, default.version, &installed.content_hash).await;
+    };
+
+    if skill_hash(&skill.content, &skill.parameters) != installed.content_hash {
+        println!(
+            "Default skill {} has version {} but was modified by the user, keeping user content",
+            default.key, default.version
+        );
+        return Ok(());
+    }
+
+    write_default(pool, &skill.id, default).await?;
+    record(pool, &default.key, Some(&skill.id), default.version, &default_hash(default)).await?;
+    println!("✅ Default skill upgraded: {} (v{})", default.name, default.version);
+    Ok(())
+}
+
+/// 没有安装记录：按名称认领已有技能（旧数据库），否则安装
+async fn adopt_or_install(pool: &SqlitePool, default: &DefaultSkill, legacy: bool) -> Result<(), String> {
+    let hash = default_hash(default);
+    let row = sqlx::query("SELECT * FROM skills WHERE name = ?")
+        .bind(&default.name)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(row) = row {
+        let skill = Skill::from_db_row(&row).map_err(|e| e.to_string())?;
+        if skill_hash(&skill.content, &skill.parameters) == hash {
+            return record(pool, &default.key, Some(&skill.id), default.version, &hash).await;
+        }
+        // 初始化后从未编辑过的技能是旧版本的内置内容，可以直接升级
+        if skill.created_at == skill.updated_at {
+            write_default(pool, &skill.id, default).await?;
+            return record(pool, &default.key, Some(&skill.id), default.version, &hash).await;
+        }
+        // 用户修改过：记为版本 0，保留内容并提示手动升级
+        return record(pool, &default.key, Some(&skill.id), 0, &hash).await;
+    }
+
+    if legacy {
+        return record(pool, &default.key, None, default.version, &hash).await;
+    }
+
+    let skill_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp_millis();
+    sqlx::query(
+        r#"
+        INSERT INTO skills (id, name, content, parameters, is_active, is_system, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&skill_id)
+    .bind(&default.name)
+    .bind(&default.content)
+    .bind(parameters_json(&default.parameters)?)
+    .bind(if default.is_active { 1 } else { 0 })
+    .bind(if default.is_system { 1 } else { 0 })
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    record(pool, &default.key, Some(&skill_id), default.version, &hash).await?;
+    println!("✅ Default skill initialized: {}", default.name);
+    Ok(())
+}
+
+async fn write_default(pool: &SqlitePool, skill_id: &str, default: &DefaultSkill) -> Result<(), String> {
+    sqlx::query("UPDATE skills SET content = ?, parameters = ?, updated_at = ? WHERE id = ?")
+        .bind(&default.content)
+        .bind(parameters_json(&default.parameters)?)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(skill_id)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("更新内置技能失败: {}", e))?;
+    Ok(())
+}
+
+async fn record(
+    pool: &SqlitePool,
+    key: &str,
+    skill_id: Option<&str>,
+    version: i64,
+    content_hash: &str,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        INSERT INTO skill_defaults (key, skill_id, version, content_hash, updated_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(key) DO UPDATE SET
+            skill_id = excluded.skill_id,
+            version = excluded.version,
+            content_hash = excluded.content_hash,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(key)
+    .bind(skill_id)
+    .bind(version)
+    .bind(content_hash)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await
+    .map_err(|e| format!("记录内置技能版本失败: {}", e))?;
+    Ok(())
+}
+
+async fn load_installed(pool: &SqlitePool, key: &str) -> Result<Option<Installed>, String> {
+    let row = sqlx::query("SELECT skill_id, version, content_hash FROM skill_defaults WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(row.map(|row| Installed {
+        skill_id: row.get("skill_id"),
+        version: row.get("version"),
+        content_hash: row.get("content_hash"),
+    }))
+}
+
+async fn load_skill(pool: &SqlitePool, skill_id: Option<&str>) -> Result<Option<Skill>, String> {
+    let Some(skill_id) = skill_id else {
+        return Ok(None);
+    };
+    let row = sqlx::query("SELECT * FROM skills WHERE id = ?")
+        .bind(skill_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    row.map(|row| Skill::from_db_row(&row).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+fn default_hash(default: &DefaultSkill) -> String {
+    skill_hash(&default.content, &default.parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(include_str!("../schema.sql")).execute(&pool).await.unwrap();
+        pool
+    }
+
+    async fn skill_by_name(pool: &SqlitePool, name: &str) -> Option<Skill> {
+        sqlx::query("SELECT * FROM skills WHERE name = ?")
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+            .map(|row| Skill::from_db_row(&row).unwrap())
+    }
+
+    async fn add_skill(pool: &SqlitePool, name: &str, content: &str, created_at: i64, updated_at: i64) {
+        sqlx::query("INSERT INTO skills (id, name, content, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(name)
+            .bind(content)
+            .bind(created_at)
+            .bind(updated_at)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn find_default<'a>(defaults: &'a [DefaultSkill], key: &str) -> &'a DefaultSkill {
+        defaults.iter().find(|d| d.key == key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_install_on_empty_database() {
+        let pool = test_pool().await;
+        sync_default_skills(&pool).await.unwrap();
+        sync_default_skills(&pool).await.unwrap();
+
+        let defaults = default_skills().unwrap();
+        for default in &defaults {
+            let skill = skill_by_name(&pool, &default.name).await.unwrap();
+            assert_eq!(skill.content, default.content);
+            let installed = load_installed(&pool, &default.key).await.unwrap().unwrap();
+            assert_eq!(installed.skill_id.as_deref(), Some(skill.id.as_str()));
+            assert_eq!(installed.version, default.version);
+            assert_eq!(installed.content_hash, default_hash(default));
+        }
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM skills").fetch_one(&pool).await.unwrap();
+        assert_eq!(count, defaults.len() as i64);
+        assert!(pending_upgrades(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_keeps_user_edits() {
+        let pool = test_pool().await;
+        sync_default_skills(&pool).await.unwrap();
+        let defaults = default_skills().unwrap();
+        let edited = find_default(&defaults, "mind-map");
+        let untouched = find_default(&defaults, "system-prompt");
+
+        // 模拟新版本发布：安装记录回退到版本 0
+        sqlx::query("UPDATE skill_defaults SET version = 0").execute(&pool).await.unwrap();
+        // 未修改的技能停留在旧版本内容，哈希与安装记录一致
+        sqlx::query("UPDATE skills SET content = 'old' WHERE name = ?")
+            .bind(&untouched.name)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let untouched_id = skill_by_name(&pool, &untouched.name).await.unwrap().id;
+        record(&pool, &untouched.key, Some(&untouched_id), 0, &skill_hash("old", &[])).await.unwrap();
+        // 用户修改过的技能，哈希与安装记录不同
+        sqlx::query("UPDATE skills SET content = 'mine' WHERE name = ?")
+            .bind(&edited.name)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        sync_default_skills(&pool).await.unwrap();
+
+        assert_eq!(skill_by_name(&pool, &untouched.name).await.unwrap().content, untouched.content);
+        assert_eq!(load_installed(&pool, &untouched.key).await.unwrap().unwrap().version, untouched.version);
+
+        let skill = skill_by_name(&pool, &edited.name).await.unwrap();
+        assert_eq!(skill.content, "mine");
+        assert_eq!(load_installed(&pool, &edited.key).await.unwrap().unwrap().version, 0);
+
+        let upgrades = pending_upgrades(&pool).await.unwrap();
+        assert_eq!(upgrades.len(), 1);
+        assert_eq!(upgrades[0].skill_id, skill.id);
+        assert_eq!(upgrades[0].installed_version, 0);
+        assert_eq!(upgrades[0].available_version, edited.version);
+
+        apply_upgrade(&pool, &skill.id).await.unwrap();
+        assert_eq!(skill_by_name(&pool, &edited.name).await.unwrap().content, edited.content);
+        assert!(pending_upgrades(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_database_does_not_reinstall_deleted() {
+        let pool = test_pool().await;
+        let defaults = default_skills().unwrap();
+        let deleted = find_default(&defaults, "system-prompt");
+        let edited = find_default(&defaults, "mind-map");
+
+        // 旧数据库：没有安装记录，用户删除了一个内置技能并修改过另一个
+        add_skill(&pool, "我的技能", "user", 0, 0).await;
+        add_skill(&pool, &edited.name, "mine", 0, 1).await;
+
+        sync_default_skills(&pool).await.unwrap();
+        sync_default_skills(&pool).await.unwrap();
+
+        assert!(skill_by_name(&pool, &deleted.name).await.is_none());
+        let installed = load_installed(&pool, &deleted.key).await.unwrap().unwrap();
+        assert_eq!(installed.skill_id, None);
+        assert_eq!(installed.version, deleted.version);
+
+        let skill = skill_by_name(&pool, &edited.name).await.unwrap();
+        assert_eq!(skill.content, "mine");
+        let installed = load_installed(&pool, &edited.key).await.unwrap().unwrap();
+        assert_eq!(installed.skill_id.as_deref(), Some(skill.id.as_str()));
+        assert_eq!(installed.version, 0);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_database_adopts_unedited() {
+        let pool = test_pool().await;
+        let defaults = default_skills().unwrap();
+        let default = find_default(&defaults, "mind-map");
+
+        // 初始化后从未编辑过的旧版本内置技能直接升级
+        add_skill(&pool, &default.name, "old", 5, 5).await;
+        sync_default_skills(&pool).await.unwrap();
+
+        let skill = skill_by_name(&pool, &default.name).await.unwrap();
+        assert_eq!(skill.content, default.content);
+        let installed = load_installed(&pool, &default.key).await.unwrap().unwrap();
+        assert_eq!(installed.skill_id.as_deref(), Some(skill.id.as_str()));
+        assert_eq!(installed.version, default.version);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod commands;
+pub mod defaults;
 pub mod models;
+pub mod pack;
+pub mod template;
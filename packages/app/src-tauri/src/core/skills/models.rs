@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Skill {
     pub id: String,
     pub name: String,
     pub content: String,
+    pub parameters: Vec<SkillParameter>, // 为空时 content 是普通 Markdown，不作为模板渲染
     #[serde(rename = "isActive")]
     pub is_active: bool,
     #[serde(rename = "isSystem")]
@@ -19,6 +21,7 @@ pub struct Skill {
 pub struct SkillCreateData {
     pub name: String,
     pub content: String,
+    pub parameters: Option<Vec<SkillParameter>>,
     #[serde(rename = "isActive")]
     pub is_active: Option<bool>,
     #[serde(rename = "isSystem")]
@@ -29,6 +32,7 @@ pub struct SkillCreateData {
 pub struct SkillUpdateData {
     pub name: Option<String>,
     pub content: Option<String>,
+    pub parameters: Option<Vec<SkillParameter>>,
     #[serde(rename = "isActive")]
     pub is_active: Option<bool>,
     #[serde(rename = "updatedAt")]
@@ -36,12 +40,20 @@ pub struct SkillUpdateData {
 }
 
 impl Skill {
-    pub fn new(id: String, name: String, content: String, is_active: bool, is_system: bool) -> Self {
+    pub fn new(
+        id: String,
+        name: String,
+        content: String,
+        parameters: Vec<SkillParameter>,
+        is_active: bool,
+        is_system: bool,
+    ) -> Self {
         let now = chrono::Utc::now().timestamp_millis();
         Self {
             id,
             name,
             content,
+            parameters,
             is_active,
             is_system,
             created_at: now,
@@ -56,6 +68,10 @@ impl Skill {
             id: row.try_get("id")?,
             name: row.try_get("name")?,
             content: row.try_get("content")?,
+            parameters: row
+                .try_get::<Option<String>, _>("parameters")?
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
             is_active: row.try_get::<i32, _>("is_active")? != 0,
             is_system: row.try_get::<i32, _>("is_system")? != 0,
             created_at: row.try_get("created_at")?,
@@ -63,3 +79,201 @@ impl Skill {
         })
     }
 }
+
+/// 模板参数的类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillParameterType {
+    String,
+    Text, // 多行文本
+    Number,
+    Boolean,
+    Enum,
+}
+
+/// 参数的自动取值来源；未设置时由用户填写
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SkillParameterSource {
+    BookTitle,
+    BookAuthor,
+    BookMetadata, // 书籍目录下的 metadata.md
+    ChapterTitle, // 当前目录节点
+    ChapterHref,
+    Selection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkillParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: SkillParameterType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SkillParameterSource>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>, // enum 的可选值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// skills.parameters 列的值：没有参数时为 NULL
+pub fn parameters_json(parameters: &[SkillParameter]) -> Result<Option<String>, String> {
+    if parameters.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(parameters)
+        .map(Some)
+        .map_err(|e| format!("序列化技能参数失败: {}", e))
+}
+
+/// 模板或参数定义中的问题；line/column 从 1 开始，参数定义的问题没有位置
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkillTemplateIssue {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for SkillTemplateIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "第 {} 行第 {} 列：{}", line, column, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SkillChapterContext {
+    pub title: String,
+    pub href: Option<String>,
+}
+
+/// 渲染技能时的上下文：书籍、当前目录节点、选中文本，以及用户填写的参数值
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SkillRenderContext {
+    #[serde(rename = "bookId")]
+    pub book_id: Option<String>,
+    pub chapter: Option<SkillChapterContext>,
+    pub selection: Option<String>,
+    #[serde(default)]
+    pub values: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RenderedSkill {
+    pub content: String,
+    pub values: HashMap<String, serde_json::Value>, // 实际使用的参数值
+}
+
+/// 技能包中的一个技能；JSON 技能包内联 content，zip 技能包通过 file 指向其中的 Markdown 文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkillPackEntry {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<SkillParameter>,
+    #[serde(rename = "isActive", default = "default_true")]
+    pub is_active: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 技能包清单：JSON 技能包就是清单本身，zip 技能包中为 manifest.json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkillPackManifest {
+    pub format: String,
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "exportedAt", default)]
+    pub exported_at: i64,
+    pub skills: Vec<SkillPackEntry>,
+}
+
+/// 导入时与已有技能同名的处理方式
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillConflictStrategy {
+    #[default]
+    Skip,
+    Overwrite, // 覆盖内容与参数，保留启用状态
+    Rename,    // 以“名称 (2)”等新名称导入
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportSkillPackPayload {
+    #[serde(rename = "filePath")]
+    pub file_path: String, // .zip 导出为 Markdown 文件加清单，其他扩展名导出为 JSON
+    #[serde(rename = "skillIds")]
+    pub skill_ids: Option<Vec<String>>, // 为空时导出全部非系统技能
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SkillPackExportReport {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    #[serde(rename = "exportedSkills")]
+    pub exported_skills: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SkillPackPreviewItem {
+    pub name: String,
+    #[serde(rename = "isTemplate")]
+    pub is_template: bool,
+    pub conflict: bool, // 已存在同名技能
+    pub unchanged: bool, // 同名技能的内容与参数完全相同
+    #[serde(rename = "conflictIsSystem")]
+    pub conflict_is_system: bool,
+    pub error: Option<String>, // 模板校验失败时的原因，导入时会跳过
+}
+
+#[derive(Serialize, Debug)]
+pub struct SkillPackPreview {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub skills: Vec<SkillPackPreviewItem>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SkillPackImportReport {
+    #[serde(rename = "packName")]
+    pub pack_name: String,
+    #[serde(rename = "packVersion")]
+    pub pack_version: String,
+    pub created: Vec<String>, // 新建的技能名称（重命名导入的为新名称）
+    pub overwritten: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub skipped: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// 有新版本、但因用户修改过而没有自动升级的内置技能
+#[derive(Serialize, Debug)]
+pub struct SkillUpgrade {
+    #[serde(rename = "skillId")]
+    pub skill_id: String,
+    pub name: String,
+    #[serde(rename = "installedVersion")]
+    pub installed_version: i64,
+    #[serde(rename = "availableVersion")]
+    pub available_version: i64,
+    pub content: String, // 新版本的内容
+}
@@ -0,0 +1,385 @@
+// 技能包：JSON 文件（清单中内联技能内容），或 zip 文件（manifest.json 加每个技能一个 Markdown 文件）
+
+use super::defaults::skill_hash;
+use super::models::{
+    parameters_json, Skill, SkillConflictStrategy, SkillPackEntry, SkillPackImportReport, SkillPackManifest,
+    SkillPackPreviewItem,
+};
+use super::template;
+use crate::core::notes::markdown::file_stem;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+pub const PACK_FORMAT: &str = "sageread-skill-pack";
+pub const PACK_FORMAT_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const SKILLS_FOLDER: &str = "skills";
+
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// 读取技能包；zip 中的 Markdown 文件内容会填入 content
+pub fn read_pack(path: &Path) -> Result<SkillPackManifest, String> {
+    let mut manifest: SkillPackManifest = if is_zip(path) {
+        let file = fs::File::open(path).map_err(|e| format!("打开技能包失败: {}", e))?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("读取技能包失败: {}", e))?;
+        let manifest_text = read_zip_entry(&mut archive, MANIFEST_FILE)?;
+        let mut manifest: SkillPackManifest =
+            serde_json::from_str(&manifest_text).map_err(|e| format!("技能包清单无效: {}", e))?;
+        for entry in &mut manifest.skills {
+            if let (None, Some(file)) = (&entry.content, &entry.file) {
+                entry.content = Some(read_zip_entry(&mut archive, file)?);
+            }
+        }
+        manifest
+    } else {
+        let text = fs::read_to_string(path).map_err(|e| format!("读取技能包失败: {}", e))?;
+        serde_json::from_str(&text).map_err(|e| format!("技能包无效: {}", e))?
+    };
+
+    if manifest.format != PACK_FORMAT {
+        return Err("不是技能包文件".to_string());
+    }
+    if manifest.format_version > PACK_FORMAT_VERSION {
+        return Err(format!(
+            "技能包格式版本 {} 过新，请升级应用后再导入",
+            manifest.format_version
+        ));
+    }
+    for entry in &mut manifest.skills {
+        if entry.content.is_none() {
+            return Err(format!("技能 '{}' 缺少内容", entry.name));
+        }
+        entry.file = None;
+    }
+
+    Ok(manifest)
+}
+
+/// 写出技能包；zip 技能包中每个技能保存为 skills/<名称>.md
+pub fn write_pack(path: &Path, manifest: &SkillPackManifest) -> Result<(), String> {
+    if !is_zip(path) {
+        let json = serde_json::to_string_pretty(manifest).map_err(|e| format!("序列化技能包失败: {}", e))?;
+        return fs::write(path, json).map_err(|e| format!("写入技能包失败: {}", e));
+    }
+
+    let mut zip_manifest = manifest.clone();
+    let mut files = Vec::new();
+    let mut used = HashSet::new();
+    for entry in &mut zip_manifest.skills {
+        let stem = file_stem(Some(&entry.name));
+        let mut file = format!("{}/{}.md", SKILLS_FOLDER, stem);
+        let mut counter = 2;
+        while !used.insert(file.to_lowercase()) {
+            file = format!("{}/{} ({}).md", SKILLS_FOLDER, stem, counter);
+            counter += 1;
+        }
+        files.push((file.clone(), entry.content.take().unwrap_or_default()));
+        entry.file = Some(file);
+    }
+    let manifest_json =
+        serde_json::to_string_pretty(&zip_manifest).map_err(|e| format!("序列化技能包失败: {}", e))?;
+
+    let file = fs::File::create(path).map_err(|e| format!("创建文件失败: {}", e))?;
+    write_zip(file, &manifest_json, &files).map_err(|e| format!("写入技能包失败: {}", e))
+}
+
+/// 逐个技能检查与已有技能的冲突以及模板是否有效
+pub async fn preview(pool: &SqlitePool, manifest: &SkillPackManifest) -> Result<Vec<SkillPackPreviewItem>, String> {
+    let mut items = Vec::new();
+
+    for entry in &manifest.skills {
+        let content = entry.content.as_deref().unwrap_or_default();
+        let existing = find_by_name(pool, &entry.name).await?;
+        items.push(SkillPackPreviewItem {
+            name: entry.name.clone(),
+            is_template: !entry.parameters.is_empty(),
+            conflict: existing.is_some(),
+            unchanged: existing
+                .as_ref()
+                .is_some_and(|skill| same_content(skill, entry)),
+            conflict_is_system: existing.as_ref().is_some_and(|skill| skill.is_system),
+            error: check_entry(entry, content).err(),
+        });
+    }
+
+    Ok(items)
+}
+
+enum ImportOutcome {
+    Created(String), // 实际使用的名称
+    Overwritten,
+    Unchanged,
+    Skipped,
+}
+
+/// 导入技能包；内容与已有同名技能完全相同的不算冲突。导入的技能都是用户技能
+pub async fn import(
+    pool: &SqlitePool,
+    manifest: &SkillPackManifest,
+    strategy: SkillConflictStrategy,
+) -> Result<SkillPackImportReport, String> {
+    let mut report = SkillPackImportReport {
+        pack_name: manifest.name.clone(),
+        pack_version: manifest.version.clone(),
+        ..Default::default()
+    };
+
+    for entry in &manifest.skills {
+        let content = entry.content.as_deref().unwrap_or_default();
+        if let Err(e) = check_entry(entry, content) {
+            report.errors.push(format!("{}: {}", entry.name, e));
+            continue;
+        }
+
+        let outcome = match find_by_name(pool, &entry.name).await? {
+            None => insert(pool, &entry.name, entry).await.map(|_| ImportOutcome::Created(entry.name.clone())),
+            Some(skill) if same_content(&skill, entry) => Ok(ImportOutcome::Unchanged),
+            Some(skill) => match strategy {
+                SkillConflictStrategy::Skip => Ok(ImportOutcome::Skipped),
+                SkillConflictStrategy::Overwrite => overwrite(pool, &skill.id, entry)
+                    .await
+                    .map(|_| ImportOutcome::Overwritten),
+                SkillConflictStrategy::Rename => {
+                    let name = free_name(pool, &entry.name).await?;
+                    insert(pool, &name, entry).await.map(|_| ImportOutcome::Created(name))
+                }
+            },
+        };
+
+        match outcome {
+            Ok(ImportOutcome::Created(name)) => report.created.push(name),
+            Ok(ImportOutcome::Overwritten) => report.overwritten.push(entry.name.clone()),
+            Ok(ImportOutcome::Unchanged) => report.unchanged.push(entry.name.clone()),
+            Ok(ImportOutcome::Skipped) => report.skipped.push(entry.name.clone()),
+            Err(e) => report.errors.push(format!("{}: {}", entry.name, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+pub fn pack_entry(skill: &Skill) -> SkillPackEntry {
+    SkillPackEntry {
+        name: skill.name.clone(),
+        file: None,
+        content: Some(skill.content.clone()),
+        parameters: skill.parameters.clone(),
+        is_active: skill.is_active,
+    }
+}
+
+fn check_entry(entry: &SkillPackEntry, content: &str) -> Result<(), String> {
+    if entry.name.trim().is_empty() {
+        return Err("技能名不能为空".to_string());
+    }
+    match template::validate(content, &entry.parameters).first() {
+        Some(issue) => Err(format!("技能模板无效: {}", issue)),
+        None => Ok(()),
+    }
+}
+
+fn same_content(skill: &Skill, entry: &SkillPackEntry) -> bool {
+    skill_hash(&skill.content, &skill.parameters)
+        == skill_hash(entry.content.as_deref().unwrap_or_default(), &entry.parameters)
+}
+
+async fn find_by_name(pool: &SqlitePool, name: &str) -> Result<Option<Skill>, String> {
+    let row = sqlx::query("SELECT * FROM skills WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("检查技能名失败: {}", e))?;
+    row.map(|row| Skill::from_db_row(&row).map_err(|e| format!("转换查询结果失败: {}", e)))
+        .transpose()
+}
+
+/// “名称 (2)”、“名称 (3)”……中第一个未被使用的名称
+async fn free_name(pool: &SqlitePool, name: &str) -> Result<String, String> {
+    let mut counter = 2;
+    loop {
+        let candidate = format!("{} ({})", name, counter);
+        if find_by_name(pool, &candidate).await?.is_none() {
+            return Ok(candidate);
+        }
+        counter += 1;
+    }
+}
+
+async fn insert(pool: &SqlitePool, name: &str, entry: &SkillPackEntry) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    sqlx::query(
+        r#"
+        INSERT INTO skills (id, name, content, parameters, is_active, is_system, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, 0, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(name)
+    .bind(entry.content.as_deref().unwrap_or_default())
+    .bind(parameters_json(&entry.parameters)?)
+    .bind(if entry.is_active { 1 } else { 0 })
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("创建技能失败: {}", e))?;
+    Ok(())
+}
+
+async fn overwrite(pool: &SqlitePool, skill_id: &str, entry: &SkillPackEntry) -> Result<(), String> {
+    sqlx::query("UPDATE skills SET content = ?, parameters = ?, updated_at = ? WHERE id = ?")
+        .bind(entry.content.as_deref().unwrap_or_default())
+        .bind(parameters_json(&entry.parameters)?)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .bind(skill_id)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("更新技能内容失败: {}", e))?;
+    Ok(())
+}
+
+fn read_zip_entry(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> Result<String, String> {
+    let mut file = archive
+        .by_name(name)
+        .map_err(|_| format!("技能包中缺少 {}", name))?;
+    let mut text = String::new();
+    file.read_to_string(&mut text)
+        .map_err(|e| format!("读取 {} 失败: {}", name, e))?;
+    Ok(text)
+}
+
+fn write_zip(file: fs::File, manifest: &str, files: &[(String, String)]) -> zip::result::ZipResult<()> {
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(manifest.as_bytes())?;
+    for (name, content) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(include_str!("../schema.sql")).execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn entry(name: &str, content: &str) -> SkillPackEntry {
+        SkillPackEntry {
+            name: name.to_string(),
+            file: None,
+            content: Some(content.to_string()),
+            parameters: Vec::new(),
+            is_active: true,
+        }
+    }
+
+    fn manifest(skills: Vec<SkillPackEntry>) -> SkillPackManifest {
+        SkillPackManifest {
+            format: PACK_FORMAT.to_string(),
+            format_version: PACK_FORMAT_VERSION,
+            name: "pack".to_string(),
+            version: "1.0".to_string(),
+            description: None,
+            exported_at: 0,
+            skills,
+        }
+    }
+
+    /// 已有技能：A 与包内相同，B 与包内不同且已停用，“B (2)” 占用了第一个重命名
+    async fn existing(pool: &SqlitePool) {
+        insert(pool, "A", &entry("A", "same")).await.unwrap();
+        insert(pool, "B", &SkillPackEntry { is_active: false, ..entry("B", "mine") }).await.unwrap();
+        insert(pool, "B (2)", &entry("B (2)", "other")).await.unwrap();
+    }
+
+    fn pack() -> SkillPackManifest {
+        manifest(vec![entry("A", "same"), entry("B", "theirs"), entry("C", "new"), entry(" ", "blank")])
+    }
+
+    #[tokio::test]
+    async fn test_preview_conflicts() {
+        let pool = test_pool().await;
+        existing(&pool).await;
+
+        let items = preview(&pool, &pack()).await.unwrap();
+        let flags: Vec<_> = items
+            .iter()
+            .map(|i| (i.name.as_str(), i.conflict, i.unchanged, i.error.is_some()))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                ("A", true, true, false),
+                ("B", true, false, false),
+                ("C", false, false, false),
+                (" ", false, false, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_skip() {
+        let pool = test_pool().await;
+        existing(&pool).await;
+
+        let report = import(&pool, &pack(), SkillConflictStrategy::Skip).await.unwrap();
+        assert_eq!(report.created, ["C"]);
+        assert_eq!(report.unchanged, ["A"]);
+        assert_eq!(report.skipped, ["B"]);
+        assert!(report.overwritten.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(find_by_name(&pool, "B").await.unwrap().unwrap().content, "mine");
+    }
+
+    #[tokio::test]
+    async fn test_import_overwrite() {
+        let pool = test_pool().await;
+        existing(&pool).await;
+
+        let report = import(&pool, &pack(), SkillConflictStrategy::Overwrite).await.unwrap();
+        assert_eq!(report.created, ["C"]);
+        assert_eq!(report.overwritten, ["B"]);
+        assert_eq!(report.unchanged, ["A"]);
+        // 覆盖内容，保留启用状态
+        let skill = find_by_name(&pool, "B").await.unwrap().unwrap();
+        assert_eq!(skill.content, "theirs");
+        assert!(!skill.is_active);
+    }
+
+    #[tokio::test]
+    async fn test_import_rename() {
+        let pool = test_pool().await;
+        existing(&pool).await;
+
+        let report = import(&pool, &pack(), SkillConflictStrategy::Rename).await.unwrap();
+        assert_eq!(report.created, ["B (3)", "C"]);
+        assert_eq!(report.unchanged, ["A"]);
+        assert_eq!(find_by_name(&pool, "B").await.unwrap().unwrap().content, "mine");
+        let renamed = find_by_name(&pool, "B (3)").await.unwrap().unwrap();
+        assert_eq!(renamed.content, "theirs");
+        assert!(!renamed.is_system);
+    }
+}
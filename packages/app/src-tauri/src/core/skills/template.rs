@@ -0,0 +1,933 @@
+// 技能模板：语法是 MiniJinja 的子集
+//   {{ name }}、{{ name | trim | default("无") }}、{{ "字面量" }}
+//   {% if name %}…{% elif name == "选项" %}…{% else %}…{% endif %}（支持 not、==、!=）
+//   {# 注释 #}
+// 块标签与注释后紧跟的一个换行会被去掉。解析与渲染错误都带有行列号
+
+use super::models::{SkillParameter, SkillParameterSource, SkillParameterType, SkillTemplateIssue};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+const FILTERS: &str = "upper、lower、trim、default(\"…\")、truncate(n)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+fn issue(pos: Pos, message: impl Into<String>) -> SkillTemplateIssue {
+    SkillTemplateIssue {
+        line: Some(pos.line),
+        column: Some(pos.column),
+        message: message.into(),
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output {
+        expr: Expr,
+        filters: Vec<Filter>,
+    },
+    If {
+        branches: Vec<(Condition, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+enum Expr {
+    Var(String, Pos),
+    Str(String),
+}
+
+#[derive(Debug)]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    Default(String),
+    Truncate(usize),
+}
+
+#[derive(Debug)]
+struct Condition {
+    var: String,
+    pos: Pos,
+    negate: bool,
+    compare: Option<(String, Pos)>, // == 的右侧；negate 与 != 对应
+}
+
+/// 模板中的变量引用：变量名、位置，以及条件中与之比较的字面量
+type VarRef<'a> = (&'a str, Pos, Option<(&'a str, Pos)>);
+
+/// 块的结束标签（elif/else/endif）及其位置
+type BlockEnd = Option<(String, Pos)>;
+
+/// 解析后的模板
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, SkillTemplateIssue> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, index: 0 };
+        let (nodes, end) = parser.parse_block()?;
+        if let Some((tag, pos)) = end {
+            return Err(issue(pos, format!("多余的 {{% {} %}}", tag)));
+        }
+        Ok(Self { nodes })
+    }
+
+    /// 模板中引用的变量及其位置（按出现顺序）
+    fn variables(&self) -> Vec<VarRef<'_>> {
+        fn walk<'a>(nodes: &'a [Node], out: &mut Vec<VarRef<'a>>) {
+            for node in nodes {
+                match node {
+                    Node::Text(_) | Node::Output { expr: Expr::Str(_), .. } => {}
+                    Node::Output { expr: Expr::Var(name, pos), .. } => out.push((name, *pos, None)),
+                    Node::If { branches, otherwise } => {
+                        for (condition, body) in branches {
+                            let compare = condition.compare.as_ref().map(|(v, p)| (v.as_str(), *p));
+                            out.push((&condition.var, condition.pos, compare));
+                            walk(body, out);
+                        }
+                        walk(otherwise, out);
+                    }
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.nodes, &mut out);
+        out
+    }
+
+    pub fn render(&self, values: &HashMap<String, Value>) -> Result<String, SkillTemplateIssue> {
+        let mut out = String::new();
+        render_nodes(&self.nodes, values, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// 校验参数定义与模板：参数名合法且唯一、enum 有可选值、默认值与类型一致，模板中的变量都已声明。
+/// 没有声明参数的技能是普通 Markdown，不做模板校验
+pub fn validate(content: &str, parameters: &[SkillParameter]) -> Vec<SkillTemplateIssue> {
+    let mut issues = Vec::new();
+    let plain = |message: String| SkillTemplateIssue {
+        line: None,
+        column: None,
+        message,
+    };
+
+    let mut names = HashSet::new();
+    for parameter in parameters {
+        let name = parameter.name.as_str();
+        if !is_identifier(name) {
+            issues.push(plain(format!("参数名 '{}' 只能包含字母、数字和下划线，且不能以数字开头", name)));
+        }
+        if !names.insert(name) {
+            issues.push(plain(format!("参数 '{}' 重复声明", name)));
+        }
+        if parameter.kind == SkillParameterType::Enum {
+            if parameter.options.is_empty() {
+                issues.push(plain(format!("枚举参数 '{}' 没有可选值", name)));
+            }
+            let unique: HashSet<&String> = parameter.options.iter().collect();
+            if unique.len() != parameter.options.len() {
+                issues.push(plain(format!("枚举参数 '{}' 的可选值重复", name)));
+            }
+        }
+        if let Some(source) = parameter.source {
+            if !matches!(parameter.kind, SkillParameterType::String | SkillParameterType::Text) {
+                issues.push(plain(format!(
+                    "参数 '{}' 的取值来源 {} 只能用于 string 或 text 类型",
+                    name,
+                    source_label(source)
+                )));
+            }
+        }
+        if let Some(default) = &parameter.default {
+            if let Err(message) = coerce(parameter, default) {
+                issues.push(plain(format!("参数 '{}' 的默认值无效：{}", name, message)));
+            }
+        }
+    }
+
+    if parameters.is_empty() {
+        return issues;
+    }
+
+    let template = match Template::parse(content) {
+        Ok(template) => template,
+        Err(e) => {
+            issues.push(e);
+            return issues;
+        }
+    };
+    let declared: HashMap<&str, &SkillParameter> = parameters.iter().map(|p| (p.name.as_str(), p)).collect();
+    for (name, pos, compare) in template.variables() {
+        let Some(parameter) = declared.get(name) else {
+            issues.push(issue(pos, format!("变量 '{}' 未在参数中声明", name)));
+            continue;
+        };
+        if let Some((value, value_pos)) = compare {
+            if parameter.kind == SkillParameterType::Enum && !parameter.options.iter().any(|o| o == value) {
+                issues.push(issue(
+                    value_pos,
+                    format!("'{}' 不是参数 '{}' 的可选值（{}）", value, name, parameter.options.join("、")),
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+/// 确定每个参数的取值：用户填写的值优先，其次是取值来源，最后是默认值；必填参数没有值时报错
+pub fn resolve_values(
+    parameters: &[SkillParameter],
+    explicit: &HashMap<String, Value>,
+    sources: &HashMap<SkillParameterSource, String>,
+) -> Result<HashMap<String, Value>, SkillTemplateIssue> {
+    let mut values = HashMap::new();
+
+    for parameter in parameters {
+        let name = &parameter.name;
+        let explicit_value = explicit.get(name).filter(|v| !is_blank(v)).cloned();
+        let source_value = parameter
+            .source
+            .and_then(|source| sources.get(&source))
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| Value::String(s.clone()));
+        let value = explicit_value
+            .or(source_value)
+            .or_else(|| parameter.default.clone())
+            .unwrap_or(Value::Null);
+
+        if value.is_null() && parameter.required {
+            return Err(SkillTemplateIssue {
+                line: None,
+                column: None,
+                message: match parameter.source {
+                    Some(source) => format!("缺少参数 '{}'（取值来源 {} 没有内容）", name, source_label(source)),
+                    None => format!("缺少参数 '{}'", name),
+                },
+            });
+        }
+        let value = coerce(parameter, &value).map_err(|message| SkillTemplateIssue {
+            line: None,
+            column: None,
+            message: format!("参数 '{}' 的值无效：{}", name, message),
+        })?;
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// 把参数值转换为声明的类型；数字与布尔值也接受字符串形式
+pub fn coerce(parameter: &SkillParameter, value: &Value) -> Result<Value, String> {
+    match (parameter.kind, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (SkillParameterType::String | SkillParameterType::Text, Value::String(_)) => Ok(value.clone()),
+        (SkillParameterType::String | SkillParameterType::Text, Value::Number(n)) => Ok(Value::String(n.to_string())),
+        (SkillParameterType::Number, Value::Number(_)) => Ok(value.clone()),
+        (SkillParameterType::Number, Value::String(s)) => {
+            let s = s.trim();
+            s.parse::<i64>()
+                .map(serde_json::Number::from)
+                .ok()
+                .or_else(|| s.parse::<f64>().ok().and_then(serde_json::Number::from_f64))
+                .map(Value::Number)
+                .ok_or_else(|| format!("'{}' 不是数字", s))
+        }
+        (SkillParameterType::Boolean, Value::Bool(_)) => Ok(value.clone()),
+        (SkillParameterType::Boolean, Value::String(s)) => match s.trim() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' 不是布尔值", s)),
+        },
+        (SkillParameterType::Enum, Value::String(s)) if parameter.options.contains(s) => Ok(value.clone()),
+        (SkillParameterType::Enum, Value::String(s)) => {
+            Err(format!("'{}' 不是可选值（{}）", s, parameter.options.join("、")))
+        }
+        (kind, _) => Err(format!("需要 {} 类型的值", type_label(kind))),
+    }
+}
+
+fn type_label(kind: SkillParameterType) -> &'static str {
+    match kind {
+        SkillParameterType::String => "string",
+        SkillParameterType::Text => "text",
+        SkillParameterType::Number => "number",
+        SkillParameterType::Boolean => "boolean",
+        SkillParameterType::Enum => "enum",
+    }
+}
+
+fn source_label(source: SkillParameterSource) -> &'static str {
+    match source {
+        SkillParameterSource::BookTitle => "bookTitle",
+        SkillParameterSource::BookAuthor => "bookAuthor",
+        SkillParameterSource::BookMetadata => "bookMetadata",
+        SkillParameterSource::ChapterTitle => "chapterTitle",
+        SkillParameterSource::ChapterHref => "chapterHref",
+        SkillParameterSource::Selection => "selection",
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// ---------- 词法 ----------
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Output(Vec<Word>, Pos),
+    Tag(Vec<Word>, Pos),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum WordKind {
+    Ident(String),
+    Str(String),
+    Int(usize),
+    Pipe,
+    LParen,
+    RParen,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone)]
+struct Word {
+    kind: WordKind,
+    pos: Pos,
+}
+
+struct Source<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    fn pos(&self, offset: usize) -> Pos {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.text[self.line_starts[line]..offset].chars().count() + 1;
+        Pos { line: line + 1, column }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SkillTemplateIssue> {
+    let source = Source::new(text);
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    let mut trim_newline = false;
+
+    while offset < text.len() {
+        let rest = &text[offset..];
+        // 找到最近的一个开标记
+        let next = ["{{", "{%", "{#"]
+            .iter()
+            .filter_map(|open| rest.find(open).map(|i| (i, *open)))
+            .min_by_key(|(i, _)| *i);
+        let Some((open_at, open)) = next else {
+            push_text(&mut tokens, rest, trim_newline);
+            break;
+        };
+
+        push_text(&mut tokens, &rest[..open_at], trim_newline);
+        trim_newline = false;
+
+        let tag_start = offset + open_at;
+        let close = match open {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let inner_start = tag_start + 2;
+        let Some(close_at) = text[inner_start..].find(close) else {
+            return Err(issue(source.pos(tag_start), format!("{} 没有对应的 {}", open, close)));
+        };
+        let inner_end = inner_start + close_at;
+        offset = inner_end + 2;
+
+        match open {
+            "{#" => trim_newline = true,
+            "{{" => tokens.push(Token::Output(
+                lex(&source, inner_start, inner_end)?,
+                source.pos(tag_start),
+            )),
+            _ => {
+                tokens.push(Token::Tag(lex(&source, inner_start, inner_end)?, source.pos(tag_start)));
+                trim_newline = true;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn push_text(tokens: &mut Vec<Token>, text: &str, trim_newline: bool) {
+    let text = if trim_newline {
+        text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(text)
+    } else {
+        text
+    };
+    if !text.is_empty() {
+        tokens.push(Token::Text(text.to_string()));
+    }
+}
+
+fn lex(source: &Source, start: usize, end: usize) -> Result<Vec<Word>, SkillTemplateIssue> {
+    let text = &source.text[start..end];
+    let mut words = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        let pos = source.pos(start + i);
+        let single = |kind| Word { kind, pos };
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '|' => {
+                chars.next();
+                words.push(single(WordKind::Pipe));
+            }
+            '(' => {
+                chars.next();
+                words.push(single(WordKind::LParen));
+            }
+            ')' => {
+                chars.next();
+                words.push(single(WordKind::RParen));
+            }
+            '=' | '!' => {
+                chars.next();
+                if chars.next_if(|&(_, next)| next == '=').is_none() {
+                    return Err(issue(pos, format!("无法识别的符号 '{}'，比较请使用 == 或 !=", c)));
+                }
+                words.push(single(if c == '=' { WordKind::Eq } else { WordKind::Ne }));
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(match escaped {
+                                    'n' => '\n',
+                                    other => other,
+                                });
+                            }
+                        }
+                        next if next == c => {
+                            closed = true;
+                            break;
+                        }
+                        next => value.push(next),
+                    }
+                }
+                if !closed {
+                    return Err(issue(pos, "字符串没有结束引号"));
+                }
+                words.push(single(WordKind::Str(value)));
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some((_, d)) = chars.next_if(|(_, d)| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                let value = digits.parse().map_err(|_| issue(pos, "数字过大"))?;
+                words.push(single(WordKind::Int(value)));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some((_, d)) = chars.next_if(|(_, d)| d.is_alphanumeric() || *d == '_') {
+                    ident.push(d);
+                }
+                words.push(single(WordKind::Ident(ident)));
+            }
+            other => return Err(issue(pos, format!("无法识别的字符 '{}'", other))),
+        }
+    }
+
+    Ok(words)
+}
+
+// ---------- 语法 ----------
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    /// 解析到 elif/else/endif 或结尾；返回遇到的结束标签
+    fn parse_block(&mut self) -> Result<(Vec<Node>, BlockEnd), SkillTemplateIssue> {
+        let mut nodes = Vec::new();
+
+        while self.index < self.tokens.len() {
+            let index = self.index;
+            self.index += 1;
+            match &self.tokens[index] {
+                Token::Text(text) => nodes.push(Node::Text(text.clone())),
+                Token::Output(words, pos) => nodes.push(parse_output(words, *pos)?),
+                Token::Tag(words, pos) => {
+                    let pos = *pos;
+                    let Some(Word { kind: WordKind::Ident(tag), .. }) = words.first() else {
+                        return Err(issue(pos, "空的块标签"));
+                    };
+                    match tag.as_str() {
+                        "if" => {
+                            let condition = parse_condition(&words[1..], pos)?;
+                            nodes.push(self.parse_if(condition, pos)?);
+                        }
+                        "elif" | "else" | "endif" => return Ok((nodes, Some((tag.clone(), pos)))),
+                        other => return Err(issue(pos, format!("不支持的块标签 '{}'，只支持 if/elif/else/endif", other))),
+                    }
+                }
+            }
+        }
+
+        Ok((nodes, None))
+    }
+
+    fn parse_if(&mut self, first: Condition, if_pos: Pos) -> Result<Node, SkillTemplateIssue> {
+        let mut branches = Vec::new();
+        let mut condition = first;
+
+        loop {
+            let (body, end) = self.parse_block()?;
+            let Some((tag, pos)) = end else {
+                return Err(issue(if_pos, "{% if %} 没有对应的 {% endif %}"));
+            };
+            branches.push((condition, body));
+
+            let words = match &self.tokens[self.index - 1] {
+                Token::Tag(words, _) => words,
+                _ => unreachable!("parse_block 只会在块标签处返回"),
+            };
+            match tag.as_str() {
+                "elif" => condition = parse_condition(&words[1..], pos)?,
+                "else" => {
+                    expect_end(&words[1..])?;
+                    let (otherwise, end) = self.parse_block()?;
+                    return match end {
+                        Some((tag, _)) if tag == "endif" => Ok(Node::If { branches, otherwise }),
+                        Some((tag, pos)) => Err(issue(pos, format!("{{% else %}} 之后不能再出现 {{% {} %}}", tag))),
+                        None => Err(issue(if_pos, "{% if %} 没有对应的 {% endif %}")),
+                    };
+                }
+                _ => {
+                    expect_end(&words[1..])?;
+                    return Ok(Node::If {
+                        branches,
+                        otherwise: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn expect_end(words: &[Word]) -> Result<(), SkillTemplateIssue> {
+    match words.first() {
+        Some(word) => Err(issue(word.pos, "块标签中有多余的内容")),
+        None => Ok(()),
+    }
+}
+
+fn parse_output(words: &[Word], pos: Pos) -> Result<Node, SkillTemplateIssue> {
+    let mut words = words.iter();
+    let expr = match words.next() {
+        Some(Word { kind: WordKind::Ident(name), pos }) => Expr::Var(name.clone(), *pos),
+        Some(Word { kind: WordKind::Str(value), .. }) => Expr::Str(value.clone()),
+        Some(word) => return Err(issue(word.pos, "这里需要变量名或字符串")),
+        None => return Err(issue(pos, "空的 {{ }}")),
+    };
+
+    let mut filters = Vec::new();
+    while let Some(word) = words.next() {
+        if word.kind != WordKind::Pipe {
+            return Err(issue(word.pos, "这里需要 | 或 }}"));
+        }
+        let Some(Word { kind: WordKind::Ident(name), pos: name_pos }) = words.next() else {
+            return Err(issue(word.pos, "| 之后需要过滤器名称"));
+        };
+        let filter = match name.as_str() {
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            "trim" => Filter::Trim,
+            "default" | "truncate" => {
+                let argument = match (words.next(), words.next(), words.next()) {
+                    (
+                        Some(Word { kind: WordKind::LParen, .. }),
+                        Some(argument),
+                        Some(Word { kind: WordKind::RParen, .. }),
+                    ) => argument,
+                    _ => return Err(issue(*name_pos, format!("过滤器 {} 需要一个括号内的参数", name))),
+                };
+                match (name.as_str(), &argument.kind) {
+                    ("default", WordKind::Str(value)) => Filter::Default(value.clone()),
+                    ("truncate", WordKind::Int(length)) => Filter::Truncate(*length),
+                    ("default", _) => return Err(issue(argument.pos, "default 的参数必须是字符串")),
+                    _ => return Err(issue(argument.pos, "truncate 的参数必须是整数")),
+                }
+            }
+            other => {
+                return Err(issue(
+                    *name_pos,
+                    format!("未知的过滤器 '{}'，可用的过滤器：{}", other, FILTERS),
+                ))
+            }
+        };
+        filters.push(filter);
+    }
+
+    Ok(Node::Output { expr, filters })
+}
+
+fn parse_condition(words: &[Word], tag_pos: Pos) -> Result<Condition, SkillTemplateIssue> {
+    let mut words = words.iter().peekable();
+    let negate = words
+        .next_if(|w| w.kind == WordKind::Ident("not".to_string()))
+        .is_some();
+
+    let (var, pos) = match words.next() {
+        Some(Word { kind: WordKind::Ident(name), pos }) => (name.clone(), *pos),
+        Some(word) => return Err(issue(word.pos, "条件中需要变量名")),
+        None => return Err(issue(tag_pos, "条件为空")),
+    };
+
+    let mut condition = Condition {
+        var,
+        pos,
+        negate,
+        compare: None,
+    };
+    match words.next() {
+        None => {}
+        Some(word @ Word { kind: WordKind::Eq | WordKind::Ne, .. }) => {
+            if negate {
+                return Err(issue(word.pos, "not 不能与比较同时使用，请改用 !="));
+            }
+            let Some(Word { kind: WordKind::Str(value), pos }) = words.next() else {
+                return Err(issue(word.pos, "比较的右侧必须是字符串"));
+            };
+            condition.negate = word.kind == WordKind::Ne;
+            condition.compare = Some((value.clone(), *pos));
+        }
+        Some(word) => return Err(issue(word.pos, "条件中只能使用 ==、!= 或 not")),
+    }
+    if let Some(word) = words.next() {
+        return Err(issue(word.pos, "条件中有多余的内容"));
+    }
+
+    Ok(condition)
+}
+
+// ---------- 渲染 ----------
+
+fn render_nodes(nodes: &[Node], values: &HashMap<String, Value>, out: &mut String) -> Result<(), SkillTemplateIssue> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output { expr, filters } => {
+                let mut text = match expr {
+                    Expr::Str(value) => value.clone(),
+                    Expr::Var(name, pos) => to_text(lookup(values, name, *pos)?),
+                };
+                for filter in filters {
+                    text = match filter {
+                        Filter::Upper => text.to_uppercase(),
+                        Filter::Lower => text.to_lowercase(),
+                        Filter::Trim => text.trim().to_string(),
+                        Filter::Default(fallback) if text.trim().is_empty() => fallback.clone(),
+                        Filter::Default(_) => text,
+                        Filter::Truncate(length) if text.chars().count() > *length => {
+                            text.chars().take(*length).collect::<String>() + "…"
+                        }
+                        Filter::Truncate(_) => text,
+                    };
+                }
+                out.push_str(&text);
+            }
+            Node::If { branches, otherwise } => {
+                let mut matched = None;
+                for (condition, body) in branches {
+                    let value = lookup(values, &condition.var, condition.pos)?;
+                    let result = match &condition.compare {
+                        Some((expected, _)) => to_text(value) == *expected,
+                        None => is_truthy(value),
+                    };
+                    if result != condition.negate {
+                        matched = Some(body);
+                        break;
+                    }
+                }
+                render_nodes(matched.unwrap_or(otherwise), values, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(values: &'a HashMap<String, Value>, name: &str, pos: Pos) -> Result<&'a Value, SkillTemplateIssue> {
+    values
+        .get(name)
+        .ok_or_else(|| issue(pos, format!("变量 '{}' 没有值", name)))
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.trim().is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn param(name: &str, kind: SkillParameterType) -> SkillParameter {
+        SkillParameter {
+            name: name.to_string(),
+            kind,
+            source: None,
+            required: false,
+            options: Vec::new(),
+            default: None,
+            description: None,
+        }
+    }
+
+    fn enum_param(name: &str, options: &[&str]) -> SkillParameter {
+        SkillParameter {
+            options: options.iter().map(|o| o.to_string()).collect(),
+            ..param(name, SkillParameterType::Enum)
+        }
+    }
+
+    fn values(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+
+    fn render(source: &str, pairs: &[(&str, Value)]) -> Result<String, SkillTemplateIssue> {
+        Template::parse(source)?.render(&values(pairs))
+    }
+
+    fn position(issue: &SkillTemplateIssue) -> (Option<usize>, Option<usize>) {
+        (issue.line, issue.column)
+    }
+
+    #[test]
+    fn test_substitution_and_filters() {
+        let text = render(
+            "书名：{{ title }}，{{ lang | upper }}{{ \"!\" }}\n{{ note | trim | default(\"无\") }}|{{ summary | truncate(3) }}",
+            &[
+                ("title", json!("红楼梦")),
+                ("lang", json!("zh")),
+                ("note", json!("   ")),
+                ("summary", json!("一二三四五")),
+            ],
+        )
+        .unwrap();
+        assert_eq!(text, "书名：红楼梦，ZH!\n无|一二三…");
+
+        // 数字、布尔与 null 的输出
+        let text = render("{{ n }}/{{ b }}/{{ x }}", &[("n", json!(3)), ("b", json!(true)), ("x", Value::Null)]).unwrap();
+        assert_eq!(text, "3/true/");
+    }
+
+    #[test]
+    fn test_conditions_and_whitespace() {
+        let source = "{% if style == \"简洁\" %}\n短\n{% elif not detail %}\n中\n{% else %}\n长\n{% endif %}\n{# 注释 #}\n尾";
+        let run = |style: &str, detail: bool| render(source, &[("style", json!(style)), ("detail", json!(detail))]).unwrap();
+        assert_eq!(run("简洁", true), "短\n尾");
+        assert_eq!(run("详细", false), "中\n尾");
+        assert_eq!(run("详细", true), "长\n尾");
+
+        let text = render("{% if a != \"x\" %}yes{% endif %}", &[("a", json!("y"))]).unwrap();
+        assert_eq!(text, "yes");
+        let text = render("{% if items %}yes{% endif %}", &[("items", json!([]))]).unwrap();
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn test_missing_variable_reports_position() {
+        let err = render("第一行\n  {{ missing }}", &[]).unwrap_err();
+        assert_eq!(position(&err), (Some(2), Some(6)));
+        assert!(err.message.contains("missing"));
+
+        let err = render("{% if flag %}x{% endif %}", &[]).unwrap_err();
+        assert_eq!(position(&err), (Some(1), Some(7)));
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let cases = [
+            ("abc\n{{ name", (2, 1), "没有对应的 }}"),
+            ("{{ name | shout }}", (1, 11), "未知的过滤器 'shout'"),
+            ("{{ name | truncate(\"a\") }}", (1, 20), "truncate 的参数必须是整数"),
+            ("{{ }}", (1, 1), "空的 {{ }}"),
+            ("{{ a = \"b\" }}", (1, 6), "无法识别的符号"),
+            ("{{ \"abc }}", (1, 4), "字符串没有结束引号"),
+            ("x\n{% if a %}\nbody", (2, 1), "没有对应的 {% endif %}"),
+            ("{% endif %}", (1, 1), "多余的 {% endif %}"),
+            ("{% for x in y %}", (1, 1), "不支持的块标签 'for'"),
+            ("{% if a %}{% else %}{% elif b %}{% endif %}", (1, 21), "之后不能再出现"),
+            ("{% if not a == \"x\" %}{% endif %}", (1, 13), "not 不能与比较同时使用"),
+            ("{% if a == b %}{% endif %}", (1, 9), "比较的右侧必须是字符串"),
+            ("{% if a %}{% endif extra %}", (1, 20), "多余的内容"),
+            ("中文{{ 名 | upper }}{{ $ }}", (1, 21), "无法识别的字符 '$'"),
+        ];
+        for (source, (line, column), message) in cases {
+            let err = Template::parse(source).unwrap_err();
+            assert_eq!(position(&err), (Some(line), Some(column)), "{}", source);
+            assert!(err.message.contains(message), "{}: {}", source, err.message);
+        }
+    }
+
+    #[test]
+    fn test_validate_undeclared_variables_and_enum_options() {
+        let parameters = vec![param("title", SkillParameterType::String), enum_param("style", &["简洁", "详细"])];
+        let content = "{{ title }}\n{% if style == \"花哨\" %}{{ unknown }}{% endif %}";
+        let issues = validate(content, &parameters);
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert_eq!(position(&issues[0]), (Some(2), Some(16)));
+        assert!(issues[0].message.contains("'花哨' 不是参数 'style' 的可选值"));
+        assert_eq!(position(&issues[1]), (Some(2), Some(26)));
+        assert!(issues[1].message.contains("变量 'unknown' 未在参数中声明"));
+
+        // 没有参数的技能不做模板校验
+        assert!(validate("{{ anything", &[]).is_empty());
+        // 模板语法错误只报告一次
+        let issues = validate("{{ title", &parameters);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(position(&issues[0]), (Some(1), Some(1)));
+    }
+
+    #[test]
+    fn test_validate_parameter_definitions() {
+        let mut number = param("count", SkillParameterType::Number);
+        number.default = Some(json!("abc"));
+        let mut sourced = param("flag", SkillParameterType::Boolean);
+        sourced.source = Some(SkillParameterSource::BookTitle);
+        let parameters = vec![
+            param("1st", SkillParameterType::String),
+            param("dup", SkillParameterType::String),
+            param("dup", SkillParameterType::Text),
+            enum_param("empty", &[]),
+            enum_param("twice", &["a", "a"]),
+            number,
+            sourced,
+        ];
+        let messages: Vec<String> = validate("", &parameters).into_iter().map(|i| i.message).collect();
+        assert_eq!(messages.len(), 6, "{:?}", messages);
+        assert!(messages[0].contains("'1st'"));
+        assert!(messages[1].contains("'dup' 重复声明"));
+        assert!(messages[2].contains("'empty' 没有可选值"));
+        assert!(messages[3].contains("'twice' 的可选值重复"));
+        assert!(messages[4].contains("'count' 的默认值无效：'abc' 不是数字"));
+        assert!(messages[5].contains("取值来源 bookTitle 只能用于 string 或 text 类型"));
+    }
+
+    #[test]
+    fn test_coerce_types() {
+        let number = param("n", SkillParameterType::Number);
+        assert_eq!(coerce(&number, &json!(" 42 ")), Ok(json!(42)));
+        assert_eq!(coerce(&number, &json!("1.5")), Ok(json!(1.5)));
+        assert!(coerce(&number, &json!(true)).unwrap_err().contains("需要 number 类型的值"));
+
+        let boolean = param("b", SkillParameterType::Boolean);
+        assert_eq!(coerce(&boolean, &json!("false")), Ok(json!(false)));
+        assert!(coerce(&boolean, &json!("yes")).is_err());
+
+        let text = param("t", SkillParameterType::Text);
+        assert_eq!(coerce(&text, &json!(7)), Ok(json!("7")));
+        assert_eq!(coerce(&text, &Value::Null), Ok(Value::Null));
+
+        let style = enum_param("style", &["简洁", "详细"]);
+        assert_eq!(coerce(&style, &json!("详细")), Ok(json!("详细")));
+        assert!(coerce(&style, &json!("其他")).unwrap_err().contains("简洁、详细"));
+    }
+
+    #[test]
+    fn test_resolve_values_precedence() {
+        let mut title = param("title", SkillParameterType::String);
+        title.source = Some(SkillParameterSource::BookTitle);
+        title.default = Some(json!("默认书名"));
+        let mut count = param("count", SkillParameterType::Number);
+        count.default = Some(json!("3"));
+        let parameters = vec![title, count, param("extra", SkillParameterType::Text)];
+        let sources = HashMap::from([(SkillParameterSource::BookTitle, "来源书名".to_string())]);
+
+        let resolved = resolve_values(&parameters, &values(&[("title", json!("  "))]), &sources).unwrap();
+        assert_eq!(resolved["title"], json!("来源书名"));
+        assert_eq!(resolved["count"], json!(3));
+        assert_eq!(resolved["extra"], Value::Null);
+
+        let resolved = resolve_values(&parameters, &values(&[("title", json!("填写"))]), &HashMap::new()).unwrap();
+        assert_eq!(resolved["title"], json!("填写"));
+        let resolved = resolve_values(&parameters, &HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(resolved["title"], json!("默认书名"));
+
+        let err = resolve_values(&parameters, &values(&[("count", json!("many"))]), &sources).unwrap_err();
+        assert!(err.message.contains("参数 'count' 的值无效"));
+    }
+
+    #[test]
+    fn test_resolve_values_required() {
+        let mut selection = param("selection", SkillParameterType::Text);
+        selection.source = Some(SkillParameterSource::Selection);
+        selection.required = true;
+        let err = resolve_values(&[selection], &HashMap::new(), &HashMap::new()).unwrap_err();
+        assert_eq!(position(&err), (None, None));
+        assert_eq!(err.message, "缺少参数 'selection'（取值来源 selection 没有内容）");
+
+        let mut name = param("name", SkillParameterType::String);
+        name.required = true;
+        let err = resolve_values(&[name], &values(&[("name", json!(""))]), &HashMap::new()).unwrap_err();
+        assert_eq!(err.message, "缺少参数 'name'");
+    }
+}
@@ -0,0 +1,228 @@
+// 对话分支：同一父消息下的多条消息互为分支，切换分支即改变 threads.active_leaf_id
+
+use super::messages::{set_active_leaf, BRANCH_CTE};
+use super::models::ThreadMessage;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// 把没有父消息信息的线性对话按 seq 串成一条分支
+pub async fn link_linear_messages(pool: &SqlitePool, thread_id: &str) -> Result<(), String> {
+    let ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM thread_messages WHERE thread_id = ? AND role != 'summary' ORDER BY seq",
+    )
+    .bind(thread_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for pair in ids.windows(2) {
+        sqlx::query("UPDATE thread_messages SET parent_id = ? WHERE thread_id = ? AND id = ?")
+            .bind(&pair[0])
+            .bind(thread_id)
+            .bind(&pair[1])
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    set_active_leaf(&mut tx, thread_id, ids.last().map(String::as_str)).await?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// 切换到包含指定消息的分支：从该消息起每层选择最新的子消息，直到分支末端
+pub async fn switch_branch(pool: &SqlitePool, thread_id: &str, message_id: &str) -> Result<(), String> {
+    ensure_message(pool, thread_id, message_id).await?;
+
+    let mut leaf = message_id.to_string();
+    while let Some(child) = sqlx::query_scalar::<_, String>(
+        "SELECT id FROM thread_messages WHERE thread_id = ? AND parent_id = ? ORDER BY seq DESC LIMIT 1",
+    )
+    .bind(thread_id)
+    .bind(&leaf)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    {
+        leaf = child;
+    }
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    set_active_leaf(&mut conn, thread_id, Some(&leaf)).await
+}
+
+/// 与指定消息同一父消息的所有消息（包括它自己），按写入顺序排列
+pub async fn sibling_messages(
+    pool: &SqlitePool,
+    thread_id: &str,
+    message_id: &str,
+) -> Result<Vec<ThreadMessage>, String> {
+    ensure_message(pool, thread_id, message_id).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT * FROM thread_messages
+        WHERE thread_id = ? AND role != 'summary'
+          AND parent_id IS (SELECT parent_id FROM thread_messages WHERE thread_id = ? AND id = ?)
+        ORDER BY seq
+        "#,
+    )
+    .bind(thread_id)
+    .bind(thread_id)
+    .bind(message_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    rows.iter()
+        .map(ThreadMessage::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// 以指定消息为末端复制出一个新对话，只包含从根消息到该消息的分支；返回新对话 id
+pub async fn fork_thread(
+    pool: &SqlitePool,
+    thread_id: &str,
+    message_id: &str,
+    title: Option<&str>,
+) -> Result<String, String> {
+    ensure_message(pool, thread_id, message_id).await?;
+
+    let new_thread_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO threads (id, book_id, metadata, title, messages, active_leaf_id, created_at, updated_at)
+        SELECT ?, book_id, metadata, COALESCE(?, title), '[]', ?, ?, ?
+        FROM threads WHERE id = ?
+        "#,
+    )
+    .bind(&new_thread_id)
+    .bind(title)
+    .bind(message_id)
+    .bind(now)
+    .bind(now)
+    .bind(thread_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    sqlx::query(&format!(
+        r#"
+        {}
+        INSERT INTO thread_messages (
+            id, thread_id, parent_id, seq, role, content, parts, tool_calls, metadata,
+            input_tokens, output_tokens, total_tokens, created_at
+        )
+        SELECT m.id, ?, m.parent_id, m.seq, m.role, m.content, m.parts, m.tool_calls, m.metadata,
+               m.input_tokens, m.output_tokens, m.total_tokens, m.created_at
+        FROM branch b JOIN thread_messages m ON m.thread_id = ? AND m.id = b.id
+        ORDER BY b.depth DESC
+        "#,
+        BRANCH_CTE
+    ))
+    .bind(thread_id)
+    .bind(message_id)
+    .bind(thread_id)
+    .bind(&new_thread_id)
+    .bind(thread_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(new_thread_id)
+}
+
+/// 摘要消息不属于任何分支，不能作为分支操作的目标
+async fn ensure_message(pool: &SqlitePool, thread_id: &str, message_id: &str) -> Result<(), String> {
+    let exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM thread_messages WHERE thread_id = ? AND id = ? AND role != 'summary'",
+    )
+    .bind(thread_id)
+    .bind(message_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    if exists == 0 {
+        return Err("Message not found".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::threads::messages::tests::{append, branch_ids, message, save, test_pool};
+    use crate::core::threads::messages::load_messages;
+
+    #[tokio::test]
+    async fn test_link_linear_messages() {
+        let pool = test_pool().await;
+        for (seq, (id, role)) in [("u1", "user"), ("s1", "summary"), ("a1", "assistant")].into_iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO thread_messages (id, thread_id, seq, role, content, parts, created_at) VALUES (?, 't', ?, ?, '', '[]', 0)",
+            )
+            .bind(id)
+            .bind(seq as i64)
+            .bind(role)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        link_linear_messages(&pool, "t").await.unwrap();
+        // 摘要消息不属于任何分支
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a1"]);
+        let summary_parent: Option<String> =
+            sqlx::query_scalar("SELECT parent_id FROM thread_messages WHERE id = 's1'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(summary_parent, None);
+    }
+
+    #[tokio::test]
+    async fn test_switch_branch_follows_latest_child() {
+        let pool = test_pool().await;
+        save(
+            &pool,
+            &[message("u1", "user", "q"), message("a1", "assistant", "x"), message("u2", "user", "more")],
+        )
+        .await;
+        append(&pool, message("a2", "assistant", "y"), Some("u1")).await;
+        append(&pool, message("u3", "user", "other"), None).await;
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a2", "u3"]);
+
+        switch_branch(&pool, "t", "a1").await.unwrap();
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a1", "u2"]);
+
+        // 从根消息出发时选择最新的子消息
+        switch_branch(&pool, "t", "u1").await.unwrap();
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a2", "u3"]);
+
+        assert!(switch_branch(&pool, "t", "missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fork_thread_copies_branch() {
+        let pool = test_pool().await;
+        save(&pool, &[message("u1", "user", "q"), message("a1", "assistant", "x")]).await;
+        append(&pool, message("a2", "assistant", "y"), Some("u1")).await;
+
+        let forked = fork_thread(&pool, "t", "a1", Some("Fork")).await.unwrap();
+        let ids: Vec<String> = load_messages(&pool, &forked).await.unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec!["u1", "a1"]);
+        let copied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM thread_messages WHERE thread_id = ?")
+            .bind(&forked)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(copied, 2);
+
+        // 原对话不受影响
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a2"]);
+    }
+}
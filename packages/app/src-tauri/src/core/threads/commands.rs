@@ -1,10 +1,27 @@
-use super::models::{EditThreadPayload, NewThreadPayload, Thread, ThreadSummary};
+use super::branches;
+use super::compaction::{self, SummarizerConfig, DEFAULT_KEEP_RECENT_TOKENS, DEFAULT_TOKEN_BUDGET};
+use super::export::{build_export, render, ExportFormat};
+use super::messages::{append_message, load_branch, load_page, messages_json, save_branch};
+use super::models::{
+    AppendThreadMessagePayload, CompactThreadPayload, EditThreadPayload, ForkThreadPayload,
+    NewThreadPayload, Thread, ThreadCompactedContext, ThreadCompactionReport, ThreadExportReport,
+    ThreadMessage, ThreadMessagePage, ThreadSummary,
+};
+use crate::core::notes::markdown::file_stem;
 use crate::core::state::AppState;
-use serde_json;
-use sqlx::Row;
-use tauri::State;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashSet;
+use std::path::Path;
+use tauri::{AppHandle, Manager, State};
 use uuid::Uuid;
 
+const THREAD_COLUMNS: &str = "id, book_id, metadata, title, active_leaf_id, created_at, updated_at";
+
+const SUMMARY_COLUMNS: &str = r#"
+    t.id, t.book_id, t.metadata, t.title, t.created_at, t.updated_at,
+    (SELECT COUNT(*) FROM thread_messages m WHERE m.thread_id = t.id AND m.role != 'summary') AS message_count
+"#;
+
 #[tauri::command]
 pub async fn create_thread(
     payload: NewThreadPayload,
@@ -17,13 +34,12 @@ pub async fn create_thread(
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
     sqlx::query(
-        "INSERT INTO threads (id, book_id, metadata, title, messages, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO threads (id, book_id, metadata, title, messages, created_at, updated_at) VALUES (?, ?, ?, ?, '[]', ?, ?)",
     )
     .bind(&thread_id)
     .bind(&payload.book_id)
     .bind(&payload.metadata)
     .bind(&payload.title)
-    .bind(&payload.messages_json)
     .bind(current_timestamp)
     .bind(current_timestamp)
     .execute(pool)
@@ -33,16 +49,14 @@ pub async fn create_thread(
         e.to_string()
     })?;
 
-    let new_thread = Thread {
-        id: thread_id,
-        book_id: payload.book_id,
-        metadata: payload.metadata,
-        title: payload.title,
-        messages: payload.messages_json,
-        created_at: current_timestamp,
-        updated_at: current_timestamp,
-    };
-    Ok(new_thread)
+    save_branch(pool, &thread_id, &payload.messages_json)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to save thread messages: {}", e);
+            e
+        })?;
+
+    load_thread(pool, &thread_id).await
 }
 
 #[tauri::command]
@@ -55,57 +69,89 @@ pub async fn edit_thread(
     let db_pool_guard = state.db_pool.lock().await;
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
-    let row = sqlx::query(
-        "SELECT id, book_id, metadata, title, messages, created_at, updated_at FROM threads WHERE id = ?"
-    )
-    .bind(&payload.id)
-    .fetch_one(pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Failed to fetch existing thread: {}", e);
-        "Thread not found".to_string()
-    })?;
+    let row = sqlx::query("SELECT title, metadata FROM threads WHERE id = ?")
+        .bind(&payload.id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch existing thread: {}", e);
+            "Thread not found".to_string()
+        })?;
 
-    let existing_thread = Thread {
-        id: row.get("id"),
-        book_id: row.get("book_id"),
-        metadata: row.get("metadata"),
-        title: row.get("title"),
-        messages: row.get("messages"),
-        created_at: row.get("created_at"),
-        updated_at: row.get("updated_at"),
-    };
+    let existing_title: String = row.get("title");
+    let existing_metadata: String = row.get("metadata");
+    let new_title = payload.title.unwrap_or(existing_title);
+    let new_metadata = payload.metadata.unwrap_or(existing_metadata);
+
+    // 消息列表作为当前分支保存，只写入新增或变化的消息，旧分支保留
+    if let Some(messages_json) = &payload.messages_json {
+        save_branch(pool, &payload.id, messages_json)
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to update thread messages: {}", e);
+                e
+            })?;
+    }
 
-    let new_title = payload.title.unwrap_or(existing_thread.title);
-    let new_metadata = payload.metadata.unwrap_or(existing_thread.metadata);
-    let new_messages = payload.messages_json.unwrap_or(existing_thread.messages);
+    sqlx::query("UPDATE threads SET title = ?, metadata = ?, updated_at = ? WHERE id = ?")
+        .bind(&new_title)
+        .bind(&new_metadata)
+        .bind(current_timestamp)
+        .bind(&payload.id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to update thread: {}", e);
+            e.to_string()
+        })?;
 
-    sqlx::query(
-        "UPDATE threads SET title = ?, metadata = ?, messages = ?, updated_at = ? WHERE id = ?",
+    load_thread(pool, &payload.id).await
+}
+
+/// 追加一条消息（UIMessage JSON）并切换到该分支；id 已存在时原位更新
+#[tauri::command]
+pub async fn append_thread_message(
+    payload: AppendThreadMessagePayload,
+    state: State<'_, AppState>,
+) -> Result<ThreadMessage, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    let exists: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM threads WHERE id = ?")
+        .bind(&payload.thread_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if exists == 0 {
+        return Err("Thread not found".to_string());
+    }
+
+    append_message(
+        pool,
+        &payload.thread_id,
+        &payload.message_json,
+        payload.parent_id.as_deref(),
     )
-    .bind(&new_title)
-    .bind(&new_metadata)
-    .bind(&new_messages)
-    .bind(current_timestamp)
-    .bind(&payload.id)
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Failed to update thread: {}", e);
-        e.to_string()
-    })?;
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to append thread message: {}", e);
+            e
+        })
+}
 
-    let updated_thread = Thread {
-        id: existing_thread.id,
-        book_id: existing_thread.book_id,
-        metadata: new_metadata,
-        title: new_title,
-        messages: new_messages,
-        created_at: existing_thread.created_at,
-        updated_at: current_timestamp,
-    };
+/// 分页获取当前分支的消息：从分支末端往前翻，before_seq 为上一页第一条消息的 seq；每页内按时间正序
+#[tauri::command]
+pub async fn get_thread_messages(
+    thread_id: String,
+    before_seq: Option<i64>,
+    limit: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<ThreadMessagePage, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+    let limit = limit.unwrap_or(50).clamp(1, 500);
 
-    Ok(updated_thread)
+    load_page(pool, &thread_id, before_seq, limit).await
 }
 
 #[tauri::command]
@@ -117,7 +163,7 @@ pub async fn get_latest_thread_by_book_id(
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
     let row_result = sqlx::query(
-        "SELECT id, book_id, metadata, title, messages, created_at, updated_at FROM threads WHERE book_id IS ? ORDER BY updated_at DESC LIMIT 1"
+        &format!("SELECT {} FROM threads WHERE book_id IS ? ORDER BY updated_at DESC LIMIT 1", THREAD_COLUMNS),
     )
     .bind(&book_id)
     .fetch_optional(pool)
@@ -127,19 +173,9 @@ pub async fn get_latest_thread_by_book_id(
         e.to_string()
     })?;
 
-    if let Some(row) = row_result {
-        let thread = Thread {
-            id: row.get("id"),
-            book_id: row.get("book_id"),
-            metadata: row.get("metadata"),
-            title: row.get("title"),
-            messages: row.get("messages"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        };
-        Ok(Some(thread))
-    } else {
-        Ok(None)
+    match row_result {
+        Some(row) => Ok(Some(thread_from_row(pool, &row).await?)),
+        None => Ok(None),
     }
 }
 
@@ -151,9 +187,10 @@ pub async fn get_threads_by_book_id(
     let db_pool_guard = state.db_pool.lock().await;
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
-    let rows = sqlx::query(
-        "SELECT id, book_id, metadata, title, messages, created_at, updated_at FROM threads WHERE book_id IS ? ORDER BY updated_at DESC"
-    )
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM threads t WHERE t.book_id IS ? ORDER BY t.updated_at DESC",
+        SUMMARY_COLUMNS
+    ))
     .bind(&book_id)
     .fetch_all(pool)
     .await
@@ -162,34 +199,7 @@ pub async fn get_threads_by_book_id(
         e.to_string()
     })?;
 
-    let thread_summaries: Vec<ThreadSummary> = rows
-        .into_iter()
-        .map(|row| {
-            let messages_json: String = row.get("messages");
-            let message_count = match serde_json::from_str::<serde_json::Value>(&messages_json) {
-                Ok(messages_array) => {
-                    if let Some(array) = messages_array.as_array() {
-                        array.len() as i32
-                    } else {
-                        0
-                    }
-                }
-                Err(_) => 0,
-            };
-
-            ThreadSummary {
-                id: row.get("id"),
-                book_id: row.get("book_id"),
-                metadata: row.get("metadata"),
-                title: row.get("title"),
-                message_count,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            }
-        })
-        .collect();
-
-    Ok(thread_summaries)
+    Ok(rows.iter().map(summary_from_row).collect())
 }
 
 #[tauri::command]
@@ -197,9 +207,10 @@ pub async fn get_all_threads(state: State<'_, AppState>) -> Result<Vec<ThreadSum
     let db_pool_guard = state.db_pool.lock().await;
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
-    let rows = sqlx::query(
-        "SELECT id, book_id, metadata, title, messages, created_at, updated_at FROM threads ORDER BY updated_at DESC"
-    )
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM threads t ORDER BY t.updated_at DESC",
+        SUMMARY_COLUMNS
+    ))
     .fetch_all(pool)
     .await
     .map_err(|e| {
@@ -207,34 +218,7 @@ pub async fn get_all_threads(state: State<'_, AppState>) -> Result<Vec<ThreadSum
         e.to_string()
     })?;
 
-    let thread_summaries: Vec<ThreadSummary> = rows
-        .into_iter()
-        .map(|row| {
-            let messages_json: String = row.get("messages");
-            let message_count = match serde_json::from_str::<serde_json::Value>(&messages_json) {
-                Ok(messages_array) => {
-                    if let Some(array) = messages_array.as_array() {
-                        array.len() as i32
-                    } else {
-                        0
-                    }
-                }
-                Err(_) => 0,
-            };
-
-            ThreadSummary {
-                id: row.get("id"),
-                book_id: row.get("book_id"),
-                metadata: row.get("metadata"),
-                title: row.get("title"),
-                message_count,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            }
-        })
-        .collect();
-
-    Ok(thread_summaries)
+    Ok(rows.iter().map(summary_from_row).collect())
 }
 
 #[tauri::command]
@@ -245,28 +229,7 @@ pub async fn get_thread_by_id(
     let db_pool_guard = state.db_pool.lock().await;
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
-    let row = sqlx::query(
-        "SELECT id, book_id, metadata, title, messages, created_at, updated_at FROM threads WHERE id = ?"
-    )
-    .bind(&thread_id)
-    .fetch_one(pool)
-    .await
-    .map_err(|e| {
-        eprintln!("Failed to fetch thread by id: {}", e);
-        "Thread not found".to_string()
-    })?;
-
-    let thread = Thread {
-        id: row.get("id"),
-        book_id: row.get("book_id"),
-        metadata: row.get("metadata"),
-        title: row.get("title"),
-        messages: row.get("messages"),
-        created_at: row.get("created_at"),
-        updated_at: row.get("updated_at"),
-    };
-
-    Ok(thread)
+    load_thread(pool, &thread_id).await
 }
 
 #[tauri::command]
@@ -274,6 +237,7 @@ pub async fn delete_thread(thread_id: String, state: State<'_, AppState>) -> Res
     let db_pool_guard = state.db_pool.lock().await;
     let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
 
+    // 外键约束会自动删除 thread_messages 中的消息
     let result = sqlx::query("DELETE FROM threads WHERE id = ?")
         .bind(&thread_id)
         .execute(pool)
@@ -289,3 +253,257 @@ pub async fn delete_thread(thread_id: String, state: State<'_, AppState>) -> Res
 
     Ok(())
 }
+
+/// 以指定消息为末端复制出一个新对话
+#[tauri::command]
+pub async fn fork_thread(payload: ForkThreadPayload, state: State<'_, AppState>) -> Result<Thread, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    let new_thread_id = branches::fork_thread(
+        pool,
+        &payload.thread_id,
+        &payload.message_id,
+        payload.title.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fork thread: {}", e);
+        e
+    })?;
+
+    load_thread(pool, &new_thread_id).await
+}
+
+/// 切换到包含指定消息的分支，返回切换后的对话
+#[tauri::command]
+pub async fn switch_thread_branch(
+    thread_id: String,
+    message_id: String,
+    state: State<'_, AppState>,
+) -> Result<Thread, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    branches::switch_branch(pool, &thread_id, &message_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to switch thread branch: {}", e);
+            e
+        })?;
+
+    load_thread(pool, &thread_id).await
+}
+
+/// 同一位置上的其他分支（包括该消息自身），按写入顺序排列
+#[tauri::command]
+pub async fn get_message_siblings(
+    thread_id: String,
+    message_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<ThreadMessage>, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    branches::sibling_messages(pool, &thread_id, &message_id).await
+}
+
+/// 按顺序返回一条分支上的全部消息；leaf_id 为空时取当前分支
+#[tauri::command]
+pub async fn get_thread_branch(
+    thread_id: String,
+    leaf_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ThreadMessage>, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    let thread = load_thread(pool, &thread_id).await?;
+    match leaf_id.or(thread.active_leaf_id) {
+        Some(leaf_id) => {
+            let messages = load_branch(pool, &thread_id, &leaf_id).await?;
+            if messages.is_empty() {
+                return Err("Message not found".to_string());
+            }
+            Ok(messages)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// 当前分支超过 token 预算时，把较早的消息总结为摘要消息；原消息保留
+#[tauri::command]
+pub async fn compact_thread(
+    payload: CompactThreadPayload,
+    state: State<'_, AppState>,
+) -> Result<ThreadCompactionReport, String> {
+    // 总结需要等待模型返回，不持有数据库锁
+    let pool = state
+        .db_pool
+        .lock()
+        .await
+        .clone()
+        .ok_or("Database not initialized")?;
+
+    let config = SummarizerConfig {
+        base_url: payload.base_url,
+        api_key: payload.api_key,
+        model: payload.model,
+    };
+    compaction::compact_thread(
+        &pool,
+        &payload.thread_id,
+        &config,
+        payload.token_budget.unwrap_or(DEFAULT_TOKEN_BUDGET),
+        payload.keep_recent_tokens.unwrap_or(DEFAULT_KEEP_RECENT_TOKENS),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to compact thread: {}", e);
+        e
+    })
+}
+
+/// 发送给模型的上下文：最近一次摘要加上其后的消息
+#[tauri::command]
+pub async fn get_thread_context(
+    thread_id: String,
+    state: State<'_, AppState>,
+) -> Result<ThreadCompactedContext, String> {
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    compaction::compacted_context(pool, &thread_id).await
+}
+
+/// 导出一个对话（format: markdown | html | json）；leaf_id 为空时导出当前分支
+#[tauri::command]
+pub async fn export_thread(
+    app_handle: AppHandle,
+    thread_id: String,
+    format: String,
+    file_path: String,
+    leaf_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<ThreadExportReport, String> {
+    let format = ExportFormat::parse(&format)?;
+    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
+
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    let thread = build_export(pool, &app_data_dir, &thread_id, leaf_id.as_deref()).await?;
+    write_export(Path::new(&file_path), &render(&thread, format)?)?;
+
+    Ok(ThreadExportReport {
+        exported_threads: 1,
+        file_paths: vec![file_path],
+    })
+}
+
+/// 把一本书的全部对话导出到目录，每个对话一个文件；book_id 为空时导出未关联书籍的对话
+#[tauri::command]
+pub async fn export_book_threads(
+    app_handle: AppHandle,
+    book_id: Option<String>,
+    format: String,
+    dir_path: String,
+    state: State<'_, AppState>,
+) -> Result<ThreadExportReport, String> {
+    let format = ExportFormat::parse(&format)?;
+    let app_data_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
+
+    let db_pool_guard = state.db_pool.lock().await;
+    let pool = db_pool_guard.as_ref().ok_or("Database not initialized")?;
+
+    let thread_ids: Vec<String> =
+        sqlx::query_scalar("SELECT id FROM threads WHERE book_id IS ? ORDER BY created_at ASC")
+            .bind(&book_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to fetch threads for export: {}", e);
+                e.to_string()
+            })?;
+
+    let dir = Path::new(&dir_path);
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create export directory: {}", e))?;
+
+    let mut used_names = HashSet::new();
+    let mut file_paths = Vec::new();
+    for thread_id in &thread_ids {
+        let thread = build_export(pool, &app_data_dir, thread_id, None).await?;
+
+        // 标题相同的对话在文件名后追加 id 前缀区分
+        let title = Some(thread.title.as_str()).filter(|t| !t.trim().is_empty()).or(Some("对话"));
+        let mut name = format!("{}.{}", file_stem(title), format.extension());
+        if !used_names.insert(name.to_lowercase()) {
+            let short_id: String = thread.id.chars().take(8).collect();
+            name = format!("{}-{}.{}", file_stem(title), short_id, format.extension());
+            used_names.insert(name.to_lowercase());
+        }
+
+        let path = dir.join(&name);
+        write_export(&path, &render(&thread, format)?)?;
+        file_paths.push(path.to_string_lossy().to_string());
+    }
+
+    Ok(ThreadExportReport {
+        exported_threads: file_paths.len() as i64,
+        file_paths,
+    })
+}
+
+fn write_export(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create export directory: {}", e))?;
+    }
+    std::fs::write(path, content).map_err(|e| {
+        eprintln!("Failed to write export file: {}", e);
+        e.to_string()
+    })
+}
+
+async fn load_thread(pool: &SqlitePool, thread_id: &str) -> Result<Thread, String> {
+    let row = sqlx::query(&format!("SELECT {} FROM threads WHERE id = ?", THREAD_COLUMNS))
+        .bind(thread_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch thread by id: {}", e);
+            "Thread not found".to_string()
+        })?;
+
+    thread_from_row(pool, &row).await
+}
+
+async fn thread_from_row(pool: &SqlitePool, row: &sqlx::sqlite::SqliteRow) -> Result<Thread, String> {
+    let id: String = row.get("id");
+    let messages = messages_json(pool, &id).await.map_err(|e| {
+        eprintln!("Failed to load thread messages: {}", e);
+        e
+    })?;
+
+    Ok(Thread {
+        id,
+        book_id: row.get("book_id"),
+        metadata: row.get("metadata"),
+        title: row.get("title"),
+        messages,
+        active_leaf_id: row.get("active_leaf_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+fn summary_from_row(row: &sqlx::sqlite::SqliteRow) -> ThreadSummary {
+    ThreadSummary {
+        id: row.get("id"),
+        book_id: row.get("book_id"),
+        metadata: row.get("metadata"),
+        title: row.get("title"),
+        message_count: row.get::<i64, _>("message_count") as i32,
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
@@ -0,0 +1,298 @@
+// 对话压缩：当前分支的上下文超过 token 预算时，请模型把较早的消息总结为一条摘要消息（role = summary）。
+// 原消息保持不变，摘要通过 summary_from_id/summary_to_id 指向它替代的范围；token 数按 o200k_base 计算
+
+use super::messages::load_messages;
+use super::models::{ThreadCompactedContext, ThreadCompactionReport, ThreadMessage};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::sync::OnceLock;
+use tauri_plugin_epub::TextTokenizer;
+use uuid::Uuid;
+
+pub const DEFAULT_TOKEN_BUDGET: usize = 8192;
+pub const DEFAULT_KEEP_RECENT_TOKENS: usize = 2048;
+/// 无论 token 多少，最近的几条消息总是原样保留
+const MIN_RECENT_MESSAGES: usize = 2;
+/// 每条消息的角色标记等固定开销
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+const SUMMARY_PROMPT: &str = "你是对话压缩助手。请把下面关于书籍的对话历史压缩为一段摘要，供后续对话作为上下文使用。\
+保留用户的问题与关注点、得出的关键结论与事实、引用过的章节与 [chunk_id] 标注、尚未解决的问题。\
+不要编造对话中没有的内容，使用对话所用的语言，只输出摘要正文。";
+
+static TOKENIZER: OnceLock<TextTokenizer> = OnceLock::new();
+
+/// OpenAI 兼容的对话接口
+pub struct SummarizerConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+/// 当前分支发送给模型的上下文：最近一次摘要加上其后尚未被摘要的消息
+pub async fn compacted_context(pool: &SqlitePool, thread_id: &str) -> Result<ThreadCompactedContext, String> {
+    let tokenizer = tokenizer()?;
+    let branch = load_messages(pool, thread_id).await?;
+    let previous = latest_summary(pool, thread_id, &branch).await?;
+
+    let start = previous.as_ref().map(|(_, end)| end + 1).unwrap_or(0);
+    let messages = branch[start..].to_vec();
+    let summary = previous.map(|(summary, _)| summary);
+    let total_tokens = summary.iter().chain(&messages).map(|m| message_tokens(tokenizer, m)).sum::<usize>();
+
+    Ok(ThreadCompactedContext {
+        summary,
+        messages,
+        total_tokens: total_tokens as i64,
+    })
+}
+
+/// 超出预算时，把上次摘要之后、最近 keep_recent_tokens 之前的消息连同上次摘要一起重新总结
+pub async fn compact_thread(
+    pool: &SqlitePool,
+    thread_id: &str,
+    config: &SummarizerConfig,
+    token_budget: usize,
+    keep_recent_tokens: usize,
+) -> Result<ThreadCompactionReport, String> {
+    let tokenizer = tokenizer()?;
+    let branch = load_messages(pool, thread_id).await?;
+    let previous = latest_summary(pool, thread_id, &branch).await?;
+
+    let start = previous.as_ref().map(|(_, end)| end + 1).unwrap_or(0);
+    let pending = &branch[start..];
+    let summary_tokens = previous.as_ref().map(|(s, _)| message_tokens(tokenizer, s)).unwrap_or(0);
+    let pending_tokens: Vec<usize> = pending.iter().map(|m| message_tokens(tokenizer, m)).collect();
+    let tokens_before = summary_tokens + pending_tokens.iter().sum::<usize>();
+
+    let Some(plan) = plan_compaction(summary_tokens, &pending_tokens, token_budget, keep_recent_tokens) else {
+        return Ok(ThreadCompactionReport {
+            compacted: false,
+            summary: None,
+            summarized_messages: 0,
+            tokens_before: tokens_before as i64,
+            tokens_after: tokens_before as i64,
+        });
+    };
+    let to_summarize = &pending[..plan.summarize];
+    let last = &to_summarize[plan.summarize - 1];
+
+    let previous_text = previous.as_ref().map(|(s, _)| s.content.as_str());
+    let (text, completion_tokens) = summarize(config, previous_text, to_summarize).await?;
+
+    // 新摘要包含上次摘要的内容，范围从上次摘要的起点开始
+    let from_id = previous
+        .as_ref()
+        .and_then(|(s, _)| s.summary_from_id.clone())
+        .unwrap_or_else(|| branch[0].id.clone());
+    let summarized_messages = start + to_summarize.len();
+    let text_tokens = tokenizer.estimate_tokens(&text) as i64;
+    let summary_id = Uuid::new_v4().to_string();
+
+    sqlx::query(
+        r#"
+        INSERT INTO thread_messages (
+            id, thread_id, parent_id, seq, role, content, parts, metadata,
+            output_tokens, total_tokens, summary_from_id, summary_to_id, created_at
+        ) VALUES (
+            ?, ?, NULL, (SELECT COALESCE(MAX(seq) + 1, 0) FROM thread_messages WHERE thread_id = ?),
+            'summary', ?, ?, ?, ?, ?, ?, ?, ?
+        )
+        "#,
+    )
+    .bind(&summary_id)
+    .bind(thread_id)
+    .bind(thread_id)
+    .bind(&text)
+    .bind(json!([{ "type": "text", "text": text }]).to_string())
+    .bind(json!({ "model": config.model, "summarizedMessages": summarized_messages }).to_string())
+    .bind(completion_tokens)
+    .bind(text_tokens)
+    .bind(&from_id)
+    .bind(&last.id)
+    .bind(chrono::Utc::now().timestamp_millis())
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let row = sqlx::query("SELECT * FROM thread_messages WHERE thread_id = ? AND id = ?")
+        .bind(thread_id)
+        .bind(&summary_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let summary = ThreadMessage::from_db_row(&row).map_err(|e| e.to_string())?;
+
+    Ok(ThreadCompactionReport {
+        tokens_after: (message_tokens(tokenizer, &summary) + plan.kept_tokens) as i64,
+        compacted: true,
+        summary: Some(summary),
+        summarized_messages: summarized_messages as i64,
+        tokens_before: tokens_before as i64,
+    })
+}
+
+/// 一次压缩的划分：待摘要消息中前 summarize 条被总结，其余原样保留
+#[derive(Debug, PartialEq)]
+struct CompactionPlan {
+    summarize: usize,
+    kept_tokens: usize,
+}
+
+/// 按各条待摘要消息的 token 数划分；未超出预算或没有可总结的消息时返回 None。
+/// 从最近的消息往前保留，至少 MIN_RECENT_MESSAGES 条，此外不超过 keep_recent_tokens
+fn plan_compaction(
+    summary_tokens: usize,
+    pending_tokens: &[usize],
+    token_budget: usize,
+    keep_recent_tokens: usize,
+) -> Option<CompactionPlan> {
+    if summary_tokens + pending_tokens.iter().sum::<usize>() <= token_budget {
+        return None;
+    }
+
+    let mut kept = 0;
+    let mut kept_tokens = 0;
+    for &tokens in pending_tokens.iter().rev() {
+        if kept >= MIN_RECENT_MESSAGES && kept_tokens + tokens > keep_recent_tokens {
+            break;
+        }
+        kept += 1;
+        kept_tokens += tokens;
+    }
+    let summarize = pending_tokens.len() - kept;
+    (summarize > 0).then_some(CompactionPlan { summarize, kept_tokens })
+}
+
+/// 当前分支上范围最靠后的摘要，以及它覆盖到的消息在分支中的下标
+async fn latest_summary(
+    pool: &SqlitePool,
+    thread_id: &str,
+    branch: &[ThreadMessage],
+) -> Result<Option<(ThreadMessage, usize)>, String> {
+    let rows = sqlx::query("SELECT * FROM thread_messages WHERE thread_id = ? AND role = 'summary' ORDER BY seq")
+        .bind(thread_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let position = |id: &Option<String>| branch.iter().position(|m| Some(&m.id) == id.as_ref());
+    let mut latest: Option<(ThreadMessage, usize)> = None;
+    for row in &rows {
+        let summary = ThreadMessage::from_db_row(row).map_err(|e| e.to_string())?;
+        // 范围必须完整地落在当前分支上，其他分支上的摘要不适用
+        let (Some(from), Some(to)) = (position(&summary.summary_from_id), position(&summary.summary_to_id)) else {
+            continue;
+        };
+        if from <= to && latest.as_ref().is_none_or(|(_, end)| to >= *end) {
+            latest = Some((summary, to));
+        }
+    }
+    Ok(latest)
+}
+
+async fn summarize(
+    config: &SummarizerConfig,
+    previous: Option<&str>,
+    messages: &[ThreadMessage],
+) -> Result<(String, Option<i64>), String> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("此前对话的摘要：\n{}\n\n", previous));
+    }
+    transcript.push_str("对话记录：\n");
+    for message in messages {
+        let role = match message.role.as_str() {
+            "user" => "用户",
+            "assistant" => "助手",
+            other => other,
+        };
+        transcript.push_str(&format!("\n{}：{}\n", role, message.content.trim()));
+    }
+
+    let body = json!({
+        "model": config.model,
+        "messages": [
+            { "role": "system", "content": SUMMARY_PROMPT },
+            { "role": "user", "content": transcript },
+        ],
+        "temperature": 0.2,
+        "stream": false,
+    });
+
+    let url = format!("{}/chat/completions", config.base_url.trim_end_matches('/'));
+    let mut request = reqwest::Client::new()
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    if let Some(api_key) = config.api_key.as_deref().filter(|k| !k.is_empty()) {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("无法连接摘要接口: {}", e))?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("摘要请求失败 ({}): {}", status, text));
+    }
+
+    let value: Value =
+        serde_json::from_str(&text).map_err(|e| format!("摘要响应格式无效: {}", e))?;
+    let content = value["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+    // 推理模型会把思考过程放在 <think> 中
+    let content = content.rsplit_once("</think>").map(|(_, rest)| rest).unwrap_or(content).trim();
+    if content.is_empty() {
+        return Err("摘要结果为空".to_string());
+    }
+
+    Ok((content.to_string(), value["usage"]["completion_tokens"].as_i64()))
+}
+
+fn message_tokens(tokenizer: &TextTokenizer, message: &ThreadMessage) -> usize {
+    tokenizer.estimate_tokens(&message.content) + MESSAGE_OVERHEAD_TOKENS
+}
+
+fn tokenizer() -> Result<&'static TextTokenizer, String> {
+    if let Some(tokenizer) = TOKENIZER.get() {
+        return Ok(tokenizer);
+    }
+    let tokenizer = TextTokenizer::new().map_err(|e| e.to_string())?;
+    Ok(TOKENIZER.get_or_init(|| tokenizer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(summarize: usize, kept_tokens: usize) -> Option<CompactionPlan> {
+        Some(CompactionPlan { summarize, kept_tokens })
+    }
+
+    #[test]
+    fn test_plan_within_budget() {
+        assert_eq!(plan_compaction(0, &[100, 100, 100], 300, 50), None);
+        assert_eq!(plan_compaction(50, &[100, 100], 250, 50), None);
+        assert_eq!(plan_compaction(0, &[], 0, 0), None);
+    }
+
+    #[test]
+    fn test_plan_keeps_recent_tokens() {
+        // 最近的 40 + 30 + 20 在 100 以内，再加上 50 就超出
+        assert_eq!(plan_compaction(0, &[80, 60, 50, 40, 30, 20], 200, 100), plan(3, 90));
+        // 恰好等于 keep_recent_tokens 时保留
+        assert_eq!(plan_compaction(0, &[80, 60, 50, 40, 30, 20], 200, 140), plan(2, 140));
+        // 上次摘要计入预算，但不参与划分
+        assert_eq!(plan_compaction(500, &[10, 10, 10], 100, 20), plan(1, 20));
+    }
+
+    #[test]
+    fn test_plan_keeps_minimum_messages() {
+        // 最近两条即使超出 keep_recent_tokens 也原样保留
+        assert_eq!(plan_compaction(0, &[100, 500, 600], 500, 10), plan(1, 1100));
+        // 只有两条消息时没有可总结的内容
+        assert_eq!(plan_compaction(0, &[5000, 5000], 100, 10), None);
+    }
+}
@@ -0,0 +1,429 @@
+// 对话导出：把对话的一条分支渲染为 Markdown、独立 HTML 或结构化 JSON。
+// 消息 metadata 与工具调用结果中记录的引用块按 chunk_id 到书籍的向量库中查询所在章节
+
+use super::messages::{load_branch, load_messages};
+use super::models::ThreadMessage;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unsupported export format: {}", value)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedThread {
+    pub id: String,
+    pub title: String,
+    pub book_id: Option<String>,
+    pub book_title: Option<String>,
+    pub book_author: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedMessage {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+    pub created_at: i64,
+    pub total_tokens: Option<i64>,
+    pub citations: Vec<ExportedCitation>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportedCitation {
+    pub chunk_id: i64,
+    pub chapter_title: Option<String>, // 来自向量库的 related_chapter_titles
+    pub source: Option<String>,        // 工具结果中的来源描述，向量库不可用时作为后备
+    pub preview: Option<String>,
+}
+
+/// 读取对话并解析引用；leaf_id 为空时导出当前分支
+pub async fn build_export(
+    pool: &SqlitePool,
+    app_data_dir: &Path,
+    thread_id: &str,
+    leaf_id: Option<&str>,
+) -> Result<ExportedThread, String> {
+    let row = sqlx::query(
+        r#"
+        SELECT t.id, t.title, t.book_id, t.created_at, t.updated_at,
+               b.title AS book_title, b.author AS book_author
+        FROM threads t LEFT JOIN books b ON b.id = t.book_id
+        WHERE t.id = ?
+        "#,
+    )
+    .bind(thread_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or("Thread not found")?;
+
+    let messages = match leaf_id {
+        Some(leaf_id) => {
+            let messages = load_branch(pool, thread_id, leaf_id).await?;
+            if messages.is_empty() {
+                return Err("Message not found".to_string());
+            }
+            messages
+        }
+        None => load_messages(pool, thread_id).await?,
+    };
+
+    let mut exported: Vec<ExportedMessage> = messages
+        .iter()
+        .map(|message| ExportedMessage {
+            id: message.id.clone(),
+            role: message.role.clone(),
+            content: message.content.clone(),
+            created_at: message.created_at,
+            total_tokens: message.total_tokens,
+            citations: collect_citations(message),
+        })
+        .filter(|message| !message.content.trim().is_empty() || !message.citations.is_empty())
+        .collect();
+
+    let book_id: Option<String> = row.get("book_id");
+    if let Some(book_id) = &book_id {
+        let chunk_ids: HashSet<i64> = exported
+            .iter()
+            .flat_map(|m| m.citations.iter().map(|c| c.chunk_id))
+            .collect();
+        let titles = chapter_titles(app_data_dir, book_id, &chunk_ids).await;
+        for citation in exported.iter_mut().flat_map(|m| m.citations.iter_mut()) {
+            citation.chapter_title = titles.get(&citation.chunk_id).cloned();
+        }
+    }
+
+    Ok(ExportedThread {
+        id: row.get("id"),
+        title: row.get("title"),
+        book_id,
+        book_title: row.get("book_title"),
+        book_author: row.get("book_author"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        messages: exported,
+    })
+}
+
+pub fn render(thread: &ExportedThread, format: ExportFormat) -> Result<String, String> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(thread)),
+        ExportFormat::Html => Ok(render_html(thread)),
+        ExportFormat::Json => serde_json::to_string_pretty(thread).map_err(|e| e.to_string()),
+    }
+}
+
+/// 引用来自消息 metadata.citations 与工具调用结果中的 output.citations，按 chunk_id 合并
+fn collect_citations(message: &ThreadMessage) -> Vec<ExportedCitation> {
+    let parse = |text: Option<&str>| text.and_then(|t| serde_json::from_str::<Value>(t).ok());
+
+    let mut lists: Vec<Value> = Vec::new();
+    if let Some(citations) = parse(message.metadata.as_deref()).and_then(|m| m.get("citations").cloned()) {
+        lists.push(citations);
+    }
+    if let Some(Value::Array(tool_calls)) = parse(message.tool_calls.as_deref()) {
+        lists.extend(
+            tool_calls
+                .iter()
+                .filter_map(|call| call.get("output")?.get("citations").cloned()),
+        );
+    }
+
+    let mut citations: Vec<ExportedCitation> = Vec::new();
+    for citation in lists.iter().filter_map(Value::as_array).flatten() {
+        let chunk_id = match citation.get("chunk_id") {
+            Some(Value::Number(n)) => n.as_i64(),
+            Some(Value::String(s)) => s.trim().parse().ok(),
+            _ => None,
+        };
+        let Some(chunk_id) = chunk_id else {
+            continue;
+        };
+        let text = |key: &str| citation.get(key).and_then(Value::as_str).map(str::to_string);
+
+        // 同一分块可能同时出现在 metadata 与工具结果中，合并各自的字段
+        match citations.iter_mut().find(|c| c.chunk_id == chunk_id) {
+            Some(existing) => {
+                existing.source = existing.source.take().or_else(|| text("source"));
+                existing.preview = existing.preview.take().or_else(|| text("preview"));
+            }
+            None => citations.push(ExportedCitation {
+                chunk_id,
+                chapter_title: None,
+                source: text("source"),
+                preview: text("preview"),
+            }),
+        }
+    }
+    citations
+}
+
+/// 从书籍的向量库查询分块所在章节；向量库不存在或无法读取时返回空表，导出仍然继续
+async fn chapter_titles(app_data_dir: &Path, book_id: &str, chunk_ids: &HashSet<i64>) -> HashMap<i64, String> {
+    let db_path = app_data_dir.join("books").join(book_id).join("vectors.sqlite");
+    if chunk_ids.is_empty() || !db_path.exists() {
+        return HashMap::new();
+    }
+
+    let result = async {
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&db_path)
+            .read_only(true)
+            .connect()
+            .await?;
+
+        let mut builder =
+            sqlx::QueryBuilder::new("SELECT id, related_chapter_titles FROM document_chunks WHERE id IN (");
+        let mut separated = builder.separated(", ");
+        for chunk_id in chunk_ids {
+            separated.push_bind(*chunk_id);
+        }
+        builder.push(")");
+        builder.build_query_as::<(i64, String)>().fetch_all(&mut conn).await
+    }
+    .await;
+
+    match result {
+        Ok(rows) => rows.into_iter().collect(),
+        Err(e) => {
+            eprintln!("Failed to resolve chapter titles for book {}: {}", book_id, e);
+            HashMap::new()
+        }
+    }
+}
+
+fn render_markdown(thread: &ExportedThread) -> String {
+    let mut out = format!("# {}\n\n", thread.title);
+    if let Some(book_title) = &thread.book_title {
+        match thread.book_author.as_deref().filter(|a| !a.is_empty()) {
+            Some(author) => out.push_str(&format!("- 书籍：《{}》 {}\n", book_title, author)),
+            None => out.push_str(&format!("- 书籍：《{}》\n", book_title)),
+        }
+    }
+    out.push_str(&format!("- 创建时间：{}\n", format_time(thread.created_at)));
+    out.push_str(&format!("- 更新时间：{}\n", format_time(thread.updated_at)));
+
+    for message in &thread.messages {
+        out.push_str(&format!(
+            "\n---\n\n### {} · {}\n\n",
+            role_label(&message.role),
+            format_time(message.created_at)
+        ));
+        out.push_str(message.content.trim());
+        out.push('\n');
+
+        if !message.citations.is_empty() {
+            out.push_str("\n> 引用\n>\n");
+            for citation in &message.citations {
+                out.push_str(&format!("> - [{}] {}", citation.chunk_id, citation_label(citation)));
+                if let Some(preview) = &citation.preview {
+                    out.push_str(&format!("：{}", single_line(preview)));
+                }
+                out.push('\n');
+            }
+        }
+    }
+
+    out
+}
+
+fn render_html(thread: &ExportedThread) -> String {
+    let mut body = format!("<h1>{}</h1>\n<ul class=\"meta\">\n", escape_html(&thread.title));
+    if let Some(book_title) = &thread.book_title {
+        let author = thread.book_author.as_deref().unwrap_or_default();
+        body.push_str(&format!(
+            "<li>书籍：《{}》 {}</li>\n",
+            escape_html(book_title),
+            escape_html(author)
+        ));
+    }
+    body.push_str(&format!("<li>创建时间：{}</li>\n", format_time(thread.created_at)));
+    body.push_str(&format!("<li>更新时间：{}</li>\n</ul>\n", format_time(thread.updated_at)));
+
+    for (index, message) in thread.messages.iter().enumerate() {
+        let chunk_ids: HashSet<i64> = message.citations.iter().map(|c| c.chunk_id).collect();
+        body.push_str(&format!(
+            "<section class=\"message {}\">\n<h3>{} · {}</h3>\n{}",
+            escape_html(&message.role),
+            role_label(&message.role),
+            format_time(message.created_at),
+            html_paragraphs(&message.content, index, &chunk_ids)
+        ));
+
+        if !message.citations.is_empty() {
+            body.push_str("<ol class=\"citations\">\n");
+            for citation in &message.citations {
+                body.push_str(&format!(
+                    "<li id=\"m{}-c{}\"><span class=\"chunk\">[{}]</span> {}",
+                    index,
+                    citation.chunk_id,
+                    citation.chunk_id,
+                    escape_html(&citation_label(citation))
+                ));
+                if let Some(preview) = &citation.preview {
+                    body.push_str(&format!("<div class=\"preview\">{}</div>", escape_html(preview)));
+                }
+                body.push_str("</li>\n");
+            }
+            body.push_str("</ol>\n");
+        }
+        body.push_str("</section>\n");
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="zh">
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>
+body {{ max-width: 760px; margin: 2rem auto; padding: 0 1rem; font-family: -apple-system, "PingFang SC", "Microsoft YaHei", sans-serif; line-height: 1.7; color: #222; }}
+.meta {{ color: #666; font-size: 0.9rem; }}
+.message {{ border-top: 1px solid #e5e5e5; padding: 0.5rem 0 1rem; }}
+.message.user h3 {{ color: #2563eb; }}
+.message h3 {{ font-size: 0.95rem; color: #444; }}
+pre {{ background: #f5f5f5; padding: 0.75rem; overflow-x: auto; border-radius: 4px; }}
+.citations {{ font-size: 0.85rem; color: #555; background: #fafafa; padding: 0.5rem 0.5rem 0.5rem 2rem; }}
+.citations .preview {{ color: #888; }}
+sup a {{ text-decoration: none; }}
+</style>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        escape_html(&thread.title),
+        body
+    )
+}
+
+/// 按空行分段，围栏代码块原样保留；文中引用了的 [chunk_id] 链接到消息下方的引用列表
+fn html_paragraphs(content: &str, message_index: usize, chunk_ids: &HashSet<i64>) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+
+    let flush = |paragraph: &mut Vec<&str>, html: &mut String| {
+        if paragraph.is_empty() {
+            return;
+        }
+        let text = escape_html(&paragraph.join("\n")).replace('\n', "<br>\n");
+        html.push_str(&format!("<p>{}</p>\n", link_citations(&text, message_index, chunk_ids)));
+        paragraph.clear();
+    };
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            match code.take() {
+                Some(lines) => {
+                    html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+                }
+                None => {
+                    flush(&mut paragraph, &mut html);
+                    code = Some(Vec::new());
+                }
+            }
+            continue;
+        }
+        match code.as_mut() {
+            Some(lines) => lines.push(line),
+            None if line.trim().is_empty() => flush(&mut paragraph, &mut html),
+            None => paragraph.push(line),
+        }
+    }
+    if let Some(lines) = code {
+        html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+    }
+    flush(&mut paragraph, &mut html);
+    html
+}
+
+fn link_citations(text: &str, message_index: usize, chunk_ids: &HashSet<i64>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let linked = tail[1..].find(']').and_then(|end| {
+            let id: i64 = tail[1..=end].parse().ok()?;
+            chunk_ids.contains(&id).then_some((id, end + 2))
+        });
+        match linked {
+            Some((id, len)) => {
+                out.push_str(&format!("<sup><a href=\"#m{}-c{}\">[{}]</a></sup>", message_index, id, id));
+                rest = &tail[len..];
+            }
+            None => {
+                out.push('[');
+                rest = &tail[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn citation_label(citation: &ExportedCitation) -> String {
+    citation
+        .chapter_title
+        .clone()
+        .or_else(|| citation.source.clone())
+        .filter(|label| !label.trim().is_empty())
+        .unwrap_or_else(|| "未知章节".to_string())
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "用户",
+        "assistant" => "助手",
+        "system" => "系统",
+        other => other,
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,689 @@
+// 对话消息的存取：前端的 UIMessage（id、role、parts、metadata）与 thread_messages 行之间的转换。
+// 消息通过 parent_id 组成树，threads.active_leaf_id 指向当前分支的末端
+
+use super::models::{ThreadMessage, ThreadMessagePage};
+use serde_json::Value;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// 由一条 UIMessage 拆出的列
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageRecord {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+    pub parts: String,
+    pub tool_calls: Option<String>,
+    pub metadata: Option<String>,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+    pub created_at: i64,
+}
+
+impl MessageRecord {
+    pub fn from_ui_message(message: &Value, now: i64) -> Result<Self, String> {
+        let object = message.as_object().ok_or("Message must be a JSON object")?;
+        let parts = object
+            .get("parts")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let metadata = object.get("metadata").filter(|m| !m.is_null());
+
+        let content = parts
+            .iter()
+            .filter(|part| part.get("type").and_then(Value::as_str) == Some("text"))
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tool_calls: Vec<&Value> = parts
+            .iter()
+            .filter(|part| {
+                part.get("type")
+                    .and_then(Value::as_str)
+                    .is_some_and(|t| t.starts_with("tool-") || t == "dynamic-tool")
+            })
+            .collect();
+
+        // 前端的 totalUsage 使用 promptTokens/completionTokens，AI SDK 的 usage 使用 inputTokens/outputTokens
+        let usage = metadata.and_then(|m| m.get("totalUsage").or_else(|| m.get("usage")));
+        let token = |keys: &[&str]| {
+            usage.and_then(|u| keys.iter().find_map(|key| u.get(*key).and_then(Value::as_i64)))
+        };
+        // createdAt 由前端以秒记录
+        let created_at = metadata
+            .and_then(|m| m.get("createdAt"))
+            .and_then(Value::as_i64)
+            .map(|t| if t < 100_000_000_000 { t * 1000 } else { t })
+            .unwrap_or(now);
+
+        Ok(Self {
+            id: object
+                .get("id")
+                .and_then(Value::as_str)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            role: object
+                .get("role")
+                .and_then(Value::as_str)
+                .unwrap_or("user")
+                .to_string(),
+            content,
+            parts: Value::Array(parts.clone()).to_string(),
+            tool_calls: (!tool_calls.is_empty())
+                .then(|| serde_json::to_string(&tool_calls).unwrap_or_default()),
+            metadata: metadata.map(Value::to_string),
+            input_tokens: token(&["promptTokens", "inputTokens"]),
+            output_tokens: token(&["completionTokens", "outputTokens"]),
+            total_tokens: token(&["totalTokens"]),
+            created_at,
+        })
+    }
+}
+
+/// 从指定消息沿 parent_id 向上直到根消息；depth 为到该消息的距离。参数依次为 thread_id、消息 id、thread_id
+pub(crate) const BRANCH_CTE: &str = r#"
+    WITH RECURSIVE branch(id, depth) AS (
+        SELECT id, 0 FROM thread_messages WHERE thread_id = ? AND id = ?
+        UNION ALL
+        SELECT m.parent_id, b.depth + 1
+        FROM branch b JOIN thread_messages m ON m.thread_id = ? AND m.id = b.id
+        WHERE m.parent_id IS NOT NULL
+    )
+"#;
+
+/// 数据库中已有的消息，用于判断是否需要重写
+struct StoredMessage {
+    parent_id: Option<String>,
+    seq: i64,
+    role: String,
+    parts: String,
+    metadata: Option<String>,
+}
+
+impl StoredMessage {
+    fn matches(&self, record: &MessageRecord, parent_id: Option<&str>) -> bool {
+        self.parent_id.as_deref() == parent_id
+            && self.role == record.role
+            && self.parts == record.parts
+            && self.metadata == record.metadata
+    }
+}
+
+/// 还原为前端使用的 UIMessage
+pub fn to_ui_message(message: &ThreadMessage) -> Value {
+    let mut object = serde_json::Map::new();
+    object.insert("id".to_string(), Value::String(message.id.clone()));
+    object.insert("role".to_string(), Value::String(message.role.clone()));
+    object.insert(
+        "parts".to_string(),
+        serde_json::from_str(&message.parts).unwrap_or_else(|_| Value::Array(Vec::new())),
+    );
+    if let Some(metadata) = message.metadata.as_deref().and_then(|m| serde_json::from_str(m).ok()) {
+        object.insert("metadata".to_string(), metadata);
+    }
+    Value::Object(object)
+}
+
+/// 当前分支的消息，从根消息到分支末端
+pub async fn load_messages(pool: &SqlitePool, thread_id: &str) -> Result<Vec<ThreadMessage>, String> {
+    let leaf: Option<String> = sqlx::query_scalar("SELECT active_leaf_id FROM threads WHERE id = ?")
+        .bind(thread_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .flatten();
+
+    match leaf {
+        Some(leaf) => load_branch(pool, thread_id, &leaf).await,
+        None => Ok(Vec::new()),
+    }
+}
+
+/// 从根消息到指定消息的整条分支
+pub async fn load_branch(pool: &SqlitePool, thread_id: &str, leaf_id: &str) -> Result<Vec<ThreadMessage>, String> {
+    let rows = sqlx::query(&format!(
+        "{} SELECT m.* FROM branch b JOIN thread_messages m ON m.thread_id = ? AND m.id = b.id ORDER BY b.depth DESC",
+        BRANCH_CTE
+    ))
+    .bind(thread_id)
+    .bind(leaf_id)
+    .bind(thread_id)
+    .bind(thread_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    rows.iter()
+        .map(ThreadMessage::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// 当前分支的一页消息：从分支末端往前翻，before_seq 为上一页第一条消息的 seq；页内按时间正序
+pub async fn load_page(
+    pool: &SqlitePool,
+    thread_id: &str,
+    before_seq: Option<i64>,
+    limit: i64,
+) -> Result<ThreadMessagePage, String> {
+    let leaf: Option<String> = sqlx::query_scalar("SELECT active_leaf_id FROM threads WHERE id = ?")
+        .bind(thread_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Thread not found")?;
+    let Some(leaf) = leaf else {
+        return Ok(ThreadMessagePage { messages: Vec::new(), has_more: false });
+    };
+
+    // seq 沿分支递增，可以直接按 seq 翻页
+    let rows = sqlx::query(&format!(
+        r#"
+        {}
+        SELECT m.* FROM branch b JOIN thread_messages m ON m.thread_id = ? AND m.id = b.id
+        WHERE (? IS NULL OR m.seq < ?)
+        ORDER BY m.seq DESC LIMIT ?
+        "#,
+        BRANCH_CTE
+    ))
+    .bind(thread_id)
+    .bind(&leaf)
+    .bind(thread_id)
+    .bind(thread_id)
+    .bind(before_seq)
+    .bind(before_seq)
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch thread messages: {}", e);
+        e.to_string()
+    })?;
+
+    let has_more = rows.len() as i64 > limit;
+    let mut messages = rows
+        .iter()
+        .take(limit as usize)
+        .map(ThreadMessage::from_db_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    messages.reverse();
+
+    Ok(ThreadMessagePage { messages, has_more })
+}
+
+/// 当前分支的 JSON 数组，与旧版本 threads.messages 的格式一致
+pub async fn messages_json(pool: &SqlitePool, thread_id: &str) -> Result<String, String> {
+    let messages = load_messages(pool, thread_id).await?;
+    let values: Vec<Value> = messages.iter().map(to_ui_message).collect();
+    serde_json::to_string(&values).map_err(|e| e.to_string())
+}
+
+/// 把完整的消息列表保存为当前分支：只写入新增或变化的消息，每条消息的父消息为列表中的前一条。
+/// 列表中已不存在的旧消息作为其他分支保留（例如重新生成前的回答）；列表为空时清空对话
+pub async fn save_branch(pool: &SqlitePool, thread_id: &str, messages_json: &str) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    write_branch(&mut tx, thread_id, messages_json).await?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// save_branch 的实现，在调用方的事务中执行
+async fn write_branch(conn: &mut SqliteConnection, thread_id: &str, messages_json: &str) -> Result<(), String> {
+    let incoming: Vec<Value> =
+        serde_json::from_str(messages_json).map_err(|e| format!("Invalid messages JSON: {}", e))?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    if incoming.is_empty() {
+        sqlx::query("DELETE FROM thread_messages WHERE thread_id = ?")
+            .bind(thread_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        set_active_leaf(conn, thread_id, None).await?;
+        return Ok(());
+    }
+
+    let rows = sqlx::query("SELECT id, parent_id, seq, role, parts, metadata FROM thread_messages WHERE thread_id = ?")
+        .bind(thread_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let existing: HashMap<String, StoredMessage> = rows
+        .iter()
+        .map(|row| {
+            (
+                row.get("id"),
+                StoredMessage {
+                    parent_id: row.get("parent_id"),
+                    seq: row.get("seq"),
+                    role: row.get("role"),
+                    parts: row.get("parts"),
+                    metadata: row.get("metadata"),
+                },
+            )
+        })
+        .collect();
+    let mut next_seq = existing.values().map(|m| m.seq + 1).max().unwrap_or(0);
+
+    let mut parent: Option<String> = None;
+    let mut parent_seq = -1;
+    let mut seen = HashSet::new();
+    for message in &incoming {
+        let record = MessageRecord::from_ui_message(message, now)?;
+        if !seen.insert(record.id.clone()) {
+            continue;
+        }
+
+        let seq = match existing.get(&record.id) {
+            Some(stored) if stored.seq > parent_seq && stored.matches(&record, parent.as_deref()) => stored.seq,
+            Some(stored) => {
+                // 保证 seq 沿分支递增，分页依赖这一点
+                let seq = if stored.seq > parent_seq { stored.seq } else { take_seq(&mut next_seq) };
+                update_message(conn, thread_id, &record, parent.as_deref(), seq).await?;
+                seq
+            }
+            None => {
+                let seq = take_seq(&mut next_seq);
+                insert_message(conn, thread_id, &record, parent.as_deref(), seq).await?;
+                seq
+            }
+        };
+        parent = Some(record.id);
+        parent_seq = seq;
+    }
+
+    set_active_leaf(conn, thread_id, parent.as_deref()).await
+}
+
+/// 追加一条消息并切换到以它为末端的分支；id 已存在时原位更新（例如流式输出结束后保存最终内容）。
+/// 未指定父消息时接在当前分支末端之后，指定父消息时在该处产生新的分支
+pub async fn append_message(
+    pool: &SqlitePool,
+    thread_id: &str,
+    message_json: &str,
+    parent_id: Option<&str>,
+) -> Result<ThreadMessage, String> {
+    let message: Value =
+        serde_json::from_str(message_json).map_err(|e| format!("Invalid message JSON: {}", e))?;
+    let now = chrono::Utc::now().timestamp_millis();
+    let record = MessageRecord::from_ui_message(&message, now)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let existing: Option<(Option<String>, i64)> =
+        sqlx::query_as("SELECT parent_id, seq FROM thread_messages WHERE thread_id = ? AND id = ?")
+            .bind(thread_id)
+            .bind(&record.id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    match existing {
+        Some((stored_parent, seq)) => {
+            update_message(&mut tx, thread_id, &record, stored_parent.as_deref(), seq).await?;
+        }
+        None => {
+            let parent = match parent_id {
+                Some(parent_id) => {
+                    let exists: i64 = sqlx::query_scalar(
+                        "SELECT COUNT(*) FROM thread_messages WHERE thread_id = ? AND id = ? AND role != 'summary'",
+                    )
+                    .bind(thread_id)
+                    .bind(parent_id)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    if exists == 0 {
+                        return Err("Parent message not found".to_string());
+                    }
+                    Some(parent_id.to_string())
+                }
+                None => sqlx::query_scalar("SELECT active_leaf_id FROM threads WHERE id = ?")
+                    .bind(thread_id)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            };
+            let seq: i64 = sqlx::query_scalar(
+                "SELECT COALESCE(MAX(seq) + 1, 0) FROM thread_messages WHERE thread_id = ?",
+            )
+            .bind(thread_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+            insert_message(&mut tx, thread_id, &record, parent.as_deref(), seq).await?;
+            set_active_leaf(&mut tx, thread_id, Some(&record.id)).await?;
+        }
+    }
+
+    sqlx::query("UPDATE threads SET updated_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let row = sqlx::query("SELECT * FROM thread_messages WHERE thread_id = ? AND id = ?")
+        .bind(thread_id)
+        .bind(&record.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    ThreadMessage::from_db_row(&row).map_err(|e| e.to_string())
+}
+
+/// 把旧版本 threads.messages 中的消息拆分到 thread_messages，并清空原字段。
+/// 两步在同一事务中完成，中途失败时下次启动可以完整重试
+pub async fn migrate_legacy_messages(pool: &SqlitePool, thread_id: &str) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let legacy: String = sqlx::query_scalar("SELECT messages FROM threads WHERE id = ?")
+        .bind(thread_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // 无法解析的旧数据保留原样，不丢弃
+    if serde_json::from_str::<Vec<Value>>(&legacy).is_err() {
+        eprintln!("Skipping thread {} with invalid messages JSON", thread_id);
+        return Ok(());
+    }
+    write_branch(&mut tx, thread_id, &legacy).await?;
+
+    sqlx::query("UPDATE threads SET messages = '[]' WHERE id = ?")
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+pub(crate) async fn insert_message(
+    conn: &mut SqliteConnection,
+    thread_id: &str,
+    record: &MessageRecord,
+    parent_id: Option<&str>,
+    seq: i64,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        INSERT INTO thread_messages (
+            id, thread_id, parent_id, seq, role, content, parts, tool_calls, metadata,
+            input_tokens, output_tokens, total_tokens, created_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&record.id)
+    .bind(thread_id)
+    .bind(parent_id)
+    .bind(seq)
+    .bind(&record.role)
+    .bind(&record.content)
+    .bind(&record.parts)
+    .bind(&record.tool_calls)
+    .bind(&record.metadata)
+    .bind(record.input_tokens)
+    .bind(record.output_tokens)
+    .bind(record.total_tokens)
+    .bind(record.created_at)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn update_message(
+    conn: &mut SqliteConnection,
+    thread_id: &str,
+    record: &MessageRecord,
+    parent_id: Option<&str>,
+    seq: i64,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        UPDATE thread_messages
+        SET parent_id = ?, seq = ?, role = ?, content = ?, parts = ?, tool_calls = ?, metadata = ?,
+            input_tokens = ?, output_tokens = ?, total_tokens = ?
+        WHERE thread_id = ? AND id = ?
+        "#,
+    )
+    .bind(parent_id)
+    .bind(seq)
+    .bind(&record.role)
+    .bind(&record.content)
+    .bind(&record.parts)
+    .bind(&record.tool_calls)
+    .bind(&record.metadata)
+    .bind(record.input_tokens)
+    .bind(record.output_tokens)
+    .bind(record.total_tokens)
+    .bind(thread_id)
+    .bind(&record.id)
+    .execute(conn)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub(crate) async fn set_active_leaf(
+    conn: &mut SqliteConnection,
+    thread_id: &str,
+    leaf_id: Option<&str>,
+) -> Result<(), String> {
+    sqlx::query("UPDATE threads SET active_leaf_id = ? WHERE id = ?")
+        .bind(leaf_id)
+        .bind(thread_id)
+        .execute(conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn take_seq(next_seq: &mut i64) -> i64 {
+    let seq = *next_seq;
+    *next_seq += 1;
+    seq
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    pub(crate) async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(include_str!("../schema.sql")).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO threads (id, metadata, title, messages, created_at, updated_at) VALUES ('t', '{}', 'T', '[]', 0, 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    pub(crate) fn message(id: &str, role: &str, text: &str) -> Value {
+        json!({ "id": id, "role": role, "parts": [{ "type": "text", "text": text }] })
+    }
+
+    pub(crate) async fn save(pool: &SqlitePool, messages: &[Value]) {
+        save_branch(pool, "t", &Value::Array(messages.to_vec()).to_string()).await.unwrap();
+    }
+
+    pub(crate) async fn append(pool: &SqlitePool, message: Value, parent_id: Option<&str>) -> ThreadMessage {
+        append_message(pool, "t", &message.to_string(), parent_id).await.unwrap()
+    }
+
+    pub(crate) async fn branch_ids(pool: &SqlitePool) -> Vec<String> {
+        load_messages(pool, "t").await.unwrap().into_iter().map(|m| m.id).collect()
+    }
+
+    async fn row_count(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM thread_messages WHERE thread_id = 't'")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resave_changed_branch() {
+        let pool = test_pool().await;
+        save(&pool, &[message("u1", "user", "hi"), message("a1", "assistant", "hel")]).await;
+        let before = load_messages(&pool, "t").await.unwrap();
+
+        // 流式输出结束后以完整内容再次保存：原位更新，不产生新行
+        save(&pool, &[message("u1", "user", "hi"), message("a1", "assistant", "hello")]).await;
+        let after = load_messages(&pool, "t").await.unwrap();
+        assert_eq!(row_count(&pool).await, 2);
+        assert_eq!(after[1].content, "hello");
+        assert_eq!(after[1].seq, before[1].seq);
+        assert_eq!(after[1].parent_id.as_deref(), Some("u1"));
+
+        save(
+            &pool,
+            &[
+                message("u1", "user", "hi"),
+                message("a1", "assistant", "hello"),
+                message("u2", "user", "more"),
+                message("a2", "assistant", "sure"),
+            ],
+        )
+        .await;
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a1", "u2", "a2"]);
+
+        // 全文索引随消息内容更新
+        let indexed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM thread_messages_fts WHERE content = 'hello'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(indexed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_keeps_sibling() {
+        let pool = test_pool().await;
+        save(&pool, &[message("u1", "user", "hi"), message("a1", "assistant", "first")]).await;
+
+        // 重新生成：前端保存替换了末条回答的列表，旧回答保留为同一父消息下的分支
+        save(&pool, &[message("u1", "user", "hi"), message("a2", "assistant", "second")]).await;
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a2"]);
+        assert_eq!(row_count(&pool).await, 3);
+
+        let a3 = append(&pool, message("a3", "assistant", "third"), Some("u1")).await;
+        assert_eq!(a3.parent_id.as_deref(), Some("u1"));
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a3"]);
+
+        let siblings = crate::core::threads::branches::sibling_messages(&pool, "t", "a1").await.unwrap();
+        let ids: Vec<&str> = siblings.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["a1", "a2", "a3"]);
+
+        // 未指定父消息时接在当前分支末端之后；已存在的 id 原位更新
+        append(&pool, message("u2", "user", "again"), None).await;
+        assert_eq!(branch_ids(&pool).await, vec!["u1", "a3", "u2"]);
+        append(&pool, message("a3", "assistant", "third, edited"), Some("u2")).await;
+        let current = load_messages(&pool, "t").await.unwrap();
+        assert_eq!(current[1].content, "third, edited");
+        assert_eq!(current[1].parent_id.as_deref(), Some("u1"));
+
+        let missing = append_message(&pool, "t", &message("x", "user", "x").to_string(), Some("nope")).await;
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_messages() {
+        let pool = test_pool().await;
+        let legacy = json!([message("u1", "user", "hi"), message("a1", "assistant", "hello")]).to_string();
+        sqlx::query(
+            "INSERT INTO threads (id, metadata, title, messages, created_at, updated_at) VALUES ('old', '{}', 'Old', ?, 0, 0), ('bad', '{}', 'Bad', '[{broken', 0, 0)",
+        )
+        .bind(&legacy)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        migrate_legacy_messages(&pool, "old").await.unwrap();
+        let ids: Vec<String> = load_messages(&pool, "old").await.unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec!["u1", "a1"]);
+        let cleared: String = sqlx::query_scalar("SELECT messages FROM threads WHERE id = 'old'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cleared, "[]");
+
+        // 无法解析的旧数据保留原样
+        migrate_legacy_messages(&pool, "bad").await.unwrap();
+        let kept: String = sqlx::query_scalar("SELECT messages FROM threads WHERE id = 'bad'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(kept, "[{broken");
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM thread_messages WHERE thread_id = 'bad'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[tokio::test]
+    async fn test_page_across_branch_point() {
+        let pool = test_pool().await;
+        save(
+            &pool,
+            &[
+                message("u1", "user", "1"),
+                message("a1", "assistant", "2"),
+                message("u2", "user", "3"),
+                message("a2", "assistant", "4"),
+            ],
+        )
+        .await;
+        // 在 u1 处产生新分支，新分支的消息 seq 都大于旧分支
+        append(&pool, message("b1", "assistant", "5"), Some("u1")).await;
+        append(&pool, message("b2", "user", "6"), None).await;
+        append(&pool, message("b3", "assistant", "7"), None).await;
+
+        let page = load_page(&pool, "t", None, 2).await.unwrap();
+        let ids: Vec<&str> = page.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["b2", "b3"]);
+        assert!(page.has_more);
+
+        let page = load_page(&pool, "t", Some(page.messages[0].seq), 2).await.unwrap();
+        let ids: Vec<&str> = page.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["u1", "b1"]);
+        assert!(!page.has_more);
+
+        // 把旧分支的后半段接到新分支之后保存：沿用的消息 seq 小于新父消息，需要重新编号
+        save(
+            &pool,
+            &[
+                message("u1", "user", "1"),
+                message("b1", "assistant", "5"),
+                message("u2", "user", "3"),
+                message("a2", "assistant", "4"),
+            ],
+        )
+        .await;
+        let branch = load_messages(&pool, "t").await.unwrap();
+        let ids: Vec<&str> = branch.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["u1", "b1", "u2", "a2"]);
+        assert!(branch.windows(2).all(|w| w[0].seq < w[1].seq));
+
+        let page = load_page(&pool, "t", None, 3).await.unwrap();
+        let ids: Vec<&str> = page.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["b1", "u2", "a2"]);
+        let page = load_page(&pool, "t", Some(page.messages[0].seq), 3).await.unwrap();
+        let ids: Vec<&str> = page.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["u1"]);
+
+        assert!(load_page(&pool, "missing", None, 3).await.is_err());
+    }
+}
@@ -1,2 +1,6 @@
+pub mod branches;
 pub mod commands;
+pub mod compaction;
+pub mod export;
+pub mod messages;
 pub mod models;
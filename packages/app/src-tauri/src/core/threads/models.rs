@@ -7,6 +7,7 @@ pub struct Thread {
     pub metadata: String,
     pub title: String,
     pub messages: String,
+    pub active_leaf_id: Option<String>,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -37,3 +38,101 @@ pub struct EditThreadPayload {
     pub metadata: Option<String>,
     pub messages_json: Option<String>,
 }
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ThreadMessage {
+    pub id: String,
+    pub thread_id: String,
+    pub parent_id: Option<String>,
+    pub seq: i64,
+    pub role: String,
+    pub content: String,
+    pub parts: String,
+    pub tool_calls: Option<String>,
+    pub metadata: Option<String>,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+    pub summary_from_id: Option<String>,
+    pub summary_to_id: Option<String>,
+    pub created_at: i64,
+}
+
+impl ThreadMessage {
+    pub fn from_db_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            thread_id: row.try_get("thread_id")?,
+            parent_id: row.try_get("parent_id")?,
+            seq: row.try_get("seq")?,
+            role: row.try_get("role")?,
+            content: row.try_get("content")?,
+            parts: row.try_get("parts")?,
+            tool_calls: row.try_get("tool_calls")?,
+            metadata: row.try_get("metadata")?,
+            input_tokens: row.try_get("input_tokens")?,
+            output_tokens: row.try_get("output_tokens")?,
+            total_tokens: row.try_get("total_tokens")?,
+            summary_from_id: row.try_get("summary_from_id")?,
+            summary_to_id: row.try_get("summary_to_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ThreadMessagePage {
+    pub messages: Vec<ThreadMessage>,
+    pub has_more: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AppendThreadMessagePayload {
+    pub thread_id: String,
+    pub message_json: String,
+    /// 为空时接在当前分支末端之后
+    pub parent_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForkThreadPayload {
+    pub thread_id: String,
+    pub message_id: String,
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ThreadExportReport {
+    pub exported_threads: i64,
+    pub file_paths: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompactThreadPayload {
+    pub thread_id: String,
+    /// OpenAI 兼容接口地址，例如本地 llama-server 的 http://127.0.0.1:8080/v1
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub token_budget: Option<usize>,
+    pub keep_recent_tokens: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ThreadCompactionReport {
+    pub compacted: bool,
+    pub summary: Option<ThreadMessage>,
+    pub summarized_messages: i64,
+    pub tokens_before: i64,
+    pub tokens_after: i64,
+}
+
+/// 发送给模型的上下文：最近一次摘要加上其后未被摘要的消息
+#[derive(Serialize, Debug)]
+pub struct ThreadCompactedContext {
+    pub summary: Option<ThreadMessage>,
+    pub messages: Vec<ThreadMessage>,
+    pub total_tokens: i64,
+}
@@ -2,6 +2,11 @@
 
 mod core;
 use crate::core::{
+    annotations::commands::{
+        export_koreader_annotations, export_readwise_csv, export_web_annotations,
+        import_kobo_annotations, import_koreader_annotations, import_web_annotations,
+        reanchor_book_notes,
+    },
     books::commands::{
         create_book_note,
         create_reading_session,
@@ -17,6 +22,7 @@ use crate::core::{
         get_books_with_status,
         get_reading_session,
         get_reading_sessions_by_book,
+        replace_book_file,
         save_book,
         update_book,
         update_book_note,
@@ -30,18 +36,32 @@ use crate::core::{
         ensure_llamacpp_directories, get_app_data_dir, get_llamacpp_backend_path, greet,
         list_local_models, llama_server_binary_name_cmd,
     },
-    notes::commands::{create_note, delete_note, get_note_by_id, get_notes, update_note},
+    notes::commands::{
+        add_note_attachment, cleanup_note_attachments, create_note, delete_note,
+        delete_note_attachment, diff_note_revisions, export_notes_markdown, get_note_attachments,
+        get_note_backlinks, get_note_by_id, get_note_graph, get_note_revisions, get_notes,
+        get_unresolved_note_links, import_notes_markdown, restore_note_revision,
+        save_note_attachment_data, update_note,
+    },
+    review::commands::{
+        create_review_cards, delete_review_card, export_review_deck_apkg, get_due_cards,
+        get_review_logs, review_card,
+    },
+    search::commands::search_everything,
     skills::commands::{
-        create_skill, delete_skill, get_skill_by_id, get_skills, toggle_skill_active,
-        update_skill,
+        apply_skill_upgrade, create_skill, delete_skill, export_skill_pack, get_skill_by_id,
+        get_skill_upgrades, get_skills, import_skill_pack, preview_skill_pack, render_skill,
+        toggle_skill_active, update_skill, validate_skill_template,
     },
     state::AppState,
     tags::commands::{
         create_tag, delete_tag, get_tag_by_id, get_tag_by_name, get_tags, update_tag,
     },
     threads::commands::{
-        create_thread, delete_thread, edit_thread, get_all_threads, get_latest_thread_by_book_id,
-        get_thread_by_id, get_threads_by_book_id,
+        append_thread_message, compact_thread, create_thread, delete_thread, edit_thread,
+        export_book_threads, export_thread, fork_thread, get_all_threads,
+        get_latest_thread_by_book_id, get_message_siblings, get_thread_branch, get_thread_by_id,
+        get_thread_context, get_thread_messages, get_threads_by_book_id, switch_thread_branch,
     },
 };
 use tauri::Manager;
@@ -122,10 +142,21 @@ pub fn run() {
             get_threads_by_book_id,
             get_thread_by_id,
             get_all_threads,
+            append_thread_message,
+            get_thread_messages,
+            fork_thread,
+            switch_thread_branch,
+            get_message_siblings,
+            get_thread_branch,
+            export_thread,
+            export_book_threads,
+            compact_thread,
+            get_thread_context,
             save_book,
             get_books,
             get_book_by_id,
             update_book,
+            replace_book_file,
             delete_book,
             get_book_status,
             update_book_status,
@@ -143,6 +174,14 @@ pub fn run() {
             get_book_notes,
             update_book_note,
             delete_book_note,
+            // annotation import/export
+            import_koreader_annotations,
+            import_kobo_annotations,
+            export_koreader_annotations,
+            export_readwise_csv,
+            export_web_annotations,
+            import_web_annotations,
+            reanchor_book_notes,
             create_tag,
             get_tags,
             get_tag_by_id,
@@ -155,6 +194,28 @@ pub fn run() {
             delete_note,
             get_note_by_id,
             get_notes,
+            get_note_backlinks,
+            get_unresolved_note_links,
+            get_note_graph,
+            get_note_revisions,
+            diff_note_revisions,
+            restore_note_revision,
+            add_note_attachment,
+            save_note_attachment_data,
+            get_note_attachments,
+            delete_note_attachment,
+            cleanup_note_attachments,
+            export_notes_markdown,
+            import_notes_markdown,
+            // search
+            search_everything,
+            // review
+            create_review_cards,
+            get_due_cards,
+            review_card,
+            get_review_logs,
+            delete_review_card,
+            export_review_deck_apkg,
             // skills
             create_skill,
             get_skills,
@@ -162,6 +223,13 @@ pub fn run() {
             update_skill,
             delete_skill,
             toggle_skill_active,
+            validate_skill_template,
+            render_skill,
+            export_skill_pack,
+            preview_skill_pack,
+            import_skill_pack,
+            get_skill_upgrades,
+            apply_skill_upgrade,
             // fonts
             upload_and_convert_font,
             upload_font_data,
@@ -4,7 +4,7 @@ import { getFullPathFromAppData } from "@/utils/path";
 import { convertFileSrc } from "@tauri-apps/api/core";
 import { marked } from "marked";
 import { memo, useEffect, useId, useMemo, useState } from "react";
-import ReactMarkdown, { type Components } from "react-markdown";
+import ReactMarkdown, { type Components, defaultUrlTransform } from "react-markdown";
 import remarkBreaks from "remark-breaks";
 import remarkCjkFriendly from "remark-cjk-friendly";
 import remarkGfm from "remark-gfm";
@@ -28,9 +28,42 @@ function extractLanguage(className?: string): string {
   return match ? match[1] : "plaintext";
 }
 
+// 笔记附件地址 attachment://{noteId}/{file} 对应 appDataDir 下的 notes/{noteId}/{file}
+const ATTACHMENT_SCHEME = "attachment://";
+
 // 检查是否是相对于appDataDir的路径
 function isAppDataRelativePath(src: string): boolean {
-  return src.startsWith("books/");
+  return src.startsWith("books/") || src.startsWith("notes/") || src.startsWith(ATTACHMENT_SCHEME);
+}
+
+// 路径中不能出现空段、. 与 ..，也不能含反斜杠，否则可能解析到 appDataDir 之外
+function hasUnsafeSegment(segments: string[]): boolean {
+  return segments.some((segment) => segment === "" || segment === "." || segment === ".." || segment.includes("\\"));
+}
+
+// 附件地址只能指向 notes/{noteId}/ 下的单个文件；不安全的路径返回 null
+function toAppDataRelativePath(src: string): string | null {
+  if (!src.startsWith(ATTACHMENT_SCHEME)) {
+    return hasUnsafeSegment(src.split("/")) ? null : src;
+  }
+
+  let rest: string;
+  try {
+    rest = decodeURIComponent(src.slice(ATTACHMENT_SCHEME.length));
+  } catch {
+    return null;
+  }
+  const segments = rest.split("/");
+  if (segments.length !== 2 || hasUnsafeSegment(segments)) {
+    return null;
+  }
+  const [noteId, fileName] = segments;
+  return `notes/${noteId}/${fileName}`;
+}
+
+// react-markdown 默认会过滤掉未知协议的链接
+function urlTransform(url: string): string {
+  return url.startsWith(ATTACHMENT_SCHEME) ? url : defaultUrlTransform(url);
 }
 
 const INITIAL_COMPONENTS: Partial<Components> = {
@@ -116,7 +149,11 @@ const MemoizedMarkdownBlock = memo(
     components?: Partial<Components>;
   }) {
     return (
-      <ReactMarkdown remarkPlugins={[remarkGfm, remarkBreaks, remarkCjkFriendly]} components={components}>
+      <ReactMarkdown
+        remarkPlugins={[remarkGfm, remarkBreaks, remarkCjkFriendly]}
+        components={components}
+        urlTransform={urlTransform}
+      >
         {content}
       </ReactMarkdown>
     );
@@ -148,7 +185,13 @@ function MarkdownComponent({ children, id, className, components }: MarkdownProp
 
         // 处理相对于appDataDir的路径
         if (isAppDataRelativePath(src)) {
-          getFullPathFromAppData(src)
+          const relativePath = toAppDataRelativePath(src);
+          if (!relativePath) {
+            console.warn(`Rejected unsafe app-data path: ${src}`);
+            setResolvedSrc("");
+            return;
+          }
+          getFullPathFromAppData(relativePath)
             .then((fullPath) => {
               const tauriSrc = convertFileSrc(fullPath);
               setResolvedSrc(tauriSrc);
@@ -54,6 +54,8 @@ export interface BookNote {
     before: string;
     after: string;
   };
+  // 替换书籍文件后重定位不可信（fuzzy）或失败（lost）
+  anchorStatus?: "fuzzy" | "lost";
 
   createdAt: number;
   updatedAt: number;
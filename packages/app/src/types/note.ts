@@ -30,6 +30,7 @@ export interface UpdateNoteData {
   bookMeta?: BookMeta | null; // null表示清空书籍信息
   title?: string | null; // null表示清空标题
   content?: string | null; // null表示清空内容
+  source?: "user" | "ai"; // 编辑来源，AI 改写前总是保存历史版本
 }
 
 // 查询笔记时的选项